@@ -14,7 +14,7 @@ pub fn maplibre_apple_main() {
     run_multithreaded(async {
         MapBuilder::new()
             .with_map_window_config(WinitMapWindowConfig::new("maplibre apple".to_string()))
-            .with_http_client(ReqwestHttpClient::new(None))
+            .with_http_client(ReqwestHttpClient::new())
             .with_schedule_method(TokioScheduleMethod::new())
             .build()
             .initialize()
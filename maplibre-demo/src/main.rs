@@ -1,8 +1,11 @@
+use maplibre::io::persistent_cache::sqlite::SqliteCacheBackend;
+use maplibre::io::persistent_cache::CachePolicy;
 use maplibre::platform::http_client::ReqwestHttpClient;
 use maplibre::platform::run_multithreaded;
 use maplibre::platform::schedule_method::TokioScheduleMethod;
 use maplibre::MapBuilder;
 use maplibre_winit::winit::{WinitEventLoop, WinitMapWindow, WinitMapWindowConfig, WinitWindow};
+use std::sync::Arc;
 
 #[cfg(feature = "enable-tracing")]
 fn enable_tracing() {
@@ -15,10 +18,14 @@ fn enable_tracing() {
 }
 
 fn run_in_window() {
+    let cache_backend =
+        SqliteCacheBackend::open("./maplibre-cache.sqlite").expect("tile cache can not be opened");
+
     run_multithreaded(async {
         MapBuilder::new()
             .with_map_window_config(WinitMapWindowConfig::new("maplibre".to_string()))
-            .with_http_client(ReqwestHttpClient::new(None))
+            .with_http_client(ReqwestHttpClient::new())
+            .with_tile_cache(Arc::new(cache_backend), CachePolicy::default())
             .with_schedule_method(TokioScheduleMethod::new())
             .build()
             .initialize()
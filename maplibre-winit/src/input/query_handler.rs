@@ -65,9 +65,9 @@ impl UpdateState for QueryHandler {
                 let _z = state.visible_level(); // FIXME: can be wrong, if tiles of different z are visible
                 let _zoom = state.zoom();
 
-                if let Some(_coordinates) = state
-                    .camera
-                    .window_to_world_at_ground(&window_position, &inverted_view_proj)
+                // Picks the position on the terrain, if the style has one
+                if let Some(_coordinates) =
+                    state.window_to_world(&window_position, &inverted_view_proj)
                 {
                     /*state
                     .scheduler()
@@ -5,6 +5,7 @@ use std::fmt::Formatter;
 
 use cgmath::num_traits::Pow;
 use cgmath::{AbsDiffEq, Matrix4, Point3, Vector3};
+use serde::{Deserialize, Serialize};
 
 use crate::style::source::TileAddressingScheme;
 
@@ -108,6 +109,11 @@ impl Zoom {
     pub fn level(&self) -> u8 {
         self.0.floor() as u8
     }
+
+    /// The zoom level including its fractional part
+    pub fn value(&self) -> f64 {
+        self.0
+    }
 }
 
 impl SignificantlyDifferent for Zoom {
@@ -137,7 +143,7 @@ pub struct InnerCoords {
 /// # Coordinate System Origin
 ///
 /// For Web Mercator the origin of the coordinate system is in the upper-left corner.
-#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct TileCoords {
     pub x: u32,
     pub y: u32,
@@ -246,8 +252,13 @@ impl WorldTileCoords {
 
         // Divide by EXTENT to normalize tile
         // Scale tiles where zoom level = self.z to 512x512
-        let normalize_and_scale =
-            Matrix4::from_nonuniform_scale(tile_scale / EXTENT, tile_scale / EXTENT, 1.0);
+        // The z axis is scaled like x and y, such that elevations of the terrain can be given in
+        // units of the tile
+        let normalize_and_scale = Matrix4::from_nonuniform_scale(
+            tile_scale / EXTENT,
+            tile_scale / EXTENT,
+            tile_scale / EXTENT,
+        );
         translate * normalize_and_scale
     }
 
@@ -325,6 +336,21 @@ impl WorldTileCoords {
             z: self.z - 1,
         })
     }
+
+    /// Get the tile at zoom level `z` which contains this one. Returns `None` if `z` is higher
+    /// than the zoom level of this tile.
+    pub fn get_ancestor(&self, z: u8) -> Option<WorldTileCoords> {
+        if z > self.z {
+            return None;
+        }
+
+        let delta = self.z - z;
+        Some(WorldTileCoords {
+            x: self.x >> delta,
+            y: self.y >> delta,
+            z,
+        })
+    }
 }
 
 impl From<(i32, i32, u8)> for WorldTileCoords {
@@ -484,6 +510,68 @@ impl ViewRegion {
     }
 }
 
+/// The maximum latitude which can be represented in the Web Mercator projection.
+const MAX_LATITUDE: f64 = 85.05112877980659;
+
+/// A rectangular range of tiles at the zoom level `z`. Both the minimum and the maximum tile are
+/// part of the range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileRange {
+    min_tile: WorldTileCoords,
+    max_tile: WorldTileCoords,
+    z: u8,
+}
+
+impl TileRange {
+    /// Creates the range of tiles at zoom level `z` which intersect with the area described by
+    /// `bounds`. The bounds are given as `(west, south, east, north)` in degrees, like the `bounds`
+    /// of a source or TileJSON. If `west` is greater than `east`, the bounds cross the
+    /// antimeridian.
+    pub fn from_lng_lat_bounds(bounds: (f64, f64, f64, f64), z: u8) -> Self {
+        let (west, south, east, north) = bounds;
+        let tiles = 2f64.powi(z as i32);
+        let max_index = tiles as i32 - 1;
+
+        let x = |lng: f64| {
+            let x = ((lng.clamp(-180.0, 180.0) + 180.0) / 360.0 * tiles).floor() as i32;
+            x.clamp(0, max_index)
+        };
+        let y = |lat: f64| {
+            let lat = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+            let y = ((1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / std::f64::consts::PI) / 2.0
+                * tiles)
+                .floor() as i32;
+            y.clamp(0, max_index)
+        };
+
+        Self {
+            min_tile: (x(west), y(north), z).into(),
+            max_tile: (x(east), y(south), z).into(),
+            z,
+        }
+    }
+
+    pub fn zoom_level(&self) -> u8 {
+        self.z
+    }
+
+    /// Returns whether the tile at `world_coords` is part of this range. Tiles of other zoom levels
+    /// are never part of the range.
+    pub fn contains(&self, world_coords: &WorldTileCoords) -> bool {
+        let contains_x = if self.min_tile.x <= self.max_tile.x {
+            world_coords.x >= self.min_tile.x && world_coords.x <= self.max_tile.x
+        } else {
+            // The range crosses the antimeridian
+            world_coords.x >= self.min_tile.x || world_coords.x <= self.max_tile.x
+        };
+
+        contains_x
+            && world_coords.y >= self.min_tile.y
+            && world_coords.y <= self.max_tile.y
+            && world_coords.z == self.z
+    }
+}
+
 impl fmt::Display for TileCoords {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -520,7 +608,7 @@ mod tests {
     use crate::style::source::TileAddressingScheme;
 
     use crate::coords::{
-        Quadkey, TileCoords, ViewRegion, WorldCoords, WorldTileCoords, Zoom, EXTENT,
+        Quadkey, TileCoords, TileRange, ViewRegion, WorldCoords, WorldTileCoords, Zoom, EXTENT,
     };
     use crate::util::math::Aabb2;
 
@@ -578,6 +666,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_ancestor() {
+        let tile = WorldTileCoords::from((17425, 11365, 15));
+        assert_eq!(tile.get_ancestor(15), Some(tile));
+        assert_eq!(tile.get_ancestor(14), tile.get_parent());
+        assert_eq!(
+            tile.get_ancestor(12),
+            Some(WorldTileCoords::from((2178, 1420, 12)))
+        );
+        assert_eq!(tile.get_ancestor(0), Some(WorldTileCoords::from((0, 0, 0))));
+        assert_eq!(tile.get_ancestor(16), None);
+    }
+
+    #[test]
+    fn test_tile_range() {
+        // Approximately the extent of Germany
+        let germany = (5.8, 47.2, 15.1, 55.1);
+
+        let range = TileRange::from_lng_lat_bounds(germany, 0);
+        assert!(range.contains(&(0, 0, 0).into()));
+
+        let range = TileRange::from_lng_lat_bounds(germany, 5);
+        assert!(range.contains(&(16, 10, 5).into()));
+        assert!(range.contains(&(17, 11, 5).into()));
+        assert!(!range.contains(&(15, 10, 5).into()));
+        assert!(!range.contains(&(16, 9, 5).into()));
+        assert!(!range.contains(&(16, 10, 6).into()));
+
+        let range = TileRange::from_lng_lat_bounds((170.0, -10.0, -170.0, 10.0), 2);
+        assert!(range.contains(&(3, 1, 2).into()));
+        assert!(range.contains(&(0, 2, 2).into()));
+        assert!(!range.contains(&(1, 1, 2).into()));
+    }
+
     #[test]
     fn test_view_region() {
         for tile_coords in ViewRegion::new(
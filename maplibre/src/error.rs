@@ -33,11 +33,26 @@ impl RenderError {
 #[derive(Debug)]
 pub enum Error {
     Schedule,
+    /// A transient network failure like a timeout, a connection error or a server error. Retrying
+    /// the request might succeed.
     Network(String),
+    /// The requested resource does not exist, e.g. a tile which is missing in a tileset.
+    NotFound(String),
+    IO(String),
+    Style(String),
+    /// A payload like a tile is malformed or uses an unsupported compression.
+    Decode(String),
     Tesselation(TessellationError),
     Render(RenderError),
 }
 
+impl Error {
+    /// Returns whether the failed operation might succeed if it is retried.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Error::Network(_))
+    }
+}
+
 impl From<SurfaceError> for Error {
     fn from(e: SurfaceError) -> Self {
         Error::Render(RenderError::Surface(e))
@@ -0,0 +1,167 @@
+//! Decompression of tile payloads. Vector tiles are often stored gzip-compressed, e.g. within
+//! MBTiles files or on static hosts. PMTiles archives can additionally use brotli.
+
+use std::io::Read;
+
+use geozero::mvt::Tile;
+use prost::Message;
+
+use crate::error::Error;
+
+/// The first two bytes of a gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Buffer size of the brotli decompressor
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// Encoding of a payload, like in the HTTP `Content-Encoding` header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Identity,
+    Gzip,
+    Brotli,
+}
+
+impl ContentEncoding {
+    /// Parses the value of a `Content-Encoding` header. Returns `None` for unsupported encodings.
+    pub fn from_header(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "identity" => Some(ContentEncoding::Identity),
+            "gzip" | "x-gzip" => Some(ContentEncoding::Gzip),
+            "br" => Some(ContentEncoding::Brotli),
+            _ => None,
+        }
+    }
+
+    /// Detects gzip streams by their magic bytes. Brotli streams do not have a signature,
+    /// therefore anything else is considered uncompressed.
+    pub fn sniff(data: &[u8]) -> Self {
+        if data.starts_with(&GZIP_MAGIC) {
+            ContentEncoding::Gzip
+        } else {
+            ContentEncoding::Identity
+        }
+    }
+}
+
+/// Decompresses `data` which is encoded with `encoding`.
+pub fn decompress(data: &[u8], encoding: ContentEncoding) -> Result<Vec<u8>, Error> {
+    let mut decompressed = Vec::with_capacity(data.len() * 2);
+    let result = match encoding {
+        ContentEncoding::Identity => return Ok(data.to_vec()),
+        ContentEncoding::Gzip => flate2::read::GzDecoder::new(data).read_to_end(&mut decompressed),
+        ContentEncoding::Brotli => brotli_decompressor::Decompressor::new(data, BROTLI_BUFFER_SIZE)
+            .read_to_end(&mut decompressed),
+    };
+
+    result.map_err(|e| Error::Decode(format!("{:?} payload is malformed: {}", encoding, e)))?;
+    Ok(decompressed)
+}
+
+/// Decompresses `data` if it starts with the gzip magic bytes. Otherwise it is returned unchanged.
+pub fn decompress_sniffed(data: Vec<u8>) -> Result<Vec<u8>, Error> {
+    match ContentEncoding::sniff(&data) {
+        ContentEncoding::Identity => Ok(data),
+        encoding => decompress(&data, encoding),
+    }
+}
+
+/// Decodes a [Mapbox Vector Tile](https://github.com/mapbox/vector-tile-spec).
+///
+/// If the `encoding` is not known, gzip-compressed payloads are detected by their magic bytes.
+/// Payloads which are neither gzip-compressed nor valid tiles are tried to be decoded as brotli
+/// streams, because brotli can not be detected reliably.
+pub fn decode_tile(data: &[u8], encoding: Option<ContentEncoding>) -> Result<Tile, Error> {
+    let decode = |data: &[u8]| {
+        Tile::decode(data).map_err(|e| Error::Decode(format!("tile is malformed: {}", e)))
+    };
+
+    match encoding {
+        Some(encoding) => decode(&decompress(data, encoding)?),
+        None => match ContentEncoding::sniff(data) {
+            ContentEncoding::Identity => decode(data).or_else(|e| {
+                decompress(data, ContentEncoding::Brotli)
+                    .and_then(|decompressed| decode(&decompressed))
+                    // Report the original error, the payload was most likely not brotli
+                    .map_err(|_| e)
+            }),
+            encoding => decode(&decompress(data, encoding)?),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use geozero::mvt::{tile, Tile};
+    use prost::Message;
+
+    use crate::error::Error;
+    use crate::io::compression::{decode_tile, ContentEncoding};
+
+    fn encoded_tile() -> Vec<u8> {
+        Tile {
+            layers: vec![tile::Layer {
+                version: 2,
+                name: "water".to_string(),
+                extent: Some(4096),
+                ..Default::default()
+            }],
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn test_content_encoding() {
+        assert_eq!(
+            ContentEncoding::from_header("GZIP"),
+            Some(ContentEncoding::Gzip)
+        );
+        assert_eq!(
+            ContentEncoding::from_header("br"),
+            Some(ContentEncoding::Brotli)
+        );
+        assert_eq!(ContentEncoding::from_header("zstd"), None);
+        assert_eq!(
+            ContentEncoding::sniff(&[0x1f, 0x8b, 0x08]),
+            ContentEncoding::Gzip
+        );
+        assert_eq!(ContentEncoding::sniff(&[0x1a]), ContentEncoding::Identity);
+    }
+
+    #[test]
+    fn test_decode_tile() {
+        let data = encoded_tile();
+        assert_eq!(decode_tile(&data, None).unwrap().layers[0].name, "water");
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(
+            decode_tile(&compressed, None).unwrap().layers[0].name,
+            "water"
+        );
+        assert_eq!(
+            decode_tile(&compressed, Some(ContentEncoding::Gzip))
+                .unwrap()
+                .layers[0]
+                .name,
+            "water"
+        );
+    }
+
+    #[test]
+    fn test_malformed_tile() {
+        assert!(matches!(
+            decode_tile(&[0xff, 0xff, 0xff], None),
+            Err(Error::Decode(_))
+        ));
+        assert!(matches!(
+            decode_tile(&[0x1f, 0x8b, 0x00], None),
+            Err(Error::Decode(_))
+        ));
+    }
+}
@@ -0,0 +1,189 @@
+//! Reads vector tiles from a directory tree on the local filesystem, like the ones which are
+//! written by `tippecanoe --output-to-directory` or by `maplibre_build_tools::mbtiles::extract`.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::coords::WorldTileCoords;
+use crate::error::Error;
+use crate::io::compression::decompress_sniffed;
+use crate::io::tile_url::TileUrlTemplates;
+use crate::style::source::TileAddressingScheme;
+
+/// Layout of the tiles within the directory, if the source only references the directory itself.
+const DEFAULT_LAYOUT: &str = "{z}/{x}/{y}.pbf";
+
+/// Extension of gzip-compressed tiles which is tried if a tile does not exist uncompressed.
+const GZIP_EXTENSION: &str = ".gz";
+
+/// Source client which serves tiles from files at paths like `/tiles/{z}/{x}/{y}.pbf`.
+///
+/// The template supports the same placeholders as tile URLs. If a tile does not exist, the same
+/// path with an additional `.gz` extension is tried. Gzip-compressed tiles are decompressed,
+/// regardless of their extension. If the root directory of the tiles contains a `metadata.json`,
+/// it is read like the `metadata` table of a MBTiles file.
+#[derive(Clone)]
+pub struct DirectorySourceClient {
+    root: PathBuf,
+    template: TileUrlTemplates,
+    scheme: TileAddressingScheme,
+    metadata: Arc<HashMap<String, String>>,
+}
+
+impl DirectorySourceClient {
+    /// Creates a client for the path `template`. If the template does not contain any
+    /// placeholders, it is treated as the root directory of the tiles and the layout
+    /// `{z}/{x}/{y}.pbf` is used.
+    pub fn open(template: &str, scheme: TileAddressingScheme) -> Result<Self, Error> {
+        let template = if template.contains("{z}") {
+            template.to_string()
+        } else {
+            format!("{}/{}", template.trim_end_matches('/'), DEFAULT_LAYOUT)
+        };
+
+        // The root is the directory which contains the first placeholder
+        let prefix = &template[..template.find('{').unwrap_or(template.len())];
+        let root = PathBuf::from(&prefix[..prefix.rfind('/').map_or(0, |index| index + 1)]);
+        if !root.is_dir() {
+            return Err(Error::IO(format!(
+                "tile directory {:?} does not exist",
+                root
+            )));
+        }
+
+        let metadata = Self::read_metadata(&root)?;
+
+        Ok(Self {
+            root,
+            template: TileUrlTemplates::new(&[template]),
+            scheme,
+            metadata: Arc::new(metadata),
+        })
+    }
+
+    /// Reads the `metadata.json` within `root`. Values which are not strings are kept as JSON.
+    fn read_metadata(root: &Path) -> Result<HashMap<String, String>, Error> {
+        let content = match std::fs::read(root.join("metadata.json")) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(Error::IO(e.to_string())),
+        };
+
+        let metadata: HashMap<String, serde_json::Value> = serde_json::from_slice(&content)
+            .map_err(|e| Error::IO(format!("invalid metadata.json in {:?}: {}", root, e)))?;
+        Ok(metadata
+            .into_iter()
+            .map(|(name, value)| match value {
+                serde_json::Value::String(value) => (name, value),
+                value => (name, value.to_string()),
+            })
+            .collect())
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Key-value pairs from the `metadata.json`, e.g. `name`, `format`, `minzoom` or `json`.
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
+
+    pub fn minzoom(&self) -> Option<u8> {
+        self.metadata
+            .get("minzoom")
+            .and_then(|zoom| zoom.parse().ok())
+    }
+
+    pub fn maxzoom(&self) -> Option<u8> {
+        self.metadata
+            .get("maxzoom")
+            .and_then(|zoom| zoom.parse().ok())
+    }
+
+    pub async fn fetch(&self, coords: &WorldTileCoords) -> Result<Vec<u8>, Error> {
+        self.sync_fetch(coords)
+    }
+
+    pub fn sync_fetch(&self, coords: &WorldTileCoords) -> Result<Vec<u8>, Error> {
+        let path = self
+            .template
+            .expand(coords, &self.scheme)
+            .ok_or_else(|| Error::NotFound(format!("Tile {} is out of bounds", coords)))?;
+
+        let data = match std::fs::read(&path) {
+            Err(e) if e.kind() == ErrorKind::NotFound && !path.ends_with(GZIP_EXTENSION) => {
+                std::fs::read(format!("{}{}", path, GZIP_EXTENSION))
+            }
+            result => result,
+        };
+
+        match data {
+            Ok(data) => decompress_sniffed(data),
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(Error::NotFound(format!(
+                "Tile {} not found at {}",
+                coords, path
+            ))),
+            Err(e) => Err(Error::IO(e.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use crate::coords::WorldTileCoords;
+    use crate::error::Error;
+    use crate::style::source::TileAddressingScheme;
+
+    use super::DirectorySourceClient;
+
+    #[tokio::test]
+    async fn test_fetch_layouts() {
+        let root = std::env::temp_dir().join(format!("maplibre-directory-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("1/1")).unwrap();
+
+        std::fs::write(
+            root.join("metadata.json"),
+            r#"{"minzoom": "0", "maxzoom": 1}"#,
+        )
+        .unwrap();
+        std::fs::write(root.join("1/1/0.pbf"), b"tile").unwrap();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"compressed").unwrap();
+        std::fs::write(root.join("1/1/1.pbf.gz"), encoder.finish().unwrap()).unwrap();
+
+        let client =
+            DirectorySourceClient::open(root.to_str().unwrap(), TileAddressingScheme::XYZ).unwrap();
+        assert_eq!(client.minzoom(), Some(0));
+        assert_eq!(client.maxzoom(), Some(1));
+
+        let tile: WorldTileCoords = (1, 0, 1).into();
+        assert_eq!(client.fetch(&tile).await.unwrap(), b"tile".to_vec());
+        let compressed: WorldTileCoords = (1, 1, 1).into();
+        assert_eq!(
+            client.fetch(&compressed).await.unwrap(),
+            b"compressed".to_vec()
+        );
+        let missing: WorldTileCoords = (0, 0, 1).into();
+        assert!(matches!(
+            client.fetch(&missing).await,
+            Err(Error::NotFound(_))
+        ));
+
+        // With the TMS scheme the rows are flipped
+        let template = format!("{}/{{z}}/{{x}}/{{y}}.pbf", root.to_str().unwrap());
+        let client = DirectorySourceClient::open(&template, TileAddressingScheme::TMS).unwrap();
+        assert_eq!(client.fetch(&compressed).await.unwrap(), b"tile".to_vec());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
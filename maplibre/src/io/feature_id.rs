@@ -0,0 +1,188 @@
+//! Identifiers of features which are stable across tiles. A feature which is split into several
+//! tiles has the same id in each of them.
+
+use std::fmt;
+
+use geozero::mvt::tile;
+
+use crate::style::source::PromoteId;
+
+/// Identifier of a feature. It is either the `id` of the feature within the vector tile or the
+/// value of the property which is promoted by the `promoteId` of the source.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FeatureId {
+    Number(u64),
+    String(String),
+}
+
+impl FeatureId {
+    /// Numeric representation of the id which is written into GPU buffers. Strings are hashed
+    /// with FNV-1a, such that a string results in the same number on every thread.
+    pub fn numeric(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+        const FNV_PRIME: u64 = 0x100000001b3;
+
+        match self {
+            FeatureId::Number(id) => *id,
+            FeatureId::String(id) => id.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+            }),
+        }
+    }
+
+    /// Converts the value of a promoted property. Like in MapLibre GL, booleans are converted to
+    /// numbers. Numbers which are not unsigned integers are kept as strings.
+    fn from_value(value: &tile::Value) -> Option<Self> {
+        if let Some(value) = &value.string_value {
+            Some(FeatureId::String(value.clone()))
+        } else if let Some(value) = value.uint_value {
+            Some(FeatureId::Number(value))
+        } else if let Some(value) = value.int_value.or(value.sint_value) {
+            Some(if value >= 0 {
+                FeatureId::Number(value as u64)
+            } else {
+                FeatureId::String(value.to_string())
+            })
+        } else if let Some(value) = value.double_value.or(value.float_value.map(f64::from)) {
+            Some(
+                if value >= 0.0 && value.fract() == 0.0 && value < u64::MAX as f64 {
+                    FeatureId::Number(value as u64)
+                } else {
+                    FeatureId::String(value.to_string())
+                },
+            )
+        } else {
+            value
+                .bool_value
+                .map(|value| FeatureId::Number(value as u64))
+        }
+    }
+}
+
+impl fmt::Display for FeatureId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeatureId::Number(id) => write!(f, "{}", id),
+            FeatureId::String(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// Returns the ids of the features of `layer` in the order of the features. If `promote_id`
+/// names a property for the layer, its values are used as ids. Otherwise, the ids of the features
+/// within the tile are used. Features without id are `None`.
+pub fn feature_ids(layer: &tile::Layer, promote_id: Option<&PromoteId>) -> Vec<Option<FeatureId>> {
+    let promoted_key = promote_id
+        .and_then(|promote_id| promote_id.property(&layer.name))
+        .map(|property| {
+            layer
+                .keys
+                .iter()
+                .position(|key| key == property)
+                .map(|index| index as u32)
+        });
+
+    layer
+        .features
+        .iter()
+        .map(|feature| match promoted_key {
+            // The property is not used within this layer
+            Some(None) => None,
+            Some(Some(key_index)) => feature
+                .tags
+                .chunks_exact(2)
+                .find(|tag| tag[0] == key_index)
+                .and_then(|tag| layer.values.get(tag[1] as usize))
+                .and_then(FeatureId::from_value),
+            None => feature.id.map(FeatureId::Number),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use geozero::mvt::tile;
+
+    use crate::style::source::PromoteId;
+
+    use super::{feature_ids, FeatureId};
+
+    fn layer() -> tile::Layer {
+        let string_value = tile::Value {
+            string_value: Some("way/1".to_string()),
+            ..Default::default()
+        };
+        let number_value = tile::Value {
+            uint_value: Some(42),
+            ..Default::default()
+        };
+
+        tile::Layer {
+            version: 2,
+            name: "building".to_string(),
+            keys: vec!["name".to_string(), "osm_id".to_string()],
+            values: vec![string_value, number_value],
+            features: vec![
+                tile::Feature {
+                    id: Some(1),
+                    tags: vec![0, 0, 1, 1],
+                    ..Default::default()
+                },
+                tile::Feature {
+                    id: Some(2),
+                    tags: vec![1, 0],
+                    ..Default::default()
+                },
+                tile::Feature {
+                    id: None,
+                    tags: vec![],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_feature_ids() {
+        let layer = layer();
+
+        assert_eq!(
+            feature_ids(&layer, None),
+            vec![Some(FeatureId::Number(1)), Some(FeatureId::Number(2)), None]
+        );
+
+        let promote_id = PromoteId::Property("osm_id".to_string());
+        assert_eq!(
+            feature_ids(&layer, Some(&promote_id)),
+            vec![
+                Some(FeatureId::Number(42)),
+                Some(FeatureId::String("way/1".to_string())),
+                None
+            ]
+        );
+
+        // The property is promoted only within other layers
+        let promote_id =
+            PromoteId::PerLayer(HashMap::from([("water".to_string(), "osm_id".to_string())]));
+        assert_eq!(
+            feature_ids(&layer, Some(&promote_id)),
+            vec![Some(FeatureId::Number(1)), Some(FeatureId::Number(2)), None]
+        );
+    }
+
+    #[test]
+    fn test_numeric() {
+        assert_eq!(FeatureId::Number(7).numeric(), 7);
+        assert_eq!(
+            FeatureId::String("way/1".to_string()).numeric(),
+            FeatureId::String("way/1".to_string()).numeric()
+        );
+        assert_ne!(
+            FeatureId::String("way/1".to_string()).numeric(),
+            FeatureId::String("way/2".to_string()).numeric()
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! Serves tiles which are sliced from the GeoJSON of a `geojson` source.
+
+use std::sync::{Arc, Mutex};
+
+use geozero::mvt::Tile;
+use prost::Message;
+
+use crate::coords::WorldTileCoords;
+use crate::error::Error;
+use crate::io::geojson_tiler::{GeoJsonIndex, TilerOptions, DEFAULT_BUFFER, DEFAULT_TOLERANCE};
+use crate::io::source_client::HTTPClient;
+use crate::style::layer::GEOJSON_SOURCE_LAYER;
+use crate::style::source::{GeoJsonData, GeoJsonSource, GEOJSON_DEFAULT_MAXZOOM};
+
+/// Source client which slices GeoJSON into vector tiles with a single layer called
+/// [`GEOJSON_SOURCE_LAYER`].
+///
+/// The GeoJSON is loaded and indexed during the first request, which runs on a worker thread like
+/// any other tile request. Tiles are encoded like Mapbox Vector Tiles, such that they are
+/// tessellated the same way as tiles of vector sources.
+///
+/// Clones of the client share their data, such that [`GeoJsonSourceClient::set_data`] affects
+/// the requests of all clones.
+#[derive(Clone)]
+pub struct GeoJsonSourceClient<HC>
+where
+    HC: HTTPClient,
+{
+    http_client: HC,
+    options: TilerOptions,
+    state: Arc<Mutex<GeoJsonState>>,
+}
+
+struct GeoJsonState {
+    data: GeoJsonData,
+    index: Option<Arc<GeoJsonIndex>>,
+    /// Incremented each time the data is replaced. Indices which are built from outdated data are
+    /// not stored.
+    revision: u64,
+}
+
+impl<HC> GeoJsonSourceClient<HC>
+where
+    HC: HTTPClient,
+{
+    pub fn new(source: &GeoJsonSource, http_client: HC) -> Self {
+        Self {
+            http_client,
+            options: TilerOptions {
+                max_zoom: source.maxzoom.unwrap_or(GEOJSON_DEFAULT_MAXZOOM),
+                tolerance: source.tolerance.unwrap_or(DEFAULT_TOLERANCE),
+                buffer: source.buffer.unwrap_or(DEFAULT_BUFFER),
+                ..TilerOptions::default()
+            },
+            state: Arc::new(Mutex::new(GeoJsonState {
+                data: source.data.clone(),
+                index: None,
+                revision: 0,
+            })),
+        }
+    }
+
+    /// Returns the index of the GeoJSON. The index is created if it does not exist yet. Requests
+    /// which run concurrently before the index exists can create it multiple times.
+    async fn index(&self) -> Result<Arc<GeoJsonIndex>, Error> {
+        let (data, revision) = {
+            let state = self
+                .state
+                .lock()
+                .map_err(|_| Error::IO("GeoJSON state is poisoned".to_string()))?;
+            if let Some(index) = &state.index {
+                return Ok(index.clone());
+            }
+            (state.data.clone(), state.revision)
+        };
+
+        let geojson = match data {
+            GeoJsonData::Url(url) => {
+                let data = self.http_client.fetch(url).await?;
+                serde_json::from_slice(&data)
+                    .map_err(|e| Error::Decode(format!("invalid GeoJSON at {}: {}", url, e)))?
+            }
+            GeoJsonData::Inline(geojson) => geojson,
+        };
+
+        let index = Arc::new(GeoJsonIndex::new(&geojson, self.options.clone())?);
+        log::info!("indexed {} GeoJSON features", index.len());
+
+        if let Ok(mut state) = self.state.lock() {
+            // The data might have been replaced while it was loaded
+            if state.revision == revision {
+                state.index = Some(index.clone());
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Replaces the GeoJSON of the source. The new data is indexed immediately, such that invalid
+    /// GeoJSON is reported to the caller and the source keeps its previous data.
+    ///
+    /// Returns the previous index, if the previous data had been indexed already, and the new
+    /// index. These tell which tiles are affected by the change.
+    pub fn set_data(
+        &self,
+        geojson: serde_json::Value,
+    ) -> Result<(Option<Arc<GeoJsonIndex>>, Arc<GeoJsonIndex>), Error> {
+        let index = Arc::new(GeoJsonIndex::new(&geojson, self.options.clone())?);
+        log::info!("indexed {} GeoJSON features", index.len());
+
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| Error::IO("GeoJSON state is poisoned".to_string()))?;
+        state.data = GeoJsonData::Inline(geojson);
+        state.revision += 1;
+        let previous = state.index.replace(index.clone());
+
+        Ok((previous, index))
+    }
+
+    pub async fn fetch(&self, coords: &WorldTileCoords) -> Result<Vec<u8>, Error> {
+        let index = self.index().await?;
+
+        // Tiles without features lack the layer, which marks it as unavailable
+        let tile = Tile {
+            layers: index
+                .tile_layer(coords, GEOJSON_SOURCE_LAYER)
+                .into_iter()
+                .collect(),
+        };
+        Ok(tile.encode_to_vec())
+    }
+}
@@ -0,0 +1,833 @@
+//! Slices GeoJSON into vector tiles on the fly, similar to
+//! [geojson-vt](https://github.com/mapbox/geojson-vt).
+//!
+//! The GeoJSON is converted once into an index of projected features. The importance of each
+//! vertex for the Douglas-Peucker simplification is calculated at that point, such that tiles of
+//! each zoom level can be simplified by just skipping unimportant vertices. Tiles are created by
+//! clipping the simplified features to the bounds of the tile including a buffer.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use geozero::mvt::tile;
+use serde_json::{Map, Value};
+
+use crate::coords::{WorldTileCoords, EXTENT_UINT};
+use crate::error::Error;
+
+/// Default of the simplification `tolerance` according to the style specification
+pub const DEFAULT_TOLERANCE: f64 = 0.375;
+/// Default of the tile `buffer` according to the style specification
+pub const DEFAULT_BUFFER: u32 = 128;
+
+const COMMAND_MOVE_TO: u32 = 1;
+const COMMAND_LINE_TO: u32 = 2;
+const COMMAND_CLOSE_PATH: u32 = 7;
+
+#[derive(Clone, Debug)]
+pub struct TilerOptions {
+    /// Zoom level up to which vertices are kept by the simplification
+    pub max_zoom: u8,
+    /// Tolerance of the simplification in tile units
+    pub tolerance: f64,
+    /// Size of the buffer around each tile in tile units
+    pub buffer: u32,
+    pub extent: u32,
+}
+
+impl Default for TilerOptions {
+    fn default() -> Self {
+        Self {
+            max_zoom: 18,
+            tolerance: DEFAULT_TOLERANCE,
+            buffer: DEFAULT_BUFFER,
+            extent: EXTENT_UINT,
+        }
+    }
+}
+
+/// A vertex in projected coordinates within `[0, 1]` together with its importance. The importance
+/// is the squared distance at which the vertex is removed by the simplification.
+#[derive(Clone, Copy, Debug)]
+struct Vertex {
+    x: f64,
+    y: f64,
+    importance: f64,
+}
+
+type Line = Vec<Vertex>;
+
+enum Geometry {
+    Points(Vec<Vertex>),
+    Lines(Vec<Line>),
+    /// Polygons which consist of an exterior ring followed by its holes
+    Polygons(Vec<Vec<Line>>),
+}
+
+struct Feature {
+    id: Option<u64>,
+    geometry: Geometry,
+    properties: Vec<(String, tile::Value)>,
+    /// Bounding box as `(min_x, min_y, max_x, max_y)` in projected coordinates
+    bbox: (f64, f64, f64, f64),
+}
+
+/// Index of the features of a GeoJSON object from which tiles are sliced.
+pub struct GeoJsonIndex {
+    features: Vec<Feature>,
+    /// Bounding box of all features, `None` if there are no features
+    bbox: Option<(f64, f64, f64, f64)>,
+    options: TilerOptions,
+}
+
+impl GeoJsonIndex {
+    /// Converts a GeoJSON `FeatureCollection`, `Feature` or geometry. Features without a geometry
+    /// are skipped.
+    pub fn new(geojson: &Value, options: TilerOptions) -> Result<Self, Error> {
+        let max_zoom_tolerance =
+            options.tolerance / ((1u64 << options.max_zoom) as f64 * options.extent as f64);
+        let mut converter = Converter {
+            sq_tolerance: max_zoom_tolerance * max_zoom_tolerance,
+            features: Vec::new(),
+        };
+        converter.convert(geojson)?;
+
+        let bbox = converter
+            .features
+            .iter()
+            .map(|feature| feature.bbox)
+            .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)));
+
+        Ok(Self {
+            features: converter.features,
+            bbox,
+            options,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.features.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+
+    /// Bounds of the tile at `coords` including the buffer as `(min_x, min_y, max_x, max_y)` in
+    /// projected coordinates.
+    fn tile_bounds(&self, coords: &WorldTileCoords) -> (f64, f64, f64, f64) {
+        let scale = (1u64 << coords.z) as f64;
+        let buffer = self.options.buffer as f64 / self.options.extent as f64;
+        (
+            (coords.x as f64 - buffer) / scale,
+            (coords.y as f64 - buffer) / scale,
+            (coords.x as f64 + 1.0 + buffer) / scale,
+            (coords.y as f64 + 1.0 + buffer) / scale,
+        )
+    }
+
+    /// Returns whether the tile at `coords` can contain any features of this index.
+    pub fn intersects(&self, coords: &WorldTileCoords) -> bool {
+        self.bbox
+            .map_or(false, |bbox| intersects(bbox, self.tile_bounds(coords)))
+    }
+
+    /// Slices the features which intersect the tile at `coords` into a vector tile layer called
+    /// `layer_name`. Returns `None` if the tile does not contain any features.
+    pub fn tile_layer(&self, coords: &WorldTileCoords, layer_name: &str) -> Option<tile::Layer> {
+        let extent = self.options.extent as f64;
+        let scale = (1u64 << coords.z) as f64;
+        let tolerance = self.options.tolerance / (scale * extent);
+        let sq_tolerance = tolerance * tolerance;
+
+        let tile_bounds = self.tile_bounds(coords);
+        let (min_x, min_y, max_x, max_y) = tile_bounds;
+
+        let to_tile = |vertex: &[f64; 2]| -> [i64; 2] {
+            [
+                ((vertex[0] * scale - coords.x as f64) * extent).round() as i64,
+                ((vertex[1] * scale - coords.y as f64) * extent).round() as i64,
+            ]
+        };
+
+        let mut builder = LayerBuilder::new(layer_name, self.options.extent);
+
+        for feature in &self.features {
+            if !intersects(feature.bbox, tile_bounds) {
+                continue;
+            }
+
+            let mut encoder = GeometryEncoder::default();
+            let geom_type = match &feature.geometry {
+                Geometry::Points(points) => {
+                    let points: Vec<[i64; 2]> = points
+                        .iter()
+                        .filter(|point| {
+                            point.x >= min_x
+                                && point.x <= max_x
+                                && point.y >= min_y
+                                && point.y <= max_y
+                        })
+                        .map(|point| to_tile(&[point.x, point.y]))
+                        .collect();
+                    encoder.points(&points);
+                    tile::GeomType::Point
+                }
+                Geometry::Lines(lines) => {
+                    for line in lines {
+                        let simplified = simplified(line, sq_tolerance);
+                        for clipped in clip_line(&simplified, min_x, max_x, 0)
+                            .iter()
+                            .flat_map(|line| clip_line(line, min_y, max_y, 1))
+                        {
+                            encoder.line(&dedup(clipped.iter().map(to_tile)));
+                        }
+                    }
+                    tile::GeomType::Linestring
+                }
+                Geometry::Polygons(polygons) => {
+                    for polygon in polygons {
+                        for (i, ring) in polygon.iter().enumerate() {
+                            let simplified = simplified(ring, sq_tolerance);
+                            let clipped = clip_ring(
+                                &clip_ring(&simplified, min_x, max_x, 0),
+                                min_y,
+                                max_y,
+                                1,
+                            );
+                            let exterior = i == 0;
+                            if !encoder.ring(dedup(clipped.iter().map(to_tile)), exterior)
+                                && exterior
+                            {
+                                // Holes of polygons whose exterior is outside of the tile are dropped
+                                break;
+                            }
+                        }
+                    }
+                    tile::GeomType::Polygon
+                }
+            };
+
+            if !encoder.geometry.is_empty() {
+                builder.add_feature(feature, geom_type, encoder.geometry);
+            }
+        }
+
+        builder.build()
+    }
+}
+
+/// Converts GeoJSON objects into projected and simplified features.
+struct Converter {
+    /// Squared tolerance of the simplification at the maximum zoom level
+    sq_tolerance: f64,
+    features: Vec<Feature>,
+}
+
+impl Converter {
+    fn convert(&mut self, geojson: &Value) -> Result<(), Error> {
+        match geojson.get("type").and_then(Value::as_str) {
+            Some("FeatureCollection") => {
+                let features = geojson
+                    .get("features")
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| invalid("FeatureCollection without features"))?;
+                for feature in features {
+                    self.convert_feature(feature)?;
+                }
+                Ok(())
+            }
+            Some("Feature") => self.convert_feature(geojson),
+            Some(_) => self.convert_geometry(geojson, None, &Map::new()),
+            None => Err(invalid("object without type")),
+        }
+    }
+
+    fn convert_feature(&mut self, feature: &Value) -> Result<(), Error> {
+        let id = feature.get("id").and_then(Value::as_u64);
+        let empty = Map::new();
+        let properties = feature
+            .get("properties")
+            .and_then(Value::as_object)
+            .unwrap_or(&empty);
+
+        match feature.get("geometry") {
+            Some(Value::Null) | None => Ok(()),
+            Some(geometry) => self.convert_geometry(geometry, id, properties),
+        }
+    }
+
+    fn convert_geometry(
+        &mut self,
+        geometry: &Value,
+        id: Option<u64>,
+        properties: &Map<String, Value>,
+    ) -> Result<(), Error> {
+        let typ = geometry
+            .get("type")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid("geometry without type"))?;
+
+        if typ == "GeometryCollection" {
+            let geometries = geometry
+                .get("geometries")
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid("GeometryCollection without geometries"))?;
+            for geometry in geometries {
+                self.convert_geometry(geometry, id, properties)?;
+            }
+            return Ok(());
+        }
+
+        let coordinates = geometry
+            .get("coordinates")
+            .ok_or_else(|| invalid("geometry without coordinates"))?;
+
+        let geometry = match typ {
+            "Point" => Geometry::Points(vec![project(coordinates)?]),
+            "MultiPoint" => Geometry::Points(
+                array(coordinates)?
+                    .iter()
+                    .map(project)
+                    .collect::<Result<_, _>>()?,
+            ),
+            "LineString" => Geometry::Lines(vec![self.convert_line(coordinates)?]),
+            "MultiLineString" => Geometry::Lines(self.convert_lines(coordinates)?),
+            "Polygon" => Geometry::Polygons(vec![self.convert_lines(coordinates)?]),
+            "MultiPolygon" => Geometry::Polygons(
+                array(coordinates)?
+                    .iter()
+                    .map(|polygon| self.convert_lines(polygon))
+                    .collect::<Result<_, _>>()?,
+            ),
+            typ => return Err(invalid(&format!("unknown geometry type {}", typ))),
+        };
+
+        let bbox = bbox(&geometry);
+        self.features.push(Feature {
+            id,
+            geometry,
+            properties: properties
+                .iter()
+                .filter_map(|(key, value)| Some((key.clone(), property_value(value)?)))
+                .collect(),
+            bbox,
+        });
+        Ok(())
+    }
+
+    fn convert_lines(&self, lines: &Value) -> Result<Vec<Line>, Error> {
+        array(lines)?
+            .iter()
+            .map(|line| self.convert_line(line))
+            .collect()
+    }
+
+    fn convert_line(&self, line: &Value) -> Result<Line, Error> {
+        let mut vertices: Line = array(line)?.iter().map(project).collect::<Result<_, _>>()?;
+
+        if let (Some(first), Some(last)) = (vertices.first_mut(), vertices.last_mut()) {
+            first.importance = 1.0;
+            last.importance = 1.0;
+        }
+        simplify(&mut vertices, self.sq_tolerance);
+
+        Ok(vertices)
+    }
+}
+
+fn intersects(a: (f64, f64, f64, f64), b: (f64, f64, f64, f64)) -> bool {
+    a.0 <= b.2 && a.2 >= b.0 && a.1 <= b.3 && a.3 >= b.1
+}
+
+fn invalid(message: &str) -> Error {
+    Error::Decode(format!("invalid GeoJSON: {}", message))
+}
+
+fn array(value: &Value) -> Result<&Vec<Value>, Error> {
+    value
+        .as_array()
+        .ok_or_else(|| invalid("coordinates are not an array"))
+}
+
+/// Projects a GeoJSON position into web mercator coordinates within `[0, 1]`. The y axis points
+/// south, like the rows of tiles.
+fn project(position: &Value) -> Result<Vertex, Error> {
+    let (lng, lat) = match position.as_array().map(Vec::as_slice) {
+        Some([lng, lat, ..]) => match (lng.as_f64(), lat.as_f64()) {
+            (Some(lng), Some(lat)) => (lng, lat),
+            _ => return Err(invalid("position is not numeric")),
+        },
+        _ => return Err(invalid("position with less than two coordinates")),
+    };
+
+    let sin = lat.to_radians().sin();
+    let y = 0.5 - 0.25 * ((1.0 + sin) / (1.0 - sin)).ln() / PI;
+
+    Ok(Vertex {
+        x: lng / 360.0 + 0.5,
+        y: y.clamp(0.0, 1.0),
+        importance: 0.0,
+    })
+}
+
+fn bbox(geometry: &Geometry) -> (f64, f64, f64, f64) {
+    let vertices: Box<dyn Iterator<Item = &Vertex>> = match geometry {
+        Geometry::Points(points) => Box::new(points.iter()),
+        Geometry::Lines(lines) => Box::new(lines.iter().flatten()),
+        Geometry::Polygons(polygons) => Box::new(polygons.iter().flatten().flatten()),
+    };
+
+    vertices.fold(
+        (
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ),
+        |(min_x, min_y, max_x, max_y), vertex| {
+            (
+                min_x.min(vertex.x),
+                min_y.min(vertex.y),
+                max_x.max(vertex.x),
+                max_y.max(vertex.y),
+            )
+        },
+    )
+}
+
+fn property_value(value: &Value) -> Option<tile::Value> {
+    let mut tile_value = tile::Value::default();
+    match value {
+        Value::Null => return None,
+        Value::Bool(value) => tile_value.bool_value = Some(*value),
+        Value::Number(number) => {
+            if let Some(value) = number.as_u64() {
+                tile_value.uint_value = Some(value);
+            } else if let Some(value) = number.as_i64() {
+                tile_value.sint_value = Some(value);
+            } else {
+                tile_value.double_value = number.as_f64();
+            }
+        }
+        Value::String(value) => tile_value.string_value = Some(value.clone()),
+        // Vector tiles do not support nested values, these are stored as JSON
+        value => tile_value.string_value = Some(value.to_string()),
+    }
+    Some(tile_value)
+}
+
+/// Calculates the importance of the vertices between the first and the last vertex with the
+/// Douglas-Peucker algorithm. Vertices whose importance is not larger than `sq_tolerance` are
+/// removed at every zoom level.
+fn simplify(vertices: &mut [Vertex], sq_tolerance: f64) {
+    if vertices.len() < 3 {
+        return;
+    }
+
+    let mut ranges = vec![(0, vertices.len() - 1)];
+    while let Some((first, last)) = ranges.pop() {
+        let (a, b) = (vertices[first], vertices[last]);
+        let mid = first + (last - first) / 2;
+
+        let mut max_sq_dist = sq_tolerance;
+        let mut min_pos_to_mid = last - first;
+        let mut index = None;
+
+        for (i, vertex) in vertices.iter().enumerate().take(last).skip(first + 1) {
+            let sq_dist = sq_segment_distance(vertex, &a, &b);
+            if sq_dist > max_sq_dist {
+                index = Some(i);
+                max_sq_dist = sq_dist;
+            } else if sq_dist == max_sq_dist && index.is_some() {
+                // Prefer vertices in the middle, such that the ranges are balanced
+                let pos_to_mid = if i > mid { i - mid } else { mid - i };
+                if pos_to_mid < min_pos_to_mid {
+                    index = Some(i);
+                    min_pos_to_mid = pos_to_mid;
+                }
+            }
+        }
+
+        if let Some(index) = index {
+            vertices[index].importance = max_sq_dist;
+            if index - first > 1 {
+                ranges.push((first, index));
+            }
+            if last - index > 1 {
+                ranges.push((index, last));
+            }
+        }
+    }
+}
+
+/// Squared distance between `p` and the segment from `a` to `b`.
+fn sq_segment_distance(p: &Vertex, a: &Vertex, b: &Vertex) -> f64 {
+    let (mut x, mut y) = (a.x, a.y);
+    let (dx, dy) = (b.x - x, b.y - y);
+
+    if dx != 0.0 || dy != 0.0 {
+        let t = ((p.x - x) * dx + (p.y - y) * dy) / (dx * dx + dy * dy);
+        if t > 1.0 {
+            x = b.x;
+            y = b.y;
+        } else if t > 0.0 {
+            x += dx * t;
+            y += dy * t;
+        }
+    }
+
+    let (dx, dy) = (p.x - x, p.y - y);
+    dx * dx + dy * dy
+}
+
+/// Returns the vertices which are kept at the zoom level with the squared tolerance `sq_tolerance`.
+fn simplified(line: &[Vertex], sq_tolerance: f64) -> Vec<[f64; 2]> {
+    line.iter()
+        .filter(|vertex| vertex.importance > sq_tolerance)
+        .map(|vertex| [vertex.x, vertex.y])
+        .collect()
+}
+
+/// Intersects the segment from `a` to `b` with the line at `k` on `axis`.
+fn intersect(a: &[f64; 2], b: &[f64; 2], k: f64, axis: usize) -> [f64; 2] {
+    let t = (k - a[axis]) / (b[axis] - a[axis]);
+    let mut point = [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t];
+    point[axis] = k;
+    point
+}
+
+/// Appends the part of the segment from `a` to `b` which lies between `k1` and `k2` on `axis`,
+/// excluding `b`. Returns whether the segment leaves the range.
+fn clip_segment(
+    a: &[f64; 2],
+    b: &[f64; 2],
+    k1: f64,
+    k2: f64,
+    axis: usize,
+    out: &mut Vec<[f64; 2]>,
+) -> bool {
+    let (ak, bk) = (a[axis], b[axis]);
+
+    if ak < k1 {
+        if bk >= k1 {
+            out.push(intersect(a, b, k1, axis));
+        }
+        if bk > k2 {
+            out.push(intersect(a, b, k2, axis));
+            return true;
+        }
+    } else if ak > k2 {
+        if bk <= k2 {
+            out.push(intersect(a, b, k2, axis));
+        }
+        if bk < k1 {
+            out.push(intersect(a, b, k1, axis));
+            return true;
+        }
+    } else {
+        out.push(*a);
+        if bk < k1 {
+            out.push(intersect(a, b, k1, axis));
+            return true;
+        } else if bk > k2 {
+            out.push(intersect(a, b, k2, axis));
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Clips a line to the range between `k1` and `k2` on `axis`. The line is split into multiple
+/// lines if it leaves the range.
+fn clip_line(line: &[[f64; 2]], k1: f64, k2: f64, axis: usize) -> Vec<Vec<[f64; 2]>> {
+    let mut lines = Vec::new();
+    let mut slice = Vec::new();
+
+    for segment in line.windows(2) {
+        if clip_segment(&segment[0], &segment[1], k1, k2, axis, &mut slice) {
+            lines.push(std::mem::take(&mut slice));
+        }
+    }
+
+    if let Some(last) = line.last() {
+        if last[axis] >= k1 && last[axis] <= k2 {
+            slice.push(*last);
+        }
+    }
+    lines.push(slice);
+
+    lines.retain(|line| line.len() >= 2);
+    lines
+}
+
+/// Clips a closed ring to the range between `k1` and `k2` on `axis`. The result is closed again.
+fn clip_ring(ring: &[[f64; 2]], k1: f64, k2: f64, axis: usize) -> Vec<[f64; 2]> {
+    let mut clipped = Vec::new();
+
+    for segment in ring.windows(2) {
+        clip_segment(&segment[0], &segment[1], k1, k2, axis, &mut clipped);
+    }
+
+    if let (Some(first), Some(last)) = (clipped.first().cloned(), clipped.last()) {
+        if first != *last {
+            clipped.push(first);
+        }
+    }
+    clipped
+}
+
+/// Removes consecutive duplicates, which occur once vertices are rounded to tile units.
+fn dedup(vertices: impl Iterator<Item = [i64; 2]>) -> Vec<[i64; 2]> {
+    let mut result: Vec<[i64; 2]> = Vec::new();
+    for vertex in vertices {
+        if result.last() != Some(&vertex) {
+            result.push(vertex);
+        }
+    }
+    result
+}
+
+/// Encodes geometries as the commands of the
+/// [vector tile specification](https://github.com/mapbox/vector-tile-spec/tree/master/2.1#43-geometry-encoding).
+#[derive(Default)]
+struct GeometryEncoder {
+    geometry: Vec<u32>,
+    cursor: [i64; 2],
+}
+
+impl GeometryEncoder {
+    fn command(&mut self, id: u32, count: usize) {
+        self.geometry.push((id & 0x7) | ((count as u32) << 3));
+    }
+
+    fn parameters(&mut self, vertex: &[i64; 2]) {
+        for (value, cursor) in vertex.iter().zip(self.cursor.iter_mut()) {
+            let delta = value - *cursor;
+            self.geometry.push(((delta << 1) ^ (delta >> 63)) as u32);
+            *cursor = *value;
+        }
+    }
+
+    fn points(&mut self, points: &[[i64; 2]]) {
+        if points.is_empty() {
+            return;
+        }
+
+        self.command(COMMAND_MOVE_TO, points.len());
+        for point in points {
+            self.parameters(point);
+        }
+    }
+
+    fn line(&mut self, line: &[[i64; 2]]) {
+        if line.len() < 2 {
+            return;
+        }
+
+        self.command(COMMAND_MOVE_TO, 1);
+        self.parameters(&line[0]);
+        self.command(COMMAND_LINE_TO, line.len() - 1);
+        for vertex in &line[1..] {
+            self.parameters(vertex);
+        }
+    }
+
+    /// Encodes a closed ring. Exterior rings are wound clockwise and holes counterclockwise in
+    /// tile coordinates, as required by the specification. Returns false if the ring is
+    /// degenerated and has been skipped.
+    fn ring(&mut self, mut ring: Vec<[i64; 2]>, exterior: bool) -> bool {
+        // The closing vertex is implied by the ClosePath command
+        if ring.len() > 1 && ring.first() == ring.last() {
+            ring.pop();
+        }
+        if ring.len() < 3 {
+            return false;
+        }
+
+        let area: i64 = (0..ring.len())
+            .map(|i| {
+                let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+                a[0] * b[1] - b[0] * a[1]
+            })
+            .sum();
+        if area == 0 {
+            return false;
+        }
+        if (area > 0) != exterior {
+            ring.reverse();
+        }
+
+        self.command(COMMAND_MOVE_TO, 1);
+        self.parameters(&ring[0]);
+        self.command(COMMAND_LINE_TO, ring.len() - 1);
+        for vertex in &ring[1..] {
+            self.parameters(vertex);
+        }
+        self.command(COMMAND_CLOSE_PATH, 1);
+        true
+    }
+}
+
+/// Collects features and deduplicates their keys and values.
+struct LayerBuilder {
+    layer: tile::Layer,
+    keys: HashMap<String, u32>,
+    /// Values keyed by their debug representation, because values are not hashable
+    values: HashMap<String, u32>,
+}
+
+impl LayerBuilder {
+    fn new(name: &str, extent: u32) -> Self {
+        Self {
+            layer: tile::Layer {
+                version: 2,
+                name: name.to_string(),
+                extent: Some(extent),
+                ..Default::default()
+            },
+            keys: HashMap::new(),
+            values: HashMap::new(),
+        }
+    }
+
+    fn add_feature(&mut self, feature: &Feature, geom_type: tile::GeomType, geometry: Vec<u32>) {
+        let mut tags = Vec::with_capacity(feature.properties.len() * 2);
+        for (key, value) in &feature.properties {
+            let layer = &mut self.layer;
+            let key_index = *self.keys.entry(key.clone()).or_insert_with(|| {
+                layer.keys.push(key.clone());
+                layer.keys.len() as u32 - 1
+            });
+            let value_index = *self
+                .values
+                .entry(format!("{:?}", value))
+                .or_insert_with(|| {
+                    layer.values.push(value.clone());
+                    layer.values.len() as u32 - 1
+                });
+            tags.push(key_index);
+            tags.push(value_index);
+        }
+
+        self.layer.features.push(tile::Feature {
+            id: feature.id,
+            tags,
+            r#type: Some(geom_type as i32),
+            geometry,
+        });
+    }
+
+    fn build(self) -> Option<tile::Layer> {
+        if self.layer.features.is_empty() {
+            None
+        } else {
+            Some(self.layer)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geozero::mvt::tile;
+    use serde_json::json;
+
+    use crate::io::geojson_tiler::{GeoJsonIndex, TilerOptions};
+
+    fn index() -> GeoJsonIndex {
+        GeoJsonIndex::new(
+            &json!({
+                "type": "FeatureCollection",
+                "features": [
+                    {
+                        "type": "Feature",
+                        "id": 7,
+                        "properties": {"name": "depot", "capacity": 12},
+                        "geometry": {"type": "Point", "coordinates": [45.0, 45.0]}
+                    },
+                    {
+                        "type": "Feature",
+                        "properties": {"name": "route"},
+                        "geometry": {
+                            "type": "LineString",
+                            "coordinates": [[-90.0, 10.0], [-45.0, 10.0], [90.0, 10.0]]
+                        }
+                    },
+                    {
+                        "type": "Feature",
+                        "properties": null,
+                        "geometry": {
+                            "type": "Polygon",
+                            "coordinates": [[[-10.0, -10.0], [10.0, -10.0], [10.0, 10.0], [-10.0, 10.0], [-10.0, -10.0]]]
+                        }
+                    },
+                    {"type": "Feature", "properties": {}, "geometry": null}
+                ]
+            }),
+            TilerOptions::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_tile_layer() {
+        let index = index();
+        assert_eq!(index.len(), 3);
+
+        let layer = index.tile_layer(&(0, 0, 0).into(), "layer").unwrap();
+        assert_eq!(layer.name, "layer");
+        assert_eq!(layer.features.len(), 3);
+
+        let point = &layer.features[0];
+        assert_eq!(point.id, Some(7));
+        assert_eq!(point.r#type, Some(tile::GeomType::Point as i32));
+        // MoveTo(1) at (2560, 1473)
+        assert_eq!(point.geometry, vec![9, 5120, 2946]);
+        assert_eq!(point.tags.len(), 4);
+        assert_eq!(layer.keys.len(), 2);
+
+        // The unimportant middle vertex of the line is removed
+        let line = &layer.features[1];
+        assert_eq!(line.geometry.len(), 6);
+
+        let polygon = &layer.features[2];
+        assert_eq!(polygon.r#type, Some(tile::GeomType::Polygon as i32));
+        assert_eq!(*polygon.geometry.last().unwrap(), 15);
+    }
+
+    #[test]
+    fn test_clipping() {
+        let index = index();
+
+        // Only the polygon intersects the south-eastern tile
+        let layer = index.tile_layer(&(1, 1, 1).into(), "layer").unwrap();
+        assert_eq!(layer.features.len(), 1);
+        assert_eq!(
+            layer.features[0].r#type,
+            Some(tile::GeomType::Polygon as i32)
+        );
+
+        // The line is clipped at the western edge of the buffer of the north-eastern tile
+        let layer = index.tile_layer(&(1, 0, 1).into(), "layer").unwrap();
+        assert_eq!(layer.features.len(), 3);
+        let line = &layer.features[1];
+        // MoveTo(1) at x = -128
+        assert_eq!(&line.geometry[0..2], &[9, 255]);
+
+        // No features in the far north-west
+        assert!(index.tile_layer(&(0, 0, 4).into(), "layer").is_none());
+        assert!(!index.intersects(&(0, 0, 4).into()));
+        assert!(index.intersects(&(1, 1, 1).into()));
+    }
+
+    #[test]
+    fn test_invalid_geojson() {
+        assert!(GeoJsonIndex::new(&json!({"type": "Point"}), TilerOptions::default()).is_err());
+        assert!(GeoJsonIndex::new(
+            &json!({"type": "Point", "coordinates": [1.0]}),
+            TilerOptions::default()
+        )
+        .is_err());
+    }
+}
@@ -10,10 +10,13 @@ use geozero::{ColumnValue, FeatureProcessor, GeomProcessor, PropertyProcessor};
 use rstar::{Envelope, PointDistance, RTree, RTreeObject, AABB};
 
 use crate::coords::{InnerCoords, Quadkey, WorldCoords, WorldTileCoords, Zoom, EXTENT, TILE_SIZE};
+use crate::io::feature_id::FeatureId;
 use crate::util::math::bounds_from_points;
 
+/// Geometries of the tiles, indexed by tile and source. Several sources can have a tile at the
+/// same coordinates.
 pub struct GeometryIndex {
-    index: BTreeMap<Quadkey, TileIndex>,
+    index: BTreeMap<Quadkey, HashMap<String, TileIndex>>,
 }
 
 impl GeometryIndex {
@@ -23,10 +26,26 @@ impl GeometryIndex {
         }
     }
 
-    pub fn index_tile(&mut self, coords: &WorldTileCoords, tile_index: TileIndex) {
-        coords
-            .build_quad_key()
-            .and_then(|key| self.index.insert(key, tile_index));
+    pub fn index_tile(&mut self, source_id: &str, coords: &WorldTileCoords, tile_index: TileIndex) {
+        if let Some(key) = coords.build_quad_key() {
+            self.index
+                .entry(key)
+                .or_default()
+                .insert(source_id.to_string(), tile_index);
+        }
+    }
+
+    /// Removes the geometries of the tile at `coords` of the source `source_id`. The geometries of
+    /// other sources at `coords` are kept.
+    pub fn remove_tile(&mut self, source_id: &str, coords: &WorldTileCoords) {
+        if let Some(key) = coords.build_quad_key() {
+            if let Some(sources) = self.index.get_mut(&key) {
+                sources.remove(source_id);
+                if sources.is_empty() {
+                    self.index.remove(&key);
+                }
+            }
+        }
     }
 
     pub fn query_point(
@@ -37,7 +56,7 @@ impl GeometryIndex {
     ) -> Option<Vec<&IndexedGeometry<f64>>> {
         let world_tile_coords = world_coords.into_world_tile(z, zoom);
 
-        if let Some(index) = world_tile_coords
+        if let Some(sources) = world_tile_coords
             .build_quad_key()
             .and_then(|key| self.index.get(&key))
         {
@@ -48,7 +67,12 @@ impl GeometryIndex {
 
             let x = delta_x * EXTENT;
             let y = delta_y * EXTENT;
-            Some(index.point_query(InnerCoords { x, y }))
+            Some(
+                sources
+                    .values()
+                    .flat_map(|index| index.point_query(InnerCoords { x, y }))
+                    .collect(),
+            )
         } else {
             None
         }
@@ -93,6 +117,8 @@ where
     pub bounds: AABB<Point<T>>,
     pub exact: ExactGeometry<T>,
     pub properties: HashMap<String, String>,
+    /// The id of the feature of the geometry, if the feature has one
+    pub id: Option<FeatureId>,
 }
 
 #[derive(Debug, Clone)]
@@ -108,18 +134,24 @@ impl<T> IndexedGeometry<T>
 where
     T: CoordFloat + Bounded + Signed + PartialOrd,
 {
-    fn from_polygon(polygon: Polygon<T>, properties: HashMap<String, String>) -> Option<Self> {
+    fn from_polygon(
+        polygon: Polygon<T>,
+        properties: HashMap<String, String>,
+        id: Option<FeatureId>,
+    ) -> Option<Self> {
         let (min, max) = bounds_from_points(polygon.exterior().points())?;
 
         Some(Self {
             exact: ExactGeometry::Polygon(polygon),
             bounds: AABB::from_corners(Point::from(min), Point::from(max)),
             properties,
+            id,
         })
     }
     fn from_linestring(
         linestring: LineString<T>,
         properties: HashMap<String, String>,
+        id: Option<FeatureId>,
     ) -> Option<Self> {
         let bounds = linestring.envelope();
 
@@ -127,6 +159,7 @@ where
             exact: ExactGeometry::LineString(linestring),
             bounds,
             properties,
+            id,
         })
     }
 }
@@ -162,6 +195,9 @@ pub struct IndexProcessor {
     geo_writer: GeoWriter,
     geometries: Vec<IndexedGeometry<f64>>,
     properties: Option<HashMap<String, String>>,
+    /// Ids of the features of the layer which is processed, indexed by feature
+    feature_ids: Vec<Option<FeatureId>>,
+    id: Option<FeatureId>,
 }
 
 impl IndexProcessor {
@@ -170,9 +206,17 @@ impl IndexProcessor {
             geo_writer: GeoWriter::new(),
             geometries: Vec::new(),
             properties: None,
+            feature_ids: Vec::new(),
+            id: None,
         }
     }
 
+    /// Sets the ids of the features of the next layer which is processed. The ids are attached
+    /// to the indexed geometries of the features.
+    pub fn begin_layer(&mut self, feature_ids: Vec<Option<FeatureId>>) {
+        self.feature_ids = feature_ids;
+    }
+
     pub fn build_tree(self) -> RTree<IndexedGeometry<f64>> {
         RTree::bulk_load(self.geometries)
     }
@@ -189,9 +233,15 @@ impl GeomProcessor for IndexProcessor {
     fn point_begin(&mut self, idx: usize) -> Result<(), GeozeroError> {
         self.geo_writer.point_begin(idx)
     }
+    fn point_end(&mut self, idx: usize) -> Result<(), GeozeroError> {
+        self.geo_writer.point_end(idx)
+    }
     fn multipoint_begin(&mut self, size: usize, idx: usize) -> Result<(), GeozeroError> {
         self.geo_writer.multipoint_begin(size, idx)
     }
+    fn multipoint_end(&mut self, idx: usize) -> Result<(), GeozeroError> {
+        self.geo_writer.multipoint_end(idx)
+    }
     fn linestring_begin(
         &mut self,
         tagged: bool,
@@ -218,6 +268,9 @@ impl GeomProcessor for IndexProcessor {
     fn multipolygon_begin(&mut self, size: usize, idx: usize) -> Result<(), GeozeroError> {
         self.geo_writer.multipolygon_begin(size, idx)
     }
+    fn multipolygon_end(&mut self, idx: usize) -> Result<(), GeozeroError> {
+        self.geo_writer.multipolygon_end(idx)
+    }
 }
 
 impl PropertyProcessor for IndexProcessor {
@@ -245,7 +298,10 @@ impl FeatureProcessor for IndexProcessor {
         Ok(())
     }
     /// Begin of feature processing
-    fn feature_begin(&mut self, _idx: u64) -> Result<(), GeozeroError> {
+    fn feature_begin(&mut self, idx: u64) -> Result<(), GeozeroError> {
+        self.id = self.feature_ids.get(idx as usize).cloned().flatten();
+        // The geometry of the previous feature must not be indexed again
+        self.geo_writer = GeoWriter::new();
         Ok(())
     }
     /// End of feature processing
@@ -267,19 +323,117 @@ impl FeatureProcessor for IndexProcessor {
     }
     /// End of feature geometry processing
     fn geometry_end(&mut self) -> Result<(), GeozeroError> {
-        let geometry = self.geo_writer.geometry().cloned().unwrap();
-
-        match geometry {
-            Geometry::Polygon(polygon) => self.geometries.push(
-                IndexedGeometry::from_polygon(polygon, self.properties.take().unwrap()).unwrap(),
-            ),
-            Geometry::LineString(linestring) => self.geometries.push(
-                IndexedGeometry::from_linestring(linestring, self.properties.take().unwrap())
-                    .unwrap(),
-            ),
-            _ => {}
+        let properties = self.properties.take().unwrap_or_default();
+
+        let indexed = match self.geo_writer.geometry().cloned() {
+            Some(Geometry::Polygon(polygon)) => {
+                IndexedGeometry::from_polygon(polygon, properties, self.id.clone())
+            }
+            Some(Geometry::LineString(linestring)) => {
+                IndexedGeometry::from_linestring(linestring, properties, self.id.clone())
+            }
+            _ => None,
         };
+        self.geometries.extend(indexed);
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use geozero::mvt::tile;
+    use geozero::GeozeroDatasource;
+
+    use crate::coords::{WorldCoords, WorldTileCoords, Zoom};
+    use crate::io::feature_id::{feature_ids, FeatureId};
+    use crate::style::source::PromoteId;
+
+    use super::{GeometryIndex, IndexProcessor, TileIndex};
+
+    /// Layer with a single square which covers the whole tile
+    fn layer() -> tile::Layer {
+        let number_value = tile::Value {
+            uint_value: Some(42),
+            ..Default::default()
+        };
+
+        tile::Layer {
+            version: 2,
+            name: "building".to_string(),
+            keys: vec!["osm_id".to_string()],
+            values: vec![number_value],
+            features: vec![tile::Feature {
+                id: Some(1),
+                tags: vec![0, 0],
+                r#type: Some(tile::GeomType::Polygon as i32),
+                geometry: vec![9, 0, 0, 26, 8192, 0, 0, 8192, 8191, 0, 15],
+            }],
+            extent: Some(4096),
+        }
+    }
+
+    const COORDS: WorldTileCoords = WorldTileCoords { x: 0, y: 0, z: 0 };
+
+    fn index_layer(index: &mut GeometryIndex, source_id: &str, promote_id: Option<&PromoteId>) {
+        let mut layer = layer();
+
+        let mut processor = IndexProcessor::new();
+        processor.begin_layer(feature_ids(&layer, promote_id));
+        layer.process(&mut processor).unwrap();
+
+        index.index_tile(
+            source_id,
+            &COORDS,
+            TileIndex::Linear {
+                list: processor.get_geometries(),
+            },
+        );
+    }
+
+    /// Ids of the geometries at the center of the tile
+    fn query_ids(index: &GeometryIndex) -> Option<Vec<Option<FeatureId>>> {
+        index
+            .query_point(&WorldCoords { x: 256.0, y: 256.0 }, 0, Zoom::new(0.0))
+            .map(|geometries| {
+                geometries
+                    .into_iter()
+                    .map(|geometry| geometry.id.clone())
+                    .collect()
+            })
+    }
+
+    #[test]
+    fn test_query_point_ids() {
+        let mut index = GeometryIndex::new();
+        index_layer(&mut index, "openmaptiles", None);
+        assert_eq!(query_ids(&index), Some(vec![Some(FeatureId::Number(1))]));
+
+        let mut index = GeometryIndex::new();
+        let promote_id = PromoteId::Property("osm_id".to_string());
+        index_layer(&mut index, "openmaptiles", Some(&promote_id));
+        assert_eq!(query_ids(&index), Some(vec![Some(FeatureId::Number(42))]));
+    }
+
+    #[test]
+    fn test_remove_tile_of_source() {
+        let mut index = GeometryIndex::new();
+        let promote_id = PromoteId::Property("osm_id".to_string());
+        index_layer(&mut index, "openmaptiles", None);
+        index_layer(&mut index, "geojson", Some(&promote_id));
+
+        let mut ids = query_ids(&index).unwrap();
+        ids.sort_by_key(|id| id.as_ref().map(FeatureId::numeric));
+        assert_eq!(
+            ids,
+            vec![Some(FeatureId::Number(1)), Some(FeatureId::Number(42))]
+        );
+
+        // The geometries of other sources at the same coordinates are kept
+        index.remove_tile("geojson", &COORDS);
+        assert_eq!(query_ids(&index), Some(vec![Some(FeatureId::Number(1))]));
+
+        index.remove_tile("openmaptiles", &COORDS);
+        assert_eq!(query_ids(&index), None);
+    }
+}
@@ -0,0 +1,185 @@
+//! Reads vector tiles from a local [MBTiles](https://github.com/mapbox/mbtiles-spec) file.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+
+use crate::coords::WorldTileCoords;
+use crate::error::Error;
+use crate::io::compression::decompress_sniffed;
+use crate::style::source::TileAddressingScheme;
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::IO(err.to_string())
+    }
+}
+
+/// Source client which serves tiles from the `tiles` table of a MBTiles SQLite database.
+///
+/// MBTiles stores rows according to the [TMS](https://wiki.osgeo.org/wiki/Tile_Map_Service_Specification)
+/// scheme, therefore the y coordinate is flipped before querying. Tile blobs are usually
+/// gzip-compressed, they are decompressed before they are returned.
+#[derive(Clone)]
+pub struct MbtilesSourceClient {
+    path: PathBuf,
+    // rusqlite connections are Send but not Sync. Access to the connection is serialized.
+    connection: Arc<Mutex<Connection>>,
+    metadata: Arc<HashMap<String, String>>,
+}
+
+impl MbtilesSourceClient {
+    /// Opens the MBTiles file at `path` in read-only mode and reads its `metadata` table.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        if !path.is_file() {
+            return Err(Error::IO(format!("MBTiles file {:?} does not exist", path)));
+        }
+
+        let connection = Connection::open_with_flags(
+            &path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+
+        let metadata = Self::read_metadata(&connection)?;
+
+        if let Some(format) = metadata.get("format") {
+            if format != "pbf" {
+                log::warn!(
+                    "MBTiles file {:?} contains tiles of format {}, expected pbf",
+                    path,
+                    format
+                );
+            }
+        }
+
+        Ok(Self {
+            path,
+            connection: Arc::new(Mutex::new(connection)),
+            metadata: Arc::new(metadata),
+        })
+    }
+
+    fn read_metadata(connection: &Connection) -> Result<HashMap<String, String>, Error> {
+        // language=SQL
+        let mut statement = connection.prepare("SELECT name, value FROM metadata;")?;
+        let metadata = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .filter_map(|result| result.ok())
+            .collect::<HashMap<String, String>>();
+        Ok(metadata)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Key-value pairs from the `metadata` table, e.g. `name`, `format`, `minzoom` or `json`.
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
+
+    pub fn minzoom(&self) -> Option<u8> {
+        self.metadata
+            .get("minzoom")
+            .and_then(|zoom| zoom.parse().ok())
+    }
+
+    pub fn maxzoom(&self) -> Option<u8> {
+        self.metadata
+            .get("maxzoom")
+            .and_then(|zoom| zoom.parse().ok())
+    }
+
+    pub async fn fetch(&self, coords: &WorldTileCoords) -> Result<Vec<u8>, Error> {
+        self.sync_fetch(coords)
+    }
+
+    pub fn sync_fetch(&self, coords: &WorldTileCoords) -> Result<Vec<u8>, Error> {
+        let tile_coords = coords
+            .into_tile(TileAddressingScheme::TMS)
+            .ok_or_else(|| Error::IO(format!("Tile {} is out of bounds", coords)))?;
+
+        let tile_data: Option<Vec<u8>> = {
+            let connection = self
+                .connection
+                .lock()
+                .map_err(|_e| Error::IO("MBTiles connection is poisoned".to_string()))?;
+            // language=SQL
+            let mut statement = connection.prepare_cached(
+                "SELECT tile_data FROM tiles
+                        WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3;",
+            )?;
+            statement
+                .query_row(
+                    params![tile_coords.z, tile_coords.x, tile_coords.y],
+                    |row| row.get(0),
+                )
+                .optional()?
+        };
+
+        let tile_data = tile_data.ok_or_else(|| {
+            Error::NotFound(format!("Tile {} not found in {:?}", coords, self.path))
+        })?;
+
+        decompress_sniffed(tile_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use rusqlite::{params, Connection};
+
+    use crate::coords::WorldTileCoords;
+
+    use super::MbtilesSourceClient;
+
+    #[tokio::test]
+    async fn test_fetch_flipped_and_compressed() {
+        let path =
+            std::env::temp_dir().join(format!("maplibre-test-{}.mbtiles", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let connection = Connection::open(&path).unwrap();
+        // language=SQL
+        connection
+            .execute_batch(
+                "CREATE TABLE metadata (name TEXT, value TEXT);
+                 CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB);
+                 INSERT INTO metadata VALUES ('format', 'pbf'), ('maxzoom', '14');",
+            )
+            .unwrap();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"tile").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        // WT(x=1,y=0,z=1) is stored in row 1 because MBTiles uses TMS
+        connection
+            .execute(
+                "INSERT INTO tiles VALUES (?1, ?2, ?3, ?4);",
+                params![1, 1, 1, compressed],
+            )
+            .unwrap();
+        drop(connection);
+
+        let client = MbtilesSourceClient::open(&path).unwrap();
+        assert_eq!(client.maxzoom(), Some(14));
+
+        let tile: WorldTileCoords = (1, 0, 1).into();
+        assert_eq!(client.fetch(&tile).await.unwrap(), b"tile".to_vec());
+
+        let missing: WorldTileCoords = (1, 1, 1).into();
+        assert!(client.fetch(&missing).await.is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
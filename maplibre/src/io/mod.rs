@@ -2,20 +2,41 @@
 
 use crate::coords::WorldTileCoords;
 
+use crate::io::feature_id::FeatureId;
+use crate::io::raster::{DemImage, RasterImage};
 use crate::render::ShaderVertex;
+use crate::style::layer::RASTER_SOURCE_LAYER;
 use crate::tessellation::{IndexDataType, OverAlignedVertexBuffer};
 
 use geozero::mvt::tile;
 use std::collections::HashSet;
 use std::fmt;
-
+use std::sync::Arc;
+
+pub mod compression;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod directory_source_client;
+pub mod feature_id;
+pub mod geojson_source_client;
+pub mod geojson_tiler;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod mbtiles_source_client;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod offline;
+pub mod persistent_cache;
+pub mod pmtiles_source_client;
+pub mod raster;
 pub mod scheduler;
 pub mod source_client;
+pub mod sprite;
 pub mod static_tile_fetcher;
+pub mod tile_url;
 
 pub mod geometry_index;
 pub mod shared_thread_state;
 pub mod tile_cache;
+pub mod tile_feature;
+pub mod tile_request_queue;
 pub mod tile_request_state;
 
 pub enum TileFetchResult {
@@ -52,17 +73,45 @@ pub struct TileTessellateMessage {
 }
 
 pub enum LayerTessellateMessage {
+    /// The source layer is not available. If `style_layer_id` is set, only the style layer with
+    /// this id failed, e.g. because its features could not be tessellated. Otherwise, the source
+    /// layer is unavailable for all style layers.
     UnavailableLayer {
+        source_id: String,
+        style_layer_id: Option<String>,
         coords: WorldTileCoords,
         layer_name: String,
     },
+    /// The features of a source layer which pass the filter of the style layer `style_layer_id`.
+    /// Each style layer which draws a source layer is tessellated separately.
     TessellatedLayer {
+        source_id: String,
+        style_layer_id: String,
         coords: WorldTileCoords,
         buffer: OverAlignedVertexBuffer<ShaderVertex, IndexDataType>,
-        /// Holds for each feature the count of indices
-        feature_indices: Vec<u32>,
+        /// Holds for each feature the count of vertices
+        feature_vertices: Vec<u32>,
+        /// Holds for each feature the count of vertices of its outline, which precede the
+        /// vertices of its fill. Only polygons of fill layers are outlined.
+        feature_outline_vertices: Vec<u32>,
+        /// Holds for each feature its id, in the same order as `feature_vertices`
+        feature_ids: Vec<Option<FeatureId>>,
         layer_data: tile::Layer,
     },
+    /// The decoded image of a raster tile. It is cached under the source layer
+    /// [`RASTER_SOURCE_LAYER`].
+    RasterLayer {
+        source_id: String,
+        coords: WorldTileCoords,
+        image: RasterImage,
+    },
+    /// The decoded elevations of a raster DEM tile. Like raster images, they are cached under the
+    /// source layer [`RASTER_SOURCE_LAYER`]. The elevations are shared with the terrain.
+    DemLayer {
+        source_id: String,
+        coords: WorldTileCoords,
+        dem: Arc<DemImage>,
+    },
 }
 
 impl fmt::Debug for LayerTessellateMessage {
@@ -76,6 +125,31 @@ impl LayerTessellateMessage {
         match self {
             LayerTessellateMessage::UnavailableLayer { coords, .. } => *coords,
             LayerTessellateMessage::TessellatedLayer { coords, .. } => *coords,
+            LayerTessellateMessage::RasterLayer { coords, .. } => *coords,
+            LayerTessellateMessage::DemLayer { coords, .. } => *coords,
+        }
+    }
+
+    pub fn source_id(&self) -> &str {
+        match self {
+            LayerTessellateMessage::UnavailableLayer { source_id, .. } => source_id.as_str(),
+            LayerTessellateMessage::TessellatedLayer { source_id, .. } => source_id.as_str(),
+            LayerTessellateMessage::RasterLayer { source_id, .. } => source_id.as_str(),
+            LayerTessellateMessage::DemLayer { source_id, .. } => source_id.as_str(),
+        }
+    }
+
+    /// The id of the style layer for which the features have been tessellated or failed to be
+    /// tessellated. Other messages are shared by all style layers which draw the source layer.
+    pub fn style_layer_id(&self) -> Option<&str> {
+        match self {
+            LayerTessellateMessage::TessellatedLayer { style_layer_id, .. } => {
+                Some(style_layer_id.as_str())
+            }
+            LayerTessellateMessage::UnavailableLayer { style_layer_id, .. } => {
+                style_layer_id.as_deref()
+            }
+            _ => None,
         }
     }
 
@@ -83,19 +157,80 @@ impl LayerTessellateMessage {
         match self {
             LayerTessellateMessage::UnavailableLayer { layer_name, .. } => layer_name.as_str(),
             LayerTessellateMessage::TessellatedLayer { layer_data, .. } => &layer_data.name,
+            LayerTessellateMessage::RasterLayer { .. }
+            | LayerTessellateMessage::DemLayer { .. } => RASTER_SOURCE_LAYER,
+        }
+    }
+
+    /// Approximate amount of memory in bytes which is occupied by this message, including the
+    /// tessellated buffer and the decoded layer data or image.
+    pub fn approximate_size(&self) -> usize {
+        let size = std::mem::size_of::<Self>()
+            + self.source_id().len()
+            + self.style_layer_id().map_or(0, str::len)
+            + self.layer_name().len();
+
+        match self {
+            LayerTessellateMessage::UnavailableLayer { .. } => size,
+            LayerTessellateMessage::RasterLayer { image, .. } => size + image.data.capacity(),
+            LayerTessellateMessage::DemLayer { dem, .. } => {
+                size + dem.elevations.capacity() * std::mem::size_of::<f32>()
+            }
+            LayerTessellateMessage::TessellatedLayer {
+                buffer,
+                feature_vertices,
+                feature_outline_vertices,
+                feature_ids,
+                layer_data,
+                ..
+            } => {
+                let features_size: usize = layer_data
+                    .features
+                    .iter()
+                    .map(|feature| {
+                        std::mem::size_of_val(feature)
+                            + (feature.tags.len() + feature.geometry.len())
+                                * std::mem::size_of::<u32>()
+                    })
+                    .sum();
+                let ids_size: usize = feature_ids
+                    .iter()
+                    .map(|id| match id {
+                        Some(FeatureId::String(id)) => id.len(),
+                        _ => 0,
+                    })
+                    .sum::<usize>()
+                    + feature_ids.capacity() * std::mem::size_of::<Option<FeatureId>>();
+                let keys_size: usize = layer_data.keys.iter().map(|key| key.len()).sum();
+                let values_size = layer_data.values.len() * std::mem::size_of::<tile::Value>();
+
+                size + buffer.approximate_size()
+                    + (feature_vertices.capacity() + feature_outline_vertices.capacity())
+                        * std::mem::size_of::<u32>()
+                    + ids_size
+                    + features_size
+                    + keys_size
+                    + values_size
+            }
         }
     }
 }
 
 #[derive(Clone)]
 pub struct TileRequest {
+    /// The id of the source within the style from which the tile is requested
+    pub source_id: String,
     pub coords: WorldTileCoords,
     pub layers: HashSet<String>,
 }
 
 impl fmt::Debug for TileRequest {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "TileRequest({}, {:?})", &self.coords, &self.layers)
+        write!(
+            f,
+            "TileRequest({}, {}, {:?})",
+            &self.source_id, &self.coords, &self.layers
+        )
     }
 }
 
@@ -0,0 +1,351 @@
+//! Downloads regions of a tileset for offline usage. The tiles are stored in an
+//! [MBTiles](https://github.com/mapbox/mbtiles-spec) file which can be used as a source afterwards.
+
+use std::borrow::Cow;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::StreamExt;
+use rusqlite::{params, Connection, OptionalExtension};
+use tile_grid::Extent;
+
+use crate::coords::{TileCoords, WorldTileCoords};
+use crate::error::Error;
+use crate::io::compression::ContentEncoding;
+use crate::io::source_client::{HTTPClient, SourceClient};
+use crate::style::source::TileAddressingScheme;
+use crate::util::grid::{google_mercator, tile_coordinates};
+
+/// Highest zoom level of the [`google_mercator`] grid.
+const MAX_ZOOM: u8 = 22;
+
+/// Default number of tiles which are downloaded concurrently.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// A region which is downloaded for offline usage.
+#[derive(Clone, Debug)]
+pub struct OfflineRegion {
+    /// The bounds of the region as `(west, south, east, north)` in degrees
+    pub bounds: (f64, f64, f64, f64),
+    pub minzoom: u8,
+    /// The highest zoom level which is downloaded. It is clamped to the zoom level 22.
+    pub maxzoom: u8,
+}
+
+impl OfflineRegion {
+    pub fn new(bounds: (f64, f64, f64, f64), minzoom: u8, maxzoom: u8) -> Self {
+        Self {
+            bounds,
+            minzoom,
+            maxzoom,
+        }
+    }
+
+    /// Enumerates the coordinates of all tiles within the region, starting with the lowest zoom
+    /// level.
+    pub fn tile_coords(&self) -> impl Iterator<Item = WorldTileCoords> {
+        let (west, south, east, north) = self.bounds;
+        let maxzoom = self.maxzoom.min(MAX_ZOOM);
+
+        tile_coordinates(
+            &google_mercator(),
+            &Extent {
+                minx: west,
+                miny: south,
+                maxx: east,
+                maxy: north,
+            },
+            self.minzoom.min(maxzoom),
+            maxzoom,
+        )
+        .filter_map(|(z, x, y)| TileCoords { x, y, z }.into_world_tile(TileAddressingScheme::XYZ))
+    }
+
+    /// Number of tiles within the region.
+    pub fn tile_count(&self) -> usize {
+        self.tile_coords().count()
+    }
+}
+
+/// Progress of a download which is reported after each tile.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OfflineProgress {
+    /// Number of tiles within the region
+    pub total: usize,
+    /// Number of tiles which have been stored, including the tiles which were stored by a
+    /// previous download
+    pub completed: usize,
+    /// Number of tiles which do not exist in the source
+    pub missing: usize,
+    /// Number of tiles which failed to download. Downloading the region again retries these.
+    pub failed: usize,
+}
+
+impl OfflineProgress {
+    /// Returns whether each tile of the region has been processed.
+    pub fn is_finished(&self) -> bool {
+        self.completed + self.missing + self.failed >= self.total
+    }
+}
+
+/// Persistent storage for downloaded tiles.
+pub trait OfflineTileStore {
+    fn contains(&self, coords: &WorldTileCoords) -> Result<bool, Error>;
+
+    fn insert(&self, coords: &WorldTileCoords, data: &[u8]) -> Result<(), Error>;
+}
+
+/// Stores tiles in a MBTiles file which can be read by
+/// [`MbtilesSourceClient`](crate::io::mbtiles_source_client::MbtilesSourceClient).
+pub struct MbtilesTileStore {
+    path: PathBuf,
+    connection: Mutex<Connection>,
+}
+
+impl MbtilesTileStore {
+    /// Opens the MBTiles file at `path`. The file and its tables are created if they do not exist
+    /// yet. Existing tiles are kept, such that interrupted downloads can be resumed.
+    pub fn create<P: AsRef<Path>>(path: P, region: &OfflineRegion) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let connection = Connection::open(&path)?;
+
+        // language=SQL
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS metadata (name TEXT PRIMARY KEY, value TEXT);
+             CREATE TABLE IF NOT EXISTS tiles (
+                 zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB,
+                 PRIMARY KEY (zoom_level, tile_column, tile_row)
+             );",
+        )?;
+
+        let (west, south, east, north) = region.bounds;
+        let metadata = [
+            ("name", "offline".to_string()),
+            ("format", "pbf".to_string()),
+            ("minzoom", region.minzoom.to_string()),
+            ("maxzoom", region.maxzoom.min(MAX_ZOOM).to_string()),
+            ("bounds", format!("{},{},{},{}", west, south, east, north)),
+        ];
+        for (name, value) in metadata {
+            // language=SQL
+            connection.execute(
+                "INSERT OR REPLACE INTO metadata (name, value) VALUES (?1, ?2);",
+                params![name, value],
+            )?;
+        }
+
+        Ok(Self {
+            path,
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<Connection>, Error> {
+        self.connection
+            .lock()
+            .map_err(|_e| Error::IO("MBTiles connection is poisoned".to_string()))
+    }
+}
+
+/// MBTiles stores rows according to the TMS scheme.
+fn tms_coords(coords: &WorldTileCoords) -> Result<TileCoords, Error> {
+    coords
+        .into_tile(TileAddressingScheme::TMS)
+        .ok_or_else(|| Error::IO(format!("Tile {} is out of bounds", coords)))
+}
+
+impl OfflineTileStore for MbtilesTileStore {
+    fn contains(&self, coords: &WorldTileCoords) -> Result<bool, Error> {
+        let tile_coords = tms_coords(coords)?;
+        let connection = self.lock()?;
+        // language=SQL
+        let mut statement = connection.prepare_cached(
+            "SELECT 1 FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3;",
+        )?;
+        let exists = statement
+            .query_row(
+                params![tile_coords.z, tile_coords.x, tile_coords.y],
+                |_row| Ok(()),
+            )
+            .optional()?;
+        Ok(exists.is_some())
+    }
+
+    fn insert(&self, coords: &WorldTileCoords, data: &[u8]) -> Result<(), Error> {
+        let tile_coords = tms_coords(coords)?;
+
+        // Tiles in MBTiles files are usually gzip-compressed. Tiles which are served compressed
+        // already are stored unchanged.
+        let compressed: Cow<[u8]> = match ContentEncoding::sniff(data) {
+            ContentEncoding::Gzip => Cow::Borrowed(data),
+            _ => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder
+                    .write_all(data)
+                    .map_err(|e| Error::IO(e.to_string()))?;
+                Cow::Owned(encoder.finish().map_err(|e| Error::IO(e.to_string()))?)
+            }
+        };
+
+        let connection = self.lock()?;
+        // language=SQL
+        let mut statement = connection.prepare_cached(
+            "INSERT OR REPLACE INTO tiles (zoom_level, tile_column, tile_row, tile_data)
+                    VALUES (?1, ?2, ?3, ?4);",
+        )?;
+        statement.execute(params![
+            tile_coords.z,
+            tile_coords.x,
+            tile_coords.y,
+            compressed.as_ref()
+        ])?;
+        Ok(())
+    }
+}
+
+/// Downloads all tiles of an [`OfflineRegion`] from a [`SourceClient`] into an
+/// [`OfflineTileStore`].
+///
+/// At most `concurrency` tiles are downloaded at the same time. Tiles which are already in the
+/// store are skipped, therefore an interrupted or partially failed download can be resumed by
+/// downloading the region again.
+pub struct OfflineRegionDownloader<HC>
+where
+    HC: HTTPClient,
+{
+    source_client: SourceClient<HC>,
+    concurrency: usize,
+}
+
+impl<HC> OfflineRegionDownloader<HC>
+where
+    HC: HTTPClient,
+{
+    pub fn new(source_client: SourceClient<HC>) -> Self {
+        Self {
+            source_client,
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Downloads `region` into `store`. `on_progress` is called after each processed tile.
+    /// Errors of single tiles are counted as failed, errors of the store abort the download.
+    pub async fn download<S, F>(
+        &self,
+        region: &OfflineRegion,
+        store: &S,
+        mut on_progress: F,
+    ) -> Result<OfflineProgress, Error>
+    where
+        S: OfflineTileStore,
+        F: FnMut(&OfflineProgress),
+    {
+        let mut progress = OfflineProgress {
+            total: region.tile_count(),
+            ..OfflineProgress::default()
+        };
+
+        let mut missing_coords = Vec::new();
+        for coords in region.tile_coords() {
+            if store.contains(&coords)? {
+                progress.completed += 1;
+            } else {
+                missing_coords.push(coords);
+            }
+        }
+        on_progress(&progress);
+
+        let source_client = &self.source_client;
+        let mut downloads = futures::stream::iter(missing_coords)
+            .map(|coords| async move { (coords, source_client.fetch(&coords).await) })
+            .buffer_unordered(self.concurrency);
+
+        while let Some((coords, result)) = downloads.next().await {
+            match result {
+                Ok(data) => {
+                    store.insert(&coords, &data)?;
+                    progress.completed += 1;
+                }
+                Err(Error::NotFound(_)) => progress.missing += 1,
+                Err(e) => {
+                    log::warn!("offline download of tile {} failed: {:?}", coords, e);
+                    progress.failed += 1;
+                }
+            }
+            on_progress(&progress);
+        }
+
+        Ok(progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use crate::coords::WorldTileCoords;
+    use crate::io::mbtiles_source_client::MbtilesSourceClient;
+    use crate::io::offline::{MbtilesTileStore, OfflineRegion, OfflineTileStore};
+
+    #[test]
+    fn test_region_tile_coords() {
+        let region = OfflineRegion::new((-179.9, -85.0, 179.9, 85.0), 0, 2);
+        assert_eq!(region.tile_count(), 1 + 4 + 16);
+
+        // Approximately the extent of Germany
+        let region = OfflineRegion::new((5.8, 47.2, 15.1, 55.1), 5, 5);
+        let coords: Vec<WorldTileCoords> = region.tile_coords().collect();
+        assert_eq!(coords.len(), 4);
+        assert!(coords.contains(&(16, 10, 5).into()));
+        assert!(coords.contains(&(17, 11, 5).into()));
+    }
+
+    #[tokio::test]
+    async fn test_store_is_readable_by_source_client() {
+        let path =
+            std::env::temp_dir().join(format!("maplibre-offline-{}.mbtiles", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let region = OfflineRegion::new((5.8, 47.2, 15.1, 55.1), 0, 4);
+        let coords: WorldTileCoords = (8, 5, 4).into();
+
+        let store = MbtilesTileStore::create(&path, &region).unwrap();
+        assert!(!store.contains(&coords).unwrap());
+        store.insert(&coords, b"tile").unwrap();
+        assert!(store.contains(&coords).unwrap());
+
+        // Compressed tiles are not compressed a second time
+        let compressed_coords: WorldTileCoords = (8, 6, 4).into();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"compressed tile").unwrap();
+        store
+            .insert(&compressed_coords, &encoder.finish().unwrap())
+            .unwrap();
+        drop(store);
+
+        let client = MbtilesSourceClient::open(&path).unwrap();
+        assert_eq!(client.maxzoom(), Some(4));
+        assert_eq!(client.fetch(&coords).await.unwrap(), b"tile".to_vec());
+        assert_eq!(
+            client.fetch(&compressed_coords).await.unwrap(),
+            b"compressed tile".to_vec()
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
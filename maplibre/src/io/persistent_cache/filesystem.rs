@@ -0,0 +1,161 @@
+//! Cache backend which stores each tile as a file within a directory.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::io::persistent_cache::{CacheBackend, CacheKey, CacheMetadata, CachedTile};
+
+const DATA_EXTENSION: &str = "tile";
+const METADATA_EXTENSION: &str = "json";
+
+/// Content of the metadata file which is stored next to each tile.
+#[derive(Serialize, Deserialize)]
+struct MetadataFile {
+    key: CacheKey,
+    metadata: CacheMetadata,
+}
+
+/// Stores tiles at `{root}/{source_id}/{z}/{x}/{y}.tile`. The metadata of each tile is stored
+/// as JSON in `{y}.json` next to it. A tile is only considered cached if its metadata file exists.
+pub struct FilesystemCacheBackend {
+    root: PathBuf,
+}
+
+impl FilesystemCacheBackend {
+    /// Creates the backend. The directory at `root` is created if it does not exist yet.
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<Self, Error> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root).map_err(|e| Error::IO(e.to_string()))?;
+        Ok(Self { root })
+    }
+
+    fn tile_path(&self, key: &CacheKey, extension: &str) -> PathBuf {
+        self.root
+            .join(escape_source_id(&key.source_id))
+            .join(key.coords.z.to_string())
+            .join(key.coords.x.to_string())
+            .join(format!("{}.{}", key.coords.y, extension))
+    }
+
+    fn write_metadata(&self, key: &CacheKey, metadata: &CacheMetadata) -> Result<(), Error> {
+        let content = serde_json::to_vec(&MetadataFile {
+            key: key.clone(),
+            metadata: metadata.clone(),
+        })
+        .map_err(|e| Error::IO(e.to_string()))?;
+        write_atomically(&self.tile_path(key, METADATA_EXTENSION), &content)
+    }
+
+    fn read_metadata(path: &Path) -> Result<Option<MetadataFile>, Error> {
+        match fs::read(path) {
+            Ok(content) => Ok(serde_json::from_slice(&content).ok()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Error::IO(e.to_string())),
+        }
+    }
+
+    fn collect_entries(
+        directory: &Path,
+        entries: &mut Vec<(CacheKey, CacheMetadata)>,
+    ) -> Result<(), Error> {
+        for entry in fs::read_dir(directory).map_err(|e| Error::IO(e.to_string()))? {
+            let path = entry.map_err(|e| Error::IO(e.to_string()))?.path();
+            if path.is_dir() {
+                Self::collect_entries(&path, entries)?;
+            } else if path
+                .extension()
+                .map_or(false, |ext| ext == METADATA_EXTENSION)
+            {
+                if let Some(file) = Self::read_metadata(&path)? {
+                    entries.push((file.key, file.metadata));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Source ids are arbitrary strings. Characters which might not be valid within file names are
+/// percent-encoded.
+fn escape_source_id(source_id: &str) -> String {
+    let mut escaped = String::with_capacity(source_id.len());
+    for byte in source_id.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    escaped
+}
+
+/// Writes to a temporary file first, such that readers never see partially written files.
+fn write_atomically(path: &Path, content: &[u8]) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| Error::IO(e.to_string()))?;
+    }
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    fs::write(&temporary, content).map_err(|e| Error::IO(e.to_string()))?;
+    fs::rename(&temporary, path).map_err(|e| Error::IO(e.to_string()))
+}
+
+fn remove_file(path: &Path) -> Result<(), Error> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(Error::IO(e.to_string())),
+    }
+}
+
+#[cfg_attr(feature = "no-thread-safe-futures", async_trait(?Send))]
+#[cfg_attr(not(feature = "no-thread-safe-futures"), async_trait)]
+impl CacheBackend for FilesystemCacheBackend {
+    async fn get(&self, key: &CacheKey) -> Result<Option<CachedTile>, Error> {
+        let file = match Self::read_metadata(&self.tile_path(key, METADATA_EXTENSION))? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+
+        match fs::read(self.tile_path(key, DATA_EXTENSION)) {
+            Ok(data) => Ok(Some(CachedTile {
+                data,
+                metadata: file.metadata,
+            })),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Error::IO(e.to_string())),
+        }
+    }
+
+    async fn put(&self, key: &CacheKey, tile: &CachedTile) -> Result<(), Error> {
+        // The metadata is removed first, such that the entry is not visible while the data is
+        // replaced
+        remove_file(&self.tile_path(key, METADATA_EXTENSION))?;
+        write_atomically(&self.tile_path(key, DATA_EXTENSION), &tile.data)?;
+        self.write_metadata(key, &tile.metadata)
+    }
+
+    async fn update_metadata(&self, key: &CacheKey, metadata: &CacheMetadata) -> Result<(), Error> {
+        if self.tile_path(key, DATA_EXTENSION).is_file() {
+            self.write_metadata(key, metadata)?;
+        }
+        Ok(())
+    }
+
+    async fn remove(&self, key: &CacheKey) -> Result<(), Error> {
+        remove_file(&self.tile_path(key, METADATA_EXTENSION))?;
+        remove_file(&self.tile_path(key, DATA_EXTENSION))
+    }
+
+    async fn entries(&self) -> Result<Vec<(CacheKey, CacheMetadata)>, Error> {
+        let mut entries = Vec::new();
+        Self::collect_entries(&self.root, &mut entries)?;
+        Ok(entries)
+    }
+}
@@ -0,0 +1,508 @@
+//! Persistent cache for tiles which are fetched via HTTP. Cached tiles survive restarts of the
+//! application.
+//!
+//! The cache is keyed by the id of the source and the [`TileCoords`] of the tile. The storage is
+//! provided by a [`CacheBackend`]. The [`PersistentTileCache`] keeps an index of all entries in
+//! memory, evicts the least recently used tiles once the size limit is exceeded and decides
+//! whether cached tiles are still fresh according to the `Cache-Control` header. Stale tiles are
+//! revalidated via their `ETag`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::coords::TileCoords;
+use crate::error::Error;
+use crate::io::source_client::CacheHeaders;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod filesystem;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod sqlite;
+
+/// Persisting the access time on each read would turn each cache hit into a write. Access times
+/// are therefore persisted only if they are older than this amount of seconds.
+const LAST_ACCESS_RESOLUTION: u64 = 60;
+
+/// Key of a cached tile.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CacheKey {
+    pub source_id: String,
+    /// Coordinates of the tile according to the [XYZ](crate::style::source::TileAddressingScheme::XYZ)
+    /// scheme
+    pub coords: TileCoords,
+}
+
+impl CacheKey {
+    pub fn new(source_id: &str, coords: TileCoords) -> Self {
+        Self {
+            source_id: source_id.to_string(),
+            coords,
+        }
+    }
+}
+
+/// Metadata which is stored along with each cached tile. Times are seconds since the unix epoch.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheMetadata {
+    pub etag: Option<String>,
+    /// The tile needs to be revalidated after this time
+    pub expires_at: u64,
+    /// Size of the tile data in bytes
+    pub size: u64,
+    pub last_access: u64,
+}
+
+impl CacheMetadata {
+    pub fn is_fresh(&self, now: u64) -> bool {
+        now < self.expires_at
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CachedTile {
+    pub data: Vec<u8>,
+    pub metadata: CacheMetadata,
+}
+
+/// Storage of a [`PersistentTileCache`].
+///
+/// Backends only store and load entries. Size limits, eviction and expiry are handled by the
+/// [`PersistentTileCache`].
+#[cfg_attr(feature = "no-thread-safe-futures", async_trait(?Send))]
+#[cfg_attr(not(feature = "no-thread-safe-futures"), async_trait)]
+pub trait CacheBackend: Send + Sync {
+    async fn get(&self, key: &CacheKey) -> Result<Option<CachedTile>, Error>;
+
+    /// Stores the tile at `key`. An existing entry is replaced.
+    async fn put(&self, key: &CacheKey, tile: &CachedTile) -> Result<(), Error>;
+
+    /// Replaces the metadata of an existing entry without touching its data.
+    async fn update_metadata(&self, key: &CacheKey, metadata: &CacheMetadata) -> Result<(), Error>;
+
+    async fn remove(&self, key: &CacheKey) -> Result<(), Error>;
+
+    /// Lists the metadata of all entries. This is used to restore the index of the cache.
+    async fn entries(&self) -> Result<Vec<(CacheKey, CacheMetadata)>, Error>;
+}
+
+/// Backend which keeps the entries in memory. Entries do not survive restarts, therefore this is
+/// mainly useful for testing.
+#[derive(Default)]
+pub struct MemoryCacheBackend {
+    entries: Mutex<HashMap<CacheKey, CachedTile>>,
+}
+
+impl MemoryCacheBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<HashMap<CacheKey, CachedTile>>, Error> {
+        self.entries
+            .lock()
+            .map_err(|_e| Error::IO("memory cache is poisoned".to_string()))
+    }
+}
+
+#[cfg_attr(feature = "no-thread-safe-futures", async_trait(?Send))]
+#[cfg_attr(not(feature = "no-thread-safe-futures"), async_trait)]
+impl CacheBackend for MemoryCacheBackend {
+    async fn get(&self, key: &CacheKey) -> Result<Option<CachedTile>, Error> {
+        Ok(self.lock()?.get(key).cloned())
+    }
+
+    async fn put(&self, key: &CacheKey, tile: &CachedTile) -> Result<(), Error> {
+        self.lock()?.insert(key.clone(), tile.clone());
+        Ok(())
+    }
+
+    async fn update_metadata(&self, key: &CacheKey, metadata: &CacheMetadata) -> Result<(), Error> {
+        if let Some(tile) = self.lock()?.get_mut(key) {
+            tile.metadata = metadata.clone();
+        }
+        Ok(())
+    }
+
+    async fn remove(&self, key: &CacheKey) -> Result<(), Error> {
+        self.lock()?.remove(key);
+        Ok(())
+    }
+
+    async fn entries(&self) -> Result<Vec<(CacheKey, CacheMetadata)>, Error> {
+        Ok(self
+            .lock()?
+            .iter()
+            .map(|(key, tile)| (key.clone(), tile.metadata.clone()))
+            .collect())
+    }
+}
+
+/// Limits of a [`PersistentTileCache`].
+#[derive(Clone, Debug)]
+pub struct CachePolicy {
+    /// The least recently used tiles are evicted once the cached tiles exceed this size
+    pub max_size_bytes: u64,
+    /// Lifetime in seconds of tiles whose response did not specify a `max-age`
+    pub default_max_age: u64,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self {
+            max_size_bytes: 50 * 1024 * 1024,
+            default_max_age: 24 * 60 * 60,
+        }
+    }
+}
+
+/// How long a response may be cached according to its `Cache-Control` header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Freshness {
+    /// The response must not be stored (`no-store`)
+    NoStore,
+    /// The response is fresh for this amount of seconds. `no-cache` results in zero seconds, such
+    /// that the response is revalidated each time.
+    MaxAge(u64),
+}
+
+impl Freshness {
+    pub fn from_cache_control(cache_control: Option<&str>, default_max_age: u64) -> Self {
+        let mut max_age = None;
+
+        for directive in cache_control.unwrap_or_default().split(',') {
+            let directive = directive.trim().to_ascii_lowercase();
+            if directive == "no-store" {
+                return Freshness::NoStore;
+            } else if directive == "no-cache" {
+                return Freshness::MaxAge(0);
+            } else if let Some(value) = directive.strip_prefix("max-age=") {
+                max_age = value.trim_matches('"').parse::<u64>().ok().or(max_age);
+            }
+        }
+
+        Freshness::MaxAge(max_age.unwrap_or(default_max_age))
+    }
+}
+
+/// Seconds since the unix epoch.
+pub fn unix_time() -> u64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        (js_sys::Date::now() / 1000.0) as u64
+    }
+}
+
+struct IndexEntry {
+    size: u64,
+    sequence: u64,
+}
+
+/// In-memory index of the cached tiles in least recently used order.
+#[derive(Default)]
+struct LruIndex {
+    entries: HashMap<CacheKey, IndexEntry>,
+    /// Keys ordered by their last access
+    order: BTreeMap<u64, CacheKey>,
+    next_sequence: u64,
+    total_size: u64,
+}
+
+impl LruIndex {
+    /// Inserts or refreshes `key`, such that it becomes the most recently used entry.
+    fn touch(&mut self, key: &CacheKey, size: u64) {
+        self.remove(key);
+
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.order.insert(sequence, key.clone());
+        self.entries
+            .insert(key.clone(), IndexEntry { size, sequence });
+        self.total_size += size;
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.sequence);
+            self.total_size -= entry.size;
+        }
+    }
+
+    /// Removes the least recently used entries until `total_size` fits into `max_size`.
+    fn evict(&mut self, max_size: u64) -> Vec<CacheKey> {
+        let mut evicted = Vec::new();
+        while self.total_size > max_size {
+            let key = match self.order.values().next() {
+                Some(key) => key.clone(),
+                None => break,
+            };
+            self.remove(&key);
+            evicted.push(key);
+        }
+        evicted
+    }
+}
+
+/// Cache of tiles on top of a [`CacheBackend`]. Clones share the same index and backend.
+#[derive(Clone)]
+pub struct PersistentTileCache {
+    backend: Arc<dyn CacheBackend>,
+    index: Arc<Mutex<LruIndex>>,
+    policy: CachePolicy,
+}
+
+impl PersistentTileCache {
+    /// Opens the cache and restores its index from the entries of the `backend`.
+    pub async fn open(backend: Arc<dyn CacheBackend>, policy: CachePolicy) -> Result<Self, Error> {
+        let mut entries = backend.entries().await?;
+        entries.sort_by_key(|(_, metadata)| metadata.last_access);
+
+        let mut index = LruIndex::default();
+        for (key, metadata) in &entries {
+            index.touch(key, metadata.size);
+        }
+
+        let cache = Self {
+            backend,
+            index: Arc::new(Mutex::new(index)),
+            policy,
+        };
+        // The policy might have changed since the last start
+        cache.evict().await?;
+        Ok(cache)
+    }
+
+    pub fn policy(&self) -> &CachePolicy {
+        &self.policy
+    }
+
+    /// Total size in bytes of the cached tiles
+    pub fn size(&self) -> u64 {
+        self.lock_index().total_size
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock_index().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock_index(&self) -> std::sync::MutexGuard<LruIndex> {
+        // The index is never left in an inconsistent state, therefore a poisoned lock is recovered
+        self.index
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Returns the cached tile at `key`, regardless of whether it is still fresh.
+    pub async fn get(&self, key: &CacheKey) -> Result<Option<CachedTile>, Error> {
+        let tile = self.backend.get(key).await?;
+
+        match tile {
+            Some(mut tile) => {
+                self.lock_index().touch(key, tile.metadata.size);
+
+                let now = unix_time();
+                if now.saturating_sub(tile.metadata.last_access) > LAST_ACCESS_RESOLUTION {
+                    tile.metadata.last_access = now;
+                    self.backend.update_metadata(key, &tile.metadata).await?;
+                }
+                Ok(Some(tile))
+            }
+            None => {
+                self.lock_index().remove(key);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Stores `data` according to the `headers` of the response. Responses which must not be
+    /// stored or which exceed the size limit are ignored.
+    pub async fn put(
+        &self,
+        key: &CacheKey,
+        data: Vec<u8>,
+        headers: &CacheHeaders,
+    ) -> Result<(), Error> {
+        let max_age = match Freshness::from_cache_control(
+            headers.cache_control.as_deref(),
+            self.policy.default_max_age,
+        ) {
+            Freshness::NoStore => return Ok(()),
+            Freshness::MaxAge(max_age) => max_age,
+        };
+
+        let size = data.len() as u64;
+        if size > self.policy.max_size_bytes {
+            return Ok(());
+        }
+
+        let now = unix_time();
+        let tile = CachedTile {
+            data,
+            metadata: CacheMetadata {
+                etag: headers.etag.clone(),
+                expires_at: now.saturating_add(max_age),
+                size,
+                last_access: now,
+            },
+        };
+
+        self.backend.put(key, &tile).await?;
+        self.lock_index().touch(key, size);
+        self.evict().await
+    }
+
+    /// Extends the lifetime of the tile at `key` after the server confirmed that it did not change.
+    pub async fn revalidate(
+        &self,
+        key: &CacheKey,
+        mut metadata: CacheMetadata,
+        headers: &CacheHeaders,
+    ) -> Result<(), Error> {
+        let now = unix_time();
+        match Freshness::from_cache_control(
+            headers.cache_control.as_deref(),
+            self.policy.default_max_age,
+        ) {
+            Freshness::NoStore => return self.remove(key).await,
+            Freshness::MaxAge(max_age) => metadata.expires_at = now.saturating_add(max_age),
+        }
+        if headers.etag.is_some() {
+            metadata.etag = headers.etag.clone();
+        }
+        metadata.last_access = now;
+
+        self.backend.update_metadata(key, &metadata).await?;
+        self.lock_index().touch(key, metadata.size);
+        Ok(())
+    }
+
+    pub async fn remove(&self, key: &CacheKey) -> Result<(), Error> {
+        self.lock_index().remove(key);
+        self.backend.remove(key).await
+    }
+
+    async fn evict(&self) -> Result<(), Error> {
+        let evicted = self.lock_index().evict(self.policy.max_size_bytes);
+        for key in &evicted {
+            self.backend.remove(key).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::coords::TileCoords;
+    use crate::io::persistent_cache::{
+        CacheKey, CachePolicy, Freshness, MemoryCacheBackend, PersistentTileCache,
+    };
+    use crate::io::source_client::CacheHeaders;
+
+    fn key(x: u32) -> CacheKey {
+        CacheKey::new("source", TileCoords { x, y: 0, z: 5 })
+    }
+
+    #[test]
+    fn test_freshness() {
+        assert_eq!(
+            Freshness::from_cache_control(None, 10),
+            Freshness::MaxAge(10)
+        );
+        assert_eq!(
+            Freshness::from_cache_control(Some("public, max-age=3600"), 10),
+            Freshness::MaxAge(3600)
+        );
+        assert_eq!(
+            Freshness::from_cache_control(Some("no-cache"), 10),
+            Freshness::MaxAge(0)
+        );
+        assert_eq!(
+            Freshness::from_cache_control(Some("max-age=60, no-store"), 10),
+            Freshness::NoStore
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lru_eviction() {
+        let backend = Arc::new(MemoryCacheBackend::new());
+        let cache = PersistentTileCache::open(
+            backend.clone(),
+            CachePolicy {
+                max_size_bytes: 20,
+                default_max_age: 60,
+            },
+        )
+        .await
+        .unwrap();
+        let headers = CacheHeaders::default();
+
+        cache.put(&key(0), vec![0; 10], &headers).await.unwrap();
+        cache.put(&key(1), vec![1; 10], &headers).await.unwrap();
+        // Accessing the first tile makes the second one the least recently used
+        assert!(cache.get(&key(0)).await.unwrap().is_some());
+        cache.put(&key(2), vec![2; 10], &headers).await.unwrap();
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.size(), 20);
+        assert!(cache.get(&key(1)).await.unwrap().is_none());
+        assert!(cache.get(&key(0)).await.unwrap().is_some());
+
+        // The index is restored from the backend
+        let reopened = PersistentTileCache::open(backend, cache.policy().clone())
+            .await
+            .unwrap();
+        assert_eq!(reopened.len(), 2);
+        assert_eq!(reopened.size(), 20);
+    }
+
+    #[tokio::test]
+    async fn test_expiry_and_revalidation() {
+        let cache =
+            PersistentTileCache::open(Arc::new(MemoryCacheBackend::new()), CachePolicy::default())
+                .await
+                .unwrap();
+
+        let headers = CacheHeaders {
+            cache_control: Some("no-cache".to_string()),
+            etag: Some("\"v1\"".to_string()),
+        };
+        cache.put(&key(0), vec![0; 10], &headers).await.unwrap();
+        let tile = cache.get(&key(0)).await.unwrap().unwrap();
+        assert!(!tile.metadata.is_fresh(super::unix_time()));
+        assert_eq!(tile.metadata.etag.as_deref(), Some("\"v1\""));
+
+        let headers = CacheHeaders {
+            cache_control: Some("max-age=3600".to_string()),
+            etag: None,
+        };
+        cache
+            .revalidate(&key(0), tile.metadata, &headers)
+            .await
+            .unwrap();
+        let tile = cache.get(&key(0)).await.unwrap().unwrap();
+        assert!(tile.metadata.is_fresh(super::unix_time()));
+        assert_eq!(tile.metadata.etag.as_deref(), Some("\"v1\""));
+
+        let headers = CacheHeaders {
+            cache_control: Some("no-store".to_string()),
+            etag: None,
+        };
+        cache.put(&key(1), vec![1; 10], &headers).await.unwrap();
+        assert!(cache.get(&key(1)).await.unwrap().is_none());
+    }
+}
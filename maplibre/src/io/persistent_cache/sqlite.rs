@@ -0,0 +1,202 @@
+//! Cache backend which stores tiles within a SQLite database.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::coords::TileCoords;
+use crate::error::Error;
+use crate::io::persistent_cache::{CacheBackend, CacheKey, CacheMetadata, CachedTile};
+
+/// Stores tiles in the table `tiles` of a SQLite database. Compared to the
+/// [`FilesystemCacheBackend`](super::filesystem::FilesystemCacheBackend) this avoids creating
+/// one file per tile.
+pub struct SqliteCacheBackend {
+    // rusqlite connections are Send but not Sync. Access to the connection is serialized.
+    connection: Mutex<Connection>,
+}
+
+impl SqliteCacheBackend {
+    /// Opens the database at `path`. The database is created if it does not exist yet.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let connection = Connection::open(path)?;
+
+        // language=SQL
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS tiles (
+                 source_id TEXT NOT NULL,
+                 zoom_level INTEGER NOT NULL,
+                 tile_column INTEGER NOT NULL,
+                 tile_row INTEGER NOT NULL,
+                 tile_data BLOB NOT NULL,
+                 etag TEXT,
+                 expires_at INTEGER NOT NULL,
+                 size INTEGER NOT NULL,
+                 last_access INTEGER NOT NULL,
+                 PRIMARY KEY (source_id, zoom_level, tile_column, tile_row)
+             );",
+        )?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<Connection>, Error> {
+        self.connection
+            .lock()
+            .map_err(|_e| Error::IO("cache connection is poisoned".to_string()))
+    }
+}
+
+/// Reads the metadata from the columns starting at `offset`.
+fn read_metadata(row: &Row, offset: usize) -> rusqlite::Result<CacheMetadata> {
+    Ok(CacheMetadata {
+        etag: row.get(offset)?,
+        expires_at: row.get::<_, i64>(offset + 1)? as u64,
+        size: row.get::<_, i64>(offset + 2)? as u64,
+        last_access: row.get::<_, i64>(offset + 3)? as u64,
+    })
+}
+
+#[cfg_attr(feature = "no-thread-safe-futures", async_trait(?Send))]
+#[cfg_attr(not(feature = "no-thread-safe-futures"), async_trait)]
+impl CacheBackend for SqliteCacheBackend {
+    async fn get(&self, key: &CacheKey) -> Result<Option<CachedTile>, Error> {
+        let connection = self.lock()?;
+        // language=SQL
+        let mut statement = connection.prepare_cached(
+            "SELECT tile_data, etag, expires_at, size, last_access FROM tiles
+                    WHERE source_id = ?1 AND zoom_level = ?2 AND tile_column = ?3 AND tile_row = ?4;",
+        )?;
+        let tile = statement
+            .query_row(
+                params![key.source_id, key.coords.z, key.coords.x, key.coords.y],
+                |row| {
+                    Ok(CachedTile {
+                        data: row.get(0)?,
+                        metadata: read_metadata(row, 1)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(tile)
+    }
+
+    async fn put(&self, key: &CacheKey, tile: &CachedTile) -> Result<(), Error> {
+        let connection = self.lock()?;
+        // language=SQL
+        let mut statement = connection.prepare_cached(
+            "INSERT OR REPLACE INTO tiles
+                    (source_id, zoom_level, tile_column, tile_row, tile_data, etag, expires_at, size, last_access)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9);",
+        )?;
+        statement.execute(params![
+            key.source_id,
+            key.coords.z,
+            key.coords.x,
+            key.coords.y,
+            tile.data,
+            tile.metadata.etag,
+            tile.metadata.expires_at as i64,
+            tile.metadata.size as i64,
+            tile.metadata.last_access as i64,
+        ])?;
+        Ok(())
+    }
+
+    async fn update_metadata(&self, key: &CacheKey, metadata: &CacheMetadata) -> Result<(), Error> {
+        let connection = self.lock()?;
+        // language=SQL
+        let mut statement = connection.prepare_cached(
+            "UPDATE tiles SET etag = ?5, expires_at = ?6, size = ?7, last_access = ?8
+                    WHERE source_id = ?1 AND zoom_level = ?2 AND tile_column = ?3 AND tile_row = ?4;",
+        )?;
+        statement.execute(params![
+            key.source_id,
+            key.coords.z,
+            key.coords.x,
+            key.coords.y,
+            metadata.etag,
+            metadata.expires_at as i64,
+            metadata.size as i64,
+            metadata.last_access as i64,
+        ])?;
+        Ok(())
+    }
+
+    async fn remove(&self, key: &CacheKey) -> Result<(), Error> {
+        let connection = self.lock()?;
+        // language=SQL
+        let mut statement = connection.prepare_cached(
+            "DELETE FROM tiles
+                    WHERE source_id = ?1 AND zoom_level = ?2 AND tile_column = ?3 AND tile_row = ?4;",
+        )?;
+        statement.execute(params![
+            key.source_id,
+            key.coords.z,
+            key.coords.x,
+            key.coords.y
+        ])?;
+        Ok(())
+    }
+
+    async fn entries(&self) -> Result<Vec<(CacheKey, CacheMetadata)>, Error> {
+        let connection = self.lock()?;
+        // language=SQL
+        let mut statement = connection.prepare(
+            "SELECT source_id, zoom_level, tile_column, tile_row, etag, expires_at, size, last_access
+                    FROM tiles;",
+        )?;
+        let entries = statement
+            .query_map([], |row| {
+                let key = CacheKey {
+                    source_id: row.get(0)?,
+                    coords: TileCoords {
+                        z: row.get(1)?,
+                        x: row.get(2)?,
+                        y: row.get(3)?,
+                    },
+                };
+                Ok((key, read_metadata(row, 4)?))
+            })?
+            .filter_map(|result| result.ok())
+            .collect();
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coords::TileCoords;
+    use crate::io::persistent_cache::sqlite::SqliteCacheBackend;
+    use crate::io::persistent_cache::{CacheBackend, CacheKey, CacheMetadata, CachedTile};
+
+    #[tokio::test]
+    async fn test_roundtrip() {
+        let backend = SqliteCacheBackend::open(":memory:").unwrap();
+        let key = CacheKey::new("source", TileCoords { x: 1, y: 2, z: 3 });
+        let tile = CachedTile {
+            data: b"tile".to_vec(),
+            metadata: CacheMetadata {
+                etag: Some("\"v1\"".to_string()),
+                expires_at: 100,
+                size: 4,
+                last_access: 50,
+            },
+        };
+
+        backend.put(&key, &tile).await.unwrap();
+        let cached = backend.get(&key).await.unwrap().unwrap();
+        assert_eq!(cached.data, tile.data);
+        assert_eq!(cached.metadata, tile.metadata);
+
+        let entries = backend.entries().await.unwrap();
+        assert_eq!(entries, vec![(key.clone(), tile.metadata.clone())]);
+
+        backend.remove(&key).await.unwrap();
+        assert!(backend.get(&key).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,576 @@
+//! Reads vector tiles from a [PMTiles](https://github.com/protomaps/PMTiles/blob/main/spec/v3/spec.md)
+//! (version 3) archive by using byte-range reads.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::Read;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::coords::WorldTileCoords;
+use crate::error::Error;
+use crate::io::compression::ContentEncoding;
+use crate::io::source_client::HTTPClient;
+use crate::style::source::TileAddressingScheme;
+
+/// URL prefix which is used within the `tiles` or the `url` of a source to reference a PMTiles
+/// archive, e.g. `pmtiles://https://example.com/tiles.pmtiles` or
+/// `pmtiles:///path/to/tiles.pmtiles`.
+pub const PMTILES_URL_PREFIX: &str = "pmtiles://";
+
+const MAGIC: &[u8] = b"PMTiles";
+const SPEC_VERSION: u8 = 3;
+const HEADER_LENGTH: usize = 127;
+/// The root directory is guaranteed to be within the first 16 KiB of an archive. Both the header
+/// and the root directory are therefore read with a single request.
+const INITIAL_FETCH_LENGTH: u64 = 16384;
+/// Leaf directories are nested at most three levels deep.
+const MAX_DIRECTORY_DEPTH: usize = 4;
+/// Maximum number of directories which are kept in the directory cache.
+const DIRECTORY_CACHE_SIZE: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Unknown,
+    None,
+    Gzip,
+    Brotli,
+    Zstd,
+}
+
+impl From<u8> for Compression {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Compression::None,
+            2 => Compression::Gzip,
+            3 => Compression::Brotli,
+            4 => Compression::Zstd,
+            _ => Compression::Unknown,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileType {
+    Unknown,
+    Mvt,
+    Png,
+    Jpeg,
+    Webp,
+    Avif,
+}
+
+impl From<u8> for TileType {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => TileType::Mvt,
+            2 => TileType::Png,
+            3 => TileType::Jpeg,
+            4 => TileType::Webp,
+            5 => TileType::Avif,
+            _ => TileType::Unknown,
+        }
+    }
+}
+
+/// The fixed-size header at the start of each archive.
+#[derive(Clone, Debug)]
+pub struct Header {
+    pub root_directory_offset: u64,
+    pub root_directory_length: u64,
+    pub metadata_offset: u64,
+    pub metadata_length: u64,
+    pub leaf_directories_offset: u64,
+    pub leaf_directories_length: u64,
+    pub tile_data_offset: u64,
+    pub tile_data_length: u64,
+    pub internal_compression: Compression,
+    pub tile_compression: Compression,
+    pub tile_type: TileType,
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+    /// The bounds of the archive as `(west, south, east, north)`
+    pub bounds: (f64, f64, f64, f64),
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < HEADER_LENGTH || !data.starts_with(MAGIC) {
+            return Err(Error::IO("not a PMTiles archive".to_string()));
+        }
+        if data[7] != SPEC_VERSION {
+            return Err(Error::IO(format!(
+                "PMTiles version {} is not supported",
+                data[7]
+            )));
+        }
+
+        let u64_at = |i: usize| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&data[i..i + 8]);
+            u64::from_le_bytes(bytes)
+        };
+        let degrees_at = |i: usize| {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&data[i..i + 4]);
+            i32::from_le_bytes(bytes) as f64 / 10_000_000.0
+        };
+
+        Ok(Self {
+            root_directory_offset: u64_at(8),
+            root_directory_length: u64_at(16),
+            metadata_offset: u64_at(24),
+            metadata_length: u64_at(32),
+            leaf_directories_offset: u64_at(40),
+            leaf_directories_length: u64_at(48),
+            tile_data_offset: u64_at(56),
+            tile_data_length: u64_at(64),
+            internal_compression: data[97].into(),
+            tile_compression: data[98].into(),
+            tile_type: data[99].into(),
+            min_zoom: data[100],
+            max_zoom: data[101],
+            bounds: (
+                degrees_at(102),
+                degrees_at(106),
+                degrees_at(110),
+                degrees_at(114),
+            ),
+        })
+    }
+}
+
+/// An entry of a directory. Entries with a `run_length` of 0 point to leaf directories, all other
+/// entries point to tile data which is shared by `run_length` consecutive tile ids.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Entry {
+    tile_id: u64,
+    offset: u64,
+    length: u64,
+    run_length: u64,
+}
+
+type Directory = Vec<Entry>;
+
+/// The storage from which the archive is read.
+#[derive(Clone)]
+enum Backend<HC>
+where
+    HC: HTTPClient,
+{
+    Http {
+        http_client: HC,
+        url: String,
+    },
+    #[cfg(not(target_arch = "wasm32"))]
+    File {
+        path: PathBuf,
+        file: Arc<Mutex<std::fs::File>>,
+    },
+}
+
+impl<HC> Backend<HC>
+where
+    HC: HTTPClient,
+{
+    async fn read(&self, offset: u64, length: u64) -> Result<Vec<u8>, Error> {
+        match self {
+            Backend::Http { http_client, url } => {
+                http_client.fetch_range(url, offset, length).await
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Backend::File { file, .. } => {
+                use std::io::{Seek, SeekFrom};
+
+                let mut file = file
+                    .lock()
+                    .map_err(|_e| Error::IO("PMTiles file is poisoned".to_string()))?;
+                file.seek(SeekFrom::Start(offset))
+                    .map_err(|e| Error::IO(e.to_string()))?;
+                let mut data = Vec::with_capacity(length as usize);
+                file.by_ref()
+                    .take(length)
+                    .read_to_end(&mut data)
+                    .map_err(|e| Error::IO(e.to_string()))?;
+                Ok(data)
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Backend::Http { url, .. } => url.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            Backend::File { path, .. } => path.display().to_string(),
+        }
+    }
+}
+
+/// Directories which have been read recently, keyed by their offset within the archive.
+#[derive(Default)]
+struct DirectoryCache {
+    directories: HashMap<u64, Arc<Directory>>,
+    insertion_order: VecDeque<u64>,
+}
+
+impl DirectoryCache {
+    fn get(&self, offset: u64) -> Option<Arc<Directory>> {
+        self.directories.get(&offset).cloned()
+    }
+
+    fn insert(&mut self, offset: u64, directory: Arc<Directory>) {
+        if self.directories.insert(offset, directory).is_none() {
+            self.insertion_order.push_back(offset);
+        }
+
+        while self.insertion_order.len() > DIRECTORY_CACHE_SIZE {
+            if let Some(oldest) = self.insertion_order.pop_front() {
+                self.directories.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Source client which serves tiles from a PMTiles archive, either from a local file or via HTTP
+/// range requests. This allows hosting a single static file without a tile server.
+///
+/// The header is read lazily with the first request. Directories are cached, such that most tile
+/// requests require only a single read of the tile data.
+#[derive(Clone)]
+pub struct PmtilesSourceClient<HC>
+where
+    HC: HTTPClient,
+{
+    backend: Backend<HC>,
+    header: Arc<Mutex<Option<Arc<Header>>>>,
+    directory_cache: Arc<Mutex<DirectoryCache>>,
+}
+
+impl<HC> PmtilesSourceClient<HC>
+where
+    HC: HTTPClient,
+{
+    /// Creates a client for a URL which starts with [`PMTILES_URL_PREFIX`]. The archive is read
+    /// via HTTP if the remaining URL starts with `http://` or `https://`. Otherwise, it is
+    /// interpreted as a local path.
+    pub fn from_url(url: &str, http_client: HC) -> Result<Self, Error> {
+        let location = url.strip_prefix(PMTILES_URL_PREFIX).unwrap_or(url);
+
+        if location.starts_with("http://") || location.starts_with("https://") {
+            return Ok(Self::with_http_client(http_client, location));
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        return Self::open(location);
+        #[cfg(target_arch = "wasm32")]
+        return Err(Error::IO(format!(
+            "local PMTiles archive {} is not supported on this platform",
+            location
+        )));
+    }
+
+    /// Creates a client which reads the archive at `url` via HTTP range requests.
+    pub fn with_http_client(http_client: HC, url: &str) -> Self {
+        Self::new(Backend::Http {
+            http_client,
+            url: url.to_string(),
+        })
+    }
+
+    /// Creates a client which reads the archive at `path` from the filesystem.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let file = std::fs::File::open(&path)
+            .map_err(|e| Error::IO(format!("PMTiles file {:?} can not be opened: {}", path, e)))?;
+        Ok(Self::new(Backend::File {
+            path,
+            file: Arc::new(Mutex::new(file)),
+        }))
+    }
+
+    fn new(backend: Backend<HC>) -> Self {
+        Self {
+            backend,
+            header: Arc::new(Mutex::new(None)),
+            directory_cache: Arc::new(Mutex::new(DirectoryCache::default())),
+        }
+    }
+
+    /// Returns the header of the archive. The header and the root directory are read with the
+    /// first call.
+    pub async fn header(&self) -> Result<Arc<Header>, Error> {
+        let cached_header = self.lock_header()?.clone();
+        if let Some(header) = cached_header {
+            return Ok(header);
+        }
+
+        let data = self.backend.read(0, INITIAL_FETCH_LENGTH).await?;
+        let header = Arc::new(Header::parse(&data)?);
+
+        if header.internal_compression != Compression::None
+            && header.internal_compression != Compression::Gzip
+        {
+            return Err(Error::IO(format!(
+                "PMTiles archive {} uses the unsupported internal compression {:?}",
+                self.backend.name(),
+                header.internal_compression
+            )));
+        }
+
+        if header.tile_type != TileType::Mvt {
+            log::warn!(
+                "PMTiles archive {} contains tiles of type {:?}, expected MVT",
+                self.backend.name(),
+                header.tile_type
+            );
+        }
+
+        // Use the root directory from the initial read, if it is contained completely
+        let root_end = header.root_directory_offset + header.root_directory_length;
+        if root_end <= data.len() as u64 {
+            let root_directory = parse_directory(&decompress(
+                &data[header.root_directory_offset as usize..root_end as usize],
+                header.internal_compression,
+            )?)?;
+            self.lock_directory_cache()?
+                .insert(header.root_directory_offset, Arc::new(root_directory));
+        }
+
+        *self.lock_header()? = Some(header.clone());
+        Ok(header)
+    }
+
+    pub async fn fetch(&self, coords: &WorldTileCoords) -> Result<Vec<u8>, Error> {
+        let header = self.header().await?;
+
+        let tile_coords = coords
+            .into_tile(TileAddressingScheme::XYZ)
+            .ok_or_else(|| Error::IO(format!("Tile {} is out of bounds", coords)))?;
+        let tile_id = tile_id(tile_coords.z, tile_coords.x as u64, tile_coords.y as u64);
+
+        let mut directory_offset = header.root_directory_offset;
+        let mut directory_length = header.root_directory_length;
+
+        for _ in 0..MAX_DIRECTORY_DEPTH {
+            let directory = self
+                .directory(&header, directory_offset, directory_length)
+                .await?;
+
+            match find_entry(&directory, tile_id) {
+                Some(entry) if entry.run_length > 0 => {
+                    let data = self
+                        .backend
+                        .read(header.tile_data_offset + entry.offset, entry.length)
+                        .await?;
+                    return decompress(&data, header.tile_compression);
+                }
+                Some(entry) => {
+                    directory_offset = header.leaf_directories_offset + entry.offset;
+                    directory_length = entry.length;
+                }
+                None => break,
+            }
+        }
+
+        Err(Error::NotFound(format!(
+            "Tile {} not found in {}",
+            coords,
+            self.backend.name()
+        )))
+    }
+
+    async fn directory(
+        &self,
+        header: &Header,
+        offset: u64,
+        length: u64,
+    ) -> Result<Arc<Directory>, Error> {
+        let cached_directory = self.lock_directory_cache()?.get(offset);
+        if let Some(directory) = cached_directory {
+            return Ok(directory);
+        }
+
+        let data = self.backend.read(offset, length).await?;
+        let directory = Arc::new(parse_directory(&decompress(
+            &data,
+            header.internal_compression,
+        )?)?);
+        self.lock_directory_cache()?
+            .insert(offset, directory.clone());
+        Ok(directory)
+    }
+
+    fn lock_header(&self) -> Result<std::sync::MutexGuard<Option<Arc<Header>>>, Error> {
+        self.header
+            .lock()
+            .map_err(|_e| Error::IO("PMTiles header is poisoned".to_string()))
+    }
+
+    fn lock_directory_cache(&self) -> Result<std::sync::MutexGuard<DirectoryCache>, Error> {
+        self.directory_cache
+            .lock()
+            .map_err(|_e| Error::IO("PMTiles directory cache is poisoned".to_string()))
+    }
+}
+
+/// Converts tile coordinates to the tile id of PMTiles. Tile ids are ordered by zoom level and
+/// follow a Hilbert curve within each zoom level.
+fn tile_id(z: u8, x: u64, y: u64) -> u64 {
+    // Number of tiles on all zoom levels below z
+    let base = ((1u64 << (2 * z as u64)) - 1) / 3;
+    let n = 1u64 << z;
+
+    let (mut x, mut y) = (x, y);
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u64;
+        let ry = (y & s > 0) as u64;
+        d += s * s * ((3 * rx) ^ ry);
+
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        s /= 2;
+    }
+
+    base + d
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> Result<u64, Error> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *data
+            .get(*position)
+            .ok_or_else(|| Error::IO("unexpected end of PMTiles directory".to_string()))?;
+        *position += 1;
+
+        if shift >= 64 {
+            return Err(Error::IO("invalid varint in PMTiles directory".to_string()));
+        }
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+/// Parses a decompressed directory. The tile ids, run lengths, lengths and offsets of all entries
+/// are stored column-wise.
+fn parse_directory(data: &[u8]) -> Result<Directory, Error> {
+    let mut position = 0;
+    let count = read_varint(data, &mut position)? as usize;
+
+    let mut entries = vec![
+        Entry {
+            tile_id: 0,
+            offset: 0,
+            length: 0,
+            run_length: 0,
+        };
+        count
+    ];
+
+    let mut last_tile_id = 0;
+    for entry in entries.iter_mut() {
+        last_tile_id += read_varint(data, &mut position)?;
+        entry.tile_id = last_tile_id;
+    }
+    for entry in entries.iter_mut() {
+        entry.run_length = read_varint(data, &mut position)?;
+    }
+    for entry in entries.iter_mut() {
+        entry.length = read_varint(data, &mut position)?;
+    }
+    for i in 0..count {
+        let value = read_varint(data, &mut position)?;
+        // An offset of 0 means that the data directly follows the data of the previous entry
+        entries[i].offset = if value == 0 && i > 0 {
+            entries[i - 1].offset + entries[i - 1].length
+        } else {
+            value.saturating_sub(1)
+        };
+    }
+
+    Ok(entries)
+}
+
+/// Finds the entry which contains `tile_id`, or the leaf directory which might contain it.
+fn find_entry(directory: &[Entry], tile_id: u64) -> Option<&Entry> {
+    let index = match directory.binary_search_by_key(&tile_id, |entry| entry.tile_id) {
+        Ok(index) => return Some(&directory[index]),
+        Err(0) => return None,
+        Err(index) => index - 1,
+    };
+
+    let entry = &directory[index];
+    if entry.run_length == 0 || tile_id - entry.tile_id < entry.run_length {
+        Some(entry)
+    } else {
+        None
+    }
+}
+
+fn decompress(data: &[u8], compression: Compression) -> Result<Vec<u8>, Error> {
+    let encoding = match compression {
+        Compression::None | Compression::Unknown => ContentEncoding::Identity,
+        Compression::Gzip => ContentEncoding::Gzip,
+        Compression::Brotli => ContentEncoding::Brotli,
+        compression => {
+            return Err(Error::Decode(format!(
+                "PMTiles compression {:?} is not supported",
+                compression
+            )))
+        }
+    };
+    crate::io::compression::decompress(data, encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_entry, parse_directory, tile_id, Entry};
+
+    #[test]
+    fn test_tile_id() {
+        assert_eq!(tile_id(0, 0, 0), 0);
+        assert_eq!(tile_id(1, 0, 0), 1);
+        assert_eq!(tile_id(1, 0, 1), 2);
+        assert_eq!(tile_id(1, 1, 1), 3);
+        assert_eq!(tile_id(1, 1, 0), 4);
+        assert_eq!(tile_id(2, 0, 0), 5);
+        assert_eq!(tile_id(3, 7, 0), 84);
+    }
+
+    #[test]
+    fn test_parse_directory_and_find_entry() {
+        // Three entries: tile ids 1, 2 and 10; run lengths 1, 1 and 0; lengths 10, 20 and 30;
+        // offsets 0, following the previous entry and 100
+        let data = [3, 1, 1, 8, 1, 1, 0, 10, 20, 30, 1, 0, 101];
+        let directory = parse_directory(&data).unwrap();
+
+        assert_eq!(
+            directory[1],
+            Entry {
+                tile_id: 2,
+                offset: 10,
+                length: 20,
+                run_length: 1
+            }
+        );
+        assert_eq!(directory[2].offset, 100);
+
+        assert_eq!(find_entry(&directory, 0), None);
+        assert_eq!(find_entry(&directory, 2), Some(&directory[1]));
+        assert_eq!(find_entry(&directory, 5), None);
+        // Tile ids after an entry with a run length of 0 are located in a leaf directory
+        assert_eq!(find_entry(&directory, 42), Some(&directory[2]));
+    }
+}
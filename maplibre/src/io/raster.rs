@@ -0,0 +1,144 @@
+//! Decoding of raster tiles. Images are decoded on worker threads, such that the main thread only
+//! needs to upload the pixels to the GPU.
+
+use image::ImageFormat;
+
+use crate::error::Error;
+use crate::style::source::DemEncoding;
+
+/// Decoded image of a raster tile. The pixels are stored row by row as 8-bit RGBA.
+#[derive(Clone)]
+pub struct RasterImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl RasterImage {
+    /// Number of bytes of a single row of pixels
+    pub fn bytes_per_row(&self) -> u32 {
+        self.width * 4
+    }
+}
+
+/// Decodes a PNG, JPEG or WebP image. The format is detected by the signature of the image.
+pub fn decode_raster(data: &[u8]) -> Result<RasterImage, Error> {
+    let format = image::guess_format(data)
+        .map_err(|e| Error::Decode(format!("raster tile has an unknown format: {}", e)))?;
+
+    if !matches!(
+        format,
+        ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP
+    ) {
+        return Err(Error::Decode(format!(
+            "raster tiles in the format {:?} are not supported",
+            format
+        )));
+    }
+
+    let image = image::load_from_memory_with_format(data, format)
+        .map_err(|e| Error::Decode(format!("raster tile is malformed: {}", e)))?
+        .into_rgba8();
+
+    Ok(RasterImage {
+        width: image.width(),
+        height: image.height(),
+        data: image.into_raw(),
+    })
+}
+
+/// Elevations of a raster DEM tile in meters. The elevations are stored row by row.
+#[derive(Clone)]
+pub struct DemImage {
+    pub width: u32,
+    pub height: u32,
+    pub elevations: Vec<f32>,
+}
+
+impl DemImage {
+    /// Number of bytes of a single row of elevations
+    pub fn bytes_per_row(&self) -> u32 {
+        self.width * 4
+    }
+}
+
+/// Decodes the image of a raster DEM tile and converts the colors of its pixels to elevations.
+pub fn decode_dem(data: &[u8], encoding: DemEncoding) -> Result<DemImage, Error> {
+    let image = decode_raster(data)?;
+
+    let elevations = image
+        .data
+        .chunks_exact(4)
+        .map(|pixel| {
+            let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+            match encoding {
+                DemEncoding::Mapbox => -10000.0 + (r * 256.0 * 256.0 + g * 256.0 + b) * 0.1,
+                DemEncoding::Terrarium => r * 256.0 + g + b / 256.0 - 32768.0,
+            }
+        })
+        .collect();
+
+    Ok(DemImage {
+        width: image.width,
+        height: image.height,
+        elevations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{ImageOutputFormat, Rgba, RgbaImage};
+
+    use crate::error::Error;
+    use crate::io::raster::{decode_dem, decode_raster};
+    use crate::style::source::DemEncoding;
+
+    fn encode_png(pixel: [u8; 4]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        RgbaImage::from_pixel(2, 2, Rgba(pixel))
+            .write_to(&mut Cursor::new(&mut encoded), ImageOutputFormat::Png)
+            .unwrap();
+        encoded
+    }
+
+    #[test]
+    fn test_decode_png() {
+        let mut encoded = Vec::new();
+        RgbaImage::from_pixel(2, 3, Rgba([255, 0, 0, 128]))
+            .write_to(&mut Cursor::new(&mut encoded), ImageOutputFormat::Png)
+            .unwrap();
+
+        let image = decode_raster(&encoded).unwrap();
+        assert_eq!((image.width, image.height), (2, 3));
+        assert_eq!(image.bytes_per_row(), 8);
+        assert_eq!(image.data.len(), 2 * 3 * 4);
+        assert_eq!(&image.data[0..4], &[255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn test_decode_dem() {
+        // 160 + 134 * 256 + 1 * 256^2 = 100000 decimeters, which is the offset of -10000 meters
+        let image = decode_dem(&encode_png([1, 134, 160, 255]), DemEncoding::Mapbox).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(image.elevations.len(), 4);
+        assert!(image.elevations[0].abs() < 0.01);
+
+        let image = decode_dem(&encode_png([128, 100, 128, 255]), DemEncoding::Terrarium).unwrap();
+        assert_eq!(image.elevations[3], 100.5);
+    }
+
+    #[test]
+    fn test_malformed_raster() {
+        assert!(matches!(
+            decode_raster(&[0x1a, 0x45, 0xdf]),
+            Err(Error::Decode(_))
+        ));
+        // PNG signature without any chunks
+        assert!(matches!(
+            decode_raster(&[0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a]),
+            Err(Error::Decode(_))
+        ));
+    }
+}
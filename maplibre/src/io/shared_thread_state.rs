@@ -1,17 +1,23 @@
 use crate::coords::{WorldCoords, WorldTileCoords, Zoom};
 use crate::error::Error;
+use crate::io::compression::decode_tile;
+use crate::io::feature_id::feature_ids;
 use crate::io::geometry_index::{GeometryIndex, IndexProcessor, IndexedGeometry, TileIndex};
-use crate::io::tile_request_state::TileRequestState;
+use crate::io::raster::{decode_dem, decode_raster};
+use crate::io::tile_feature::filter_layer;
+use crate::io::tile_request_state::{TileRequestFailure, TileRequestState};
 use crate::io::{
     LayerTessellateMessage, TessellateMessage, TileRequest, TileRequestID, TileTessellateMessage,
 };
 
 use std::collections::HashSet;
 
+use crate::style::layer::StyleLayer;
+use crate::style::source::{DemEncoding, PromoteId};
+use crate::tessellation::stroke_options;
 use crate::tessellation::zero_tessellator::ZeroTessellator;
 
 use geozero::GeozeroDatasource;
-use prost::Message;
 use std::sync::{mpsc, Arc, Mutex};
 
 #[derive(Clone)]
@@ -29,56 +35,151 @@ impl SharedThreadState {
             .and_then(|tile_request_state| tile_request_state.get_tile_request(request_id).cloned())
     }
 
+    /// Sends `message` to the main thread if the request is still pending. The state is locked
+    /// while sending, such that no messages of a request arrive after it has been cancelled.
+    fn send_if_pending(
+        &self,
+        request_id: TileRequestID,
+        message: TessellateMessage,
+    ) -> Result<(), Error> {
+        let tile_request_state = self
+            .tile_request_state
+            .lock()
+            .map_err(|_| Error::Schedule)?;
+        if tile_request_state.is_request_id_pending(request_id) {
+            self.message_sender.send(message)?;
+        }
+        Ok(())
+    }
+
+    /// Indexes the `geometries` of the tile of the request and notifies the main thread that the
+    /// tile is finished. Like in `send_if_pending`, both happen while the state is locked and only
+    /// if the request is still pending. Otherwise, the geometries of a cancelled request could
+    /// replace those of newer data.
+    fn finish_tile(
+        &self,
+        request_id: TileRequestID,
+        tile_request: &TileRequest,
+        geometries: Vec<IndexedGeometry<f64>>,
+    ) -> Result<(), Error> {
+        let tile_request_state = self
+            .tile_request_state
+            .lock()
+            .map_err(|_| Error::Schedule)?;
+        if !tile_request_state.is_request_id_pending(request_id) {
+            return Ok(());
+        }
+
+        if let Ok(mut geometry_index) = self.geometry_index.lock() {
+            geometry_index.index_tile(
+                &tile_request.source_id,
+                &tile_request.coords,
+                TileIndex::Linear { list: geometries },
+            );
+        }
+
+        self.message_sender
+            .send(TessellateMessage::Tile(TileTessellateMessage {
+                request_id,
+                coords: tile_request.coords,
+            }))?;
+        Ok(())
+    }
+
+    /// Decodes and tessellates the tile of the request. The payload is decompressed if necessary.
+    /// Returns an error if the payload is malformed, the layers of the tile stay untouched in
+    /// that case. The ids of the features are read from the property `promote_id` of the source,
+    /// or from the tile if the source promotes no property.
+    ///
+    /// Each layer of `style_layers` which draws a requested layer of the tile is tessellated with
+    /// the features which pass its filter.
     #[tracing::instrument(skip_all)]
-    pub fn process_tile(&self, request_id: TileRequestID, data: Box<[u8]>) -> Result<(), Error> {
+    pub fn process_tile(
+        &self,
+        request_id: TileRequestID,
+        data: Box<[u8]>,
+        promote_id: Option<&PromoteId>,
+        style_layers: &[StyleLayer],
+    ) -> Result<(), Error> {
         if let Some(tile_request) = self.get_tile_request(request_id) {
             let coords = tile_request.coords;
+            let source_id = &tile_request.source_id;
 
             tracing::info!("parsing tile {} with {}bytes", &coords, data.len());
 
             let _span_ = tracing::span!(tracing::Level::TRACE, "parse_tile_bytes").entered();
 
-            let mut tile = geozero::mvt::Tile::decode(data.as_ref()).expect("failed to load tile");
+            let mut tile = decode_tile(data.as_ref(), None)?;
 
-            let index = IndexProcessor::new();
+            let mut index = IndexProcessor::new();
 
             for layer in &mut tile.layers {
-                let cloned_layer = layer.clone();
-                let layer_name: &str = &cloned_layer.name;
-                if !tile_request.layers.contains(layer_name) {
+                let layer_name = layer.name.clone();
+                if !tile_request.layers.contains(&layer_name) {
                     continue;
                 }
 
                 tracing::info!("layer {} at {} ready", layer_name, &coords);
 
-                let mut tessellator = ZeroTessellator::default();
-                if let Err(e) = layer.process(&mut tessellator) {
-                    self.message_sender.send(TessellateMessage::Layer(
-                        LayerTessellateMessage::UnavailableLayer {
-                            coords,
-                            layer_name: layer_name.to_owned(),
-                        },
-                    ))?;
+                let feature_ids = feature_ids(layer, promote_id);
+
+                for style_layer in style_layers.iter().filter(|style_layer| {
+                    style_layer.source_layer_name() == Some(layer_name.as_str())
+                }) {
+                    // The filter is evaluated before any geometry is emitted
+                    let (mut layer_data, layer_feature_ids) = match &style_layer.filter {
+                        Some(filter) => filter_layer(layer, &feature_ids, filter, coords.z as f64),
+                        None => (layer.clone(), feature_ids.clone()),
+                    };
+
+                    let mut tessellator = ZeroTessellator::new(stroke_options(style_layer))
+                        .with_outlines(style_layer.has_outlines());
+                    if let Err(e) = layer_data.process(&mut tessellator) {
+                        self.send_if_pending(
+                            request_id,
+                            TessellateMessage::Layer(LayerTessellateMessage::UnavailableLayer {
+                                source_id: source_id.clone(),
+                                // Other style layers of the source layer are not affected
+                                style_layer_id: Some(style_layer.id.clone()),
+                                coords,
+                                layer_name: layer_name.clone(),
+                            }),
+                        )?;
+
+                        tracing::error!(
+                            "layer {} of {} at {} tesselation failed {:?}",
+                            layer_name,
+                            &style_layer.id,
+                            &coords,
+                            e
+                        );
+                    } else {
+                        self.send_if_pending(
+                            request_id,
+                            TessellateMessage::Layer(LayerTessellateMessage::TessellatedLayer {
+                                source_id: source_id.clone(),
+                                style_layer_id: style_layer.id.clone(),
+                                coords,
+                                buffer: tessellator.buffer.into(),
+                                feature_vertices: tessellator.feature_vertices,
+                                feature_outline_vertices: tessellator.feature_outline_vertices,
+                                feature_ids: layer_feature_ids,
+                                layer_data,
+                            }),
+                        )?;
+                    }
+                }
 
+                // Failing to index a layer only affects queries, the layer is drawn anyway
+                index.begin_layer(feature_ids);
+                if let Err(e) = layer.process(&mut index) {
                     tracing::error!(
-                        "layer {} at {} tesselation failed {:?}",
+                        "layer {} at {} can not be indexed: {:?}",
                         layer_name,
                         &coords,
                         e
                     );
-                } else {
-                    self.message_sender.send(TessellateMessage::Layer(
-                        LayerTessellateMessage::TessellatedLayer {
-                            coords,
-                            buffer: tessellator.buffer.into(),
-                            feature_indices: tessellator.feature_indices,
-                            layer_data: cloned_layer,
-                        },
-                    ))?;
                 }
-
-                // TODO
-                // layer.process(&mut index).unwrap();
             }
 
             let available_layers: HashSet<_> = tile
@@ -88,12 +189,15 @@ impl SharedThreadState {
                 .collect::<HashSet<_>>();
 
             for missing_layer in tile_request.layers.difference(&available_layers) {
-                self.message_sender.send(TessellateMessage::Layer(
-                    LayerTessellateMessage::UnavailableLayer {
+                self.send_if_pending(
+                    request_id,
+                    TessellateMessage::Layer(LayerTessellateMessage::UnavailableLayer {
+                        source_id: source_id.clone(),
+                        style_layer_id: None,
                         coords,
                         layer_name: missing_layer.to_owned(),
-                    },
-                ))?;
+                    }),
+                )?;
 
                 tracing::info!(
                     "requested layer {} at {} not found in tile",
@@ -104,40 +208,127 @@ impl SharedThreadState {
 
             tracing::info!("tile tessellated at {} finished", &tile_request.coords);
 
-            self.message_sender
-                .send(TessellateMessage::Tile(TileTessellateMessage {
-                    request_id,
-                    coords: tile_request.coords,
-                }))?;
-
-            if let Ok(mut geometry_index) = self.geometry_index.lock() {
-                geometry_index.index_tile(
-                    &coords,
-                    TileIndex::Linear {
-                        list: index.get_geometries(),
-                    },
-                )
-            }
+            self.finish_tile(request_id, &tile_request, index.get_geometries())?;
         }
 
         Ok(())
     }
 
-    pub fn tile_unavailable(
+    /// Decodes the image of a raster tile. Returns an error if the image is malformed, the layer
+    /// of the tile stays untouched in that case.
+    #[tracing::instrument(skip_all)]
+    pub fn process_raster_tile(
         &self,
-        coords: &WorldTileCoords,
         request_id: TileRequestID,
+        data: Box<[u8]>,
     ) -> Result<(), Error> {
         if let Some(tile_request) = self.get_tile_request(request_id) {
-            for to_load in &tile_request.layers {
-                tracing::warn!("layer {} at {} unavailable", to_load, coords);
-                self.message_sender.send(TessellateMessage::Layer(
-                    LayerTessellateMessage::UnavailableLayer {
-                        coords: tile_request.coords,
-                        layer_name: to_load.to_string(),
-                    },
-                ))?;
+            let coords = tile_request.coords;
+
+            tracing::info!("decoding raster tile {} with {}bytes", &coords, data.len());
+
+            let image = decode_raster(data.as_ref())?;
+
+            self.send_if_pending(
+                request_id,
+                TessellateMessage::Layer(LayerTessellateMessage::RasterLayer {
+                    source_id: tile_request.source_id.clone(),
+                    coords,
+                    image,
+                }),
+            )?;
+
+            self.send_if_pending(
+                request_id,
+                TessellateMessage::Tile(TileTessellateMessage { request_id, coords }),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the elevations of a raster DEM tile. Returns an error if the image is malformed, the
+    /// layer of the tile stays untouched in that case.
+    #[tracing::instrument(skip_all)]
+    pub fn process_dem_tile(
+        &self,
+        request_id: TileRequestID,
+        data: Box<[u8]>,
+        encoding: DemEncoding,
+    ) -> Result<(), Error> {
+        if let Some(tile_request) = self.get_tile_request(request_id) {
+            let coords = tile_request.coords;
+
+            tracing::info!("decoding DEM tile {} with {}bytes", &coords, data.len());
+
+            let dem = decode_dem(data.as_ref(), encoding)?;
+
+            self.send_if_pending(
+                request_id,
+                TessellateMessage::Layer(LayerTessellateMessage::DemLayer {
+                    source_id: tile_request.source_id.clone(),
+                    coords,
+                    dem: Arc::new(dem),
+                }),
+            )?;
+
+            self.send_if_pending(
+                request_id,
+                TessellateMessage::Tile(TileTessellateMessage { request_id, coords }),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the request is still pending. Requests for tiles which left the view are
+    /// cancelled and should not be processed anymore.
+    pub fn is_tile_request_pending(&self, request_id: TileRequestID) -> bool {
+        self.tile_request_state
+            .lock()
+            .map_or(false, |tile_request_state| {
+                tile_request_state.is_request_id_pending(request_id)
+            })
+    }
+
+    /// Handles a failed request. Transient errors are retried later by the main thread. Otherwise,
+    /// the layers of the tile are marked as unavailable.
+    pub fn tile_failed(&self, request_id: TileRequestID, error: &Error) -> Result<(), Error> {
+        // The state stays locked while the layers are marked as unavailable, like in
+        // `send_if_pending`
+        let mut tile_request_state = self
+            .tile_request_state
+            .lock()
+            .map_err(|_| Error::Schedule)?;
+
+        match tile_request_state.fail_tile_request(request_id, error) {
+            Some(TileRequestFailure::Retry { attempts, backoff }) => {
+                tracing::warn!(
+                    "tile request {} failed {} time(s), retrying in {:?}: {:?}",
+                    request_id,
+                    attempts,
+                    backoff,
+                    error
+                );
+            }
+            Some(TileRequestFailure::GiveUp(tile_request)) => {
+                if !matches!(error, Error::NotFound(_)) {
+                    log::error!("{:?}", error);
+                }
+
+                for to_load in &tile_request.layers {
+                    tracing::warn!("layer {} at {} unavailable", to_load, tile_request.coords);
+                    self.message_sender.send(TessellateMessage::Layer(
+                        LayerTessellateMessage::UnavailableLayer {
+                            source_id: tile_request.source_id.clone(),
+                            style_layer_id: None,
+                            coords: tile_request.coords,
+                            layer_name: to_load.to_string(),
+                        },
+                    ))?;
+                }
             }
+            None => {}
         }
 
         Ok(())
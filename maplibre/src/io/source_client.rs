@@ -1,10 +1,27 @@
 use crate::coords::WorldTileCoords;
 use crate::error::Error;
-use crate::style::source::TileAddressingScheme;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::io::directory_source_client::DirectorySourceClient;
+use crate::io::geojson_source_client::GeoJsonSourceClient;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::io::mbtiles_source_client::MbtilesSourceClient;
+use crate::io::persistent_cache::{unix_time, CacheKey, PersistentTileCache};
+use crate::io::pmtiles_source_client::{PmtilesSourceClient, PMTILES_URL_PREFIX};
+use crate::io::tile_url::TileUrlTemplates;
+use crate::style::source::{RasterDemSource, Source, TileAddressingScheme, VectorSource};
+use crate::tilejson::TileJSON;
 use async_trait::async_trait;
 
 pub type HTTPClientFactory<HC> = dyn Fn() -> HC;
 
+/// URL prefix which is used within the `tiles` of a source to reference a local MBTiles file.
+/// This follows the convention of MapLibre GL Native: `mbtiles:///path/to/file.mbtiles`.
+pub const MBTILES_URL_PREFIX: &str = "mbtiles://";
+
+/// URL prefix which is used within the `tiles` of a source to reference a directory of tiles,
+/// e.g. `file:///path/to/tiles/{z}/{x}/{y}.pbf` or just `file:///path/to/tiles`.
+pub const FILE_URL_PREFIX: &str = "file://";
+
 // On the web platform futures are not thread-safe (i.e. not Send). This means we need to tell
 // async_trait that these bounds should not be placed on the async trait:
 // https://github.com/dtolnay/async-trait/blob/b70720c4c1cc0d810b7446efda44f81310ee7bf2/README.md#non-threadsafe-futures
@@ -15,6 +32,47 @@ pub type HTTPClientFactory<HC> = dyn Fn() -> HC;
 #[cfg_attr(not(feature = "no-thread-safe-futures"), async_trait)]
 pub trait HTTPClient: Clone + Sync + Send + 'static {
     async fn fetch(&self, url: &str) -> Result<Vec<u8>, Error>;
+
+    /// Fetches `length` bytes starting at the byte `offset` of the resource at `url` by using a
+    /// HTTP range request. This is used to read parts of archives like PMTiles.
+    async fn fetch_range(&self, url: &str, offset: u64, length: u64) -> Result<Vec<u8>, Error>;
+
+    /// Fetches the resource at `url` together with the headers which control caching. If an
+    /// `etag` is given, the request is conditional and the server can respond that the resource
+    /// did not change. Responses must not be served from an HTTP cache.
+    async fn fetch_conditional(
+        &self,
+        url: &str,
+        etag: Option<&str>,
+    ) -> Result<ConditionalResponse, Error>;
+}
+
+/// The headers of a response which control how long it can be cached.
+#[derive(Clone, Debug, Default)]
+pub struct CacheHeaders {
+    pub cache_control: Option<String>,
+    pub etag: Option<String>,
+}
+
+/// Response of [`HTTPClient::fetch_conditional`].
+#[derive(Clone, Debug)]
+pub enum ConditionalResponse {
+    Modified {
+        data: Vec<u8>,
+        headers: CacheHeaders,
+    },
+    /// The resource matches the given ETag (HTTP status 304)
+    NotModified { headers: CacheHeaders },
+}
+
+/// Fetches and parses the [TileJSON](https://github.com/mapbox/tilejson-spec) at `url`.
+pub async fn fetch_tilejson<HC: HTTPClient>(
+    http_client: &HC,
+    url: &str,
+) -> Result<TileJSON, Error> {
+    let data = http_client.fetch(url).await?;
+    serde_json::from_slice(&data)
+        .map_err(|e| Error::Style(format!("invalid TileJSON at {}: {}", url, e)))
 }
 
 #[derive(Clone)]
@@ -23,6 +81,10 @@ where
     HC: HTTPClient,
 {
     inner_client: HC,
+    tiles: TileUrlTemplates,
+    scheme: TileAddressingScheme,
+    /// The id of the source together with the cache in which its tiles are stored
+    cache: Option<(String, PersistentTileCache)>,
 }
 
 #[derive(Clone)]
@@ -31,19 +93,103 @@ where
     HC: HTTPClient,
 {
     Http(HttpSourceClient<HC>),
-    Mbtiles {
-        // TODO
-    },
+    /// Reads tiles from a local MBTiles file. Only available on platforms with filesystem access.
+    #[cfg(not(target_arch = "wasm32"))]
+    Mbtiles(MbtilesSourceClient),
+    /// Reads tiles from a directory tree. Only available on platforms with filesystem access.
+    #[cfg(not(target_arch = "wasm32"))]
+    Directory(DirectorySourceClient),
+    /// Reads tiles from a PMTiles archive via byte-range reads.
+    Pmtiles(PmtilesSourceClient<HC>),
+    /// Slices tiles from GeoJSON.
+    GeoJson(GeoJsonSourceClient<HC>),
 }
 
 impl<HC> SourceClient<HC>
 where
     HC: HTTPClient,
 {
+    /// Creates a client for a source of the style. The `tiles` of the source decide which client
+    /// is used.
+    pub fn from_source(source: &Source, http_client: HC) -> Result<Self, Error> {
+        match source {
+            // Raster sources are described like vector sources, only the format of the tiles differs
+            Source::Vector(source)
+            | Source::Raster(source)
+            | Source::RasterDem(RasterDemSource { source, .. }) => {
+                Self::from_vector_source(source, http_client)
+            }
+            Source::GeoJson(source) => Ok(SourceClient::GeoJson(GeoJsonSourceClient::new(
+                source,
+                http_client,
+            ))),
+        }
+    }
+
+    fn from_vector_source(source: &VectorSource, http_client: HC) -> Result<Self, Error> {
+        let tiles = source
+            .tiles
+            .as_ref()
+            .filter(|tiles| !tiles.is_empty())
+            .ok_or_else(|| Error::Style("source does not define any tiles".to_string()))?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(path) = tiles[0].strip_prefix(MBTILES_URL_PREFIX) {
+            return Ok(SourceClient::Mbtiles(MbtilesSourceClient::open(path)?));
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(template) = tiles[0].strip_prefix(FILE_URL_PREFIX) {
+            return Ok(SourceClient::Directory(DirectorySourceClient::open(
+                template,
+                source.scheme.clone().unwrap_or_default(),
+            )?));
+        }
+
+        if tiles[0].starts_with(PMTILES_URL_PREFIX) {
+            return Ok(SourceClient::Pmtiles(PmtilesSourceClient::from_url(
+                &tiles[0],
+                http_client,
+            )?));
+        }
+
+        Ok(SourceClient::Http(HttpSourceClient::new(
+            http_client,
+            TileUrlTemplates::new(tiles),
+            source.scheme.clone().unwrap_or_default(),
+        )))
+    }
+
+    /// The zoom range which is stored along with the tiles, e.g. in the metadata of MBTiles files.
+    /// Returns `(minzoom, maxzoom)`.
+    pub fn zoom_range(&self) -> (Option<u8>, Option<u8>) {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            SourceClient::Mbtiles(client) => (client.minzoom(), client.maxzoom()),
+            #[cfg(not(target_arch = "wasm32"))]
+            SourceClient::Directory(client) => (client.minzoom(), client.maxzoom()),
+            _ => (None, None),
+        }
+    }
+
+    /// Stores the tiles of the source `source_id` in the `cache`. Only tiles which are fetched via
+    /// HTTP are cached, other clients read local files or archives.
+    pub fn with_cache(self, source_id: &str, cache: PersistentTileCache) -> Self {
+        match self {
+            SourceClient::Http(client) => SourceClient::Http(client.with_cache(source_id, cache)),
+            client => client,
+        }
+    }
+
     pub async fn fetch(&self, coords: &WorldTileCoords) -> Result<Vec<u8>, Error> {
         match self {
             SourceClient::Http(client) => client.fetch(coords).await,
-            SourceClient::Mbtiles { .. } => unimplemented!(),
+            #[cfg(not(target_arch = "wasm32"))]
+            SourceClient::Mbtiles(client) => client.fetch(coords).await,
+            #[cfg(not(target_arch = "wasm32"))]
+            SourceClient::Directory(client) => client.fetch(coords).await,
+            SourceClient::Pmtiles(client) => client.fetch(coords).await,
+            SourceClient::GeoJson(client) => client.fetch(coords).await,
         }
     }
 }
@@ -52,24 +198,94 @@ impl<HC> HttpSourceClient<HC>
 where
     HC: HTTPClient,
 {
-    pub fn new(http_client: HC) -> Self {
+    pub fn new(http_client: HC, tiles: TileUrlTemplates, scheme: TileAddressingScheme) -> Self {
         Self {
             inner_client: http_client,
+            tiles,
+            scheme,
+            cache: None,
         }
     }
 
+    pub fn with_cache(mut self, source_id: &str, cache: PersistentTileCache) -> Self {
+        self.cache = Some((source_id.to_string(), cache));
+        self
+    }
+
     pub async fn fetch(&self, coords: &WorldTileCoords) -> Result<Vec<u8>, Error> {
-        let tile_coords = coords.into_tile(TileAddressingScheme::TMS).unwrap();
-        self.inner_client
-            .fetch(
-                format!(
-                    "https://maps.tuerantuer.org/europe_germany/{z}/{x}/{y}.pbf",
-                    x = tile_coords.x,
-                    y = tile_coords.y,
-                    z = tile_coords.z
-                )
-                .as_str(),
-            )
-            .await
+        let url = self
+            .tiles
+            .expand(coords, &self.scheme)
+            .ok_or_else(|| Error::NotFound(format!("no tile URL for {}", coords)))?;
+
+        let tile_coords = coords.into_tile(TileAddressingScheme::XYZ);
+        match (&self.cache, tile_coords) {
+            (Some((source_id, cache)), Some(tile_coords)) => {
+                let key = CacheKey::new(source_id, tile_coords);
+                self.fetch_cached(url.as_str(), &key, cache).await
+            }
+            _ => self.inner_client.fetch(url.as_str()).await,
+        }
+    }
+
+    /// Serves fresh tiles from the `cache`. Stale tiles are revalidated with their ETag. If the
+    /// revalidation fails because of a transient error, the stale tile is served. Errors of the
+    /// cache are logged and do not fail the request.
+    async fn fetch_cached(
+        &self,
+        url: &str,
+        key: &CacheKey,
+        cache: &PersistentTileCache,
+    ) -> Result<Vec<u8>, Error> {
+        let cached = cache.get(key).await.unwrap_or_else(|e| {
+            log::warn!("tile cache can not be read: {:?}", e);
+            None
+        });
+
+        if let Some(tile) = &cached {
+            if tile.metadata.is_fresh(unix_time()) {
+                return Ok(tile.data.clone());
+            }
+        }
+
+        let etag = cached
+            .as_ref()
+            .and_then(|tile| tile.metadata.etag.as_deref());
+        let result = self.inner_client.fetch_conditional(url, etag).await;
+
+        match (result, cached) {
+            (Ok(ConditionalResponse::Modified { data, headers }), _) => {
+                if let Err(e) = cache.put(key, data.clone(), &headers).await {
+                    log::warn!("tile can not be stored in cache: {:?}", e);
+                }
+                Ok(data)
+            }
+            (Ok(ConditionalResponse::NotModified { headers }), Some(tile)) => {
+                if let Err(e) = cache.revalidate(key, tile.metadata, &headers).await {
+                    log::warn!("tile cache can not be updated: {:?}", e);
+                }
+                Ok(tile.data)
+            }
+            // The server must not respond with 304 to an unconditional request
+            (Ok(ConditionalResponse::NotModified { .. }), None) => {
+                self.inner_client.fetch(url).await
+            }
+            (Err(e), Some(tile)) if e.is_transient() => {
+                log::warn!(
+                    "serving stale tile from cache, because {} failed: {:?}",
+                    url,
+                    e
+                );
+                Ok(tile.data)
+            }
+            (Err(e), _) => {
+                if let Error::NotFound(_) = e {
+                    if let Err(e) = cache.remove(key).await {
+                        log::warn!("tile can not be removed from cache: {:?}", e);
+                    }
+                }
+                Err(e)
+            }
+        }
     }
 }
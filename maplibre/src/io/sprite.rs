@@ -0,0 +1,98 @@
+//! Sprites of styles. A sprite consists of an image which contains all icons and patterns of the
+//! style, and of an index which locates each of them within the image. Only the sprite with a
+//! pixel ratio of 1 is loaded.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::io::raster::{decode_raster, RasterImage};
+use crate::io::source_client::HTTPClient;
+
+/// The location of an image within the image of a sprite in pixels
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SpriteImage {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The decoded image of a sprite together with the images it contains, keyed by their names
+#[derive(Clone)]
+pub struct Sprite {
+    pub image: RasterImage,
+    pub images: HashMap<String, SpriteImage>,
+}
+
+/// Returns the URL of the file of a sprite with the given `extension`. The `sprite` URL of a
+/// style omits the extension, which is inserted in front of a query string.
+fn sprite_url(sprite: &str, extension: &str) -> String {
+    match sprite.split_once('?') {
+        Some((path, query)) => format!("{}.{}?{}", path, extension, query),
+        None => format!("{}.{}", sprite, extension),
+    }
+}
+
+/// Fetches the index and the image of the sprite at `sprite`, which is the `sprite` URL of a
+/// style.
+pub async fn fetch_sprite<HC: HTTPClient>(http_client: &HC, sprite: &str) -> Result<Sprite, Error> {
+    let index = http_client.fetch(&sprite_url(sprite, "json")).await?;
+    let images: HashMap<String, SpriteImage> = serde_json::from_slice(&index)
+        .map_err(|e| Error::Style(format!("invalid sprite index at {}: {}", sprite, e)))?;
+
+    let image = decode_raster(&http_client.fetch(&sprite_url(sprite, "png")).await?)?;
+
+    let fits = |sprite_image: &SpriteImage| {
+        sprite_image.x + sprite_image.width <= image.width
+            && sprite_image.y + sprite_image.height <= image.height
+    };
+    if let Some((name, _)) = images.iter().find(|(_, sprite_image)| !fits(sprite_image)) {
+        return Err(Error::Decode(format!(
+            "sprite image {} exceeds the sprite at {}",
+            name, sprite
+        )));
+    }
+
+    Ok(Sprite { image, images })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{sprite_url, SpriteImage};
+
+    #[test]
+    fn test_sprite_url() {
+        assert_eq!(
+            sprite_url("https://example.com/sprite", "json"),
+            "https://example.com/sprite.json"
+        );
+        assert_eq!(
+            sprite_url("https://example.com/sprite?key=abc", "png"),
+            "https://example.com/sprite.png?key=abc"
+        );
+    }
+
+    #[test]
+    fn test_sprite_index() {
+        let index = r#"{
+            "grass": {"x": 0, "y": 16, "width": 8, "height": 4, "pixelRatio": 1},
+            "water": {"x": 8, "y": 0, "width": 16, "height": 16, "pixelRatio": 1, "sdf": false}
+        }"#;
+
+        let images: HashMap<String, SpriteImage> = serde_json::from_str(index).unwrap();
+        assert_eq!(
+            images["grass"],
+            SpriteImage {
+                x: 0,
+                y: 16,
+                width: 8,
+                height: 4
+            }
+        );
+        assert_eq!(images.len(), 2);
+    }
+}
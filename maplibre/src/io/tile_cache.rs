@@ -1,30 +1,83 @@
-use crate::coords::{Quadkey, WorldTileCoords};
+use crate::coords::{Quadkey, ViewRegion, WorldTileCoords};
 
+use crate::io::raster::DemImage;
 use crate::io::LayerTessellateMessage;
 
 use std::collections::{btree_map, BTreeMap, HashSet};
+use std::sync::Arc;
+
+/// Default memory budget of the [`TileCache`] in bytes.
+pub const DEFAULT_MAX_SIZE_BYTES: usize = 256 * 1024 * 1024;
 
 pub struct CachedTile {
     layers: Vec<LayerTessellateMessage>,
+    /// Approximate size of the layers in bytes
+    size: usize,
+    /// The generation of the cache in which the tile has been viewed the last time
+    last_viewed: u64,
 }
 
 impl CachedTile {
-    pub fn new(first_layer: LayerTessellateMessage) -> Self {
+    pub fn new(first_layer: LayerTessellateMessage, last_viewed: u64) -> Self {
         Self {
+            size: first_layer.approximate_size(),
             layers: vec![first_layer],
+            last_viewed,
         }
     }
 }
 
-#[derive(Default)]
+/// Statistics about the contents of a [`TileCache`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TileCacheStats {
+    pub tiles: usize,
+    pub layers: usize,
+    /// Approximate size of the cached layers in bytes
+    pub size_bytes: usize,
+    pub max_size_bytes: usize,
+    /// Number of tiles which have been evicted since the cache has been created
+    pub evicted_tiles: u64,
+    pub evicted_bytes: u64,
+}
+
+/// Cache for tessellated layers on the CPU side.
+///
+/// The size of the cache is limited to a memory budget. Once the budget is exceeded, the least
+/// recently viewed tiles are evicted. Tiles in the current view, the prefetched tiles around it
+/// and their ancestors, which are drawn as fallbacks, are never evicted. Evicted tiles are
+/// requested again if they come into view.
 pub struct TileCache {
     cache: BTreeMap<Quadkey, CachedTile>,
+    max_size_bytes: usize,
+    size_bytes: usize,
+    /// Incremented each time the view is marked, see [`TileCache::mark_viewed`]. Tiles with a
+    /// `last_viewed` equal to the generation are in view.
+    generation: u64,
+    evicted_tiles: u64,
+    evicted_bytes: u64,
+}
+
+impl Default for TileCache {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TileCache {
     pub fn new() -> Self {
+        Self::with_max_size(DEFAULT_MAX_SIZE_BYTES)
+    }
+
+    /// Creates a cache which evicts tiles once the tessellated layers occupy more than
+    /// `max_size_bytes`.
+    pub fn with_max_size(max_size_bytes: usize) -> Self {
         Self {
             cache: BTreeMap::new(),
+            max_size_bytes,
+            size_bytes: 0,
+            generation: 1,
+            evicted_tiles: 0,
+            evicted_bytes: 0,
         }
     }
 
@@ -34,14 +87,20 @@ impl TileCache {
             .build_quad_key()
             .map(|key| self.cache.entry(key))
         {
+            let size = message.approximate_size();
             match entry {
                 btree_map::Entry::Vacant(entry) => {
-                    entry.insert(CachedTile::new(message));
+                    // New tiles count as recently viewed, but are not protected from eviction
+                    // until they are marked as in view
+                    entry.insert(CachedTile::new(message, self.generation - 1));
                 }
                 btree_map::Entry::Occupied(mut entry) => {
-                    entry.get_mut().layers.push(message);
+                    let cached_tile = entry.get_mut();
+                    cached_tile.layers.push(message);
+                    cached_tile.size += size;
                 }
             }
+            self.size_bytes += size;
         }
     }
 
@@ -55,15 +114,30 @@ impl TileCache {
             .map(|results| results.layers.iter())
     }
 
+    /// Returns the elevations of the raster DEM tile of the source `source_id` at `coords`.
+    pub fn get_dem(&self, coords: &WorldTileCoords, source_id: &str) -> Option<&Arc<DemImage>> {
+        self.iter_tessellated_layers_at(coords)?
+            .find_map(|layer| match layer {
+                LayerTessellateMessage::DemLayer {
+                    source_id: dem_source_id,
+                    dem,
+                    ..
+                } if dem_source_id == source_id => Some(dem),
+                _ => None,
+            })
+    }
+
     pub fn retain_missing_layer_names(
         &self,
         coords: &WorldTileCoords,
+        source_id: &str,
         layers: &mut HashSet<String>,
     ) {
         if let Some(cached_tile) = coords.build_quad_key().and_then(|key| self.cache.get(&key)) {
             let tessellated_set: HashSet<String> = cached_tile
                 .layers
                 .iter()
+                .filter(|tessellated_layer| tessellated_layer.source_id() == source_id)
                 .map(|tessellated_layer| tessellated_layer.layer_name().to_string())
                 .collect();
 
@@ -71,11 +145,17 @@ impl TileCache {
         }
     }
 
-    pub fn is_layers_missing(&self, coords: &WorldTileCoords, layers: &HashSet<String>) -> bool {
+    pub fn is_layers_missing(
+        &self,
+        coords: &WorldTileCoords,
+        source_id: &str,
+        layers: &HashSet<String>,
+    ) -> bool {
         if let Some(cached_tile) = coords.build_quad_key().and_then(|key| self.cache.get(&key)) {
             let tessellated_set: HashSet<&str> = cached_tile
                 .layers
                 .iter()
+                .filter(|tessellated_layer| tessellated_layer.source_id() == source_id)
                 .map(|tessellated_layer| tessellated_layer.layer_name())
                 .collect();
 
@@ -89,4 +169,273 @@ impl TileCache {
         }
         true
     }
+
+    /// Marks the tiles within `view_region` and their ancestors as viewed. These tiles are
+    /// protected from eviction until the view is marked again. The region should include the
+    /// prefetched tiles, such that they are not evicted before they come into view.
+    pub fn mark_viewed(&mut self, view_region: &ViewRegion) {
+        self.generation += 1;
+
+        for world_coords in view_region.iter() {
+            let mut current = Some(world_coords);
+            while let Some(coords) = current {
+                if let Some(cached_tile) = coords
+                    .build_quad_key()
+                    .and_then(|key| self.cache.get_mut(&key))
+                {
+                    if cached_tile.last_viewed == self.generation {
+                        // The ancestors have been marked already by a sibling
+                        break;
+                    }
+                    cached_tile.last_viewed = self.generation;
+                }
+                current = coords.get_parent();
+            }
+        }
+    }
+
+    /// Evicts the least recently viewed tiles until the cache fits into its memory budget. Tiles
+    /// which have been marked by the latest call to [`TileCache::mark_viewed`] are never evicted,
+    /// therefore the cache can exceed its budget if the view alone requires more memory. Returns
+    /// the number of evicted tiles.
+    pub fn evict(&mut self) -> usize {
+        if self.size_bytes <= self.max_size_bytes {
+            return 0;
+        }
+
+        let mut candidates: Vec<(u64, Quadkey)> = self
+            .cache
+            .iter()
+            .filter(|(_, cached_tile)| cached_tile.last_viewed < self.generation)
+            .map(|(key, cached_tile)| (cached_tile.last_viewed, *key))
+            .collect();
+        candidates.sort_unstable_by_key(|(last_viewed, _)| *last_viewed);
+
+        let mut evicted = 0;
+        for (_, key) in candidates {
+            if self.size_bytes <= self.max_size_bytes {
+                break;
+            }
+
+            if let Some(cached_tile) = self.cache.remove(&key) {
+                self.size_bytes -= cached_tile.size;
+                self.evicted_bytes += cached_tile.size as u64;
+                self.evicted_tiles += 1;
+                evicted += 1;
+            }
+        }
+
+        if evicted > 0 {
+            log::debug!(
+                "evicted {} tiles from the tile cache, {} bytes remaining",
+                evicted,
+                self.size_bytes
+            );
+        }
+
+        evicted
+    }
+
+    /// Removes the layers of the source `source_id` from the tiles for which `predicate` returns
+    /// true, such that they are requested again. Tiles without any remaining layers are removed.
+    /// Returns the coordinates of the tiles from which layers have been removed.
+    pub fn remove_layers<F>(&mut self, source_id: &str, predicate: F) -> Vec<WorldTileCoords>
+    where
+        F: Fn(&WorldTileCoords) -> bool,
+    {
+        let mut removed = Vec::new();
+        for cached_tile in self.cache.values_mut() {
+            let coords = match cached_tile.layers.first() {
+                Some(layer) => layer.get_coords(),
+                None => continue,
+            };
+            if !predicate(&coords) {
+                continue;
+            }
+
+            let mut removed_size = 0;
+            let layers_before = cached_tile.layers.len();
+            cached_tile.layers.retain(|layer| {
+                if layer.source_id() == source_id {
+                    removed_size += layer.approximate_size();
+                    false
+                } else {
+                    true
+                }
+            });
+
+            if cached_tile.layers.len() != layers_before {
+                cached_tile.size -= removed_size;
+                self.size_bytes -= removed_size;
+                removed.push(coords);
+            }
+        }
+
+        self.cache
+            .retain(|_, cached_tile| !cached_tile.layers.is_empty());
+
+        removed
+    }
+
+    pub fn stats(&self) -> TileCacheStats {
+        TileCacheStats {
+            tiles: self.cache.len(),
+            layers: self
+                .cache
+                .values()
+                .map(|cached_tile| cached_tile.layers.len())
+                .sum(),
+            size_bytes: self.size_bytes,
+            max_size_bytes: self.max_size_bytes,
+            evicted_tiles: self.evicted_tiles,
+            evicted_bytes: self.evicted_bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point2;
+
+    use crate::coords::{ViewRegion, WorldTileCoords, Zoom, TILE_SIZE};
+    use crate::io::tile_cache::TileCache;
+    use crate::io::LayerTessellateMessage;
+    use crate::util::math::Aabb2;
+
+    fn layer(coords: WorldTileCoords) -> LayerTessellateMessage {
+        LayerTessellateMessage::UnavailableLayer {
+            source_id: "source".to_string(),
+            style_layer_id: None,
+            coords,
+            layer_name: "layer".to_string(),
+        }
+    }
+
+    /// A view region which contains only the tile WT(x=0,y=0,z=2).
+    fn view_region() -> ViewRegion {
+        let tile_size = TILE_SIZE / 4.0;
+        ViewRegion::new(
+            Aabb2::new(
+                Point2::new(tile_size * 0.25, tile_size * 0.25),
+                Point2::new(tile_size * 0.75, tile_size * 0.75),
+            ),
+            0,
+            Zoom::default(),
+            2,
+        )
+    }
+
+    #[test]
+    fn test_evicts_least_recently_viewed() {
+        let layer_size = layer((0, 0, 2).into()).approximate_size();
+        let mut cache = TileCache::with_max_size(layer_size * 3);
+
+        // The tile in view and its parent
+        cache.put_tessellated_layer(layer((0, 0, 2).into()));
+        cache.put_tessellated_layer(layer((0, 0, 1).into()));
+        cache.put_tessellated_layer(layer((3, 3, 2).into()));
+        cache.mark_viewed(&view_region());
+        cache.put_tessellated_layer(layer((2, 3, 2).into()));
+        cache.put_tessellated_layer(layer((1, 3, 2).into()));
+        assert_eq!(cache.evict(), 2);
+
+        let stats = cache.stats();
+        assert_eq!(stats.tiles, 3);
+        assert_eq!(stats.size_bytes, layer_size * 3);
+        assert_eq!(stats.evicted_tiles, 2);
+        assert!(cache
+            .iter_tessellated_layers_at(&(0, 0, 2).into())
+            .is_some());
+        assert!(cache
+            .iter_tessellated_layers_at(&(0, 0, 1).into())
+            .is_some());
+        // The tile which has never been viewed is evicted first
+        assert!(cache
+            .iter_tessellated_layers_at(&(3, 3, 2).into())
+            .is_none());
+    }
+
+    #[test]
+    fn test_keeps_prefetched_tiles() {
+        let layer_size = layer((0, 0, 2).into()).approximate_size();
+        // The budget is smaller than the tile in view together with the ring around it
+        let mut cache = TileCache::with_max_size(layer_size * 2);
+
+        let tile_size = TILE_SIZE / 4.0;
+        let prefetch_region = ViewRegion::new(
+            Aabb2::new(
+                Point2::new(tile_size * 1.25, tile_size * 1.25),
+                Point2::new(tile_size * 1.75, tile_size * 1.75),
+            ),
+            1,
+            Zoom::default(),
+            2,
+        );
+
+        // The tile in view WT(x=1,y=1,z=2) and two tiles of the ring around it
+        cache.put_tessellated_layer(layer((1, 1, 2).into()));
+        cache.put_tessellated_layer(layer((0, 0, 2).into()));
+        cache.put_tessellated_layer(layer((2, 2, 2).into()));
+        cache.put_tessellated_layer(layer((3, 3, 2).into()));
+        cache.mark_viewed(&prefetch_region);
+        assert_eq!(cache.evict(), 1);
+
+        for coords in [(1, 1, 2), (0, 0, 2), (2, 2, 2)] {
+            assert!(cache.iter_tessellated_layers_at(&coords.into()).is_some());
+        }
+        assert!(cache
+            .iter_tessellated_layers_at(&(3, 3, 2).into())
+            .is_none());
+    }
+
+    #[test]
+    fn test_remove_layers() {
+        let layer_size = layer((0, 0, 2).into()).approximate_size();
+        let mut cache = TileCache::new();
+        cache.put_tessellated_layer(layer((0, 0, 2).into()));
+        cache.put_tessellated_layer(LayerTessellateMessage::UnavailableLayer {
+            source_id: "other".to_string(),
+            style_layer_id: None,
+            coords: (0, 0, 2).into(),
+            layer_name: "layer".to_string(),
+        });
+        cache.put_tessellated_layer(layer((1, 0, 2).into()));
+        cache.put_tessellated_layer(layer((3, 3, 2).into()));
+
+        let removed = cache.remove_layers("source", |coords| coords.x < 2);
+        assert_eq!(removed.len(), 2);
+
+        let stats = cache.stats();
+        assert_eq!(stats.tiles, 2);
+        assert_eq!(stats.layers, 2);
+        assert_eq!(stats.size_bytes, layer_size * 2);
+        // The layer of the other source stays cached
+        assert!(cache.is_layers_missing(
+            &(0, 0, 2).into(),
+            "source",
+            &["layer".to_string()].into_iter().collect()
+        ));
+        assert!(!cache.is_layers_missing(
+            &(0, 0, 2).into(),
+            "other",
+            &["layer".to_string()].into_iter().collect()
+        ));
+        assert!(cache
+            .iter_tessellated_layers_at(&(1, 0, 2).into())
+            .is_none());
+    }
+
+    #[test]
+    fn test_never_evicts_tiles_in_view() {
+        let mut cache = TileCache::with_max_size(0);
+        cache.put_tessellated_layer(layer((0, 0, 2).into()));
+        cache.put_tessellated_layer(layer((1, 0, 2).into()));
+        cache.mark_viewed(&view_region());
+
+        assert_eq!(cache.evict(), 1);
+        assert!(cache
+            .iter_tessellated_layers_at(&(0, 0, 2).into())
+            .is_some());
+        assert_eq!(cache.stats().tiles, 1);
+    }
 }
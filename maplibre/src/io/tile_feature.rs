@@ -0,0 +1,112 @@
+//! Access to the properties of the features of vector tiles, against which the filters of style
+//! layers are evaluated.
+
+use geozero::mvt::tile;
+
+use crate::io::feature_id::FeatureId;
+use crate::style::expression::{FeatureProperties, PropertyValue};
+use crate::style::filter::Filter;
+
+impl From<&tile::Value> for PropertyValue {
+    fn from(value: &tile::Value) -> Self {
+        if let Some(value) = &value.string_value {
+            PropertyValue::String(value.clone())
+        } else if let Some(value) = value.double_value {
+            PropertyValue::Number(value)
+        } else if let Some(value) = value.float_value {
+            PropertyValue::Number(value as f64)
+        } else if let Some(value) = value.int_value.or(value.sint_value) {
+            PropertyValue::Number(value as f64)
+        } else if let Some(value) = value.uint_value {
+            PropertyValue::Number(value as f64)
+        } else if let Some(value) = value.bool_value {
+            PropertyValue::Bool(value)
+        } else {
+            PropertyValue::Null
+        }
+    }
+}
+
+/// A feature of a layer of a vector tile
+pub struct TileFeature<'a> {
+    layer: &'a tile::Layer,
+    feature: &'a tile::Feature,
+    id: Option<&'a FeatureId>,
+}
+
+impl<'a> TileFeature<'a> {
+    pub fn new(
+        layer: &'a tile::Layer,
+        feature: &'a tile::Feature,
+        id: Option<&'a FeatureId>,
+    ) -> Self {
+        Self { layer, feature, id }
+    }
+}
+
+impl FeatureProperties for TileFeature<'_> {
+    fn geometry_type(&self) -> PropertyValue {
+        match self.feature.r#type() {
+            tile::GeomType::Point => PropertyValue::String("Point".to_string()),
+            tile::GeomType::Linestring => PropertyValue::String("LineString".to_string()),
+            tile::GeomType::Polygon => PropertyValue::String("Polygon".to_string()),
+            tile::GeomType::Unknown => PropertyValue::Null,
+        }
+    }
+
+    fn id(&self) -> PropertyValue {
+        match self.id {
+            Some(FeatureId::Number(id)) => PropertyValue::Number(*id as f64),
+            Some(FeatureId::String(id)) => PropertyValue::String(id.clone()),
+            None => PropertyValue::Null,
+        }
+    }
+
+    fn property(&self, key: &str) -> PropertyValue {
+        let key_index = match self
+            .layer
+            .keys
+            .iter()
+            .position(|layer_key| layer_key == key)
+        {
+            Some(key_index) => key_index as u32,
+            None => return PropertyValue::Null,
+        };
+
+        self.feature
+            .tags
+            .chunks_exact(2)
+            .find(|tag| tag[0] == key_index)
+            .and_then(|tag| self.layer.values.get(tag[1] as usize))
+            .map_or(PropertyValue::Null, PropertyValue::from)
+    }
+}
+
+/// Returns a copy of `layer` which only contains the features for which `filter` holds at the
+/// zoom level `zoom`, together with the ids of these features. `feature_ids` holds the ids of all
+/// features of `layer`.
+pub fn filter_layer(
+    layer: &tile::Layer,
+    feature_ids: &[Option<FeatureId>],
+    filter: &Filter,
+    zoom: f64,
+) -> (tile::Layer, Vec<Option<FeatureId>>) {
+    let mut filtered_layer = tile::Layer {
+        version: layer.version,
+        name: layer.name.clone(),
+        features: Vec::new(),
+        keys: layer.keys.clone(),
+        values: layer.values.clone(),
+        extent: layer.extent,
+    };
+    let mut filtered_ids = Vec::new();
+
+    for (feature, id) in layer.features.iter().zip(feature_ids) {
+        if filter.evaluate(zoom, &TileFeature::new(layer, feature, id.as_ref())) {
+            filtered_layer.features.push(feature.clone());
+            filtered_ids.push(id.clone());
+        }
+    }
+
+    (filtered_layer, filtered_ids)
+}
@@ -0,0 +1,242 @@
+//! Queue which decides in which order tile requests are scheduled.
+
+use crate::coords::{WorldCoords, WorldTileCoords, Zoom, TILE_SIZE};
+use crate::io::TileRequest;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// Default maximum number of tile requests which are in flight at the same time.
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 16;
+
+/// Policy which decides which tiles are loaded in addition to the tiles in view.
+#[derive(Clone, Debug)]
+pub struct PrefetchPolicy {
+    /// Number of tiles around the view which are loaded, such that panning reveals tiles which
+    /// are loaded already
+    pub padding: i32,
+    /// Number of zoom levels above the view for which ancestors of the tiles in view are loaded.
+    /// These are rendered while tiles are loading and when zooming out.
+    pub ancestor_levels: u8,
+}
+
+impl Default for PrefetchPolicy {
+    fn default() -> Self {
+        Self {
+            padding: 1,
+            ancestor_levels: 2,
+        }
+    }
+}
+
+impl PrefetchPolicy {
+    /// Loads only the tiles in view.
+    pub fn disabled() -> Self {
+        Self {
+            padding: 0,
+            ancestor_levels: 0,
+        }
+    }
+}
+
+/// Priority of a queued tile request. Requests with a lower priority value are scheduled first.
+///
+/// Tiles of the zoom level which is requested for the view come first. Ancestors which serve as
+/// fallbacks follow. Within the same zoom level, tiles closer to the center of the view are
+/// preferred. Prefetched tiles outside of the view are scheduled last.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileRequestPriority {
+    prefetch: bool,
+    zoom_distance: u8,
+    center_distance: f64,
+}
+
+impl TileRequestPriority {
+    /// Calculates the priority of the tile at `coords`. `target_z` is the zoom level of the tiles
+    /// which are requested for the view and `center` is the center of the view on the ground.
+    pub fn new(coords: &WorldTileCoords, target_z: u8, center: &WorldCoords, zoom: Zoom) -> Self {
+        let tile_scale = zoom.scale_to_zoom_level(coords.z) / TILE_SIZE;
+        let dx = coords.x as f64 + 0.5 - center.x * tile_scale;
+        let dy = coords.y as f64 + 0.5 - center.y * tile_scale;
+
+        Self {
+            prefetch: false,
+            zoom_distance: target_z.abs_diff(coords.z),
+            center_distance: (dx * dx + dy * dy).sqrt(),
+        }
+    }
+
+    /// Lowers the priority for tiles which are not in view.
+    pub fn prefetched(mut self) -> Self {
+        self.prefetch = true;
+        self
+    }
+}
+
+impl Eq for TileRequestPriority {}
+
+impl PartialOrd for TileRequestPriority {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TileRequestPriority {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.prefetch
+            .cmp(&other.prefetch)
+            .then_with(|| self.zoom_distance.cmp(&other.zoom_distance))
+            .then_with(|| {
+                self.center_distance
+                    .partial_cmp(&other.center_distance)
+                    .unwrap_or(Ordering::Equal)
+            })
+    }
+}
+
+struct QueuedTileRequest {
+    priority: TileRequestPriority,
+    tile_request: TileRequest,
+}
+
+impl PartialEq for QueuedTileRequest {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority
+    }
+}
+
+impl Eq for QueuedTileRequest {}
+
+impl PartialOrd for QueuedTileRequest {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedTileRequest {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max-heap, the lowest priority value should be popped first
+        other.priority.cmp(&self.priority)
+    }
+}
+
+/// Priority queue of tile requests which have not been scheduled yet. The queue is refilled
+/// whenever the view changes. Requests are popped as long as fewer than `max_in_flight` requests
+/// are pending.
+pub struct TileRequestQueue {
+    queue: BinaryHeap<QueuedTileRequest>,
+    max_in_flight: usize,
+}
+
+impl Default for TileRequestQueue {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_IN_FLIGHT)
+    }
+}
+
+impl TileRequestQueue {
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            queue: BinaryHeap::new(),
+            max_in_flight,
+        }
+    }
+
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight
+    }
+
+    pub fn push(&mut self, tile_request: TileRequest, priority: TileRequestPriority) {
+        self.queue.push(QueuedTileRequest {
+            priority,
+            tile_request,
+        });
+    }
+
+    /// Pops the request with the highest priority, if fewer than `max_in_flight` requests are
+    /// `in_flight`.
+    pub fn pop(&mut self, in_flight: usize) -> Option<(TileRequest, TileRequestPriority)> {
+        if in_flight >= self.max_in_flight {
+            return None;
+        }
+
+        self.queue
+            .pop()
+            .map(|queued| (queued.tile_request, queued.priority))
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::coords::{WorldCoords, WorldTileCoords, Zoom, TILE_SIZE};
+    use crate::io::tile_request_queue::{TileRequestPriority, TileRequestQueue};
+    use crate::io::TileRequest;
+
+    fn push(queue: &mut TileRequestQueue, coords: WorldTileCoords, target_z: u8) {
+        // The center of the tile WT(x=2,y=2,z=2)
+        let center = WorldCoords::at_ground(TILE_SIZE * 2.5 / 4.0, TILE_SIZE * 2.5 / 4.0);
+        queue.push(
+            TileRequest {
+                source_id: "source".to_string(),
+                coords,
+                layers: HashSet::new(),
+            },
+            TileRequestPriority::new(&coords, target_z, &center, Zoom::default()),
+        );
+    }
+
+    #[test]
+    fn test_order() {
+        let mut queue = TileRequestQueue::new(3);
+        push(&mut queue, (0, 0, 2).into(), 2);
+        push(&mut queue, (1, 1, 1).into(), 2);
+        push(&mut queue, (2, 2, 2).into(), 2);
+        push(&mut queue, (1, 2, 2).into(), 2);
+
+        let order: Vec<WorldTileCoords> = std::iter::from_fn(|| queue.pop(0))
+            .map(|(tile_request, _)| tile_request.coords)
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (2, 2, 2).into(),
+                (1, 2, 2).into(),
+                (0, 0, 2).into(),
+                (1, 1, 1).into()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prefetched_last() {
+        let center = WorldCoords::at_ground(0.0, 0.0);
+        let in_view = TileRequestPriority::new(&(3, 3, 2).into(), 2, &center, Zoom::default());
+        let prefetched =
+            TileRequestPriority::new(&(0, 0, 2).into(), 2, &center, Zoom::default()).prefetched();
+        assert!(in_view < prefetched);
+    }
+
+    #[test]
+    fn test_max_in_flight() {
+        let mut queue = TileRequestQueue::new(2);
+        push(&mut queue, (0, 0, 2).into(), 2);
+
+        assert!(queue.pop(2).is_none());
+        assert_eq!(queue.len(), 1);
+        assert!(queue.pop(1).is_some());
+        assert!(queue.is_empty());
+    }
+}
@@ -1,33 +1,131 @@
 use crate::coords::WorldTileCoords;
+use crate::error::Error;
 use crate::io::{TileRequest, TileRequestID};
+use instant::{Duration, Instant};
 use std::collections::{HashMap, HashSet};
 
+/// Policy for retrying tile requests which failed because of transient errors like timeouts or
+/// server errors. Between the attempts the backoff grows exponentially.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the time to wait after the failed attempt `attempts`.
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+}
+
+/// Outcome of a failed tile request.
+pub enum TileRequestFailure {
+    /// The request can be started again after the backoff
+    Retry { attempts: u32, backoff: Duration },
+    /// The request is not retried, because the error is permanent or the retries are exhausted
+    GiveUp(TileRequest),
+}
+
+struct FailedTileRequest {
+    attempts: u32,
+    retry_at: Instant,
+}
+
 #[derive(Default)]
 pub struct TileRequestState {
     current_id: TileRequestID,
     pending_tile_requests: HashMap<TileRequestID, TileRequest>,
-    pending_coords: HashSet<WorldTileCoords>,
+    pending_coords: HashSet<(String, WorldTileCoords)>,
+    retry_policy: RetryPolicy,
+    failed_requests: HashMap<(String, WorldTileCoords), FailedTileRequest>,
+    /// The earliest time at which a failed request can be retried, unless the main thread has
+    /// been notified about it already
+    next_retry_at: Option<Instant>,
 }
 
 impl TileRequestState {
     pub fn new() -> Self {
+        Self::with_retry_policy(RetryPolicy::default())
+    }
+
+    pub fn with_retry_policy(retry_policy: RetryPolicy) -> Self {
         Self {
             current_id: 1,
             pending_tile_requests: Default::default(),
             pending_coords: Default::default(),
+            retry_policy,
+            failed_requests: Default::default(),
+            next_retry_at: None,
         }
     }
 
-    pub fn is_tile_request_pending(&self, coords: &WorldTileCoords) -> bool {
-        self.pending_coords.contains(coords)
+    pub fn is_tile_request_pending(&self, source_id: &str, coords: &WorldTileCoords) -> bool {
+        self.pending_coords
+            .contains(&(source_id.to_string(), *coords))
+    }
+
+    /// Returns whether the request with `id` has been started and has been neither finished nor
+    /// cancelled.
+    pub fn is_request_id_pending(&self, id: TileRequestID) -> bool {
+        self.pending_tile_requests.contains_key(&id)
+    }
+
+    /// Number of requests which have been started and have been neither finished nor cancelled
+    pub fn pending_count(&self) -> usize {
+        self.pending_tile_requests.len()
+    }
+
+    /// Returns whether a previous request for the tile failed and the backoff has not elapsed yet.
+    pub fn is_backing_off(&self, source_id: &str, coords: &WorldTileCoords) -> bool {
+        self.failed_requests
+            .get(&(source_id.to_string(), *coords))
+            .map_or(false, |failed| failed.retry_at > Instant::now())
+    }
+
+    /// Returns whether the backoff of a failed request elapsed since the previous call. The main
+    /// thread requests the tiles in view again in that case, even if the view did not change.
+    /// Retries which wait for a free slot afterwards do not cause further notifications.
+    pub fn take_due_retries(&mut self) -> bool {
+        let now = Instant::now();
+        match self.next_retry_at {
+            Some(next_retry_at) if next_retry_at <= now => {
+                self.next_retry_at = self
+                    .failed_requests
+                    .values()
+                    .map(|failed| failed.retry_at)
+                    .filter(|retry_at| *retry_at > now)
+                    .min();
+                true
+            }
+            _ => false,
+        }
     }
 
     pub fn start_tile_request(&mut self, tile_request: TileRequest) -> Option<TileRequestID> {
-        if self.is_tile_request_pending(&tile_request.coords) {
+        if self.is_tile_request_pending(&tile_request.source_id, &tile_request.coords)
+            || self.is_backing_off(&tile_request.source_id, &tile_request.coords)
+        {
             return None;
         }
 
-        self.pending_coords.insert(tile_request.coords);
+        self.pending_coords
+            .insert((tile_request.source_id.clone(), tile_request.coords));
         let id = self.current_id;
         self.pending_tile_requests.insert(id, tile_request);
         self.current_id += 1;
@@ -36,12 +134,173 @@ impl TileRequestState {
 
     pub fn finish_tile_request(&mut self, id: TileRequestID) -> Option<TileRequest> {
         self.pending_tile_requests.remove(&id).map(|request| {
-            self.pending_coords.remove(&request.coords);
+            let key = (request.source_id.clone(), request.coords);
+            self.failed_requests.remove(&key);
+            self.pending_coords.remove(&key);
             request
         })
     }
 
+    /// Finishes the request with `id` after it failed with `error`. Transient errors are retried
+    /// according to the [`RetryPolicy`]. Returns `None` if the request is not pending anymore.
+    pub fn fail_tile_request(
+        &mut self,
+        id: TileRequestID,
+        error: &Error,
+    ) -> Option<TileRequestFailure> {
+        let request = self.pending_tile_requests.remove(&id)?;
+        let key = (request.source_id.clone(), request.coords);
+        self.pending_coords.remove(&key);
+
+        let attempts = self
+            .failed_requests
+            .get(&key)
+            .map_or(1, |failed| failed.attempts + 1);
+
+        if !error.is_transient() || attempts >= self.retry_policy.max_attempts {
+            self.failed_requests.remove(&key);
+            return Some(TileRequestFailure::GiveUp(request));
+        }
+
+        let backoff = self.retry_policy.backoff(attempts);
+        let retry_at = Instant::now() + backoff;
+        self.failed_requests
+            .insert(key, FailedTileRequest { attempts, retry_at });
+        self.next_retry_at = Some(
+            self.next_retry_at
+                .map_or(retry_at, |next_retry_at| next_retry_at.min(retry_at)),
+        );
+        Some(TileRequestFailure::Retry { attempts, backoff })
+    }
+
+    /// Cancels all pending requests and scheduled retries for which `should_cancel` returns true.
+    /// Workers skip cancelled requests. Returns the number of cancelled requests.
+    pub fn cancel_tile_requests<F>(&mut self, should_cancel: F) -> usize
+    where
+        F: Fn(&str, &WorldTileCoords) -> bool,
+    {
+        let cancelled: Vec<TileRequestID> = self
+            .pending_tile_requests
+            .iter()
+            .filter(|(_, request)| should_cancel(&request.source_id, &request.coords))
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in &cancelled {
+            if let Some(request) = self.pending_tile_requests.remove(id) {
+                self.pending_coords
+                    .remove(&(request.source_id, request.coords));
+            }
+        }
+
+        self.failed_requests
+            .retain(|(source_id, coords), _| !should_cancel(source_id, coords));
+
+        cancelled.len()
+    }
+
     pub fn get_tile_request(&self, id: TileRequestID) -> Option<&TileRequest> {
         self.pending_tile_requests.get(&id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::coords::WorldTileCoords;
+    use crate::error::Error;
+    use crate::io::tile_request_state::{RetryPolicy, TileRequestFailure, TileRequestState};
+    use crate::io::TileRequest;
+    use instant::Duration;
+
+    fn tile_request(coords: WorldTileCoords) -> TileRequest {
+        TileRequest {
+            source_id: "source".to_string(),
+            coords,
+            layers: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(4), Duration::from_millis(500));
+        assert_eq!(policy.backoff(100), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_retry_transient_errors() {
+        let mut state = TileRequestState::with_retry_policy(RetryPolicy {
+            max_attempts: 2,
+            initial_backoff: Duration::from_secs(60),
+            max_backoff: Duration::from_secs(60),
+        });
+        let coords: WorldTileCoords = (1, 2, 3).into();
+
+        let id = state.start_tile_request(tile_request(coords)).unwrap();
+        assert!(matches!(
+            state.fail_tile_request(id, &Error::Network("timeout".to_string())),
+            Some(TileRequestFailure::Retry { attempts: 1, .. })
+        ));
+        assert!(state.is_backing_off("source", &coords));
+        assert!(state.start_tile_request(tile_request(coords)).is_none());
+
+        let mut state = TileRequestState::new();
+        let id = state.start_tile_request(tile_request(coords)).unwrap();
+        assert!(matches!(
+            state.fail_tile_request(id, &Error::NotFound("404".to_string())),
+            Some(TileRequestFailure::GiveUp(_))
+        ));
+        assert!(!state.is_backing_off("source", &coords));
+    }
+
+    #[test]
+    fn test_due_retries() {
+        let mut state = TileRequestState::with_retry_policy(RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        });
+        let coords: WorldTileCoords = (1, 2, 3).into();
+        assert!(!state.take_due_retries());
+
+        // The failed request is due without any change of the view
+        let id = state.start_tile_request(tile_request(coords)).unwrap();
+        state.fail_tile_request(id, &Error::Network("timeout".to_string()));
+        assert!(state.take_due_retries());
+        // The main thread is notified only once, even if the request is not started again yet
+        assert!(!state.take_due_retries());
+
+        let id = state.start_tile_request(tile_request(coords)).unwrap();
+        state.fail_tile_request(id, &Error::Network("timeout".to_string()));
+        assert!(state.take_due_retries());
+
+        let id = state.start_tile_request(tile_request(coords)).unwrap();
+        state.finish_tile_request(id);
+        assert!(!state.take_due_retries());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut state = TileRequestState::new();
+        let id = state
+            .start_tile_request(tile_request((1, 2, 3).into()))
+            .unwrap();
+        let other_id = state
+            .start_tile_request(tile_request((2, 2, 3).into()))
+            .unwrap();
+
+        assert_eq!(state.cancel_tile_requests(|_, coords| coords.x == 1), 1);
+        assert!(!state.is_request_id_pending(id));
+        assert!(state.is_request_id_pending(other_id));
+        assert!(state.finish_tile_request(id).is_none());
+    }
+}
@@ -0,0 +1,183 @@
+//! Expansion of tile URL templates like `https://{a-c}.example.com/{z}/{x}/{y}.pbf`.
+
+use crate::coords::{TileCoords, WorldTileCoords};
+use crate::style::source::{TileAddressingScheme, TileUrl};
+
+/// A list of tile URL templates as found in the `tiles` property of a source or in a TileJSON.
+///
+/// The following placeholders are supported:
+/// * `{x}`, `{y}` and `{z}`: The tile coordinates according to the addressing scheme of the source
+/// * `{quadkey}`: The [Bing Maps quadkey](https://docs.microsoft.com/en-us/bingmaps/articles/bing-maps-tile-system)
+///   of the tile
+/// * `{prefix}`: Two hex characters derived from `x` and `y` which are used by some tile hosts to
+///   shard tiles
+/// * `{a-c}` or `{1-4}`: Subdomain ranges which are expanded to one template per subdomain
+///
+/// If there are multiple templates, a template is chosen for each tile such that requests are
+/// distributed across all of them. The same tile always maps to the same URL, which keeps HTTP
+/// caches effective.
+#[derive(Clone, Debug)]
+pub struct TileUrlTemplates {
+    templates: Vec<TileUrl>,
+}
+
+impl TileUrlTemplates {
+    pub fn new(templates: &[TileUrl]) -> Self {
+        Self {
+            templates: templates
+                .iter()
+                .flat_map(|template| expand_subdomains(template))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+
+    pub fn templates(&self) -> &[TileUrl] {
+        &self.templates
+    }
+
+    /// Returns the URL of the tile at `coords`. Returns `None` if the tile exceeds its bounds or
+    /// if there are no templates.
+    pub fn expand(
+        &self,
+        coords: &WorldTileCoords,
+        scheme: &TileAddressingScheme,
+    ) -> Option<String> {
+        if self.templates.is_empty() {
+            return None;
+        }
+
+        let xyz_coords = coords.into_tile(TileAddressingScheme::XYZ)?;
+        let tile_coords = coords.into_tile(scheme.clone())?;
+
+        let template =
+            &self.templates[(xyz_coords.x as usize + xyz_coords.y as usize) % self.templates.len()];
+
+        Some(
+            template
+                .replace("{x}", &tile_coords.x.to_string())
+                .replace("{y}", &tile_coords.y.to_string())
+                .replace("{z}", &tile_coords.z.to_string())
+                .replace("{quadkey}", &quadkey(&xyz_coords))
+                .replace(
+                    "{prefix}",
+                    &format!("{:x}{:x}", xyz_coords.x % 16, xyz_coords.y % 16),
+                ),
+        )
+    }
+}
+
+/// Builds the quadkey string for XYZ tile coordinates. The first character describes the
+/// quadrant at zoom level 1.
+fn quadkey(coords: &TileCoords) -> String {
+    (1..=coords.z)
+        .rev()
+        .map(|z| {
+            let mask = 1 << (z - 1);
+            let mut digit = 0;
+            if coords.x & mask != 0 {
+                digit += 1;
+            }
+            if coords.y & mask != 0 {
+                digit += 2;
+            }
+            char::from(b'0' + digit)
+        })
+        .collect()
+}
+
+/// Expands the first subdomain range like `{a-c}` or `{1-3}` within the template.
+fn expand_subdomains(template: &str) -> Vec<TileUrl> {
+    let mut offset = 0;
+    while let Some(start) = template[offset..].find('{').map(|start| offset + start) {
+        let end = match template[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+
+        if let [from, b'-', to] = template[start + 1..end].as_bytes() {
+            if from < to {
+                return (*from..=*to)
+                    .map(|subdomain| {
+                        format!(
+                            "{}{}{}",
+                            &template[..start],
+                            char::from(subdomain),
+                            &template[end + 1..]
+                        )
+                    })
+                    .collect();
+            }
+        }
+
+        offset = end + 1;
+    }
+
+    vec![template.to_string()]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coords::WorldTileCoords;
+    use crate::style::source::TileAddressingScheme;
+
+    use super::TileUrlTemplates;
+
+    #[test]
+    fn test_expand_xyz_and_tms() {
+        let templates = TileUrlTemplates::new(&["https://example.com/{z}/{x}/{y}.pbf".to_string()]);
+        let coords: WorldTileCoords = (1, 0, 1).into();
+
+        assert_eq!(
+            templates.expand(&coords, &TileAddressingScheme::XYZ),
+            Some("https://example.com/1/1/0.pbf".to_string())
+        );
+        assert_eq!(
+            templates.expand(&coords, &TileAddressingScheme::TMS),
+            Some("https://example.com/1/1/1.pbf".to_string())
+        );
+        assert_eq!(
+            templates.expand(&(2, 0, 1).into(), &TileAddressingScheme::XYZ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_expand_quadkey_and_prefix() {
+        let templates =
+            TileUrlTemplates::new(&["https://example.com/{prefix}/{quadkey}".to_string()]);
+
+        // Example from https://docs.microsoft.com/en-us/bingmaps/articles/bing-maps-tile-system
+        assert_eq!(
+            templates.expand(&(3, 5, 3).into(), &TileAddressingScheme::XYZ),
+            Some("https://example.com/35/213".to_string())
+        );
+        assert_eq!(
+            templates.expand(&(17, 2, 5).into(), &TileAddressingScheme::XYZ),
+            Some("https://example.com/12/10021".to_string())
+        );
+    }
+
+    #[test]
+    fn test_subdomain_rotation() {
+        let templates =
+            TileUrlTemplates::new(&["https://{a-c}.example.com/{z}/{x}/{y}.pbf".to_string()]);
+
+        assert_eq!(templates.templates().len(), 3);
+        assert_eq!(
+            templates.expand(&(0, 0, 1).into(), &TileAddressingScheme::XYZ),
+            Some("https://a.example.com/1/0/0.pbf".to_string())
+        );
+        assert_eq!(
+            templates.expand(&(1, 0, 1).into(), &TileAddressingScheme::XYZ),
+            Some("https://b.example.com/1/1/0.pbf".to_string())
+        );
+        assert_eq!(
+            templates.expand(&(1, 1, 1).into(), &TileAddressingScheme::XYZ),
+            Some("https://c.example.com/1/1/1.pbf".to_string())
+        );
+    }
+}
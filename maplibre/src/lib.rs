@@ -1,16 +1,28 @@
+use crate::error::Error;
+use crate::io::persistent_cache::{CacheBackend, CachePolicy, PersistentTileCache};
+use crate::io::pmtiles_source_client::{PmtilesSourceClient, PMTILES_URL_PREFIX};
 use crate::io::scheduler::{ScheduleMethod, Scheduler};
-use crate::io::source_client::HTTPClient;
-use crate::map_state::MapState;
+use crate::io::source_client::{
+    fetch_tilejson, HTTPClient, SourceClient, FILE_URL_PREFIX, MBTILES_URL_PREFIX,
+};
+use crate::io::sprite::{fetch_sprite, Sprite};
+use crate::io::tile_cache::DEFAULT_MAX_SIZE_BYTES;
+use crate::io::tile_request_queue::PrefetchPolicy;
+use crate::map_state::{MapState, MapStateOptions};
 use crate::render::render_state::RenderState;
+use crate::style::source::{RasterDemSource, Source};
 use crate::style::Style;
 use crate::window::{MapWindow, MapWindowConfig, Runnable, WindowSize};
+use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 pub mod coords;
 pub mod error;
 pub mod io;
 pub mod platform;
 pub mod style;
+pub mod tilejson;
 pub mod window;
 
 // Used for benchmarking
@@ -20,7 +32,6 @@ pub mod benchmarking;
 pub mod map_state;
 pub mod render;
 pub(crate) mod tessellation;
-pub(crate) mod tilejson;
 pub(crate) mod util;
 
 pub struct Map<W, SM, HC>
@@ -50,6 +61,16 @@ where
     pub fn run_with_optionally_max_frames(self, max_frames: Option<u64>) {
         self.window.run(self.map_state, max_frames);
     }
+
+    /// Replaces the GeoJSON of the `geojson` source `source_id`. See
+    /// [`MapState::set_source_data`].
+    pub fn set_source_data(
+        &mut self,
+        source_id: &str,
+        geojson: serde_json::Value,
+    ) -> Result<(), Error> {
+        self.map_state.set_source_data(source_id, geojson)
+    }
 }
 
 pub struct UninitializedMap<MWC, SM, HC>
@@ -60,7 +81,11 @@ where
 {
     scheduler: Scheduler<SM>,
     http_client: HC,
+    source_clients: HashMap<String, SourceClient<HC>>,
     style: Style,
+    prefetch_policy: PrefetchPolicy,
+    tile_cache: Option<(Arc<dyn CacheBackend>, CachePolicy)>,
+    memory_cache_size: usize,
 
     map_window_config: MWC,
 }
@@ -71,7 +96,166 @@ where
     SM: ScheduleMethod,
     HC: HTTPClient,
 {
-    pub async fn initialize(self) -> Map<MWC::MapWindow, SM, HC> {
+    /// Fetches the TileJSON of each source which references one via `url` and merges it into the
+    /// source. For PMTiles archives the header is read instead. This happens before any tiles are
+    /// requested, such that tile requests can rely on `tiles`, `minzoom`, `maxzoom`, `bounds` and
+    /// `scheme`.
+    async fn resolve_tilejson_sources(&mut self) {
+        for (source_id, source) in self.style.sources.iter_mut() {
+            let vector_source = match source {
+                Source::Vector(vector_source)
+                | Source::Raster(vector_source)
+                | Source::RasterDem(RasterDemSource {
+                    source: vector_source,
+                    ..
+                }) => vector_source,
+                Source::GeoJson(_) => continue,
+            };
+
+            let url = match &vector_source.url {
+                Some(url) if vector_source.tiles.is_none() => url.clone(),
+                _ => continue,
+            };
+
+            // MBTiles files and tile directories are referenced like TileJSONs, but the tiles are
+            // read from the filesystem
+            if url.starts_with(MBTILES_URL_PREFIX) || url.starts_with(FILE_URL_PREFIX) {
+                vector_source.tiles = Some(vec![url]);
+                continue;
+            }
+
+            // The header of PMTiles archives replaces the TileJSON. The client is kept, such that
+            // the header is not read again.
+            if url.starts_with(PMTILES_URL_PREFIX) {
+                vector_source.tiles = Some(vec![url.clone()]);
+
+                let client = match PmtilesSourceClient::from_url(&url, self.http_client.clone()) {
+                    Ok(client) => client,
+                    Err(e) => {
+                        log::error!(
+                            "PMTiles archive of source {} at {} can not be opened: {:?}",
+                            source_id,
+                            url,
+                            e
+                        );
+                        continue;
+                    }
+                };
+
+                match client.header().await {
+                    Ok(header) => {
+                        vector_source.minzoom = vector_source.minzoom.or(Some(header.min_zoom));
+                        vector_source.maxzoom = vector_source.maxzoom.or(Some(header.max_zoom));
+                        vector_source.bounds = vector_source.bounds.or(Some(header.bounds));
+                    }
+                    Err(e) => log::error!(
+                        "PMTiles header of source {} at {} can not be loaded: {:?}",
+                        source_id,
+                        url,
+                        e
+                    ),
+                }
+
+                self.source_clients
+                    .entry(source_id.clone())
+                    .or_insert(SourceClient::Pmtiles(client));
+                continue;
+            }
+
+            match fetch_tilejson(&self.http_client, &url).await {
+                Ok(tilejson) => vector_source.merge_tilejson(tilejson),
+                Err(e) => log::error!(
+                    "TileJSON of source {} at {} can not be loaded: {:?}",
+                    source_id,
+                    url,
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Creates a [`SourceClient`] for each source of the style, unless a client has been
+    /// provided explicitly via [`MapBuilder::with_source_client`]. Sources for which no client can
+    /// be created are skipped.
+    ///
+    /// The zoom range of MBTiles sources and tile directories is read from their metadata, unless
+    /// it is set in the style.
+    fn create_source_clients(
+        &mut self,
+        tile_cache: Option<&PersistentTileCache>,
+    ) -> HashMap<String, SourceClient<HC>> {
+        let mut source_clients = self.source_clients.clone();
+
+        for (source_id, source) in self.style.sources.iter_mut() {
+            if source_clients.contains_key(source_id) {
+                continue;
+            }
+
+            match SourceClient::from_source(source, self.http_client.clone()) {
+                Ok(client) => {
+                    let (minzoom, maxzoom) = client.zoom_range();
+                    if let Source::Vector(vector_source)
+                    | Source::Raster(vector_source)
+                    | Source::RasterDem(RasterDemSource {
+                        source: vector_source,
+                        ..
+                    }) = source
+                    {
+                        vector_source.minzoom = vector_source.minzoom.or(minzoom);
+                        vector_source.maxzoom = vector_source.maxzoom.or(maxzoom);
+                    }
+
+                    source_clients.insert(source_id.clone(), client);
+                }
+                Err(e) => log::error!("source {} can not be loaded: {:?}", source_id, e),
+            }
+        }
+
+        if let Some(tile_cache) = tile_cache {
+            source_clients = source_clients
+                .into_iter()
+                .map(|(source_id, client)| {
+                    let client = client.with_cache(&source_id, tile_cache.clone());
+                    (source_id, client)
+                })
+                .collect();
+        }
+
+        source_clients
+    }
+
+    /// Fetches the sprite of the style, if the style references one. The map works without the
+    /// sprite, but fill layers are drawn without their patterns.
+    async fn load_sprite(&self) -> Option<Sprite> {
+        let url = self.style.sprite.as_ref()?;
+        match fetch_sprite(&self.http_client, url).await {
+            Ok(sprite) => Some(sprite),
+            Err(e) => {
+                log::error!("sprite at {} can not be loaded: {:?}", url, e);
+                None
+            }
+        }
+    }
+
+    /// Opens the persistent tile cache, if one is configured. The map works without a cache if
+    /// the cache can not be opened.
+    async fn open_tile_cache(&mut self) -> Option<PersistentTileCache> {
+        let (backend, policy) = self.tile_cache.take()?;
+        match PersistentTileCache::open(backend, policy).await {
+            Ok(tile_cache) => Some(tile_cache),
+            Err(e) => {
+                log::error!("tile cache can not be opened: {:?}", e);
+                None
+            }
+        }
+    }
+
+    pub async fn initialize(mut self) -> Map<MWC::MapWindow, SM, HC> {
+        self.resolve_tilejson_sources().await;
+        let sprite = self.load_sprite().await;
+        let tile_cache = self.open_tile_cache().await;
+        let source_clients = self.create_source_clients(tile_cache.as_ref());
+
         let instance = wgpu::Instance::new(wgpu::Backends::all());
         //let instance = wgpu::Instance::new(wgpu::Backends::GL);
         //let instance = wgpu::Instance::new(wgpu::Backends::VULKAN);
@@ -96,8 +280,13 @@ where
                 window_size,
                 render_state,
                 self.scheduler,
-                self.http_client,
+                source_clients,
                 self.style,
+                MapStateOptions {
+                    sprite,
+                    prefetch_policy: self.prefetch_policy,
+                    tile_cache_size: self.memory_cache_size,
+                },
             ),
             window,
         }
@@ -107,11 +296,16 @@ where
 pub struct MapBuilder<MWC, SM, HC>
 where
     SM: ScheduleMethod,
+    HC: HTTPClient,
 {
     schedule_method: Option<SM>,
     scheduler: Option<Scheduler<SM>>,
     http_client: Option<HC>,
+    source_clients: HashMap<String, SourceClient<HC>>,
     style: Option<Style>,
+    prefetch_policy: Option<PrefetchPolicy>,
+    tile_cache: Option<(Arc<dyn CacheBackend>, CachePolicy)>,
+    memory_cache_size: Option<usize>,
 
     map_window_config: Option<MWC>,
 }
@@ -127,7 +321,11 @@ where
             schedule_method: None,
             scheduler: None,
             http_client: None,
+            source_clients: HashMap::new(),
             style: None,
+            prefetch_policy: None,
+            tile_cache: None,
+            memory_cache_size: None,
             map_window_config: None,
         }
     }
@@ -147,6 +345,14 @@ where
         self
     }
 
+    /// Overrides the client which is used to fetch tiles of the source `source_id`. By default
+    /// a client is created from the `tiles` of the source in the style.
+    pub fn with_source_client(mut self, source_id: &str, source_client: SourceClient<HC>) -> Self {
+        self.source_clients
+            .insert(source_id.to_string(), source_client);
+        self
+    }
+
     pub fn with_existing_scheduler(mut self, scheduler: Scheduler<SM>) -> Self {
         self.scheduler = Some(scheduler);
         self
@@ -157,6 +363,27 @@ where
         self
     }
 
+    /// Configures which tiles are loaded in addition to the tiles in view. By default a ring of
+    /// tiles around the view and ancestors of the tiles in view are loaded.
+    pub fn with_prefetch_policy(mut self, prefetch_policy: PrefetchPolicy) -> Self {
+        self.prefetch_policy = Some(prefetch_policy);
+        self
+    }
+
+    /// Stores tiles which are fetched via HTTP persistently in the `backend`, such that they
+    /// survive restarts. The `policy` limits the size of the cache.
+    pub fn with_tile_cache(mut self, backend: Arc<dyn CacheBackend>, policy: CachePolicy) -> Self {
+        self.tile_cache = Some((backend, policy));
+        self
+    }
+
+    /// Limits the memory in bytes which is used by tessellated tiles on the CPU side. The least
+    /// recently viewed tiles are evicted once the limit is exceeded.
+    pub fn with_memory_cache_size(mut self, max_size_bytes: usize) -> Self {
+        self.memory_cache_size = Some(max_size_bytes);
+        self
+    }
+
     pub fn build(self) -> UninitializedMap<MWC, SM, HC> {
         let scheduler = self
             .scheduler
@@ -166,7 +393,11 @@ where
         UninitializedMap {
             scheduler,
             http_client: self.http_client.unwrap(),
+            source_clients: self.source_clients,
             style,
+            prefetch_policy: self.prefetch_policy.unwrap_or_default(),
+            tile_cache: self.tile_cache,
+            memory_cache_size: self.memory_cache_size.unwrap_or(DEFAULT_MAX_SIZE_BYTES),
             map_window_config: self.map_window_config.unwrap(),
         }
     }
@@ -1,19 +1,27 @@
-use crate::coords::{ViewRegion, WorldTileCoords, Zoom, TILE_SIZE};
+use crate::coords::{TileRange, ViewRegion, WorldCoords, WorldTileCoords, Zoom, TILE_SIZE};
 use crate::error::Error;
 use crate::io::geometry_index::GeometryIndex;
 use crate::io::scheduler::Scheduler;
 use crate::io::shared_thread_state::SharedThreadState;
-use crate::io::source_client::{HTTPClient, HttpSourceClient, SourceClient};
-use crate::io::tile_cache::TileCache;
+use crate::io::source_client::{HTTPClient, SourceClient};
+use crate::io::sprite::Sprite;
+use crate::io::tile_cache::{TileCache, TileCacheStats};
+use crate::io::tile_request_queue::{PrefetchPolicy, TileRequestPriority, TileRequestQueue};
 use crate::io::tile_request_state::TileRequestState;
-use crate::io::{TessellateMessage, TileRequest, TileTessellateMessage};
+use crate::io::{LayerTessellateMessage, TessellateMessage, TileRequest, TileTessellateMessage};
 use crate::render::camera;
-use crate::render::camera::{Camera, Perspective, ViewProjection};
+use crate::render::camera::{Camera, InvertedViewProjection, Perspective, ViewProjection};
 use crate::render::render_state::RenderState;
+use crate::render::terrain::{world_units_per_meter, TerrainElevation};
+use crate::style::layer::{StyleLayer, RASTER_SOURCE_LAYER};
+use crate::style::source::Source;
 use crate::style::Style;
+use crate::util::math::Aabb2;
 use crate::util::ChangeObserver;
 use crate::{MapWindow, MapWindowConfig, ScheduleMethod, WindowSize};
-use std::collections::HashSet;
+use cgmath::{Vector2, Vector3};
+use std::cmp;
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use std::sync::{mpsc, Arc, Mutex};
 
@@ -21,6 +29,8 @@ pub struct ViewState {
     zoom: ChangeObserver<Zoom>,
     pub camera: ChangeObserver<Camera>,
     pub perspective: Perspective,
+    /// Elevations of the terrain, if the style displaces the geometry by the terrain
+    terrain: Option<TerrainElevation>,
 }
 
 impl ViewState {
@@ -40,6 +50,56 @@ impl ViewState {
         *self.zoom = new_zoom;
         log::info!("zoom: {}", new_zoom);
     }
+
+    /// Gets the world coordinates for the specified `window` coordinates. If the style has a
+    /// terrain, the coordinates are on the terrain, otherwise on the ground.
+    pub fn window_to_world(
+        &self,
+        window: &Vector2<f64>,
+        inverted_view_proj: &InvertedViewProjection,
+    ) -> Option<Vector3<f64>> {
+        let terrain = match &self.terrain {
+            Some(terrain) => terrain,
+            None => {
+                return self
+                    .camera
+                    .window_to_world_at_ground(window, inverted_view_proj)
+            }
+        };
+
+        let zoom = self.zoom();
+        self.camera.window_to_world_at_terrain(
+            window,
+            inverted_view_proj,
+            self.elevation_range(),
+            |x, y| {
+                terrain.elevation(&WorldCoords::at_ground(x, y), zoom)
+                    * world_units_per_meter(y, zoom)
+            },
+        )
+    }
+
+    /// The lowest and highest elevation of the terrain in world coordinates. The elevations are
+    /// scaled for the latitude of the camera.
+    fn elevation_range(&self) -> (f64, f64) {
+        match &self.terrain {
+            Some(terrain) => {
+                let (min, max) = terrain.elevation_range();
+                let scale = world_units_per_meter(self.camera.position.y, self.zoom());
+                (min * scale, max * scale)
+            }
+            None => (0.0, 0.0),
+        }
+    }
+}
+
+/// Options of the [`MapState`] which are resolved while the map is initialized.
+pub struct MapStateOptions {
+    /// The sprite of the style, if the style references one
+    pub sprite: Option<Sprite>,
+    pub prefetch_policy: PrefetchPolicy,
+    /// Maximum size of the tile cache in bytes
+    pub tile_cache_size: usize,
 }
 
 pub struct MapState<MWC, SM, HC>
@@ -58,11 +118,19 @@ where
     shared_thread_state: SharedThreadState,
     tile_cache: TileCache,
 
-    source_client: SourceClient<HC>,
+    /// Clients which fetch tiles for each source of the style, keyed by source id
+    source_clients: HashMap<String, SourceClient<HC>>,
 
     style: Style,
-
-    try_failed: bool,
+    /// The sprite of the style, which is uploaded again if the render state is recreated
+    sprite: Option<Sprite>,
+
+    /// Tile requests which are waiting to be scheduled
+    tile_request_queue: TileRequestQueue,
+    prefetch_policy: PrefetchPolicy,
+    /// Set if tiles have been invalidated, such that they are requested again even though the
+    /// view did not change
+    tiles_invalidated: bool,
 }
 
 impl<MWC, SM, HC> MapState<MWC, SM, HC>
@@ -74,11 +142,18 @@ where
     pub fn new(
         map_window_config: MWC,
         window_size: WindowSize,
-        render_state: Option<RenderState>,
+        mut render_state: Option<RenderState>,
         scheduler: Scheduler<SM>,
-        http_client: HC,
+        source_clients: HashMap<String, SourceClient<HC>>,
         style: Style,
+        options: MapStateOptions,
     ) -> Self {
+        let MapStateOptions {
+            sprite,
+            prefetch_policy,
+            tile_cache_size,
+        } = options;
+
         let camera = camera::Camera::new(
             (TILE_SIZE / 2.0, TILE_SIZE / 2.0, 150.0),
             cgmath::Deg(-90.0),
@@ -97,18 +172,23 @@ where
 
         let (message_sender, message_receiver) = mpsc::channel();
 
+        if let (Some(render_state), Some(sprite)) = (render_state.as_mut(), sprite.as_ref()) {
+            render_state.upload_sprite(sprite);
+        }
+
         Self {
             map_window_config,
             view_state: ViewState {
                 zoom: ChangeObserver::default(),
                 camera: ChangeObserver::new(camera),
                 perspective,
+                terrain: Self::create_terrain(&style),
             },
 
             render_state,
             scheduler,
 
-            tile_cache: TileCache::new(),
+            tile_cache: TileCache::with_max_size(tile_cache_size),
             message_receiver,
             shared_thread_state: SharedThreadState {
                 tile_request_state: Arc::new(Mutex::new(TileRequestState::new())),
@@ -117,9 +197,28 @@ where
             },
 
             style,
+            sprite,
 
-            try_failed: false,
-            source_client: SourceClient::Http(HttpSourceClient::new(http_client)),
+            tile_request_queue: TileRequestQueue::default(),
+            prefetch_policy,
+            source_clients,
+            tiles_invalidated: false,
+        }
+    }
+
+    /// Creates the terrain of the `style`. The terrain is ignored if its source is not a
+    /// `raster-dem` source.
+    fn create_terrain(style: &Style) -> Option<TerrainElevation> {
+        let terrain = style.terrain.as_ref()?;
+        match style.sources.get(&terrain.source) {
+            Some(Source::RasterDem(_)) => Some(TerrainElevation::new(terrain)),
+            _ => {
+                log::warn!(
+                    "terrain is ignored, because {} is not a raster-dem source",
+                    terrain.source
+                );
+                None
+            }
         }
     }
 
@@ -149,6 +248,21 @@ where
                         layer_result.layer_name(),
                         layer_result.get_coords()
                     );
+
+                    if let (
+                        Some(terrain),
+                        LayerTessellateMessage::DemLayer {
+                            source_id,
+                            coords,
+                            dem,
+                        },
+                    ) = (&mut self.view_state.terrain, &layer_result)
+                    {
+                        if terrain.source_id() == source_id {
+                            terrain.insert(*coords, dem.clone());
+                        }
+                    }
+
                     self.tile_cache.put_tessellated_layer(layer_result);
                 }
                 TessellateMessage::Tile(TileTessellateMessage { request_id, coords }) => loop {
@@ -164,24 +278,169 @@ where
         }
     }
 
-    /// Request tiles which are currently in view
+    /// Queues requests for the tiles which are currently in view, ordered by their distance to
+    /// `center`. Additionally, tiles within `prefetch_region` and ancestors of the tiles in view
+    /// are requested according to the [`PrefetchPolicy`]. Requests for tiles which are not
+    /// required anymore are cancelled.
     #[tracing::instrument(skip_all)]
-    fn request_tiles_in_view(&mut self, view_region: &ViewRegion) -> bool {
-        let mut try_failed = false;
-        let source_layers: HashSet<String> = self
-            .style
-            .layers
-            .iter()
-            .filter_map(|layer| layer.source_layer.clone())
-            .collect();
+    fn request_tiles_in_view(
+        &mut self,
+        view_region: &ViewRegion,
+        prefetch_region: &ViewRegion,
+        center: &WorldCoords,
+    ) {
+        // Group the requested source layers by the source they belong to
+        let mut source_layers: HashMap<String, HashSet<String>> = HashMap::new();
+        for layer in &self.style.layers {
+            if let (Some(source), Some(source_layer)) = (&layer.source, layer.source_layer_name()) {
+                source_layers
+                    .entry(source.clone())
+                    .or_default()
+                    .insert(source_layer.to_string());
+            }
+        }
+        // The terrain is loaded even if no layer shows its source
+        if let Some(terrain) = &self.view_state.terrain {
+            source_layers
+                .entry(terrain.source_id().to_string())
+                .or_default()
+                .insert(RASTER_SOURCE_LAYER.to_string());
+        }
+
+        // The queue is rebuilt, because priorities depend on the center of the view
+        self.tile_request_queue.clear();
+
+        let zoom = self.view_state.zoom();
+        let prefetch_policy = self.prefetch_policy.clone();
+        let mut required_coords: HashMap<&str, BTreeSet<WorldTileCoords>> = HashMap::new();
+        for (source_id, layers) in &source_layers {
+            if !self.source_clients.contains_key(source_id) {
+                continue;
+            }
+
+            let (minzoom, maxzoom, bounds) = match self.style.sources.get(source_id) {
+                Some(source) => (source.minzoom(), source.maxzoom(), source.bounds()),
+                None => continue,
+            };
+
+            let z = view_region.zoom_level();
+            if z < minzoom {
+                continue;
+            }
+
+            // Above the maximum zoom level of the source the tiles of the maximum zoom level are
+            // requested. These are overzoomed during rendering.
+            let request_z = cmp::min(z, maxzoom);
+            // Tiles outside of the bounds of the source are not available
+            let in_bounds = |coords: &WorldTileCoords| {
+                bounds.map_or(true, |bounds| {
+                    TileRange::from_lng_lat_bounds(bounds, coords.z).contains(coords)
+                })
+            };
+            let tiles_at = |region: &ViewRegion, z: u8| -> BTreeSet<WorldTileCoords> {
+                region
+                    .iter()
+                    .filter_map(|coords| coords.get_ancestor(z))
+                    .filter(|coords| in_bounds(coords))
+                    .collect()
+            };
+
+            let visible = tiles_at(view_region, request_z);
+
+            // Ancestors serve as fallbacks while tiles are loading and when zooming out
+            let mut ancestors = BTreeSet::new();
+            for level in 1..=prefetch_policy.ancestor_levels {
+                match request_z.checked_sub(level) {
+                    Some(ancestor_z) if ancestor_z >= minzoom => {
+                        ancestors.extend(tiles_at(view_region, ancestor_z))
+                    }
+                    _ => break,
+                }
+            }
+
+            // The ring of tiles around the view is loaded, such that panning reveals tiles which
+            // are tessellated already
+            let ring: BTreeSet<WorldTileCoords> = tiles_at(prefetch_region, request_z)
+                .difference(&visible)
+                .cloned()
+                .collect();
+
+            let coords_to_request = visible
+                .iter()
+                .chain(ancestors.iter())
+                .map(|coords| (coords, false))
+                .chain(ring.iter().map(|coords| (coords, true)));
+
+            for (coords, is_prefetch) in coords_to_request {
+                if coords.build_quad_key().is_none()
+                    || !self.tile_cache.is_layers_missing(coords, source_id, layers)
+                {
+                    continue;
+                }
+
+                let priority = TileRequestPriority::new(coords, request_z, center, zoom);
+                self.tile_request_queue.push(
+                    TileRequest {
+                        source_id: source_id.clone(),
+                        coords: *coords,
+                        layers: layers.clone(),
+                    },
+                    if is_prefetch {
+                        priority.prefetched()
+                    } else {
+                        priority
+                    },
+                );
+            }
+
+            let mut required = visible;
+            required.extend(ancestors);
+            required.extend(ring);
+            required_coords.insert(source_id.as_str(), required);
+        }
+
+        // Requests for tiles which are not required anymore are cancelled, such that panning does
+        // not queue up stale requests
+        if let Ok(mut tile_request_state) = self.shared_thread_state.tile_request_state.try_lock() {
+            let cancelled = tile_request_state.cancel_tile_requests(|source_id, coords| {
+                !required_coords
+                    .get(source_id)
+                    .map_or(false, |required| required.contains(coords))
+            });
+
+            if cancelled > 0 {
+                tracing::info!("cancelled {} tile requests", cancelled);
+            }
+        }
+    }
+
+    /// Schedules queued tile requests with the highest priority, as long as the maximum number of
+    /// requests in flight is not reached.
+    #[tracing::instrument(skip_all)]
+    fn schedule_queued_tile_requests(&mut self) {
+        if self.tile_request_queue.is_empty() {
+            return;
+        }
 
-        for coords in view_region.iter() {
-            if coords.build_quad_key().is_some() {
-                // TODO: Make tesselation depend on style?
-                try_failed = self.try_request_tile(&coords, &source_layers).unwrap();
+        let tile_request_state = self.shared_thread_state.tile_request_state.clone();
+        let mut tile_request_state = match tile_request_state.try_lock() {
+            Ok(tile_request_state) => tile_request_state,
+            // Try again during the next frame
+            Err(_) => return,
+        };
+
+        // Requests which failed recently are skipped. The queue is rebuilt by `prepare_render`
+        // once their backoff elapsed, also if they fail after they have been popped.
+        while let Some((tile_request, _)) = self
+            .tile_request_queue
+            .pop(tile_request_state.pending_count())
+        {
+            if tile_request_state.is_backing_off(&tile_request.source_id, &tile_request.coords) {
+                continue;
             }
+
+            self.try_request_tile(&mut tile_request_state, tile_request);
         }
-        try_failed
     }
 
     #[tracing::instrument(skip_all)]
@@ -193,100 +452,187 @@ where
 
         let view_proj = self.view_state.view_projection();
 
-        let view_region = self
+        let bounding_box = self
             .view_state
             .camera
-            .view_region_bounding_box(&view_proj.invert())
-            .map(|bounding_box| {
-                ViewRegion::new(bounding_box, 0, *self.view_state.zoom, visible_level)
-            });
+            .view_region_bounding_box(&view_proj.invert(), self.view_state.elevation_range());
+        let view_region = bounding_box.as_ref().map(|bounding_box| {
+            ViewRegion::new(
+                Aabb2::new(bounding_box.min, bounding_box.max),
+                0,
+                *self.view_state.zoom,
+                visible_level,
+            )
+        });
+        // The ring of tiles around the view which is prefetched
+        let prefetch_region = bounding_box.map(|bounding_box| {
+            ViewRegion::new(
+                bounding_box,
+                self.prefetch_policy.padding,
+                *self.view_state.zoom,
+                visible_level,
+            )
+        });
 
         drop(_guard);
 
         if let Some(view_region) = &view_region {
+            let zoom = self.view_state.zoom();
             self.render_state
                 .as_mut()
                 .expect("render state not yet initialized. Call reinitialize().")
-                .upload_tile_geometry(view_region, &self.style, &self.tile_cache);
+                .upload_tile_geometry(view_region, zoom, &self.style, &self.tile_cache);
 
-            let zoom = self.view_state.zoom();
             self.render_state_mut()
                 .update_tile_view_pattern(view_region, &view_proj, zoom);
 
-            self.render_state_mut().update_metadata();
+            self.render_state
+                .as_mut()
+                .expect("render state not yet initialized. Call reinitialize().")
+                .update_metadata(zoom, &self.tile_cache);
+
+            // Prefetched tiles are protected as well, otherwise they could be evicted right
+            // after they have been loaded
+            if let Some(prefetch_region) = &prefetch_region {
+                self.tile_cache.mark_viewed(prefetch_region);
+            }
+            self.tile_cache.evict();
+
+            let tile_cache = &self.tile_cache;
+            if let Some(terrain) = &mut self.view_state.terrain {
+                let source_id = terrain.source_id().to_string();
+                terrain.retain(|coords| tile_cache.get_dem(coords, &source_id).is_some());
+            }
         }
 
         // TODO: Could we draw inspiration from StagingBelt (https://docs.rs/wgpu/latest/wgpu/util/struct.StagingBelt.html)?
         // TODO: What is StagingBelt for?
 
-        if self.view_state.camera.did_change(0.05)
+        // Failed requests are retried once their backoff elapsed, even if the view is unchanged.
+        // The tiles are requested again only once for each elapsed backoff.
+        if let Ok(mut tile_request_state) = self.shared_thread_state.tile_request_state.try_lock() {
+            if tile_request_state.take_due_retries() {
+                self.tiles_invalidated = true;
+            }
+        }
+
+        if self.tiles_invalidated
+            || self.view_state.camera.did_change(0.05)
             || self.view_state.zoom.did_change(0.05)
-            || self.try_failed
         {
-            if let Some(view_region) = &view_region {
-                // FIXME: We also need to request tiles from layers above if we are over the maximum zoom level
-                self.try_failed = self.request_tiles_in_view(view_region);
+            if let (Some(view_region), Some(prefetch_region)) = (&view_region, &prefetch_region) {
+                // Invalidated tiles are requested as soon as the view region is known
+                self.tiles_invalidated = false;
+
+                let center = self.view_center(&view_proj);
+                self.request_tiles_in_view(view_region, prefetch_region, &center);
             }
 
             self.render_state()
                 .update_globals(&view_proj, &self.view_state.camera);
         }
 
+        self.schedule_queued_tile_requests();
+
         self.view_state.camera.update_reference();
         self.view_state.zoom.update_reference();
     }
 
+    /// Returns the point on the ground at the center of the window. Falls back to the position of
+    /// the camera if the center of the window does not show the ground.
+    fn view_center(&self, view_proj: &ViewProjection) -> WorldCoords {
+        let camera = &self.view_state.camera;
+        camera
+            .window_to_world_at_ground(
+                &Vector2::new(camera.width / 2.0, camera.height / 2.0),
+                &view_proj.invert(),
+            )
+            .map(|center| WorldCoords::at_ground(center.x, center.y))
+            .unwrap_or_else(|| WorldCoords::at_ground(camera.position.x, camera.position.y))
+    }
+
     fn try_request_tile(
         &mut self,
-        coords: &WorldTileCoords,
-        layers: &HashSet<String>,
-    ) -> Result<bool, Error> {
-        if !self.tile_cache.is_layers_missing(coords, layers) {
-            return Ok(false);
+        tile_request_state: &mut TileRequestState,
+        tile_request: TileRequest,
+    ) {
+        let source_id = tile_request.source_id.clone();
+        let coords = tile_request.coords;
+
+        if !self
+            .tile_cache
+            .is_layers_missing(&coords, &source_id, &tile_request.layers)
+        {
+            return;
         }
 
-        if let Ok(mut tile_request_state) = self.shared_thread_state.tile_request_state.try_lock() {
-            if let Some(request_id) = tile_request_state.start_tile_request(TileRequest {
-                coords: *coords,
-                layers: layers.clone(),
-            }) {
-                tracing::info!("new tile request: {}", &coords);
-
-                // The following snippet can be added instead of the next code block to demonstrate
-                // an understanable approach of fetching
-                /*#[cfg(target_arch = "wasm32")]
-                if let Some(tile_coords) = coords.into_tile(TileAddressingScheme::TMS) {
-                    crate::platform::legacy_webworker_fetcher::request_tile(
-                        request_id,
-                        tile_coords,
-                    );
-                }*/
-
-                let client = self.source_client.clone();
-                let coords = *coords;
-
-                self.scheduler
-                    .schedule_method()
-                    .schedule(
-                        self.shared_thread_state.clone(),
-                        move |state: SharedThreadState| async move {
-                            match client.fetch(&coords).await {
-                                Ok(data) => state
-                                    .process_tile(request_id, data.into_boxed_slice())
-                                    .unwrap(),
-                                Err(e) => {
-                                    log::error!("{:?}", &e);
-                                    state.tile_unavailable(&coords, request_id).unwrap()
-                                }
-                            }
-                        },
-                    )
-                    .unwrap();
-            }
+        let client = match self.source_clients.get(&source_id) {
+            Some(client) => client.clone(),
+            None => return,
+        };
+        let source = self.style.sources.get(&source_id);
+        let is_raster = matches!(source, Some(Source::Raster(_)));
+        let dem_encoding = match source {
+            Some(Source::RasterDem(source)) => Some(source.encoding.unwrap_or_default()),
+            _ => None,
+        };
+        let promote_id = source.and_then(Source::promote_id).cloned();
+        // The layers of the source are tessellated on the worker, each with its own filter
+        let style_layers: Vec<StyleLayer> = self
+            .style
+            .layers
+            .iter()
+            .filter(|layer| layer.source.as_ref() == Some(&source_id))
+            .cloned()
+            .collect();
 
-            Ok(false)
-        } else {
-            Ok(true)
+        if let Some(request_id) = tile_request_state.start_tile_request(tile_request) {
+            tracing::info!("new tile request: {} from {}", &coords, source_id);
+
+            // The following snippet can be added instead of the next code block to demonstrate
+            // an understanable approach of fetching
+            /*#[cfg(target_arch = "wasm32")]
+            if let Some(tile_coords) = coords.into_tile(TileAddressingScheme::TMS) {
+                crate::platform::legacy_webworker_fetcher::request_tile(
+                    request_id,
+                    tile_coords,
+                );
+            }*/
+
+            self.scheduler
+                .schedule_method()
+                .schedule(
+                    self.shared_thread_state.clone(),
+                    move |state: SharedThreadState| async move {
+                        // The tile might have left the view before the request started
+                        if !state.is_tile_request_pending(request_id) {
+                            return;
+                        }
+
+                        let result = match (client.fetch(&coords).await, dem_encoding) {
+                            (Ok(data), Some(encoding)) => state.process_dem_tile(
+                                request_id,
+                                data.into_boxed_slice(),
+                                encoding,
+                            ),
+                            (Ok(data), None) if is_raster => {
+                                state.process_raster_tile(request_id, data.into_boxed_slice())
+                            }
+                            (Ok(data), None) => state.process_tile(
+                                request_id,
+                                data.into_boxed_slice(),
+                                promote_id.as_ref(),
+                                &style_layers,
+                            ),
+                            (Err(e), _) => Err(e),
+                        };
+
+                        if let Err(e) = result {
+                            state.tile_failed(request_id, &e).unwrap();
+                        }
+                    },
+                )
+                .unwrap();
         }
     }
 
@@ -297,6 +643,81 @@ where
         self.render_state_mut().resize(width, height)
     }
 
+    /// Replaces the GeoJSON of the `geojson` source `source_id` without reloading the map. Only
+    /// the tiles which contain features of the previous or the new data are invalidated. These are
+    /// tessellated again once they are in view.
+    ///
+    /// Returns an error if the source does not exist, is not a GeoJSON source or if the GeoJSON
+    /// is invalid. The source keeps its previous data in that case.
+    pub fn set_source_data(
+        &mut self,
+        source_id: &str,
+        geojson: serde_json::Value,
+    ) -> Result<(), Error> {
+        let client = match self.source_clients.get(source_id) {
+            Some(SourceClient::GeoJson(client)) => client,
+            Some(_) => {
+                return Err(Error::Style(format!(
+                    "source {} is not a GeoJSON source",
+                    source_id
+                )))
+            }
+            None => return Err(Error::Style(format!("source {} does not exist", source_id))),
+        };
+
+        let (previous, index) = client.set_data(geojson)?;
+        // If the previous data has never been indexed, all tiles of the source might be outdated
+        let affected = |coords: &WorldTileCoords| {
+            previous
+                .as_ref()
+                .map_or(true, |previous| previous.intersects(coords))
+                || index.intersects(coords)
+        };
+
+        let tile_request_state = self.shared_thread_state.tile_request_state.clone();
+        let mut tile_request_state = tile_request_state.lock().map_err(|_| Error::Schedule)?;
+
+        // Workers only send messages of pending requests while holding the lock. Therefore, no
+        // message of a cancelled request arrives after the channel has been drained.
+        tile_request_state.cancel_tile_requests(|request_source_id, coords| {
+            request_source_id == source_id && affected(coords)
+        });
+        for message in self.message_receiver.try_iter() {
+            match message {
+                TessellateMessage::Layer(layer_result) => {
+                    self.tile_cache.put_tessellated_layer(layer_result)
+                }
+                TessellateMessage::Tile(TileTessellateMessage { request_id, .. }) => {
+                    tile_request_state.finish_tile_request(request_id);
+                }
+            }
+        }
+
+        let removed = self.tile_cache.remove_layers(source_id, affected);
+        if let Ok(mut geometry_index) = self.shared_thread_state.geometry_index.lock() {
+            for coords in &removed {
+                geometry_index.remove_tile(source_id, coords);
+            }
+        }
+        if let Some(render_state) = &mut self.render_state {
+            render_state.remove_layers(source_id, affected);
+        }
+
+        tracing::info!(
+            "invalidated {} tiles of the GeoJSON source {}",
+            removed.len(),
+            source_id
+        );
+        self.tiles_invalidated = true;
+
+        Ok(())
+    }
+
+    /// Statistics about the memory which is used by the tessellated tiles on the CPU side.
+    pub fn tile_cache_stats(&self) -> TileCacheStats {
+        self.tile_cache.stats()
+    }
+
     pub fn scheduler(&self) -> &Scheduler<SM> {
         &self.scheduler
     }
@@ -357,9 +778,12 @@ where
                 present_mode: wgpu::PresentMode::Fifo, // VSync
             };
             let _window_size = window.size();
-            let render_state = RenderState::initialize(instance, surface, surface_config)
+            let mut render_state = RenderState::initialize(instance, surface, surface_config)
                 .await
                 .unwrap();
+            if let Some(sprite) = &self.sprite {
+                render_state.upload_sprite(sprite);
+            }
             self.render_state = Some(render_state)
         }
     }
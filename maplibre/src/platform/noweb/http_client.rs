@@ -1,14 +1,15 @@
 use crate::error::Error;
+use crate::io::source_client::{CacheHeaders, ConditionalResponse};
 use crate::HTTPClient;
 use async_trait::async_trait;
+use reqwest::header::{HeaderMap, CACHE_CONTROL, ETAG, IF_NONE_MATCH, RANGE};
 use reqwest::{Client, StatusCode};
-use reqwest_middleware::ClientWithMiddleware;
-use reqwest_middleware_cache::managers::CACacheManager;
-use reqwest_middleware_cache::{Cache, CacheMode};
 
+/// HTTP client which is based on reqwest. Responses are not cached, tiles are cached by the
+/// [`PersistentTileCache`](crate::io::persistent_cache::PersistentTileCache) instead.
 #[derive(Clone)]
 pub struct ReqwestHttpClient {
-    client: ClientWithMiddleware,
+    client: Client,
 }
 impl From<reqwest::Error> for Error {
     fn from(err: reqwest::Error) -> Self {
@@ -16,44 +17,109 @@ impl From<reqwest::Error> for Error {
     }
 }
 
-impl From<reqwest_middleware::Error> for Error {
-    fn from(err: reqwest_middleware::Error) -> Self {
-        Error::Network(err.to_string())
+/// Classifies unsuccessful HTTP statuses. Only server errors, timeouts and rate limiting are
+/// considered transient.
+fn check_status(url: &str, status: StatusCode) -> Result<(), Error> {
+    let message = || format!("{} responded with {}", url, status);
+    match status {
+        StatusCode::NOT_FOUND | StatusCode::GONE | StatusCode::NO_CONTENT => {
+            Err(Error::NotFound(message()))
+        }
+        StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS => {
+            Err(Error::Network(message()))
+        }
+        status if status.is_server_error() => Err(Error::Network(message())),
+        status if status.is_client_error() => Err(Error::IO(message())),
+        _ => Ok(()),
     }
 }
 
 impl ReqwestHttpClient {
-    /// cache_path: Under which path should we cache requests.
-    pub fn new(cache_path: Option<String>) -> Self {
-        let mut builder = reqwest_middleware::ClientBuilder::new(Client::new());
-
-        if let Some(cache_path) = cache_path {
-            builder = builder.with(Cache {
-                mode: CacheMode::Default,
-                cache_manager: CACacheManager { path: cache_path },
-            });
-        }
-
+    pub fn new() -> Self {
         Self {
-            client: builder.build(),
+            client: Client::new(),
         }
     }
 }
 
+impl Default for ReqwestHttpClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn cache_headers(headers: &HeaderMap) -> CacheHeaders {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    };
+
+    CacheHeaders {
+        cache_control: header(CACHE_CONTROL),
+        etag: header(ETAG),
+    }
+}
+
 #[async_trait]
 impl HTTPClient for ReqwestHttpClient {
     async fn fetch(&self, url: &str) -> Result<Vec<u8>, Error> {
         let response = self.client.get(url).send().await?;
-        match response.error_for_status() {
-            Ok(response) => {
-                if response.status() == StatusCode::NOT_MODIFIED {
-                    log::info!("Using data from cache");
-                }
-
-                let body = response.bytes().await?;
-                Ok(Vec::from(body.as_ref()))
+        check_status(url, response.status())?;
+
+        let body = response.bytes().await?;
+        Ok(Vec::from(body.as_ref()))
+    }
+
+    async fn fetch_range(&self, url: &str, offset: u64, length: u64) -> Result<Vec<u8>, Error> {
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+
+        let response = self
+            .client
+            .get(url)
+            .header(RANGE, format!("bytes={}-{}", offset, offset + length - 1))
+            .send()
+            .await?;
+        let status = response.status();
+        check_status(url, status)?;
+
+        let body = response.bytes().await?;
+
+        match status {
+            StatusCode::PARTIAL_CONTENT => Ok(Vec::from(body.as_ref())),
+            // The server ignored the range, therefore the range is extracted from the full body
+            _ => {
+                let start = (offset as usize).min(body.len());
+                let end = (offset.saturating_add(length) as usize).min(body.len());
+                Ok(Vec::from(&body[start..end]))
             }
-            Err(e) => Err(Error::Network(e.to_string())),
         }
     }
+
+    async fn fetch_conditional(
+        &self,
+        url: &str,
+        etag: Option<&str>,
+    ) -> Result<ConditionalResponse, Error> {
+        let mut request = self.client.get(url);
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+
+        let response = request.send().await?;
+        let headers = cache_headers(response.headers());
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(ConditionalResponse::NotModified { headers });
+        }
+
+        check_status(url, response.status())?;
+        let body = response.bytes().await?;
+        Ok(ConditionalResponse::Modified {
+            data: Vec::from(body.as_ref()),
+            headers,
+        })
+    }
 }
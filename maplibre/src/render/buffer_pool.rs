@@ -133,11 +133,12 @@ impl<Q: Queue<B>, B, V: bytemuck::Pod, I: bytemuck::Pod, TM: bytemuck::Pod, FM:
         (bytes, aligned_bytes)
     }
 
+    /// Returns the ids of the style layers which are loaded at `coords`.
     pub fn get_loaded_layers_at(&self, coords: &WorldTileCoords) -> Option<HashSet<&str>> {
         self.index.get_layers(coords).map(|layers| {
             layers
                 .iter()
-                .map(|entry| entry.style_layer.source_layer.as_ref().unwrap().as_str())
+                .map(|entry| entry.style_layer.id.as_str())
                 .collect()
         })
     }
@@ -279,6 +280,15 @@ impl<Q: Queue<B>, B, V: bytemuck::Pod, I: bytemuck::Pod, TM: bytemuck::Pod, FM:
         );
     }
 
+    /// Removes the entries for which `predicate` returns true from the index. Their space in the
+    /// backing buffers is reused once the ring buffer wraps around.
+    pub fn remove_layers<F>(&mut self, mut predicate: F)
+    where
+        F: FnMut(&IndexEntry) -> bool,
+    {
+        self.index.retain(|entry| !predicate(entry));
+    }
+
     pub fn index(&self) -> &RingIndex {
         &self.index
     }
@@ -502,6 +512,28 @@ impl RingIndex {
         }
     }
 
+    /// Keeps only the entries for which `keep` returns true. The order of the remaining entries is
+    /// preserved.
+    fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&IndexEntry) -> bool,
+    {
+        let mut tree_index = std::mem::take(&mut self.tree_index);
+        let linear_index = std::mem::take(&mut self.linear_index);
+
+        // The n-th occurrence of a key in the linear index belongs to the n-th entry of the key
+        for key in linear_index {
+            if let Some(entry) = tree_index
+                .get_mut(&key)
+                .and_then(|entries| entries.pop_front())
+            {
+                if keep(&entry) {
+                    self.push_back(entry);
+                }
+            }
+        }
+    }
+
     fn push_back(&mut self, entry: IndexEntry) {
         if let Some(key) = entry.coords.build_quad_key() {
             match self.tree_index.entry(key) {
@@ -558,6 +590,46 @@ mod tests {
         vec![TestVertex::default()]
     }
 
+    #[test]
+    fn test_remove_layers() {
+        let mut pool: BufferPool<TestQueue, TestBuffer, TestVertex, u32, u32, u32> =
+            BufferPool::new(
+                BackingBufferDescriptor::new(TestBuffer { size: 128 }, 128),
+                BackingBufferDescriptor::new(TestBuffer { size: 128 }, 128),
+                BackingBufferDescriptor::new(TestBuffer { size: 128 }, 128),
+                BackingBufferDescriptor::new(TestBuffer { size: 128 }, 128),
+            );
+
+        let queue = TestQueue {};
+        let style_layer = StyleLayer::default();
+
+        let mut data24bytes = VertexBuffers::new();
+        data24bytes.vertices.append(&mut create_24byte());
+        data24bytes.indices.append(&mut vec![1, 2, 3, 4]);
+        let data24bytes_aligned = data24bytes.into();
+
+        for coords in [(0, 0, 1), (1, 0, 1), (0, 0, 1)] {
+            pool.allocate_layer_geometry(
+                &queue,
+                coords.into(),
+                style_layer.clone(),
+                &data24bytes_aligned,
+                2,
+                &[],
+            );
+        }
+
+        pool.remove_layers(|entry| entry.coords == (0, 0, 1).into());
+        assert!(!pool.index().has_tile(&(0, 0, 1).into()));
+        assert!(pool.index().has_tile(&(1, 0, 1).into()));
+        assert_eq!(pool.index().iter().flatten().count(), 1);
+        // Only the space after the remaining entry is considered free
+        assert_eq!(
+            128 - 2 * 24,
+            pool.available_space(BackingBufferType::Vertices)
+        );
+    }
+
     #[test]
     fn test_allocate() {
         let mut pool: BufferPool<TestQueue, TestBuffer, TestVertex, u32, u32, u32> =
@@ -20,6 +20,11 @@ pub const FLIP_Y: cgmath::Matrix4<f64> = cgmath::Matrix4::new(
     0.0, 0.0, 0.0, 1.0,
 );
 
+/// Number of steps in which rays are marched to find intersections with the terrain
+const TERRAIN_RAY_STEPS: usize = 64;
+/// Number of bisections which refine an intersection with the terrain
+const TERRAIN_RAY_BISECTIONS: usize = 16;
+
 #[derive(Debug)]
 pub struct ViewProjection(Matrix4<f64>);
 
@@ -243,15 +248,21 @@ impl Camera {
         window: &Vector2<f64>,
         inverted_view_proj: &InvertedViewProjection,
     ) -> Option<Vector3<f64>> {
-        let near_world =
-            self.window_to_world(&Vector3::new(window.x, window.y, 0.0), inverted_view_proj);
+        self.window_to_world_at_elevation(window, inverted_view_proj, 0.0)
+    }
 
-        let far_world =
-            self.window_to_world(&Vector3::new(window.x, window.y, 1.0), inverted_view_proj);
+    /// Gets the world coordinates for the specified `window` coordinates on the plane at the
+    /// height `z` in world coordinates.
+    pub fn window_to_world_at_elevation(
+        &self,
+        window: &Vector2<f64>,
+        inverted_view_proj: &InvertedViewProjection,
+        z: f64,
+    ) -> Option<Vector3<f64>> {
+        let (near_world, far_world) = self.window_to_world_ray(window, inverted_view_proj);
 
-        // for z = 0 in world coordinates
         // Idea comes from: https://dondi.lmu.build/share/cg/unproject-explained.pdf
-        let u = -near_world.z / (far_world.z - near_world.z);
+        let u = (z - near_world.z) / (far_world.z - near_world.z);
         if (0.0..=1.0).contains(&u) {
             Some(near_world + u * (far_world - near_world))
         } else {
@@ -259,6 +270,93 @@ impl Camera {
         }
     }
 
+    /// Gets the world coordinates for the specified `window` coordinates on the terrain. The
+    /// `elevation` closure returns the height of the terrain in world coordinates at a position
+    /// `(x, y)`. All heights are expected to be within `elevation_range`.
+    ///
+    /// The ray through the window coordinates is marched in [`TERRAIN_RAY_STEPS`] steps between
+    /// the highest and the lowest possible height. The first intersection is refined by bisection.
+    /// Peaks which are narrower than a step can be missed.
+    pub fn window_to_world_at_terrain<F>(
+        &self,
+        window: &Vector2<f64>,
+        inverted_view_proj: &InvertedViewProjection,
+        elevation_range: (f64, f64),
+        elevation: F,
+    ) -> Option<Vector3<f64>>
+    where
+        F: Fn(f64, f64) -> f64,
+    {
+        let (min_z, max_z) = elevation_range;
+        if min_z >= max_z {
+            // The terrain is flat
+            return self.window_to_world_at_elevation(window, inverted_view_proj, min_z);
+        }
+
+        let (near_world, far_world) = self.window_to_world_ray(window, inverted_view_proj);
+        let direction = far_world - near_world;
+
+        // The part of the ray between the highest and the lowest possible height
+        let u_at = |z: f64| (z - near_world.z) / direction.z;
+        let (start, end) = if direction.z < 0.0 {
+            (u_at(max_z).max(0.0), u_at(min_z).min(1.0))
+        } else {
+            // The ray does not point downwards, it can only hit the terrain if the camera is below
+            // the highest possible height
+            (0.0, if near_world.z <= max_z { 1.0 } else { 0.0 })
+        };
+        if start > end {
+            return None;
+        }
+
+        let point_at = |u: f64| near_world + u * direction;
+        let is_below = |u: f64| {
+            let point = point_at(u);
+            point.z <= elevation(point.x, point.y)
+        };
+
+        if is_below(start) {
+            return Some(point_at(start));
+        }
+
+        let step = (end - start) / TERRAIN_RAY_STEPS as f64;
+        let mut above = start;
+        for i in 1..=TERRAIN_RAY_STEPS {
+            let u = start + i as f64 * step;
+            if is_below(u) {
+                let mut below = u;
+                for _ in 0..TERRAIN_RAY_BISECTIONS {
+                    let middle = (above + below) / 2.0;
+                    if is_below(middle) {
+                        below = middle;
+                    } else {
+                        above = middle;
+                    }
+                }
+                return Some(point_at(below));
+            }
+            above = u;
+        }
+
+        None
+    }
+
+    /// Returns the points on the near and the far plane which are projected to the `window`
+    /// coordinates.
+    fn window_to_world_ray(
+        &self,
+        window: &Vector2<f64>,
+        inverted_view_proj: &InvertedViewProjection,
+    ) -> (Vector3<f64>, Vector3<f64>) {
+        let near_world =
+            self.window_to_world(&Vector3::new(window.x, window.y, 0.0), inverted_view_proj);
+
+        let far_world =
+            self.window_to_world(&Vector3::new(window.x, window.y, 1.0), inverted_view_proj);
+
+        (near_world, far_world)
+    }
+
     /// Calculates an [`Aabb2`] bounding box which contains at least the visible area on the `z=0`
     /// plane. One can think of it as being the bounding box of the geometry which forms the
     /// intersection between the viewing frustum and the `z=0` plane.
@@ -267,24 +365,37 @@ impl Camera {
     /// window to calculate intersections points with the `z=0` plane. Then a bounding box is
     /// calculated.
     ///
+    /// If the terrain is displaced, `elevation_range` contains the lowest and the highest height
+    /// of the terrain in world coordinates. The rays are intersected with the planes at both
+    /// heights, such that the bounding box contains the visible terrain. Without terrain the range
+    /// is `(0.0, 0.0)`.
+    ///
     /// *Note:* It is possible that no such bounding box exists. This is the case if the `z=0` plane
     /// is not in view.
     pub fn view_region_bounding_box(
         &self,
         inverted_view_proj: &InvertedViewProjection,
+        elevation_range: (f64, f64),
     ) -> Option<Aabb2<f64>> {
+        let (min_z, max_z) = elevation_range;
         let screen_bounding_box = [
             Vector2::new(0.0, 0.0),
             Vector2::new(self.width, 0.0),
             Vector2::new(self.width, self.height),
             Vector2::new(0.0, self.height),
         ]
-        .map(|point| self.window_to_world_at_ground(&point, inverted_view_proj));
+        .map(|point| {
+            [
+                self.window_to_world_at_elevation(&point, inverted_view_proj, min_z),
+                self.window_to_world_at_elevation(&point, inverted_view_proj, max_z),
+            ]
+        });
 
         let (min, max) = bounds_from_points(
             screen_bounding_box
                 .into_iter()
                 .flatten()
+                .flatten()
                 .map(|point| [point.x, point.y]),
         )?;
 
@@ -492,4 +603,46 @@ mod tests {
 
         //assert!(reverse_world.abs_diff_eq(&world_pos, 0.05))
     }
+
+    #[test]
+    fn test_window_to_world_at_terrain() {
+        let camera = Camera::new(
+            (0.0, 5.0, 5000.0),
+            cgmath::Deg(-90.0),
+            cgmath::Deg(45.0),
+            1920,
+            1080,
+        );
+        let perspective = Perspective::new(1920, 1080, cgmath::Deg(45.0), 0.1, 100000.0);
+        let inverted_view_proj = camera.calc_view_proj(&perspective).invert();
+        let window = Vector2::new(960.0, 631.0);
+
+        // A flat terrain is the ground plane
+        let ground = camera
+            .window_to_world_at_ground(&window, &inverted_view_proj)
+            .unwrap();
+        let flat = camera
+            .window_to_world_at_terrain(&window, &inverted_view_proj, (0.0, 0.0), |_, _| 0.0)
+            .unwrap();
+        assert!(ground.abs_diff_eq(&flat, 0.05));
+
+        // A plateau is hit before the ground
+        let plateau = camera
+            .window_to_world_at_terrain(&window, &inverted_view_proj, (0.0, 100.0), |_, _| 100.0)
+            .unwrap();
+        let expected = camera
+            .window_to_world_at_elevation(&window, &inverted_view_proj, 100.0)
+            .unwrap();
+        assert!(plateau.abs_diff_eq(&expected, 0.05));
+
+        // The visible region grows if the terrain is elevated
+        let flat_region = camera
+            .view_region_bounding_box(&inverted_view_proj, (0.0, 0.0))
+            .unwrap();
+        let elevated_region = camera
+            .view_region_bounding_box(&inverted_view_proj, (0.0, 100.0))
+            .unwrap();
+        assert!(elevated_region.min.x <= flat_region.min.x);
+        assert!(elevated_region.max.y >= flat_region.max.y);
+    }
 }
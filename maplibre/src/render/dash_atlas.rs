@@ -0,0 +1,186 @@
+//! Dash patterns of line layers. Each distinct `line-dasharray` occupies a row of a texture, which
+//! is sampled along the lines within the fragment shader.
+
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+
+use crate::render::shaders::Vec2f32;
+
+/// Count of texels of each pattern
+const DASH_ATLAS_WIDTH: u32 = 256;
+/// Maximum count of distinct patterns. Lines with further patterns are drawn solid.
+const DASH_ATLAS_HEIGHT: u32 = 64;
+
+/// Dashes are opaque and gaps are transparent
+pub const DASH_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// A texture which holds the rasterized dash patterns of all line layers
+pub struct DashAtlas {
+    bind_group_layout: wgpu::BindGroupLayout,
+    // The texture needs to be kept alive as long as the bind group is used
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    /// Rows of the patterns, keyed by the bits of the lengths of the dashes and gaps
+    rows: HashMap<Vec<u32>, u32>,
+}
+
+impl DashAtlas {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Dash atlas bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Dash atlas texture"),
+            size: wgpu::Extent3d {
+                width: DASH_ATLAS_WIDTH,
+                height: DASH_ATLAS_HEIGHT,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DASH_TEXTURE_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        // The patterns repeat along the lines, but the rows must not bleed into each other
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Dash atlas sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Dash atlas bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        Self {
+            bind_group_layout,
+            texture,
+            bind_group,
+            rows: HashMap::new(),
+        }
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Uploads the pattern `dasharray`, unless it has been uploaded already. Returns the
+    /// vertical texture coordinate of the pattern and its length in line widths, as expected by
+    /// [`ShaderLayerMetadata::dash`](crate::render::shaders::ShaderLayerMetadata). Invalid
+    /// patterns are drawn solid.
+    pub fn prepare(&mut self, queue: &wgpu::Queue, dasharray: &[f32]) -> Vec2f32 {
+        let length: f32 = dasharray.iter().sum();
+        if !length.is_finite() || length <= 0.0 || dasharray.iter().any(|dash| *dash < 0.0) {
+            return [0.0, 0.0];
+        }
+
+        let key: Vec<u32> = dasharray.iter().map(|dash| dash.to_bits()).collect();
+        let row = match self.rows.get(&key) {
+            Some(row) => *row,
+            None => {
+                let row = self.rows.len() as u32;
+                if row >= DASH_ATLAS_HEIGHT {
+                    tracing::warn!("dash atlas is full, drawing {:?} solid", dasharray);
+                    return [0.0, 0.0];
+                }
+
+                queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &self.texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d { x: 0, y: row, z: 0 },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    &dash_texels(dasharray),
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: NonZeroU32::new(DASH_ATLAS_WIDTH),
+                        rows_per_image: None,
+                    },
+                    wgpu::Extent3d {
+                        width: DASH_ATLAS_WIDTH,
+                        height: 1,
+                        depth_or_array_layers: 1,
+                    },
+                );
+                self.rows.insert(key, row);
+                row
+            }
+        };
+
+        // Patterns with an odd count of lengths are repeated, such that dashes and gaps alternate
+        let length = if dasharray.len() % 2 == 1 {
+            2.0 * length
+        } else {
+            length
+        };
+        [(row as f32 + 0.5) / DASH_ATLAS_HEIGHT as f32, length]
+    }
+}
+
+/// Rasterizes a dash pattern into a row of the atlas. Like in SVG, a pattern with an odd count of
+/// lengths is repeated.
+fn dash_texels(dasharray: &[f32]) -> Vec<u8> {
+    let dasharray = if dasharray.len() % 2 == 1 {
+        [dasharray, dasharray].concat()
+    } else {
+        dasharray.to_vec()
+    };
+    let length: f32 = dasharray.iter().sum();
+
+    (0..DASH_ATLAS_WIDTH)
+        .map(|texel| {
+            let position = (texel as f32 + 0.5) / DASH_ATLAS_WIDTH as f32 * length;
+            let mut end = 0.0;
+            for (i, dash) in dasharray.iter().enumerate() {
+                end += dash;
+                if position < end {
+                    return if i % 2 == 0 { u8::MAX } else { 0 };
+                }
+            }
+            0
+        })
+        .collect()
+}
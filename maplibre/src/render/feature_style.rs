@@ -0,0 +1,252 @@
+//! Evaluation of the data-driven paint properties of style layers for each feature of a
+//! tessellated layer.
+
+use std::iter;
+
+use csscolorparser::Color;
+use geozero::mvt::tile;
+
+use crate::coords::{EXTENT, TILE_SIZE};
+use crate::io::feature_id::FeatureId;
+use crate::io::tile_feature::TileFeature;
+use crate::render::shaders::{ShaderFeatureStyle, Vec2f32, Vec4f32};
+use crate::render::sprite_atlas::SpritePatterns;
+use crate::style::expression::EvaluationContext;
+use crate::style::layer::{LayerPaint, StyleLayer};
+use crate::style::property::{PropertyType, StyleProperty};
+
+/// Default of `fill-color` and `line-color` according to the style specification
+const DEFAULT_COLOR: Color = Color {
+    r: 0.0,
+    g: 0.0,
+    b: 0.0,
+    a: 1.0,
+};
+
+fn evaluate<T: PropertyType>(
+    property: Option<&StyleProperty<T>>,
+    context: &EvaluationContext,
+    default: T,
+) -> T {
+    property
+        .and_then(|property| property.evaluate(context))
+        .unwrap_or(default)
+}
+
+/// A pixel spans EXTENT / TILE_SIZE tile units at the zoom level of the tile
+const TILE_UNITS_PER_PIXEL: f32 = (EXTENT / TILE_SIZE) as f32;
+
+/// Outlines of fill layers are one pixel wide
+const OUTLINE_WIDTH: f32 = TILE_UNITS_PER_PIXEL / 2.0;
+
+fn rgba(color: Color, opacity: f32) -> Vec4f32 {
+    [
+        color.r as f32,
+        color.g as f32,
+        color.b as f32,
+        color.a as f32 * opacity.clamp(0.0, 1.0),
+    ]
+}
+
+/// Evaluates a translation like `fill-translate` in tile units at the zoom level of the tile.
+/// Translations which are not pairs of numbers are ignored.
+pub fn layer_translate(
+    translate: &StyleProperty<Vec<f32>>,
+    context: &EvaluationContext,
+) -> Vec2f32 {
+    match translate.evaluate(context).as_deref() {
+        Some([x, y]) => [x * TILE_UNITS_PER_PIXEL, y * TILE_UNITS_PER_PIXEL],
+        _ => [0.0, 0.0],
+    }
+}
+
+/// Returns the metadata of the vertices of a tessellated layer. The paint properties of
+/// `style_layer` are evaluated for each feature of `layer_data` at the zoom level `zoom`.
+/// `feature_vertices` holds the amount of vertices of each feature, of which the amount in
+/// `feature_outline_vertices` belong to the outline of the feature. Patterns are looked up in
+/// `patterns`.
+pub fn feature_metadata(
+    style_layer: &StyleLayer,
+    zoom: f64,
+    layer_data: &tile::Layer,
+    feature_ids: &[Option<FeatureId>],
+    feature_vertices: &[u32],
+    feature_outline_vertices: &[u32],
+    patterns: &SpritePatterns,
+) -> Vec<ShaderFeatureStyle> {
+    layer_data
+        .features
+        .iter()
+        .zip(feature_ids)
+        .zip(feature_vertices.iter().zip(feature_outline_vertices))
+        .flat_map(|((feature, id), (vertices, outline_vertices))| {
+            let tile_feature = TileFeature::new(layer_data, feature, id.as_ref());
+            let context = EvaluationContext::new(zoom).with_feature(&tile_feature);
+
+            let (style, outline_style) = match &style_layer.paint {
+                Some(LayerPaint::Line(paint)) => {
+                    let color = evaluate(paint.line_color.as_ref(), &context, DEFAULT_COLOR);
+                    let opacity = evaluate(paint.line_opacity.as_ref(), &context, 1.0);
+                    let width = evaluate(paint.line_width.as_ref(), &context, 1.0);
+                    let offset = evaluate(paint.line_offset.as_ref(), &context, 0.0);
+
+                    // Widths and offsets are measured in pixels
+                    let style = ShaderFeatureStyle::new(
+                        rgba(color, opacity),
+                        width.max(0.0) / 2.0 * TILE_UNITS_PER_PIXEL,
+                        id.as_ref(),
+                    )
+                    .with_offset(offset * TILE_UNITS_PER_PIXEL);
+                    (style, style)
+                }
+                Some(LayerPaint::Fill(paint)) => {
+                    let opacity = evaluate(paint.fill_opacity.as_ref(), &context, 1.0);
+                    let pattern = paint
+                        .fill_pattern
+                        .as_ref()
+                        .and_then(|pattern| pattern.evaluate(&context))
+                        .and_then(|pattern| patterns.pattern(&pattern));
+
+                    // Patterns replace the color of the fill
+                    let style = match pattern {
+                        Some(pattern) => {
+                            ShaderFeatureStyle::new([1.0, 1.0, 1.0, opacity], 0.0, id.as_ref())
+                                .with_pattern(pattern)
+                        }
+                        None => {
+                            let color =
+                                evaluate(paint.fill_color.as_ref(), &context, DEFAULT_COLOR);
+                            ShaderFeatureStyle::new(rgba(color, opacity), 0.0, id.as_ref())
+                        }
+                    };
+                    let outline_style = match paint
+                        .fill_outline_color
+                        .as_ref()
+                        .and_then(|color| color.evaluate(&context))
+                    {
+                        Some(color) => ShaderFeatureStyle::new(
+                            rgba(color, opacity),
+                            OUTLINE_WIDTH,
+                            id.as_ref(),
+                        ),
+                        None => ShaderFeatureStyle {
+                            width: OUTLINE_WIDTH,
+                            ..style
+                        },
+                    };
+                    (style, outline_style)
+                }
+                _ => {
+                    let style = ShaderFeatureStyle::new(rgba(DEFAULT_COLOR, 1.0), 0.0, id.as_ref());
+                    let outline_style = ShaderFeatureStyle {
+                        width: OUTLINE_WIDTH,
+                        ..style
+                    };
+                    (style, outline_style)
+                }
+            };
+
+            iter::repeat(outline_style)
+                .take(*outline_vertices as usize)
+                .chain(iter::repeat(style).take((*vertices - *outline_vertices) as usize))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use geozero::mvt::tile;
+
+    use crate::io::feature_id::FeatureId;
+    use crate::io::sprite::SpriteImage;
+    use crate::render::feature_style::{feature_metadata, OUTLINE_WIDTH};
+    use crate::render::shaders::{ShaderFeatureStyle, Vec4f32};
+    use crate::render::sprite_atlas::SpritePatterns;
+    use crate::style::layer::StyleLayer;
+
+    /// Returns the colors, widths and patterns of `styles`
+    fn unpack(styles: &[ShaderFeatureStyle]) -> Vec<(Vec4f32, f32, Vec4f32)> {
+        styles
+            .iter()
+            .map(|style| (style.color, style.width, style.pattern))
+            .collect()
+    }
+
+    #[test]
+    fn test_fill_outline_and_pattern() {
+        // language=JSON
+        let style_layer: StyleLayer = serde_json::from_str(
+            r##"
+            {
+              "id": "building",
+              "type": "fill",
+              "source": "openmaptiles",
+              "source-layer": "building",
+              "paint": {
+                "fill-color": "#ff0000",
+                "fill-opacity": 0.5,
+                "fill-outline-color": "#0000ff",
+                "fill-pattern": ["get", "pattern"]
+              }
+            }
+            "##,
+        )
+        .unwrap();
+
+        let pattern_value = |pattern: &str| tile::Value {
+            string_value: Some(pattern.to_string()),
+            ..Default::default()
+        };
+        let layer_data = tile::Layer {
+            version: 2,
+            name: "building".to_string(),
+            keys: vec!["pattern".to_string()],
+            values: vec![pattern_value("stripes"), pattern_value("missing")],
+            features: vec![
+                tile::Feature {
+                    id: Some(1),
+                    tags: vec![0, 0],
+                    ..Default::default()
+                },
+                tile::Feature {
+                    id: Some(2),
+                    tags: vec![0, 1],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let feature_ids = vec![Some(FeatureId::Number(1)), Some(FeatureId::Number(2))];
+
+        let patterns = SpritePatterns::new(HashMap::from([(
+            "stripes".to_string(),
+            SpriteImage {
+                x: 16,
+                y: 32,
+                width: 8,
+                height: 4,
+            },
+        )]));
+
+        let styles = feature_metadata(
+            &style_layer,
+            14.0,
+            &layer_data,
+            &feature_ids,
+            &[3, 4],
+            &[1, 2],
+            &patterns,
+        );
+
+        let outline = ([0.0, 0.0, 1.0, 0.5], OUTLINE_WIDTH, [0.0; 4]);
+        let pattern = ([1.0, 1.0, 1.0, 0.5], 0.0, [16.0, 32.0, 8.0, 4.0]);
+        // Without the image of the pattern the fill falls back to `fill-color`
+        let fill = ([1.0, 0.0, 0.0, 0.5], 0.0, [0.0; 4]);
+        assert_eq!(
+            unpack(&styles),
+            vec![outline, pattern, pattern, outline, outline, fill, fill]
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! GPU resources for hillshade layers and the terrain. The elevations of raster DEM tiles are
+//! uploaded into textures, from which the shading is computed within the fragment shader. Hillshade
+//! layers draw the same quads as raster layers. The vertex shaders of all layers displace vertices
+//! by the elevations of the terrain.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+
+use cint::{Alpha, EncodedSrgb};
+use csscolorparser::Color;
+
+use crate::coords::WorldTileCoords;
+use crate::io::raster::DemImage;
+use crate::platform::MIN_BUFFER_SIZE;
+use crate::render::shaders::{ShaderDemTile, ShaderHillshade, Vec4f32};
+use crate::style::layer::{LayerPaint, StyleLayer};
+
+/// Elevations are stored in meters. 32-bit floats are not filterable, therefore the shader loads
+/// the texels directly.
+pub const DEM_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+/// Default of `hillshade-illumination-direction` according to the style specification
+const DEFAULT_ILLUMINATION_DIRECTION: f32 = 335.0;
+/// Default of `hillshade-exaggeration` according to the style specification
+const DEFAULT_EXAGGERATION: f32 = 0.5;
+
+struct DemTexture {
+    // The texture and the buffer need to be kept alive as long as the bind group is used
+    _texture: wgpu::Texture,
+    _tile_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Textures of the raster DEM tiles which are uploaded to the GPU, keyed by source id and tile.
+pub struct DemTextures {
+    bind_group_layout: wgpu::BindGroupLayout,
+    textures: HashMap<String, HashMap<WorldTileCoords, DemTexture>>,
+    /// A tile without elevation, which is bound for tiles which are not covered by the terrain
+    flat: DemTexture,
+}
+
+impl DemTextures {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("DEM bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let flat = Self::create_texture(
+            device,
+            queue,
+            &bind_group_layout,
+            &ShaderDemTile::new(0.0, 0.0, 0.0, 0.0),
+            &DemImage {
+                width: 1,
+                height: 1,
+                elevations: vec![0.0],
+            },
+        );
+
+        Self {
+            bind_group_layout,
+            textures: HashMap::new(),
+            flat,
+        }
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn contains(&self, source_id: &str, coords: &WorldTileCoords) -> bool {
+        self.get(source_id, coords).is_some()
+    }
+
+    /// Returns the bind group of a tile without elevation.
+    pub fn flat(&self) -> &wgpu::BindGroup {
+        &self.flat.bind_group
+    }
+
+    /// Returns the bind group which binds the elevations of the tile at `coords`.
+    pub fn get(&self, source_id: &str, coords: &WorldTileCoords) -> Option<&wgpu::BindGroup> {
+        self.textures
+            .get(source_id)
+            .and_then(|textures| textures.get(coords))
+            .map(|texture| &texture.bind_group)
+    }
+
+    /// Uploads the elevations of the tile at `coords` into a new texture. If the tile belongs to
+    /// the terrain, `exaggeration` is the exaggeration of the terrain, otherwise zero.
+    #[tracing::instrument(skip_all)]
+    pub fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source_id: &str,
+        coords: WorldTileCoords,
+        dem: &DemImage,
+        exaggeration: f32,
+    ) {
+        let texture = Self::create_texture(
+            device,
+            queue,
+            &self.bind_group_layout,
+            &ShaderDemTile::new(
+                coords.x as f32,
+                coords.y as f32,
+                coords.z as f32,
+                exaggeration,
+            ),
+            dem,
+        );
+
+        self.textures
+            .entry(source_id.to_string())
+            .or_default()
+            .insert(coords, texture);
+    }
+
+    fn create_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        tile: &ShaderDemTile,
+        dem: &DemImage,
+    ) -> DemTexture {
+        let size = wgpu::Extent3d {
+            width: dem.width,
+            height: dem.height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("DEM tile texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEM_TEXTURE_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&dem.elevations),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(dem.bytes_per_row()),
+                rows_per_image: NonZeroU32::new(dem.height),
+            },
+            size,
+        );
+
+        let tile_buffer = create_uniform_buffer(device, queue, "DEM tile ubo", tile);
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("DEM tile bind group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: tile_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        DemTexture {
+            _texture: texture,
+            _tile_buffer: tile_buffer,
+            bind_group,
+        }
+    }
+
+    /// Releases the textures for which `keep` returns false.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&str, &WorldTileCoords) -> bool,
+    {
+        for (source_id, textures) in &mut self.textures {
+            textures.retain(|coords, _| keep(source_id, coords));
+        }
+        self.textures.retain(|_, textures| !textures.is_empty());
+    }
+}
+
+struct HillshadeLayer {
+    // The buffer needs to be kept alive as long as the bind group is used
+    _buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Uniforms with the paint properties of each hillshade layer, keyed by layer id.
+pub struct HillshadeLayers {
+    bind_group_layout: wgpu::BindGroupLayout,
+    layers: HashMap<String, HillshadeLayer>,
+}
+
+impl HillshadeLayers {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Hillshade bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        Self {
+            bind_group_layout,
+            layers: HashMap::new(),
+        }
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    /// Returns the bind group which binds the paint properties of the layer `layer_id`.
+    pub fn get(&self, layer_id: &str) -> Option<&wgpu::BindGroup> {
+        self.layers.get(layer_id).map(|layer| &layer.bind_group)
+    }
+
+    /// Uploads the paint properties of `style_layer`, unless they have been uploaded already.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        style_layer: &StyleLayer,
+    ) {
+        if self.layers.contains_key(&style_layer.id) {
+            return;
+        }
+
+        let buffer = create_uniform_buffer(
+            device,
+            queue,
+            "Hillshade ubo",
+            &hillshade_uniform(style_layer),
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Hillshade bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        self.layers.insert(
+            style_layer.id.clone(),
+            HillshadeLayer {
+                _buffer: buffer,
+                bind_group,
+            },
+        );
+    }
+}
+
+fn create_uniform_buffer<T: bytemuck::Pod>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    label: &str,
+    value: &T,
+) -> wgpu::Buffer {
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: cmp::max(
+            MIN_BUFFER_SIZE,
+            std::mem::size_of::<T>() as wgpu::BufferAddress,
+        ),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    queue.write_buffer(&buffer, 0, bytemuck::cast_slice(&[*value]));
+    buffer
+}
+
+/// Converts the paint properties of a hillshade layer into the uniform of the shader. Properties
+/// which are not set fall back to the defaults of the style specification.
+fn hillshade_uniform(style_layer: &StyleLayer) -> ShaderHillshade {
+    let paint = match &style_layer.paint {
+        Some(LayerPaint::Hillshade(paint)) => Some(paint),
+        _ => None,
+    };
+
+    let premultiplied = |color: Option<&Color>, default: Vec4f32| -> Vec4f32 {
+        let [r, g, b, a]: Vec4f32 = color
+            .map(|color| Alpha::<EncodedSrgb<f32>>::from(color.clone()).into())
+            .unwrap_or(default);
+        [r * a, g * a, b * a, a]
+    };
+
+    let direction = paint
+        .and_then(|paint| paint.hillshade_illumination_direction)
+        .unwrap_or(DEFAULT_ILLUMINATION_DIRECTION);
+    let exaggeration = paint
+        .and_then(|paint| paint.hillshade_exaggeration)
+        .unwrap_or(DEFAULT_EXAGGERATION)
+        .clamp(0.0, 1.0);
+
+    ShaderHillshade {
+        shadow: premultiplied(
+            paint.and_then(|paint| paint.hillshade_shadow_color.as_ref()),
+            [0.0, 0.0, 0.0, 1.0],
+        ),
+        highlight: premultiplied(
+            paint.and_then(|paint| paint.hillshade_highlight_color.as_ref()),
+            [1.0, 1.0, 1.0, 1.0],
+        ),
+        accent: premultiplied(
+            paint.and_then(|paint| paint.hillshade_accent_color.as_ref()),
+            [0.0, 0.0, 0.0, 1.0],
+        ),
+        light: [exaggeration, direction.to_radians(), 0.0, 0.0],
+    }
+}
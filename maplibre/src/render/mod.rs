@@ -2,14 +2,20 @@
 //! communication with the GPU.
 
 mod buffer_pool;
+mod dash_atlas;
+mod feature_style;
+mod hillshade;
 mod options;
 mod piplines;
+mod raster;
 mod shaders;
+mod sprite_atlas;
 mod texture;
 mod tile_view_pattern;
 
 pub mod camera;
 pub mod render_state;
+pub mod terrain;
 
 // These are created during tessellation and must be public
 pub use shaders::ShaderVertex;
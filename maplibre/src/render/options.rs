@@ -1,5 +1,9 @@
+use std::mem::size_of;
+
 use wgpu::BufferAddress;
 
+use crate::render::shaders::ShaderTileMetadata;
+
 pub const DEBUG_WIREFRAME: bool = false;
 pub const DEBUG_STENCIL_PATTERN: bool = false;
 pub const INDEX_FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint32; // Must match IndexDataType
@@ -9,4 +13,11 @@ pub const FEATURE_METADATA_BUFFER_SIZE: BufferAddress = 1024 * 1024 * 32;
 pub const INDICES_BUFFER_SIZE: BufferAddress = 1024 * 1024 * 32;
 pub const LAYER_METADATA_BUFFER_SIZE: BufferAddress = 1024 * 24;
 
-pub const TILE_VIEW_BUFFER_SIZE: BufferAddress = 4096;
+// Each tile in view can require additional fallback shapes of ancestor tiles. Fallbacks which
+// exceed the buffer are skipped.
+pub const TILE_VIEW_BUFFER_SIZE: BufferAddress =
+    size_of::<ShaderTileMetadata>() as BufferAddress * 1024;
+
+/// Number of cells along each side of the grids which are used to draw tile masks and raster
+/// tiles. Raster tiles follow the elevation of the terrain only at the vertices of the grid.
+pub const TERRAIN_GRID_SIZE: u32 = 32;
@@ -61,6 +61,8 @@ pub fn create_map_render_pipeline_description<'a>(
         },
         depth_stencil: Some(wgpu::DepthStencilState {
             format: DEPTH_TEXTURE_FORMAT,
+            // The features of a layer share its depth. Overlapping features of the same layer are
+            // therefore drawn only once, which avoids that translucent features are blended twice.
             depth_write_enabled: !update_stencil,
             depth_compare: wgpu::CompareFunction::Greater, // FIXME
             stencil: wgpu::StencilState {
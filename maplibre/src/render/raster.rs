@@ -0,0 +1,216 @@
+//! GPU resources for raster tiles. Each raster tile is drawn as a quad which covers the whole tile
+//! and samples the image of the tile from a texture.
+
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+
+use lyon::tessellation::VertexBuffers;
+
+use crate::coords::{WorldTileCoords, EXTENT};
+use crate::io::raster::RasterImage;
+use crate::render::options::TERRAIN_GRID_SIZE;
+use crate::render::ShaderVertex;
+use crate::tessellation::{IndexDataType, OverAlignedVertexBuffer};
+
+/// The pixels of the images are uploaded as they are. Like the colors of vector layers, they are
+/// not converted from sRGB.
+pub const RASTER_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+struct RasterTexture {
+    // The texture needs to be kept alive as long as the bind group is used
+    _texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Textures of the raster tiles which are uploaded to the GPU, keyed by source id and tile.
+pub struct RasterTextures {
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// Geometry of a quad which covers a whole tile
+    quad: OverAlignedVertexBuffer<ShaderVertex, IndexDataType>,
+    textures: HashMap<String, HashMap<WorldTileCoords, RasterTexture>>,
+}
+
+impl RasterTextures {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Raster bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        // Clamping avoids that the edges of neighbouring tiles bleed into each other
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Raster sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            bind_group_layout,
+            sampler,
+            quad: tile_quad(),
+            textures: HashMap::new(),
+        }
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn quad(&self) -> &OverAlignedVertexBuffer<ShaderVertex, IndexDataType> {
+        &self.quad
+    }
+
+    pub fn contains(&self, source_id: &str, coords: &WorldTileCoords) -> bool {
+        self.get(source_id, coords).is_some()
+    }
+
+    /// Returns the bind group which binds the texture of the tile at `coords`.
+    pub fn get(&self, source_id: &str, coords: &WorldTileCoords) -> Option<&wgpu::BindGroup> {
+        self.textures
+            .get(source_id)
+            .and_then(|textures| textures.get(coords))
+            .map(|texture| &texture.bind_group)
+    }
+
+    /// Uploads the `image` of the tile at `coords` into a new texture.
+    #[tracing::instrument(skip_all)]
+    pub fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source_id: &str,
+        coords: WorldTileCoords,
+        image: &RasterImage,
+    ) {
+        let size = wgpu::Extent3d {
+            width: image.width,
+            height: image.height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Raster tile texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: RASTER_TEXTURE_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &image.data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(image.bytes_per_row()),
+                rows_per_image: NonZeroU32::new(image.height),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Raster tile bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        self.textures
+            .entry(source_id.to_string())
+            .or_default()
+            .insert(
+                coords,
+                RasterTexture {
+                    _texture: texture,
+                    bind_group,
+                },
+            );
+    }
+
+    /// Releases the textures for which `keep` returns false.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&str, &WorldTileCoords) -> bool,
+    {
+        for (source_id, textures) in &mut self.textures {
+            textures.retain(|coords, _| keep(source_id, coords));
+        }
+        self.textures.retain(|_, textures| !textures.is_empty());
+    }
+}
+
+/// Creates a quad which spans a tile. The quad is divided into a grid of
+/// [`TERRAIN_GRID_SIZE`] cells, such that it can follow the elevation of the terrain. The texture
+/// coordinates are derived from the positions within the shader.
+fn tile_quad() -> OverAlignedVertexBuffer<ShaderVertex, IndexDataType> {
+    let cells = TERRAIN_GRID_SIZE;
+    let cell_size = EXTENT as f32 / cells as f32;
+    let mut buffer = VertexBuffers::with_capacity(
+        ((cells + 1) * (cells + 1)) as usize,
+        (cells * cells * 6) as usize,
+    );
+
+    for y in 0..=cells {
+        for x in 0..=cells {
+            buffer.vertices.push(ShaderVertex::new(
+                [x as f32 * cell_size, y as f32 * cell_size],
+                [0.0, 0.0],
+            ));
+        }
+    }
+
+    for y in 0..cells {
+        for x in 0..cells {
+            let top_left = y * (cells + 1) + x;
+            let bottom_left = top_left + cells + 1;
+            buffer.indices.extend([
+                top_left,
+                bottom_left,
+                top_left + 1,
+                top_left + 1,
+                bottom_left,
+                bottom_left + 1,
+            ]);
+        }
+    }
+
+    buffer.into()
+}
@@ -1,24 +1,33 @@
 use std::default::Default;
 
+use std::collections::HashSet;
 use std::{cmp, iter};
 
 use tracing;
 use wgpu::{Buffer, Limits, Queue};
 
-use crate::style::Style;
+use crate::style::expression::EvaluationContext;
+use crate::style::layer::{FillPaint, LayerPaint, LinePaint, StyleLayer};
+use crate::style::{Style, Terrain};
 
-use crate::coords::{ViewRegion, Zoom};
+use crate::coords::{ViewRegion, WorldTileCoords, Zoom};
 
+use crate::io::sprite::Sprite;
 use crate::io::tile_cache::TileCache;
 use crate::io::LayerTessellateMessage;
 use crate::platform::MIN_BUFFER_SIZE;
 use crate::render::buffer_pool::{BackingBufferDescriptor, BufferPool, IndexEntry};
 
 use crate::render::camera::{Camera, ViewProjection};
+use crate::render::dash_atlas::DashAtlas;
+use crate::render::feature_style::{feature_metadata, layer_translate};
+use crate::render::hillshade::{DemTextures, HillshadeLayers};
 use crate::render::options::{
     DEBUG_WIREFRAME, FEATURE_METADATA_BUFFER_SIZE, INDEX_FORMAT, INDICES_BUFFER_SIZE,
-    LAYER_METADATA_BUFFER_SIZE, TILE_VIEW_BUFFER_SIZE, VERTEX_BUFFER_SIZE,
+    LAYER_METADATA_BUFFER_SIZE, TERRAIN_GRID_SIZE, TILE_VIEW_BUFFER_SIZE, VERTEX_BUFFER_SIZE,
 };
+use crate::render::raster::RasterTextures;
+use crate::render::sprite_atlas::SpriteAtlas;
 use crate::render::tile_view_pattern::{TileInView, TileViewPattern};
 use crate::tessellation::IndexDataType;
 use crate::util::FPSMeter;
@@ -43,6 +52,8 @@ pub struct RenderState {
 
     render_pipeline: wgpu::RenderPipeline,
     mask_pipeline: wgpu::RenderPipeline,
+    raster_pipeline: wgpu::RenderPipeline,
+    hillshade_pipeline: wgpu::RenderPipeline,
     bind_group: wgpu::BindGroup,
 
     sample_count: u32,
@@ -62,6 +73,18 @@ pub struct RenderState {
     >,
 
     tile_view_pattern: TileViewPattern<Queue, Buffer>,
+
+    raster_textures: RasterTextures,
+    dem_textures: DemTextures,
+    hillshade_layers: HillshadeLayers,
+    dash_atlas: DashAtlas,
+    sprite_atlas: SpriteAtlas,
+
+    /// The source of the terrain, if the style displaces the geometry by the terrain
+    terrain_source: Option<String>,
+
+    /// The zoom level at which the paint properties in the feature metadata have been evaluated
+    feature_metadata_zoom: Option<f64>,
 }
 
 impl RenderState {
@@ -196,8 +219,26 @@ impl RenderState {
             }],
         });
 
+        // The elevations of the terrain are bound to the group 1 of all pipelines
+        let dem_textures = DemTextures::new(&device, &queue);
+        let terrain_bind_group_layout = dem_textures.bind_group_layout();
+
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            bind_group_layouts: &[&bind_group_layout],
+            bind_group_layouts: &[&bind_group_layout, terrain_bind_group_layout],
+            push_constant_ranges: &[],
+            label: None,
+        });
+
+        let dash_atlas = DashAtlas::new(&device);
+        let sprite_atlas = SpriteAtlas::new(&device, &queue);
+
+        let tile_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[
+                &bind_group_layout,
+                terrain_bind_group_layout,
+                dash_atlas.bind_group_layout(),
+                sprite_atlas.bind_group_layout(),
+            ],
             push_constant_ranges: &[],
             label: None,
         });
@@ -206,7 +247,7 @@ impl RenderState {
         let mut fragment_shader = shaders::tile::FRAGMENT;
 
         let render_pipeline_descriptor = create_map_render_pipeline_description(
-            &pipeline_layout,
+            &tile_pipeline_layout,
             vertex_shader.create_vertex_state(&device),
             fragment_shader.create_fragment_state(&device),
             sample_count,
@@ -224,8 +265,59 @@ impl RenderState {
             true,
         );
 
+        let raster_textures = RasterTextures::new(&device);
+
+        let raster_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                bind_group_layouts: &[
+                    &bind_group_layout,
+                    terrain_bind_group_layout,
+                    raster_textures.bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+                label: None,
+            });
+
+        let mut vertex_shader = shaders::raster::VERTEX;
+        let mut fragment_shader = shaders::raster::FRAGMENT;
+
+        let raster_pipeline_descriptor = create_map_render_pipeline_description(
+            &raster_pipeline_layout,
+            vertex_shader.create_vertex_state(&device),
+            fragment_shader.create_fragment_state(&device),
+            sample_count,
+            false,
+        );
+
+        let hillshade_layers = HillshadeLayers::new(&device);
+
+        let hillshade_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                bind_group_layouts: &[
+                    &bind_group_layout,
+                    terrain_bind_group_layout,
+                    dem_textures.bind_group_layout(),
+                    hillshade_layers.bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+                label: None,
+            });
+
+        let mut vertex_shader = shaders::hillshade::VERTEX;
+        let mut fragment_shader = shaders::hillshade::FRAGMENT;
+
+        let hillshade_pipeline_descriptor = create_map_render_pipeline_description(
+            &hillshade_pipeline_layout,
+            vertex_shader.create_vertex_state(&device),
+            fragment_shader.create_fragment_state(&device),
+            sample_count,
+            false,
+        );
+
         let render_pipeline = device.create_render_pipeline(&render_pipeline_descriptor);
         let mask_pipeline = device.create_render_pipeline(&mask_pipeline_descriptor);
+        let raster_pipeline = device.create_render_pipeline(&raster_pipeline_descriptor);
+        let hillshade_pipeline = device.create_render_pipeline(&hillshade_pipeline_descriptor);
 
         let depth_texture = Texture::create_depth_texture(&device, &surface_config, sample_count);
 
@@ -247,6 +339,8 @@ impl RenderState {
             surface_config,
             render_pipeline,
             mask_pipeline,
+            raster_pipeline,
+            hillshade_pipeline,
             bind_group,
             multisampling_texture,
             depth_texture,
@@ -264,6 +358,13 @@ impl RenderState {
                 tile_view_buffer,
                 TILE_VIEW_BUFFER_SIZE,
             )),
+            raster_textures,
+            dem_textures,
+            hillshade_layers,
+            dash_atlas,
+            sprite_atlas,
+            terrain_source: None,
+            feature_metadata_zoom: None,
         })
     }
 
@@ -304,6 +405,12 @@ impl RenderState {
         };
     }
 
+    /// Uploads the image of the `sprite` of the style, from which the patterns of fill layers are
+    /// sampled. Layers which have been uploaded before keep the patterns of the previous sprite.
+    pub fn upload_sprite(&mut self, sprite: &Sprite) {
+        self.sprite_atlas.upload(&self.device, &self.queue, sprite);
+    }
+
     pub fn update_globals(&self, view_proj: &ViewProjection, camera: &Camera) {
         // Update globals
         self.queue.write_buffer(
@@ -321,73 +428,53 @@ impl RenderState {
         );
     }
 
+    /// Evaluates the paint properties of the uploaded layers again, if the zoom level crossed a
+    /// zoom stop of one of their properties since the last evaluation.
     #[tracing::instrument(skip_all)]
-    pub(crate) fn update_metadata(&mut self) {
-        /*let animated_one = 0.5
-        * (1.0
-            + ((SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs_f64()
-                * 10.0)
-                .sin()));*/
-
-        // Factor which determines how much we need to adjust the width of lines for example.
-        // If zoom == z -> zoom_factor == 1
-
-        /*  for entries in self.buffer_pool.index().iter() {
-        for entry in entries {
-            let world_coords = entry.coords;*/
-
-        // TODO: Update features
-        /*let source_layer = entry.style_layer.source_layer.as_ref().unwrap();
-
-        if let Some(result) = scheduler
-            .get_tile_cache()
-            .iter_tessellated_layers_at(&world_coords)
-            .unwrap()
-            .find(|layer| source_layer.as_str() == layer.layer_name())
-        {
-            let color: Option<Vec4f32> = entry
-                .style_layer
-                .paint
-                .as_ref()
-                .and_then(|paint| paint.get_color())
-                .map(|mut color| {
-                    color.color.b = animated_one as f32;
-                    color.into()
-                });
+    pub(crate) fn update_metadata(&mut self, zoom: Zoom, tile_cache: &TileCache) {
+        let zoom = zoom.value();
+        let last_zoom = match self.feature_metadata_zoom.replace(zoom) {
+            Some(last_zoom) => last_zoom,
+            None => return,
+        };
 
-            match result {
-                LayerTessellateResult::UnavailableLayer { .. } => {}
-                LayerTessellateResult::TessellatedLayer {
+        for entries in self.buffer_pool.index().iter() {
+            for entry in entries {
+                if !entry
+                    .style_layer
+                    .paint
+                    .as_ref()
+                    .map_or(false, |paint| paint.crosses_zoom_stop(last_zoom, zoom))
+                {
+                    continue;
+                }
+
+                if let Some(LayerTessellateMessage::TessellatedLayer {
                     layer_data,
-                    feature_indices,
+                    feature_ids,
+                    feature_vertices,
+                    feature_outline_vertices,
                     ..
-                } => {
-
-                    let feature_metadata = layer_data
-                        .features()
-                        .iter()
-                        .enumerate()
-                        .flat_map(|(i, _feature)| {
-                            iter::repeat(ShaderFeatureStyle {
-                                color: color.unwrap(),
-                            })
-                            .take(feature_indices[i] as usize)
-                        })
-                        .collect::<Vec<_>>();
-
-                    self.buffer_pool.update_feature_metadata(
-                        &self.queue,
-                        entry,
-                        &feature_metadata,
+                }) = tile_cache
+                    .iter_tessellated_layers_at(&entry.coords)
+                    .and_then(|mut layers| {
+                        layers.find(|layer| is_drawn_by(layer, &entry.style_layer))
+                    })
+                {
+                    let feature_metadata = feature_metadata(
+                        &entry.style_layer,
+                        zoom,
+                        layer_data,
+                        feature_ids,
+                        feature_vertices,
+                        feature_outline_vertices,
+                        self.sprite_atlas.patterns(),
                     );
+                    self.buffer_pool
+                        .update_feature_metadata(&self.queue, entry, &feature_metadata);
                 }
             }
-        }*/
-        /*            }
-        }*/
+        }
     }
 
     #[tracing::instrument(skip_all)]
@@ -398,7 +485,7 @@ impl RenderState {
         zoom: Zoom,
     ) {
         self.tile_view_pattern
-            .update_pattern(view_region, &self.buffer_pool, zoom);
+            .update_pattern(view_region, self.buffer_pool.index(), zoom);
         self.tile_view_pattern
             .upload_pattern(&self.queue, view_proj);
     }
@@ -407,76 +494,312 @@ impl RenderState {
     pub fn upload_tile_geometry(
         &mut self,
         view_region: &ViewRegion,
+        zoom: Zoom,
         style: &Style,
         tile_cache: &TileCache,
     ) {
         // Upload all tessellated layers which are in view
         for world_coords in view_region.iter() {
-            let loaded_layers = self
-                .buffer_pool
-                .get_loaded_layers_at(&world_coords)
-                .unwrap_or_default();
-            if let Some(available_layers) = tile_cache
-                .iter_tessellated_layers_at(&world_coords)
-                .map(|layers| {
-                    layers
-                        .filter(|result| !loaded_layers.contains(&result.layer_name()))
-                        .collect::<Vec<_>>()
+            // Layers for which no data has been found yet at this or a lower zoom level. Data of
+            // lower zoom levels is drawn as fallback while loading or if a source does not offer
+            // tiles at the current zoom level (overzooming).
+            let mut missing_layers: HashSet<(&str, &str)> = style
+                .layers
+                .iter()
+                .filter_map(|style_layer| {
+                    Some((
+                        style_layer.source.as_ref()?.as_str(),
+                        style_layer.source_layer_name()?,
+                    ))
                 })
-            {
-                for style_layer in &style.layers {
-                    let source_layer = style_layer.source_layer.as_ref().unwrap();
+                .collect();
 
-                    if let Some(message) = available_layers
-                        .iter()
-                        .find(|layer| source_layer.as_str() == layer.layer_name())
-                    {
-                        let color: Option<Vec4f32> = style_layer
-                            .paint
-                            .as_ref()
-                            .and_then(|paint| paint.get_color())
-                            .map(|color| color.into());
-
-                        match message {
-                            LayerTessellateMessage::UnavailableLayer { coords: _, .. } => {
-                                /*self.buffer_pool.mark_layer_unavailable(*coords);*/
-                            }
-                            LayerTessellateMessage::TessellatedLayer {
-                                coords,
-                                feature_indices,
+            let mut current = Some(world_coords);
+            while let Some(coords) = current {
+                if missing_layers.is_empty() {
+                    break;
+                }
+
+                self.upload_layers_at(&coords, zoom, style, tile_cache, &missing_layers);
+
+                if let Some(cached_layers) = tile_cache.iter_tessellated_layers_at(&coords) {
+                    for cached_layer in cached_layers {
+                        missing_layers
+                            .remove(&(cached_layer.source_id(), cached_layer.layer_name()));
+                    }
+                }
+
+                current = coords.get_parent();
+            }
+        }
+
+        self.terrain_source = style.terrain.as_ref().map(|terrain| terrain.source.clone());
+        if let Some(terrain) = &style.terrain {
+            for world_coords in view_region.iter() {
+                self.upload_terrain_at(&world_coords, terrain, tile_cache);
+            }
+        }
+
+        // Textures of raster and DEM tiles are released once their geometry has been evicted from
+        // the buffer pool. DEM tiles of the terrain are kept as long as they are cached.
+        let index = self.buffer_pool.index();
+        let is_used = |source_id: &str, coords: &WorldTileCoords, is_hillshade: bool| {
+            index.get_layers(coords).map_or(false, |entries| {
+                entries.iter().any(|entry| {
+                    entry.style_layer.is_hillshade() == is_hillshade
+                        && entry.style_layer.source.as_deref() == Some(source_id)
+                })
+            })
+        };
+        self.raster_textures
+            .retain(|source_id, coords| is_used(source_id, coords, false));
+        let terrain_source = self.terrain_source.as_deref();
+        self.dem_textures.retain(|source_id, coords| {
+            is_used(source_id, coords, true)
+                || (terrain_source == Some(source_id)
+                    && tile_cache.get_dem(coords, source_id).is_some())
+        });
+    }
+
+    /// Uploads the DEM tile of the terrain which covers `world_coords`. If the tile is not cached,
+    /// the closest cached ancestor is uploaded.
+    fn upload_terrain_at(
+        &mut self,
+        world_coords: &WorldTileCoords,
+        terrain: &Terrain,
+        tile_cache: &TileCache,
+    ) {
+        let mut current = Some(*world_coords);
+        while let Some(coords) = current {
+            if self.dem_textures.contains(&terrain.source, &coords) {
+                return;
+            }
+
+            if let Some(dem) = tile_cache.get_dem(&coords, &terrain.source) {
+                tracing::trace!("Uploading terrain tile at {}", &coords);
+                self.dem_textures.upload(
+                    &self.device,
+                    &self.queue,
+                    &terrain.source,
+                    coords,
+                    dem,
+                    terrain.exaggeration(),
+                );
+                return;
+            }
+
+            current = coords.get_parent();
+        }
+    }
+
+    /// Returns the bind group with the elevations of the terrain at `coords`. The DEM tile of the
+    /// closest ancestor is used while the tile is loading or if the terrain source does not offer
+    /// tiles at this zoom level.
+    fn terrain_bind_group(&self, coords: &WorldTileCoords) -> &wgpu::BindGroup {
+        let terrain_source = match &self.terrain_source {
+            Some(terrain_source) => terrain_source,
+            None => return self.dem_textures.flat(),
+        };
+
+        let mut current = Some(*coords);
+        while let Some(coords) = current {
+            if let Some(bind_group) = self.dem_textures.get(terrain_source, &coords) {
+                return bind_group;
+            }
+            current = coords.get_parent();
+        }
+
+        self.dem_textures.flat()
+    }
+
+    /// Removes the uploaded layers of the source `source_id` at the tiles for which `predicate`
+    /// returns true. The layers are uploaded again once they are available in the tile cache.
+    pub fn remove_layers<F>(&mut self, source_id: &str, predicate: F)
+    where
+        F: Fn(&WorldTileCoords) -> bool,
+    {
+        self.buffer_pool.remove_layers(|entry| {
+            entry.style_layer.source.as_deref() == Some(source_id) && predicate(&entry.coords)
+        });
+    }
+
+    /// Uploads the tessellated layers at `world_coords` which are contained in `layers` and which
+    /// are not yet loaded. The paint properties of vector layers are evaluated at `zoom`.
+    fn upload_layers_at(
+        &mut self,
+        world_coords: &WorldTileCoords,
+        zoom: Zoom,
+        style: &Style,
+        tile_cache: &TileCache,
+        layers: &HashSet<(&str, &str)>,
+    ) {
+        // The ids are copied, because the buffer pool is modified while uploading
+        let loaded_layers: HashSet<String> = self
+            .buffer_pool
+            .get_loaded_layers_at(world_coords)
+            .map(|layers| layers.into_iter().map(str::to_string).collect())
+            .unwrap_or_default();
+        if let Some(available_layers) = tile_cache
+            .iter_tessellated_layers_at(world_coords)
+            .map(|layers| layers.collect::<Vec<_>>())
+        {
+            for style_layer in &style.layers {
+                let (source, source_layer) =
+                    match (&style_layer.source, style_layer.source_layer_name()) {
+                        (Some(source), Some(source_layer)) => (source, source_layer),
+                        _ => continue,
+                    };
+
+                if !layers.contains(&(source.as_str(), source_layer))
+                    || loaded_layers.contains(&style_layer.id)
+                {
+                    continue;
+                }
+
+                if let Some(message) = available_layers
+                    .iter()
+                    .find(|layer| is_drawn_by(layer, style_layer))
+                {
+                    match message {
+                        LayerTessellateMessage::UnavailableLayer { coords: _, .. } => {
+                            /*self.buffer_pool.mark_layer_unavailable(*coords);*/
+                        }
+                        LayerTessellateMessage::TessellatedLayer {
+                            coords,
+                            feature_vertices,
+                            feature_outline_vertices,
+                            feature_ids,
+                            layer_data,
+                            buffer,
+                            ..
+                        } => {
+                            let allocate_feature_metadata =
+                                tracing::span!(tracing::Level::TRACE, "allocate_feature_metadata");
+
+                            let guard = allocate_feature_metadata.enter();
+                            let feature_metadata = feature_metadata(
+                                style_layer,
+                                zoom.value(),
                                 layer_data,
+                                feature_ids,
+                                feature_vertices,
+                                feature_outline_vertices,
+                                self.sprite_atlas.patterns(),
+                            );
+                            drop(guard);
+
+                            let context = EvaluationContext::new(zoom.value());
+                            let dash = match &style_layer.paint {
+                                Some(LayerPaint::Line(LinePaint {
+                                    line_dasharray: Some(dasharray),
+                                    ..
+                                })) => dasharray
+                                    .evaluate(&context)
+                                    .map_or([0.0, 0.0], |dasharray| {
+                                        self.dash_atlas.prepare(&self.queue, &dasharray)
+                                    }),
+                                _ => [0.0, 0.0],
+                            };
+                            let translate = match &style_layer.paint {
+                                Some(LayerPaint::Fill(FillPaint {
+                                    fill_translate: Some(translate),
+                                    ..
+                                })) => layer_translate(translate, &context),
+                                _ => [0.0, 0.0],
+                            };
+
+                            tracing::trace!("Allocating geometry at {}", &coords);
+                            self.buffer_pool.allocate_layer_geometry(
+                                &self.queue,
+                                *coords,
+                                style_layer.clone(),
                                 buffer,
-                                ..
-                            } => {
-                                let allocate_feature_metadata = tracing::span!(
-                                    tracing::Level::TRACE,
-                                    "allocate_feature_metadata"
+                                ShaderLayerMetadata::new(style_layer.index as f32)
+                                    .with_dash(dash)
+                                    .with_translate(translate),
+                                &feature_metadata,
+                            );
+                        }
+                        LayerTessellateMessage::RasterLayer {
+                            source_id,
+                            coords,
+                            image,
+                        } => {
+                            // Multiple layers can show the same raster tile
+                            if !self.raster_textures.contains(source_id, coords) {
+                                self.raster_textures.upload(
+                                    &self.device,
+                                    &self.queue,
+                                    source_id,
+                                    *coords,
+                                    image,
                                 );
+                            }
 
-                                let guard = allocate_feature_metadata.enter();
-                                let feature_metadata = layer_data
-                                    .features
-                                    .iter()
-                                    .enumerate()
-                                    .flat_map(|(i, _feature)| {
-                                        iter::repeat(ShaderFeatureStyle {
-                                            color: color.unwrap(),
-                                        })
-                                        .take(feature_indices[i] as usize)
-                                    })
-                                    .collect::<Vec<_>>();
-                                drop(guard);
+                            let quad = self.raster_textures.quad();
+                            let feature_metadata = vec![
+                                ShaderFeatureStyle::new(
+                                    [1.0, 1.0, 1.0, style_layer.raster_opacity()],
+                                    0.0,
+                                    None,
+                                );
+                                quad.buffer.vertices.len()
+                            ];
+
+                            tracing::trace!("Allocating raster tile at {}", &coords);
+                            self.buffer_pool.allocate_layer_geometry(
+                                &self.queue,
+                                *coords,
+                                style_layer.clone(),
+                                quad,
+                                ShaderLayerMetadata::new(style_layer.index as f32),
+                                &feature_metadata,
+                            );
+                        }
+                        LayerTessellateMessage::DemLayer {
+                            source_id,
+                            coords,
+                            dem,
+                        } => {
+                            if !style_layer.is_hillshade() {
+                                continue;
+                            }
 
-                                tracing::trace!("Allocating geometry at {}", &coords);
-                                self.buffer_pool.allocate_layer_geometry(
+                            // Multiple layers can shade the same DEM tile
+                            if !self.dem_textures.contains(source_id, coords) {
+                                let exaggeration = style
+                                    .terrain
+                                    .as_ref()
+                                    .filter(|terrain| terrain.source == *source_id)
+                                    .map_or(0.0, |terrain| terrain.exaggeration());
+                                self.dem_textures.upload(
+                                    &self.device,
                                     &self.queue,
+                                    source_id,
                                     *coords,
-                                    style_layer.clone(),
-                                    buffer,
-                                    ShaderLayerMetadata::new(style_layer.index as f32),
-                                    &feature_metadata,
+                                    dem,
+                                    exaggeration,
                                 );
                             }
+                            self.hillshade_layers
+                                .prepare(&self.device, &self.queue, style_layer);
+
+                            let quad = self.raster_textures.quad();
+                            let feature_metadata =
+                                vec![
+                                    ShaderFeatureStyle::new([1.0, 1.0, 1.0, 1.0], 0.0, None);
+                                    quad.buffer.vertices.len()
+                                ];
+
+                            tracing::trace!("Allocating hillshade tile at {}", &coords);
+                            self.buffer_pool.allocate_layer_geometry(
+                                &self.queue,
+                                *coords,
+                                style_layer.clone(),
+                                quad,
+                                ShaderLayerMetadata::new(style_layer.index as f32),
+                                &feature_metadata,
+                            );
                         }
                     }
                 }
@@ -547,22 +870,23 @@ impl RenderState {
                 {
                     let index = self.buffer_pool.index();
 
-                    for TileInView { shape, fallback } in self.tile_view_pattern.iter() {
+                    for TileInView {
+                        shape,
+                        fallbacks,
+                        stencil_reference,
+                    } in self.tile_view_pattern.iter()
+                    {
                         let coords = shape.coords;
                         tracing::trace!("Drawing tile at {coords}");
 
-                        let shape_to_render = fallback.as_ref().unwrap_or(shape);
-
-                        let reference = self
-                            .tile_view_pattern
-                            .stencil_reference_value(&shape_to_render.coords)
-                            as u32;
+                        let reference = *stencil_reference as u32;
 
                         // Draw mask
                         {
                             tracing::trace!("Drawing mask {}", &coords);
 
                             pass.set_pipeline(&self.mask_pipeline);
+                            pass.set_bind_group(1, self.terrain_bind_group(&coords), &[]);
                             pass.set_stencil_reference(reference);
                             pass.set_vertex_buffer(
                                 0,
@@ -570,11 +894,30 @@ impl RenderState {
                                     .buffer()
                                     .slice(shape.buffer_range.clone()),
                             );
-                            pass.draw(0..6, 0..1);
+                            pass.draw(0..TERRAIN_GRID_SIZE * TERRAIN_GRID_SIZE * 6, 0..1);
                         }
 
-                        if let Some(entries) = index.get_layers(&shape_to_render.coords) {
-                            let mut layers_to_render: Vec<&IndexEntry> = Vec::from_iter(entries);
+                        // Sources which have already been drawn at a higher zoom level
+                        let mut drawn_sources: HashSet<&str> = HashSet::new();
+
+                        for shape_to_render in iter::once(shape).chain(fallbacks.iter()) {
+                            let entries = if let Some(entries) =
+                                index.get_layers(&shape_to_render.coords)
+                            {
+                                entries
+                            } else {
+                                tracing::trace!("No layers found at {}", &shape_to_render.coords);
+                                continue;
+                            };
+
+                            let mut layers_to_render: Vec<&IndexEntry> = entries
+                                .iter()
+                                .filter(|entry| {
+                                    entry.style_layer.source.as_ref().map_or(true, |source| {
+                                        !drawn_sources.contains(source.as_str())
+                                    })
+                                })
+                                .collect();
                             layers_to_render.sort_by_key(|entry| entry.style_layer.index);
 
                             for entry in layers_to_render {
@@ -582,11 +925,49 @@ impl RenderState {
                                 {
                                     tracing::trace!(
                                         "Drawing layer {:?} at {}",
-                                        entry.style_layer.source_layer,
+                                        entry.style_layer.source_layer_name(),
                                         &entry.coords
                                     );
 
-                                    pass.set_pipeline(&self.render_pipeline);
+                                    if entry.style_layer.is_hillshade() {
+                                        let dem = entry.style_layer.source.as_deref().and_then(
+                                            |source| self.dem_textures.get(source, &entry.coords),
+                                        );
+                                        let layer =
+                                            self.hillshade_layers.get(&entry.style_layer.id);
+
+                                        match (dem, layer) {
+                                            (Some(dem), Some(layer)) => {
+                                                pass.set_pipeline(&self.hillshade_pipeline);
+                                                pass.set_bind_group(2, dem, &[]);
+                                                pass.set_bind_group(3, layer, &[]);
+                                            }
+                                            _ => continue,
+                                        }
+                                    } else if entry.style_layer.is_raster() {
+                                        let texture = entry.style_layer.source.as_deref().and_then(
+                                            |source| {
+                                                self.raster_textures.get(source, &entry.coords)
+                                            },
+                                        );
+
+                                        match texture {
+                                            Some(texture) => {
+                                                pass.set_pipeline(&self.raster_pipeline);
+                                                pass.set_bind_group(2, texture, &[]);
+                                            }
+                                            None => continue,
+                                        }
+                                    } else {
+                                        pass.set_pipeline(&self.render_pipeline);
+                                        pass.set_bind_group(2, self.dash_atlas.bind_group(), &[]);
+                                        pass.set_bind_group(3, self.sprite_atlas.bind_group(), &[]);
+                                    }
+                                    pass.set_bind_group(
+                                        1,
+                                        self.terrain_bind_group(&entry.coords),
+                                        &[],
+                                    );
                                     pass.set_stencil_reference(reference);
                                     pass.set_index_buffer(
                                         self.buffer_pool
@@ -621,8 +1002,12 @@ impl RenderState {
                                     pass.draw_indexed(entry.indices_range(), 0, 0..1);
                                 }
                             }
-                        } else {
-                            tracing::trace!("No layers found at {}", &shape_to_render.coords);
+
+                            drawn_sources.extend(
+                                entries
+                                    .iter()
+                                    .filter_map(|entry| entry.style_layer.source.as_deref()),
+                            );
                         }
                     }
                 }
@@ -652,3 +1037,14 @@ impl RenderState {
         self.suspended = false;
     }
 }
+
+/// Returns whether `message` holds the data of the source layer which is drawn by `style_layer`.
+/// Vector layers are tessellated for each style layer separately, therefore messages which belong
+/// to another style layer do not match.
+fn is_drawn_by(message: &LayerTessellateMessage, style_layer: &StyleLayer) -> bool {
+    style_layer.source.as_deref() == Some(message.source_id())
+        && style_layer.source_layer_name() == Some(message.layer_name())
+        && message
+            .style_layer_id()
+            .map_or(true, |style_layer_id| style_layer_id == style_layer.id)
+}
@@ -0,0 +1,85 @@
+struct DemTile {
+    coords: vec4<f32>;
+};
+
+struct Hillshade {
+    shadow: vec4<f32>;
+    highlight: vec4<f32>;
+    accent: vec4<f32>;
+    light: vec4<f32>;
+};
+
+[[group(2), binding(0)]] var t_dem: texture_2d<f32>;
+[[group(2), binding(1)]] var<uniform> dem_tile: DemTile;
+[[group(3), binding(0)]] var<uniform> hillshade: Hillshade;
+
+struct Output {
+    [[location(0)]] out_color: vec4<f32>;
+};
+
+let PI = 3.141592653589793;
+// Circumference of the earth at the equator in meters
+let EARTH_CIRCUMFERENCE = 40075016.686;
+
+// Elevations are not filterable, therefore they are loaded directly. Pixels at the edges are
+// repeated.
+fn elevation(pixel: vec2<i32>, size: vec2<i32>) -> f32 {
+    return textureLoad(t_dem, clamp(pixel, vec2<i32>(0, 0), size - vec2<i32>(1, 1)), 0).r;
+}
+
+[[stage(fragment)]]
+fn main([[location(0)]] v_color: vec4<f32>, [[location(1)]] v_tex_coords: vec2<f32>) -> Output {
+    let size = textureDimensions(t_dem);
+    let pixel = vec2<i32>(v_tex_coords * vec2<f32>(size));
+
+    // Sobel operator over the neighbouring pixels
+    let a = elevation(pixel + vec2<i32>(-1, -1), size);
+    let b = elevation(pixel + vec2<i32>(0, -1), size);
+    let c = elevation(pixel + vec2<i32>(1, -1), size);
+    let d = elevation(pixel + vec2<i32>(-1, 0), size);
+    let f = elevation(pixel + vec2<i32>(1, 0), size);
+    let g = elevation(pixel + vec2<i32>(-1, 1), size);
+    let h = elevation(pixel + vec2<i32>(0, 1), size);
+    let i = elevation(pixel + vec2<i32>(1, 1), size);
+
+    let z = dem_tile.coords.z;
+    let tiles = exp2(z);
+    // Pixels shrink towards the poles in web mercator
+    let mercator_y = (dem_tile.coords.y + v_tex_coords.y) / tiles;
+    let latitude = 2.0 * atan(exp(PI * (1.0 - 2.0 * mercator_y))) - PI / 2.0;
+    let pixel_size = EARTH_CIRCUMFERENCE * cos(latitude) / (tiles * f32(size.x));
+
+    // Like MapLibre, slopes at low zoom levels are exaggerated, such that large features of the
+    // terrain stay visible
+    let exaggeration_factor = select(select(0.3, 0.35, z < 4.5), 0.4, z < 2.0);
+    let exaggeration = min(z - 15.0, 0.0) * exaggeration_factor;
+
+    let deriv = vec2<f32>(
+        (c + 2.0 * f + i) - (a + 2.0 * d + g),
+        (g + 2.0 * h + i) - (a + 2.0 * b + c)
+    ) / (8.0 * pixel_size * exp2(exaggeration));
+
+    let slope = atan(1.25 * length(deriv));
+    var aspect = PI / 2.0 * sign(deriv.y);
+    if (deriv.x != 0.0) {
+        aspect = atan2(deriv.y, -deriv.x);
+    }
+
+    let intensity = hillshade.light.x;
+    let azimuth = hillshade.light.y + PI;
+
+    // Scale the slope exponentially based on the intensity
+    let base = 1.875 - intensity * 1.75;
+    let max_value = 0.5 * PI;
+    var scaled_slope = slope;
+    if (intensity != 0.5) {
+        scaled_slope = ((pow(base, slope) - 1.0) / (pow(base, max_value) - 1.0)) * max_value;
+    }
+
+    let accent = cos(scaled_slope);
+    let accent_color = (1.0 - accent) * hillshade.accent * clamp(intensity * 2.0, 0.0, 1.0);
+    let shade = abs((((aspect + azimuth) / PI + 0.5) % 2.0 + 2.0) % 2.0 - 1.0);
+    let shade_color = mix(hillshade.shadow, hillshade.highlight, vec4<f32>(shade, shade, shade, shade)) * sin(scaled_slope) * clamp(intensity * 2.0, 0.0, 1.0);
+
+    return Output(accent_color * (1.0 - shade_color.a) + shade_color);
+}
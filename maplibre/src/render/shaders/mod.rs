@@ -4,6 +4,7 @@ use wgpu::{
 };
 
 use crate::coords::WorldCoords;
+use crate::io::feature_id::FeatureId;
 use bytemuck_derive::{Pod, Zeroable};
 use cgmath::SquareMatrix;
 
@@ -86,108 +87,200 @@ pub mod tile {
 
     use super::{FragmentShaderState, VertexShaderState};
 
+    /// The vertices are displaced by the elevation of the terrain
     pub const VERTEX: VertexShaderState = VertexShaderState::new(
-        include_str!("tile.vertex.wgsl"),
-        &[
-            // vertex data
-            wgpu::VertexBufferLayout {
-                array_stride: std::mem::size_of::<ShaderVertex>() as u64,
-                step_mode: wgpu::VertexStepMode::Vertex,
-                attributes: &[
-                    // position
-                    wgpu::VertexAttribute {
-                        offset: 0,
-                        format: wgpu::VertexFormat::Float32x2,
-                        shader_location: 0,
-                    },
-                    // normal
-                    wgpu::VertexAttribute {
-                        offset: wgpu::VertexFormat::Float32x2.size(),
-                        format: wgpu::VertexFormat::Float32x2,
-                        shader_location: 1,
-                    },
-                ],
-            },
-            // tile metadata
-            wgpu::VertexBufferLayout {
-                array_stride: std::mem::size_of::<ShaderTileMetadata>() as u64,
-                step_mode: wgpu::VertexStepMode::Instance,
-                attributes: &[
-                    // translate
-                    wgpu::VertexAttribute {
-                        offset: 0,
-                        format: wgpu::VertexFormat::Float32x4,
-                        shader_location: 4,
-                    },
-                    wgpu::VertexAttribute {
-                        offset: 1 * wgpu::VertexFormat::Float32x4.size(),
-                        format: wgpu::VertexFormat::Float32x4,
-                        shader_location: 5,
-                    },
-                    wgpu::VertexAttribute {
-                        offset: 2 * wgpu::VertexFormat::Float32x4.size(),
-                        format: wgpu::VertexFormat::Float32x4,
-                        shader_location: 6,
-                    },
-                    wgpu::VertexAttribute {
-                        offset: 3 * wgpu::VertexFormat::Float32x4.size(),
-                        format: wgpu::VertexFormat::Float32x4,
-                        shader_location: 7,
-                    },
-                    // zoom_factor
-                    wgpu::VertexAttribute {
-                        offset: 4 * wgpu::VertexFormat::Float32x4.size(),
-                        format: wgpu::VertexFormat::Float32,
-                        shader_location: 9,
-                    },
-                ],
-            },
-            // layer metadata
-            wgpu::VertexBufferLayout {
-                array_stride: std::mem::size_of::<ShaderLayerMetadata>() as u64,
-                step_mode: wgpu::VertexStepMode::Instance,
-                attributes: &[
-                    // z_index
-                    wgpu::VertexAttribute {
-                        offset: 0,
-                        format: wgpu::VertexFormat::Float32,
-                        shader_location: 10,
-                    },
-                ],
-            },
-            // features
-            wgpu::VertexBufferLayout {
-                array_stride: std::mem::size_of::<ShaderFeatureStyle>() as u64,
-                step_mode: wgpu::VertexStepMode::Vertex,
-                attributes: &[
-                    // color
-                    wgpu::VertexAttribute {
-                        offset: 0,
-                        format: wgpu::VertexFormat::Float32x4,
-                        shader_location: 8,
-                    },
-                ],
-            },
-        ],
+        concat!(
+            include_str!("terrain.wgsl"),
+            include_str!("tile.vertex.wgsl")
+        ),
+        VERTEX_BUFFERS,
     );
 
+    /// Layouts of the vertex, tile metadata, layer metadata and feature buffers
+    pub const VERTEX_BUFFERS: &[wgpu::VertexBufferLayout<'static>] = &[
+        // vertex data
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ShaderVertex>() as u64,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                // position
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    format: wgpu::VertexFormat::Float32x2,
+                    shader_location: 0,
+                },
+                // normal
+                wgpu::VertexAttribute {
+                    offset: wgpu::VertexFormat::Float32x2.size(),
+                    format: wgpu::VertexFormat::Float32x2,
+                    shader_location: 1,
+                },
+                // advancement and side
+                wgpu::VertexAttribute {
+                    offset: 2 * wgpu::VertexFormat::Float32x2.size(),
+                    format: wgpu::VertexFormat::Float32x2,
+                    shader_location: 2,
+                },
+            ],
+        },
+        // tile metadata
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ShaderTileMetadata>() as u64,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                // translate
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    format: wgpu::VertexFormat::Float32x4,
+                    shader_location: 4,
+                },
+                wgpu::VertexAttribute {
+                    offset: 1 * wgpu::VertexFormat::Float32x4.size(),
+                    format: wgpu::VertexFormat::Float32x4,
+                    shader_location: 5,
+                },
+                wgpu::VertexAttribute {
+                    offset: 2 * wgpu::VertexFormat::Float32x4.size(),
+                    format: wgpu::VertexFormat::Float32x4,
+                    shader_location: 6,
+                },
+                wgpu::VertexAttribute {
+                    offset: 3 * wgpu::VertexFormat::Float32x4.size(),
+                    format: wgpu::VertexFormat::Float32x4,
+                    shader_location: 7,
+                },
+                // zoom_factor
+                wgpu::VertexAttribute {
+                    offset: 4 * wgpu::VertexFormat::Float32x4.size(),
+                    format: wgpu::VertexFormat::Float32,
+                    shader_location: 9,
+                },
+                // coords
+                wgpu::VertexAttribute {
+                    offset: 4 * wgpu::VertexFormat::Float32x4.size()
+                        + wgpu::VertexFormat::Float32.size(),
+                    format: wgpu::VertexFormat::Float32x4,
+                    shader_location: 11,
+                },
+            ],
+        },
+        // layer metadata
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ShaderLayerMetadata>() as u64,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                // z_index
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    format: wgpu::VertexFormat::Float32,
+                    shader_location: 10,
+                },
+                // dash
+                wgpu::VertexAttribute {
+                    offset: wgpu::VertexFormat::Float32.size(),
+                    format: wgpu::VertexFormat::Float32x2,
+                    shader_location: 15,
+                },
+                // translate
+                wgpu::VertexAttribute {
+                    offset: wgpu::VertexFormat::Float32.size()
+                        + wgpu::VertexFormat::Float32x2.size(),
+                    format: wgpu::VertexFormat::Float32x2,
+                    shader_location: 3,
+                },
+            ],
+        },
+        // features
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ShaderFeatureStyle>() as u64,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                // color
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    format: wgpu::VertexFormat::Float32x4,
+                    shader_location: 8,
+                },
+                // feature id
+                wgpu::VertexAttribute {
+                    offset: wgpu::VertexFormat::Float32x4.size(),
+                    format: wgpu::VertexFormat::Uint32x2,
+                    shader_location: 12,
+                },
+                // width and offset
+                wgpu::VertexAttribute {
+                    offset: wgpu::VertexFormat::Float32x4.size()
+                        + wgpu::VertexFormat::Uint32x2.size(),
+                    format: wgpu::VertexFormat::Float32x2,
+                    shader_location: 13,
+                },
+                // pattern
+                wgpu::VertexAttribute {
+                    offset: wgpu::VertexFormat::Float32x4.size()
+                        + wgpu::VertexFormat::Uint32x2.size()
+                        + wgpu::VertexFormat::Float32x2.size(),
+                    format: wgpu::VertexFormat::Float32x4,
+                    shader_location: 14,
+                },
+            ],
+        },
+    ];
+
     pub const FRAGMENT: FragmentShaderState = FragmentShaderState::new(
         include_str!("tile.fragment.wgsl"),
         &[wgpu::ColorTargetState {
             format: COLOR_TEXTURE_FORMAT,
-            /*blend: Some(wgpu::BlendState {
-                color: wgpu::BlendComponent {
-                    src_factor: wgpu::BlendFactor::SrcAlpha,
-                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                    operation: wgpu::BlendOperation::Add,
-                },
-                alpha: wgpu::BlendComponent {
-                    src_factor: wgpu::BlendFactor::SrcAlpha,
-                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                    operation: wgpu::BlendOperation::Add,
-                },
-            }),*/
-            blend: None,
+            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+            write_mask: wgpu::ColorWrites::ALL,
+        }],
+    );
+}
+
+pub mod raster {
+    use crate::platform::COLOR_TEXTURE_FORMAT;
+
+    use super::{FragmentShaderState, VertexShaderState};
+
+    /// Raster tiles use the same buffers as vector layers. The color of the features carries the
+    /// opacity of the layer.
+    pub const VERTEX: VertexShaderState = VertexShaderState::new(
+        concat!(
+            include_str!("terrain.wgsl"),
+            include_str!("raster.vertex.wgsl")
+        ),
+        super::tile::VERTEX_BUFFERS,
+    );
+
+    pub const FRAGMENT: FragmentShaderState = FragmentShaderState::new(
+        include_str!("raster.fragment.wgsl"),
+        &[wgpu::ColorTargetState {
+            format: COLOR_TEXTURE_FORMAT,
+            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+            write_mask: wgpu::ColorWrites::ALL,
+        }],
+    );
+}
+
+pub mod hillshade {
+    use crate::platform::COLOR_TEXTURE_FORMAT;
+
+    use super::{FragmentShaderState, VertexShaderState};
+
+    /// Hillshade layers draw the same quads as raster layers
+    pub const VERTEX: VertexShaderState = VertexShaderState::new(
+        concat!(
+            include_str!("terrain.wgsl"),
+            include_str!("raster.vertex.wgsl")
+        ),
+        super::tile::VERTEX_BUFFERS,
+    );
+
+    /// The fragment shader outputs colors with premultiplied alpha
+    pub const FRAGMENT: FragmentShaderState = FragmentShaderState::new(
+        include_str!("hillshade.fragment.wgsl"),
+        &[wgpu::ColorTargetState {
+            format: COLOR_TEXTURE_FORMAT,
+            blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
             write_mask: wgpu::ColorWrites::ALL,
         }],
     );
@@ -201,8 +294,13 @@ pub mod tile_mask {
 
     use super::{FragmentShaderState, VertexShaderState};
 
+    /// The mask is drawn as a grid, such that it can follow the elevation of the terrain. The
+    /// size of the grid is [`TERRAIN_GRID_SIZE`](crate::render::options::TERRAIN_GRID_SIZE).
     pub const VERTEX: VertexShaderState = VertexShaderState::new(
-        include_str!("tile_mask.vertex.wgsl"),
+        concat!(
+            include_str!("terrain.wgsl"),
+            include_str!("tile_mask.vertex.wgsl")
+        ),
         &[wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<ShaderTileMetadata>() as u64,
             step_mode: wgpu::VertexStepMode::Instance,
@@ -228,6 +326,13 @@ pub mod tile_mask {
                     format: wgpu::VertexFormat::Float32x4,
                     shader_location: 7,
                 },
+                // coords
+                wgpu::VertexAttribute {
+                    offset: 4 * wgpu::VertexFormat::Float32x4.size()
+                        + wgpu::VertexFormat::Float32.size(),
+                    format: wgpu::VertexFormat::Float32x4,
+                    shader_location: 11,
+                },
             ],
         }],
     );
@@ -294,11 +399,25 @@ impl ShaderGlobals {
 pub struct ShaderVertex {
     pub position: Vec2f32,
     pub normal: Vec2f32,
+    /// Distance along the line from its start, in tile units
+    pub advancement: f32,
+    /// `1.0` on the left side of a line and `-1.0` on its right side
+    pub side: f32,
 }
 
 impl ShaderVertex {
     pub fn new(position: Vec2f32, normal: Vec2f32) -> Self {
-        Self { position, normal }
+        Self::stroke(position, normal, 0.0, 0.0)
+    }
+
+    /// A vertex of a line, which is extruded along its `normal` within the shader
+    pub fn stroke(position: Vec2f32, normal: Vec2f32, advancement: f32, side: f32) -> Self {
+        Self {
+            position,
+            normal,
+            advancement,
+            side,
+        }
     }
 }
 
@@ -312,17 +431,69 @@ impl Default for ShaderVertex {
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
 pub struct ShaderFeatureStyle {
     pub color: Vec4f32,
+    /// The lower and upper 32 bits of [`FeatureId::numeric`]. Features without id are
+    /// [`u64::MAX`].
+    pub feature_id: [u32; 2],
+    /// Half of the width of lines and outlines in tile units at the zoom level of the tile
+    pub width: f32,
+    /// Offset of lines to their right in tile units at the zoom level of the tile
+    pub offset: f32,
+    /// The position and size of the pattern within the sprite atlas in texels. Features without
+    /// pattern have a size of zero.
+    pub pattern: Vec4f32,
+}
+
+impl ShaderFeatureStyle {
+    pub fn new(color: Vec4f32, width: f32, feature_id: Option<&FeatureId>) -> Self {
+        let feature_id = feature_id.map_or(u64::MAX, FeatureId::numeric);
+        Self {
+            color,
+            feature_id: [feature_id as u32, (feature_id >> 32) as u32],
+            width,
+            offset: 0.0,
+            pattern: [0.0, 0.0, 0.0, 0.0],
+        }
+    }
+
+    pub fn with_offset(mut self, offset: f32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_pattern(mut self, pattern: Vec4f32) -> Self {
+        self.pattern = pattern;
+        self
+    }
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, Pod, Zeroable)]
 pub struct ShaderLayerMetadata {
     pub z_index: f32,
+    /// The vertical texture coordinate of the dash pattern within the dash atlas and the length
+    /// of the pattern in line widths. Lines without dashes have a length of zero.
+    pub dash: Vec2f32,
+    /// Offset of the layer in tile units at the zoom level of the tile
+    pub translate: Vec2f32,
 }
 
 impl ShaderLayerMetadata {
     pub fn new(z_index: f32) -> Self {
-        Self { z_index }
+        Self {
+            z_index,
+            dash: [0.0, 0.0],
+            translate: [0.0, 0.0],
+        }
+    }
+
+    pub fn with_dash(mut self, dash: Vec2f32) -> Self {
+        self.dash = dash;
+        self
+    }
+
+    pub fn with_translate(mut self, translate: Vec2f32) -> Self {
+        self.translate = translate;
+        self
     }
 }
 
@@ -331,13 +502,44 @@ impl ShaderLayerMetadata {
 pub struct ShaderTileMetadata {
     pub transform: Mat4x4f32,
     pub zoom_factor: f32,
+    /// x, y and z of the tile, which are required to look up the elevation of the terrain
+    pub coords: Vec4f32,
 }
 
 impl ShaderTileMetadata {
-    pub fn new(transform: Mat4x4f32, zoom_factor: f32) -> Self {
+    pub fn new(transform: Mat4x4f32, zoom_factor: f32, coords: Vec4f32) -> Self {
         Self {
             transform,
             zoom_factor,
+            coords,
+        }
+    }
+}
+
+/// Position of a raster DEM tile, which is required to compute the scale of its pixels
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct ShaderDemTile {
+    /// x, y and z of the tile and the exaggeration of the terrain. The exaggeration is zero if the
+    /// tile does not belong to the terrain.
+    pub coords: Vec4f32,
+}
+
+impl ShaderDemTile {
+    pub fn new(x: f32, y: f32, z: f32, exaggeration: f32) -> Self {
+        Self {
+            coords: [x, y, z, exaggeration],
         }
     }
 }
+
+/// Paint properties of a hillshade layer. The colors have premultiplied alpha.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct ShaderHillshade {
+    pub shadow: Vec4f32,
+    pub highlight: Vec4f32,
+    pub accent: Vec4f32,
+    /// Exaggeration and azimuth of the light in radians
+    pub light: Vec4f32,
+}
@@ -0,0 +1,12 @@
+[[group(2), binding(0)]] var t_raster: texture_2d<f32>;
+[[group(2), binding(1)]] var s_raster: sampler;
+
+struct Output {
+    [[location(0)]] out_color: vec4<f32>;
+};
+
+[[stage(fragment)]]
+fn main([[location(0)]] v_color: vec4<f32>, [[location(1)]] v_tex_coords: vec2<f32>) -> Output {
+    // The color of the vertices carries the opacity of the layer
+    return Output(textureSample(t_raster, s_raster, v_tex_coords) * v_color);
+}
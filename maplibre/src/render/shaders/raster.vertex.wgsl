@@ -0,0 +1,41 @@
+struct ShaderCamera {
+    view_proj: mat4x4<f32>;
+    view_position: vec4<f32>;
+};
+
+struct ShaderGlobals {
+    camera: ShaderCamera;
+};
+
+[[group(0), binding(0)]] var<uniform> globals: ShaderGlobals;
+
+struct VertexOutput {
+    [[location(0)]] v_color: vec4<f32>;
+    [[location(1)]] v_tex_coords: vec2<f32>;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+let EXTENT = 4096.0;
+
+[[stage(vertex)]]
+fn main(
+    [[location(0)]] position: vec2<f32>,
+    [[location(4)]] translate1: vec4<f32>,
+    [[location(5)]] translate2: vec4<f32>,
+    [[location(6)]] translate3: vec4<f32>,
+    [[location(7)]] translate4: vec4<f32>,
+    [[location(8)]] color: vec4<f32>,
+    [[location(10)]] z_index: f32,
+    [[location(11)]] tile_coords: vec4<f32>
+) -> VertexOutput {
+    let z = terrain_elevation(position, tile_coords.xyz);
+
+    // The quad spans the whole tile, therefore the texture coordinates follow from the position
+    let tex_coords = position / EXTENT;
+
+    var clip_position = mat4x4<f32>(translate1, translate2, translate3, translate4) * vec4<f32>(position, z, 1.0);
+    // FIXME: how to fix z-fighting?
+    clip_position.z = z_index;
+
+    return VertexOutput(color, tex_coords, clip_position);
+}
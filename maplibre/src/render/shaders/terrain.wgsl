@@ -0,0 +1,45 @@
+// Displaces vertices by the elevation of the terrain. This is prepended to the vertex shaders of
+// all layers. Tiles which are not covered by the terrain are bound to a flat DEM tile with an
+// exaggeration of zero.
+struct TerrainTile {
+    // x, y and z of the DEM tile and the exaggeration of the terrain
+    coords: vec4<f32>;
+};
+
+[[group(1), binding(0)]] var t_terrain: texture_2d<f32>;
+[[group(1), binding(1)]] var<uniform> terrain_tile: TerrainTile;
+
+let TERRAIN_EXTENT = 4096.0;
+let TERRAIN_PI = 3.141592653589793;
+// Circumference of the earth at the equator in meters
+let TERRAIN_EARTH_CIRCUMFERENCE = 40075016.686;
+
+// Returns the elevation at `position` within the tile `tile` (x, y and z). The elevation is
+// returned in the units of the tile, such that it is scaled like the position.
+fn terrain_elevation(position: vec2<f32>, tile: vec3<f32>) -> f32 {
+    let exaggeration = terrain_tile.coords.w;
+    if (exaggeration == 0.0) {
+        return 0.0;
+    }
+
+    let tiles = exp2(tile.z);
+    // Position within the world in [0, 1]
+    let world = (tile.xy + position / TERRAIN_EXTENT) / tiles;
+
+    // The DEM tile can be an ancestor of the tile
+    let dem_coords = world * exp2(terrain_tile.coords.z) - terrain_tile.coords.xy;
+    let size = textureDimensions(t_terrain);
+    let pixel = clamp(
+        vec2<i32>(floor(dem_coords * vec2<f32>(size))),
+        vec2<i32>(0, 0),
+        size - vec2<i32>(1, 1)
+    );
+    let elevation = textureLoad(t_terrain, pixel, 0).r;
+
+    // Meters per unit of the tile at the latitude of the position
+    let latitude = 2.0 * atan(exp(TERRAIN_PI * (1.0 - 2.0 * world.y))) - TERRAIN_PI / 2.0;
+    let meters_per_unit = TERRAIN_EARTH_CIRCUMFERENCE * cos(latitude) / (tiles * TERRAIN_EXTENT);
+
+    return elevation * exaggeration / meters_per_unit;
+}
+
@@ -2,7 +2,43 @@ struct Output {
     [[location(0)]] out_color: vec4<f32>;
 };
 
+[[group(2), binding(0)]] var t_dash: texture_2d<f32>;
+[[group(2), binding(1)]] var s_dash: sampler;
+
+[[group(3), binding(0)]] var t_sprite: texture_2d<f32>;
+[[group(3), binding(1)]] var s_sprite: sampler;
+
 [[stage(fragment)]]
-fn main([[location(0)]] v_color: vec4<f32>) -> Output {
-    return Output(v_color);
+fn main(
+    [[location(0)]] v_color: vec4<f32>,
+    [[location(1)]] v_dash: vec2<f32>,
+    [[location(2)]] v_pattern: vec4<f32>,
+    [[location(3)]] v_pattern_position: vec2<f32>
+) -> Output {
+    // The atlases are sampled outside of the branches, because sampling requires uniform control
+    // flow
+    let dash = textureSample(t_dash, s_dash, v_dash).r;
+
+    // The texels at the border of the pattern are not interpolated with the neighbouring images of
+    // the sprite
+    let texel = v_pattern.xy + clamp(
+        fract(v_pattern_position) * v_pattern.zw,
+        vec2<f32>(0.5, 0.5),
+        max(v_pattern.zw - vec2<f32>(0.5, 0.5), vec2<f32>(0.5, 0.5))
+    );
+    let dimensions = textureDimensions(t_sprite);
+    let sprite_size = vec2<f32>(f32(dimensions.x), f32(dimensions.y));
+    let pattern = textureSample(t_sprite, s_sprite, texel / sprite_size);
+
+    if (v_dash.y >= 0.0 && dash < 0.5) {
+        discard;
+    }
+
+    // The color of features with a pattern only carries the opacity
+    var color = v_color;
+    if (v_pattern.z > 0.0) {
+        color = color * pattern;
+    }
+
+    return Output(color);
 }
@@ -11,13 +11,26 @@ struct ShaderGlobals {
 
 struct VertexOutput {
     [[location(0)]] v_color: vec4<f32>;
+    // Coordinates within the dash atlas. The y coordinate is negative for lines without dashes.
+    [[location(1)]] v_dash: vec2<f32>;
+    // Position and size of the pattern within the sprite atlas in texels
+    [[location(2)]] v_pattern: vec4<f32>;
+    // Position in multiples of the size of the pattern
+    [[location(3)]] v_pattern_position: vec2<f32>;
     [[builtin(position)]] position: vec4<f32>;
 };
 
+// A pixel spans EXTENT / TILE_SIZE tile units at the zoom level of the tile
+let TILE_UNITS_PER_PIXEL = 8.0;
+
 [[stage(vertex)]]
 fn main(
     [[location(0)]] position: vec2<f32>,
     [[location(1)]] normal: vec2<f32>,
+    // Distance along the line and side of the line
+    [[location(2)]] stroke: vec2<f32>,
+    // Offset of the layer
+    [[location(3)]] layer_translate: vec2<f32>,
     [[location(4)]] translate1: vec4<f32>,
     [[location(5)]] translate2: vec4<f32>,
     [[location(6)]] translate3: vec4<f32>,
@@ -25,19 +38,40 @@ fn main(
     [[location(8)]] color: vec4<f32>,
     [[location(9)]] zoom_factor: f32,
     [[location(10)]] z_index: f32,
+    [[location(11)]] tile_coords: vec4<f32>,
+    // Half of the width and offset of lines
+    [[location(13)]] line: vec2<f32>,
+    [[location(14)]] pattern: vec4<f32>,
+    [[location(15)]] dash: vec2<f32>,
     [[builtin(instance_index)]] instance_idx: u32 // instance_index is used when we have multiple instances of the same "object"
 ) -> VertexOutput {
-    let z = 0.0;
-    let width = 3.0 * zoom_factor;
+    let z = terrain_elevation(position, tile_coords.xyz);
+
+    // Patterns are anchored to the tile and keep their size in pixels at the zoom level of the tile
+    var v_pattern_position = vec2<f32>(0.0, 0.0);
+    if (pattern.z > 0.0 && pattern.w > 0.0) {
+        v_pattern_position = position / (pattern.zw * TILE_UNITS_PER_PIXEL);
+    }
+
+    let width = line.x * zoom_factor;
+    let offset = line.y * zoom_factor;
 
     // The following code moves all "invisible" vertices to (0, 0, 0)
     //if (color.w == 0.0) {
     //   return VertexOutput(color, vec4<f32>(0.0, 0.0, 0.0, 1.0));
     //}
 
-    var position = mat4x4<f32>(translate1, translate2, translate3, translate4) * vec4<f32>(position + normal * width, z, 1.0);
+    // Positive offsets move both sides of a line to its right
+    let displacement = normal * (width - stroke.y * offset) + layer_translate * zoom_factor;
+    var position = mat4x4<f32>(translate1, translate2, translate3, translate4) * vec4<f32>(position + displacement, z, 1.0);
     // FIXME: how to fix z-fighting?
     position.z = z_index;
 
-    return VertexOutput(color, position);
+    // The lengths of the dashes are multiples of the width of the line
+    var v_dash = vec2<f32>(0.0, -1.0);
+    if (dash.y > 0.0 && width > 0.0) {
+        v_dash = vec2<f32>(stroke.x / (dash.y * 2.0 * width), dash.x);
+    }
+
+    return VertexOutput(color, v_dash, pattern, v_pattern_position, position);
 }
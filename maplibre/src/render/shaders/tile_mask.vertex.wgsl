@@ -15,6 +15,8 @@ struct VertexOutput {
 };
 
 let EXTENT = 4096.0;
+// Must match TERRAIN_GRID_SIZE
+let GRID_SIZE = 32u;
 
 [[stage(vertex)]]
 fn main(
@@ -22,24 +24,27 @@ fn main(
     [[location(5)]] translate2: vec4<f32>,
     [[location(6)]] translate3: vec4<f32>,
     [[location(7)]] translate4: vec4<f32>,
+    [[location(11)]] tile_coords: vec4<f32>,
     [[builtin(vertex_index)]] vertex_idx: u32,
     [[builtin(instance_index)]] instance_idx: u32 // instance_index is used when we have multiple instances of the same "object"
 ) -> VertexOutput {
-    let z = 0.0;
-
     let target_width = 1.0;
     let target_height = 1.0;
     let debug_color = vec4<f32>(1.0, 0.0, 0.0, 1.0);
 
-    var VERTICES: array<vec3<f32>, 6> = array<vec3<f32>, 6>(
-        vec3<f32>(0.0, 0.0, z),
-        vec3<f32>(0.0, EXTENT, z),
-        vec3<f32>(EXTENT, 0.0, z),
-        vec3<f32>(EXTENT, 0.0, z),
-        vec3<f32>(0.0, EXTENT, z),
-        vec3<f32>(EXTENT, EXTENT, z)
+    // Corners of the two triangles of a cell
+    var VERTICES: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 1.0)
     );
-    let a_position = VERTICES[vertex_idx];
+    let cell = vertex_idx / 6u;
+    let cell_position = vec2<f32>(f32(cell % GRID_SIZE), f32(cell / GRID_SIZE));
+    let xy = (cell_position + VERTICES[vertex_idx % 6u]) * (EXTENT / f32(GRID_SIZE));
+    let a_position = vec3<f32>(xy, terrain_elevation(xy, tile_coords.xyz));
 
     let scaling: mat3x3<f32> = mat3x3<f32>(
             vec3<f32>(target_width,   0.0,            0.0),
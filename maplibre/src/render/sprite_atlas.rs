@@ -0,0 +1,195 @@
+//! The image of the sprite of the style on the GPU. Patterns of fill layers are sampled from it
+//! within the fragment shader.
+
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+
+use crate::io::raster::RasterImage;
+use crate::io::sprite::{Sprite, SpriteImage};
+use crate::render::raster::RASTER_TEXTURE_FORMAT;
+use crate::render::shaders::Vec4f32;
+
+/// Positions of the images of the sprite within the [`SpriteAtlas`], keyed by their names
+#[derive(Default)]
+pub struct SpritePatterns {
+    images: HashMap<String, SpriteImage>,
+}
+
+impl SpritePatterns {
+    pub fn new(images: HashMap<String, SpriteImage>) -> Self {
+        Self { images }
+    }
+
+    /// Returns the position and size of the image `name` in texels, as expected by
+    /// [`ShaderFeatureStyle::pattern`](crate::render::shaders::ShaderFeatureStyle).
+    pub fn pattern(&self, name: &str) -> Option<Vec4f32> {
+        self.images.get(name).map(|image| {
+            [
+                image.x as f32,
+                image.y as f32,
+                image.width as f32,
+                image.height as f32,
+            ]
+        })
+    }
+}
+
+/// A texture which holds the image of the sprite
+pub struct SpriteAtlas {
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    // The texture needs to be kept alive as long as the bind group is used
+    _texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    patterns: SpritePatterns,
+}
+
+impl SpriteAtlas {
+    /// Creates an atlas with a blank texture, which is used until a sprite is uploaded
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sprite atlas bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        // Patterns are repeated within the shader, because they are only a part of the texture
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Sprite atlas sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let blank = RasterImage {
+            width: 1,
+            height: 1,
+            data: vec![0; 4],
+        };
+        let (texture, bind_group) =
+            Self::create_texture(device, queue, &bind_group_layout, &sampler, &blank);
+
+        Self {
+            bind_group_layout,
+            sampler,
+            _texture: texture,
+            bind_group,
+            patterns: SpritePatterns::default(),
+        }
+    }
+
+    fn create_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        image: &RasterImage,
+    ) -> (wgpu::Texture, wgpu::BindGroup) {
+        let size = wgpu::Extent3d {
+            width: image.width,
+            height: image.height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Sprite atlas texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: RASTER_TEXTURE_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &image.data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(image.bytes_per_row()),
+                rows_per_image: NonZeroU32::new(image.height),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sprite atlas bind group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+
+        (texture, bind_group)
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Replaces the image of the atlas by the image of `sprite`. Sprites which exceed the maximum
+    /// size of textures are not uploaded.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, sprite: &Sprite) {
+        let max_size = device.limits().max_texture_dimension_2d;
+        if sprite.image.width > max_size || sprite.image.height > max_size {
+            tracing::error!(
+                "sprite of {}x{} pixels exceeds the maximum texture size",
+                sprite.image.width,
+                sprite.image.height
+            );
+            return;
+        }
+
+        let (texture, bind_group) = Self::create_texture(
+            device,
+            queue,
+            &self.bind_group_layout,
+            &self.sampler,
+            &sprite.image,
+        );
+        self._texture = texture;
+        self.bind_group = bind_group;
+        self.patterns = SpritePatterns::new(sprite.images.clone());
+    }
+
+    pub fn patterns(&self) -> &SpritePatterns {
+        &self.patterns
+    }
+}
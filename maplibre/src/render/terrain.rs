@@ -0,0 +1,180 @@
+//! Elevations of the terrain on the CPU. The GPU displaces the geometry of all layers by the same
+//! elevations. On the CPU they are required to compute the visible region and to pick positions on
+//! the terrain.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::coords::{WorldCoords, WorldTileCoords, Zoom, TILE_SIZE};
+use crate::io::raster::DemImage;
+use crate::style::Terrain;
+
+/// Circumference of the earth at the equator in meters
+pub const EARTH_CIRCUMFERENCE: f64 = 40_075_016.686;
+
+struct TerrainTile {
+    dem: Arc<DemImage>,
+    /// Lowest and highest elevation within the tile in meters
+    range: (f64, f64),
+}
+
+/// Raster DEM tiles of the terrain source which are loaded.
+pub struct TerrainElevation {
+    source_id: String,
+    exaggeration: f64,
+    tiles: HashMap<WorldTileCoords, TerrainTile>,
+    /// Highest zoom level of the loaded tiles
+    max_z: u8,
+}
+
+impl TerrainElevation {
+    pub fn new(terrain: &Terrain) -> Self {
+        Self {
+            source_id: terrain.source.clone(),
+            exaggeration: terrain.exaggeration() as f64,
+            tiles: HashMap::new(),
+            max_z: 0,
+        }
+    }
+
+    /// The id of the `raster-dem` source which provides the elevations
+    pub fn source_id(&self) -> &str {
+        self.source_id.as_str()
+    }
+
+    pub fn insert(&mut self, coords: WorldTileCoords, dem: Arc<DemImage>) {
+        let range = dem
+            .elevations
+            .iter()
+            .fold((f64::MAX, f64::MIN), |(min, max), elevation| {
+                (min.min(*elevation as f64), max.max(*elevation as f64))
+            });
+
+        self.max_z = self.max_z.max(coords.z);
+        self.tiles.insert(coords, TerrainTile { dem, range });
+    }
+
+    /// Releases the tiles for which `keep` returns false.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&WorldTileCoords) -> bool,
+    {
+        self.tiles.retain(|coords, _| keep(coords));
+        self.max_z = self.tiles.keys().map(|coords| coords.z).max().unwrap_or(0);
+    }
+
+    /// The lowest and highest elevation of the loaded tiles in meters, multiplied by the
+    /// exaggeration. Without loaded tiles the terrain is flat.
+    pub fn elevation_range(&self) -> (f64, f64) {
+        self.tiles
+            .values()
+            .map(|tile| tile.range)
+            .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+            .map_or((0.0, 0.0), |(min, max)| {
+                (min * self.exaggeration, max * self.exaggeration)
+            })
+    }
+
+    /// The elevation at `world_coords` in meters, multiplied by the exaggeration. The elevation is
+    /// read from the loaded tile with the highest zoom level. Where no tile is loaded the
+    /// elevation is zero.
+    pub fn elevation(&self, world_coords: &WorldCoords, zoom: Zoom) -> f64 {
+        for z in (0..=self.max_z).rev() {
+            let coords = world_coords.into_world_tile(z, zoom);
+            let tile = match self.tiles.get(&coords) {
+                Some(tile) => tile,
+                None => continue,
+            };
+
+            let dem = &tile.dem;
+            let tile_scale = zoom.scale_to_zoom_level(z) / TILE_SIZE;
+            let pixel = |world: f64, tile: i32, size: u32| {
+                let position = world * tile_scale - tile as f64;
+                ((position * size as f64) as i64).clamp(0, size as i64 - 1) as usize
+            };
+            let x = pixel(world_coords.x, coords.x, dem.width);
+            let y = pixel(world_coords.y, coords.y, dem.height);
+
+            return dem.elevations[y * dem.width as usize + x] as f64 * self.exaggeration;
+        }
+
+        0.0
+    }
+}
+
+/// Units of the world coordinates per meter at the position `world_y` for `zoom`. Like in the Web
+/// Mercator projection, the scale grows towards the poles.
+pub fn world_units_per_meter(world_y: f64, zoom: Zoom) -> f64 {
+    let world_size = TILE_SIZE / zoom.scale_to_zoom_level(0);
+    let mercator_y = (world_y / world_size).clamp(0.0, 1.0);
+    let latitude = (PI * (1.0 - 2.0 * mercator_y)).sinh().atan();
+    world_size / (EARTH_CIRCUMFERENCE * latitude.cos())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::coords::{WorldCoords, WorldTileCoords, Zoom, TILE_SIZE};
+    use crate::io::raster::DemImage;
+    use crate::style::Terrain;
+
+    use super::{world_units_per_meter, TerrainElevation, EARTH_CIRCUMFERENCE};
+
+    #[test]
+    fn test_elevation() {
+        let mut terrain = TerrainElevation::new(&Terrain {
+            source: "terrain".to_string(),
+            exaggeration: Some(2.0),
+        });
+        let zoom = Zoom::new(1.0);
+
+        // The tile at zoom level 0 covers the whole world, its left half is at 100m
+        terrain.insert(
+            WorldTileCoords::from((0, 0, 0)),
+            Arc::new(DemImage {
+                width: 2,
+                height: 1,
+                elevations: vec![100.0, 0.0],
+            }),
+        );
+        // The upper left tile at zoom level 1 is at 300m
+        terrain.insert(
+            WorldTileCoords::from((0, 0, 1)),
+            Arc::new(DemImage {
+                width: 1,
+                height: 1,
+                elevations: vec![300.0],
+            }),
+        );
+
+        assert_eq!(terrain.elevation_range(), (0.0, 600.0));
+        assert_eq!(
+            terrain.elevation(&WorldCoords::at_ground(100.0, 100.0), zoom),
+            600.0
+        );
+        assert_eq!(
+            terrain.elevation(&WorldCoords::at_ground(100.0, 800.0), zoom),
+            200.0
+        );
+        assert_eq!(
+            terrain.elevation(&WorldCoords::at_ground(800.0, 800.0), zoom),
+            0.0
+        );
+
+        terrain.retain(|coords| coords.z == 1);
+        assert_eq!(
+            terrain.elevation(&WorldCoords::at_ground(100.0, 800.0), zoom),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_world_units_per_meter() {
+        let zoom = Zoom::new(0.0);
+        let at_equator = world_units_per_meter(TILE_SIZE / 2.0, zoom);
+        assert!((at_equator - TILE_SIZE / EARTH_CIRCUMFERENCE).abs() < 1e-12);
+        assert!(world_units_per_meter(TILE_SIZE / 4.0, zoom) > at_equator);
+    }
+}
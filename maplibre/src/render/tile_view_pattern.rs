@@ -1,16 +1,14 @@
 use crate::coords::{ViewRegion, WorldTileCoords, Zoom};
 
-use crate::render::buffer_pool::{BackingBufferDescriptor, BufferPool, Queue};
+use crate::render::buffer_pool::{BackingBufferDescriptor, IndexEntry, Queue, RingIndex};
 use crate::render::camera::ViewProjection;
-use crate::render::shaders::{ShaderFeatureStyle, ShaderLayerMetadata, ShaderTileMetadata};
+use crate::render::shaders::ShaderTileMetadata;
 use cgmath::Matrix4;
 
-use crate::render::ShaderVertex;
-use crate::tessellation::IndexDataType;
+use std::collections::{HashSet, VecDeque};
 use std::marker::PhantomData;
 use std::mem::size_of;
 use std::ops::Range;
-use wgpu::Buffer;
 
 /// The tile mask pattern assigns each tile a value which can be used for stencil testing.
 pub struct TileViewPattern<Q, B> {
@@ -29,21 +27,47 @@ pub struct TileShape {
 }
 
 impl TileShape {
+    const STRIDE: u64 = size_of::<ShaderTileMetadata>() as u64;
+
     fn new(coords: WorldTileCoords, zoom: Zoom, index: u64) -> Self {
-        const STRIDE: u64 = size_of::<ShaderTileMetadata>() as u64;
         Self {
             coords,
             zoom_factor: zoom.scale_to_tile(&coords),
             transform: coords.transform_for_zoom(zoom),
-            buffer_range: index as u64 * STRIDE..(index as u64 + 1) * STRIDE,
+            buffer_range: index * Self::STRIDE..(index + 1) * Self::STRIDE,
         }
     }
+
+    fn shader_metadata(&self, view_proj: &ViewProjection) -> ShaderTileMetadata {
+        ShaderTileMetadata::new(
+            // We are casting here from 64bit to 32bit, because 32bit is more performant and is
+            // better supported.
+            view_proj
+                .to_model_view_projection(self.transform)
+                .downcast()
+                .into(),
+            self.zoom_factor as f32,
+            [
+                self.coords.x as f32,
+                self.coords.y as f32,
+                self.coords.z as f32,
+                0.0,
+            ],
+        )
+    }
 }
 
 pub struct TileInView {
     pub shape: TileShape,
 
-    pub fallback: Option<TileShape>,
+    /// Shapes of ancestor tiles whose data is drawn within the bounds of `shape`. An ancestor is
+    /// used for each source which has no data at `shape`, either because the data is still loading
+    /// or because the source does not offer tiles at this zoom level (overzooming).
+    pub fallbacks: Vec<TileShape>,
+
+    /// Value which is written to the stencil buffer within the bounds of `shape`. It is unique
+    /// for each tile in view, such that the geometry of ancestors does not bleed into other tiles.
+    pub stencil_reference: u8,
 }
 
 #[derive(Debug)]
@@ -69,58 +93,75 @@ impl<Q: Queue<B>, B> TileViewPattern<Q, B> {
         }
     }
 
+    /// Assigns a shape to each tile in view and to the ancestors which are drawn as fallbacks.
+    /// The shapes are limited by the size of the buffer. Tiles in view take precedence, fallbacks
+    /// which do not fit anymore are skipped.
     #[tracing::instrument(skip_all)]
-    pub fn update_pattern(
-        &mut self,
-        view_region: &ViewRegion,
-        buffer_pool: &BufferPool<
-            wgpu::Queue,
-            Buffer,
-            ShaderVertex,
-            IndexDataType,
-            ShaderLayerMetadata,
-            ShaderFeatureStyle,
-        >,
-        zoom: Zoom,
-    ) {
+    pub fn update_pattern(&mut self, view_region: &ViewRegion, pool_index: &RingIndex, zoom: Zoom) {
         self.in_view.clear();
 
-        let mut index = 0;
-
-        let pool_index = buffer_pool.index();
+        let capacity = (self.buffer.inner_size / TileShape::STRIDE) as usize;
+        let tiles: Vec<WorldTileCoords> = view_region
+            .iter()
+            .filter(|coords| coords.build_quad_key().is_some())
+            .take(capacity)
+            .collect();
+        let mut fallback_capacity = capacity - tiles.len();
 
-        for coords in view_region.iter() {
-            if coords.build_quad_key().is_none() {
-                continue;
-            }
+        let mut index = 0;
 
+        for coords in tiles {
             let shape = TileShape::new(coords, zoom, index);
 
             index += 1;
 
-            let fallback = {
-                if !pool_index.has_tile(&coords) {
-                    if let Some(fallback_coords) = pool_index.get_tile_coords_fallback(&coords) {
+            let mut fallbacks = Vec::new();
+            let mut covered_sources: HashSet<&str> = pool_index
+                .get_layers(&coords)
+                .map(Self::sources_of)
+                .unwrap_or_default();
+
+            let mut current = coords;
+            while let Some(parent) = current.get_parent() {
+                if let Some(entries) = pool_index.get_layers(&parent) {
+                    let sources = Self::sources_of(entries);
+
+                    if !sources.is_subset(&covered_sources) {
+                        if fallback_capacity == 0 {
+                            tracing::trace!("No room left for the fallback of {coords}");
+                            break;
+                        }
+
                         tracing::trace!(
-                            "Could not find data at {coords}. Falling back to {fallback_coords}"
+                            "Could not find all data at {coords}. Falling back to {parent}"
                         );
 
-                        let shape = TileShape::new(fallback_coords, zoom, index);
-
+                        fallbacks.push(TileShape::new(parent, zoom, index));
                         index += 1;
-                        Some(shape)
-                    } else {
-                        None
+                        fallback_capacity -= 1;
+                        covered_sources.extend(sources);
                     }
-                } else {
-                    None
                 }
-            };
+                current = parent;
+            }
 
-            self.in_view.push(TileInView { shape, fallback });
+            let stencil_reference = (self.in_view.len() % u8::MAX as usize) as u8 + 1;
+
+            self.in_view.push(TileInView {
+                shape,
+                fallbacks,
+                stencil_reference,
+            });
         }
     }
 
+    fn sources_of(entries: &VecDeque<IndexEntry>) -> HashSet<&str> {
+        entries
+            .iter()
+            .filter_map(|entry| entry.style_layer.source.as_deref())
+            .collect()
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &TileInView> + '_ {
         self.in_view.iter()
     }
@@ -134,26 +175,10 @@ impl<Q: Queue<B>, B> TileViewPattern<Q, B> {
         let mut buffer = Vec::with_capacity(self.in_view.len());
 
         for tile in &self.in_view {
-            buffer.push(ShaderTileMetadata {
-                // We are casting here from 64bit to 32bit, because 32bit is more performant and is
-                // better supported.
-                transform: view_proj
-                    .to_model_view_projection(tile.shape.transform)
-                    .downcast()
-                    .into(),
-                zoom_factor: tile.shape.zoom_factor as f32,
-            });
+            buffer.push(tile.shape.shader_metadata(view_proj));
 
-            if let Some(fallback_shape) = &tile.fallback {
-                buffer.push(ShaderTileMetadata {
-                    // We are casting here from 64bit to 32bit, because 32bit is more performant and is
-                    // better supported.
-                    transform: view_proj
-                        .to_model_view_projection(fallback_shape.transform)
-                        .downcast()
-                        .into(),
-                    zoom_factor: fallback_shape.zoom_factor as f32,
-                });
+            for fallback_shape in &tile.fallbacks {
+                buffer.push(fallback_shape.shader_metadata(view_proj));
             }
         }
 
@@ -163,15 +188,105 @@ impl<Q: Queue<B>, B> TileViewPattern<Q, B> {
             bytemuck::cast_slice(buffer.as_slice()),
         );
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use lyon::tessellation::VertexBuffers;
+    use wgpu::BufferAddress;
 
-    pub fn stencil_reference_value(&self, world_coords: &WorldTileCoords) -> u8 {
-        world_coords.z * 5
-            + match (world_coords.x, world_coords.y) {
-                (x, y) if x % 2 == 0 && y % 2 == 0 => 2,
-                (x, y) if x % 2 == 0 && y % 2 != 0 => 1,
-                (x, y) if x % 2 != 0 && y % 2 == 0 => 4,
-                (x, y) if x % 2 != 0 && y % 2 != 0 => 3,
-                _ => unreachable!(),
+    use crate::coords::{ViewRegion, Zoom};
+    use crate::render::buffer_pool::{BackingBufferDescriptor, BufferPool, Queue};
+    use crate::render::camera::{Camera, Perspective};
+    use crate::render::tile_view_pattern::{TileShape, TileViewPattern};
+    use crate::style::layer::StyleLayer;
+    use crate::util::math::Aabb2;
+    use cgmath::{Deg, Point2};
+
+    #[derive(Debug)]
+    struct TestBuffer {
+        size: BufferAddress,
+    }
+    struct TestQueue;
+
+    impl Queue<TestBuffer> for TestQueue {
+        fn write_buffer(&self, buffer: &TestBuffer, offset: BufferAddress, data: &[u8]) {
+            if offset + data.len() as BufferAddress > buffer.size {
+                panic!("write out of bounds");
             }
+        }
+    }
+
+    fn pattern(shapes: u64) -> TileViewPattern<TestQueue, TestBuffer> {
+        let size = shapes * TileShape::STRIDE;
+        TileViewPattern::new(BackingBufferDescriptor::new(TestBuffer { size }, size))
+    }
+
+    #[test]
+    fn test_fallbacks_are_limited_by_buffer() {
+        let mut pool: BufferPool<TestQueue, TestBuffer, [f32; 2], u32, u32, u32> = BufferPool::new(
+            BackingBufferDescriptor::new(TestBuffer { size: 1024 }, 1024),
+            BackingBufferDescriptor::new(TestBuffer { size: 1024 }, 1024),
+            BackingBufferDescriptor::new(TestBuffer { size: 1024 }, 1024),
+            BackingBufferDescriptor::new(TestBuffer { size: 1024 }, 1024),
+        );
+        let queue = TestQueue;
+
+        let mut geometry = VertexBuffers::new();
+        geometry.vertices.push([0.0, 0.0]);
+        geometry.indices.append(&mut vec![0, 0, 0, 0]);
+        let geometry = geometry.into();
+
+        // Each source has data only at another ancestor of the tiles in view
+        let ancestors = [
+            ("a", vec![(0, 0, 2), (1, 0, 2), (0, 1, 2), (1, 1, 2)]),
+            ("b", vec![(0, 0, 1)]),
+            ("c", vec![(0, 0, 0)]),
+        ];
+        for (source, coords) in ancestors {
+            for coords in coords {
+                let style_layer = StyleLayer {
+                    source: Some(source.to_string()),
+                    ..StyleLayer::default()
+                };
+                pool.allocate_layer_geometry(&queue, coords.into(), style_layer, &geometry, 0, &[]);
+            }
+        }
+
+        // 16 tiles at z=3, each of them falls back to an ancestor of each source
+        let zoom = Zoom::new(3.0);
+        let view_region = ViewRegion::new(
+            Aabb2::new(Point2::new(0.0, 0.0), Point2::new(2000.0, 2000.0)),
+            0,
+            zoom,
+            3,
+        );
+        let camera = Camera::new((1000.0, 1000.0, 150.0), Deg(-90.0), Deg(0.0), 800, 600);
+        let perspective = Perspective::new(800, 600, Deg(110.0), 100.0, 2000.0);
+        let view_proj = camera.calc_view_proj(&perspective);
+        let shape_count = |pattern: &TileViewPattern<TestQueue, TestBuffer>| {
+            pattern
+                .iter()
+                .map(|tile| 1 + tile.fallbacks.len())
+                .sum::<usize>()
+        };
+
+        let mut large = pattern(64);
+        large.update_pattern(&view_region, pool.index(), zoom);
+        assert_eq!(large.iter().count(), 16);
+        assert_eq!(shape_count(&large), 64);
+        large.upload_pattern(&queue, &view_proj);
+
+        let mut small = pattern(20);
+        small.update_pattern(&view_region, pool.index(), zoom);
+        assert_eq!(small.iter().count(), 16);
+        assert_eq!(shape_count(&small), 20);
+        small.upload_pattern(&queue, &view_proj);
+
+        // Tiles in view which do not fit are not drawn
+        let mut tiny = pattern(10);
+        tiny.update_pattern(&view_region, pool.index(), zoom);
+        assert_eq!(shape_count(&tiny), 10);
+        tiny.upload_pattern(&queue, &view_proj);
     }
 }
@@ -0,0 +1,1211 @@
+//! Expressions of the style specification, which compute the values of filters and properties
+//! from the zoom level, the properties of a feature and its state. Expressions are parsed from
+//! their JSON form and type-checked once, afterwards they can be evaluated repeatedly.
+//!
+//! Supported are the data expressions `get`, `has`, `id`, `geometry-type`, `feature-state`, the
+//! camera expression `zoom`, the control flow expressions `match`, `case`, `coalesce`, the ramps
+//! `interpolate` and `step`, comparisons, boolean logic, arithmetic and type conversions.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use csscolorparser::Color;
+use serde_json::Value as Json;
+
+/// Value of a property of a feature
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+/// A feature against which expressions are evaluated
+pub trait FeatureProperties {
+    /// Either `"Point"`, `"LineString"` or `"Polygon"`
+    fn geometry_type(&self) -> PropertyValue;
+    fn id(&self) -> PropertyValue;
+    fn property(&self, key: &str) -> PropertyValue;
+}
+
+/// The state of a feature, which is set at runtime and read by `feature-state` expressions
+pub type FeatureState = HashMap<String, PropertyValue>;
+
+/// The inputs of an evaluation. Expressions which read a feature evaluate to `null` without
+/// feature.
+#[derive(Clone, Copy)]
+pub struct EvaluationContext<'a> {
+    pub zoom: f64,
+    pub feature: Option<&'a dyn FeatureProperties>,
+    pub feature_state: Option<&'a FeatureState>,
+}
+
+impl<'a> EvaluationContext<'a> {
+    pub fn new(zoom: f64) -> Self {
+        Self {
+            zoom,
+            feature: None,
+            feature_state: None,
+        }
+    }
+
+    pub fn with_feature(mut self, feature: &'a dyn FeatureProperties) -> Self {
+        self.feature = Some(feature);
+        self
+    }
+
+    pub fn with_feature_state(mut self, feature_state: &'a FeatureState) -> Self {
+        self.feature_state = Some(feature_state);
+        self
+    }
+}
+
+/// Type of the value of an expression. Expressions of type [`Type::Value`] evaluate to values of
+/// any type, which are checked during evaluation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Null,
+    Boolean,
+    Number,
+    String,
+    Color,
+    Array,
+    Value,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Type::Null => "null",
+            Type::Boolean => "boolean",
+            Type::Number => "number",
+            Type::String => "string",
+            Type::Color => "color",
+            Type::Array => "array",
+            Type::Value => "value",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The result of an evaluation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Color(Color),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn typ(&self) -> Type {
+        match self {
+            Value::Null => Type::Null,
+            Value::Bool(_) => Type::Boolean,
+            Value::Number(_) => Type::Number,
+            Value::String(_) => Type::String,
+            Value::Color(_) => Type::Color,
+            Value::Array(_) => Type::Array,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_color(&self) -> Option<&Color> {
+        match self {
+            Value::Color(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn from_json(json: &Json) -> Result<Self, String> {
+        match json {
+            Json::Null => Ok(Value::Null),
+            Json::Bool(value) => Ok(Value::Bool(*value)),
+            Json::Number(value) => Ok(Value::Number(value.as_f64().unwrap_or(f64::NAN))),
+            Json::String(value) => Ok(Value::String(value.clone())),
+            Json::Array(values) => values
+                .iter()
+                .map(Value::from_json)
+                .collect::<Result<_, _>>()
+                .map(Value::Array),
+            Json::Object(_) => Err("objects are not supported".to_string()),
+        }
+    }
+}
+
+impl From<PropertyValue> for Value {
+    fn from(value: PropertyValue) -> Self {
+        match value {
+            PropertyValue::Null => Value::Null,
+            PropertyValue::Bool(value) => Value::Bool(value),
+            PropertyValue::Number(value) => Value::Number(value),
+            PropertyValue::String(value) => Value::String(value),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Number(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Color(color) => write!(f, "{}", color.to_rgb_string()),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Arithmetic {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Power,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Interpolation {
+    Linear,
+    Exponential(f64),
+}
+
+impl Interpolation {
+    /// The relative position of `input` between `lower` and `upper`
+    fn factor(&self, input: f64, lower: f64, upper: f64) -> f64 {
+        let difference = upper - lower;
+        let progress = input - lower;
+
+        if difference == 0.0 {
+            return 0.0;
+        }
+
+        match self {
+            Interpolation::Linear => progress / difference,
+            Interpolation::Exponential(base) if *base == 1.0 => progress / difference,
+            Interpolation::Exponential(base) => {
+                (base.powf(progress) - 1.0) / (base.powf(difference) - 1.0)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Literal(Value),
+    Get(String),
+    Has(String),
+    Id,
+    GeometryType,
+    FeatureState(String),
+    Zoom,
+    /// Asserts that the value has the type, e.g. the result of `get`
+    Assert(Type, Box<Expression>),
+    /// Converts the value into the type, e.g. `to-number`
+    Convert(Type, Vec<Expression>),
+    Not(Box<Expression>),
+    All(Vec<Expression>),
+    Any(Vec<Expression>),
+    Compare(Comparison, Box<Expression>, Box<Expression>),
+    In(Box<Expression>, Box<Expression>),
+    Arithmetic(Arithmetic, Vec<Expression>),
+    Coalesce(Vec<Expression>),
+    Case {
+        branches: Vec<(Expression, Expression)>,
+        fallback: Box<Expression>,
+    },
+    Match {
+        input: Box<Expression>,
+        branches: Vec<(Vec<Value>, Expression)>,
+        fallback: Box<Expression>,
+    },
+    Step {
+        input: Box<Expression>,
+        first: Box<Expression>,
+        stops: Vec<(f64, Expression)>,
+    },
+    Interpolate {
+        interpolation: Interpolation,
+        input: Box<Expression>,
+        stops: Vec<(f64, Expression)>,
+    },
+}
+
+/// A parsed and type-checked expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    node: Node,
+    typ: Type,
+}
+
+impl Expression {
+    /// Parses the JSON form of an expression, whose values must be of the type `expected`. Pass
+    /// [`Type::Value`] to accept any type.
+    pub fn parse(json: &Json, expected: Type) -> Result<Self, String> {
+        let expression = Self::parse_any(json, expected)?;
+
+        if expected == Type::Value || expression.typ == expected {
+            Ok(expression)
+        } else if expression.typ == Type::Value {
+            // The type of values read from features is checked during evaluation
+            Ok(Self {
+                node: Node::Assert(expected, Box::new(expression)),
+                typ: expected,
+            })
+        } else {
+            Err(format!(
+                "expected {} but found {}",
+                expected, expression.typ
+            ))
+        }
+    }
+
+    /// The type of the values of this expression
+    pub fn typ(&self) -> Type {
+        self.typ
+    }
+
+    /// Returns whether the value does not depend on the zoom level
+    pub fn is_zoom_constant(&self) -> bool {
+        !self.any_node(&|node| matches!(node, Node::Zoom))
+    }
+
+    /// Returns whether the value does not depend on the feature or its state
+    pub fn is_feature_constant(&self) -> bool {
+        !self.any_node(&|node| {
+            matches!(
+                node,
+                Node::Get(_) | Node::Has(_) | Node::Id | Node::GeometryType | Node::FeatureState(_)
+            )
+        })
+    }
+
+    /// The zoom levels at which the value of the expression changes abruptly or starts to change,
+    /// i.e. the stops of `step` and `interpolate` expressions whose input is the zoom level.
+    pub fn zoom_stops(&self) -> Vec<f64> {
+        let mut zoom_stops = Vec::new();
+        self.visit(&mut |expression| match &expression.node {
+            Node::Step { input, stops, .. } | Node::Interpolate { input, stops, .. }
+                if input.node == Node::Zoom =>
+            {
+                zoom_stops.extend(stops.iter().map(|(stop, _)| *stop))
+            }
+            _ => {}
+        });
+        zoom_stops
+    }
+
+    pub fn evaluate(&self, context: &EvaluationContext) -> Result<Value, String> {
+        match &self.node {
+            Node::Literal(value) => Ok(value.clone()),
+            Node::Get(key) => Ok(context
+                .feature
+                .map_or(Value::Null, |feature| feature.property(key).into())),
+            Node::Has(key) => Ok(Value::Bool(context.feature.map_or(false, |feature| {
+                feature.property(key) != PropertyValue::Null
+            }))),
+            Node::Id => Ok(context
+                .feature
+                .map_or(Value::Null, |feature| feature.id().into())),
+            Node::GeometryType => Ok(context
+                .feature
+                .map_or(Value::Null, |feature| feature.geometry_type().into())),
+            Node::FeatureState(key) => Ok(context
+                .feature_state
+                .and_then(|state| state.get(key))
+                .map_or(Value::Null, |value| value.clone().into())),
+            Node::Zoom => Ok(Value::Number(context.zoom)),
+            Node::Assert(typ, expression) => {
+                let value = expression.evaluate(context)?;
+                match (typ, value) {
+                    // Like in MapLibre GL, strings are parsed into colors
+                    (Type::Color, Value::String(color)) => parse_color(&color).map(Value::Color),
+                    (typ, value) if value.typ() == *typ => Ok(value),
+                    (typ, value) => Err(format!("expected {} but found {}", typ, value.typ())),
+                }
+            }
+            Node::Convert(typ, expressions) => {
+                // Each argument is tried in order until one can be converted
+                let mut error = None;
+                for expression in expressions {
+                    match convert(*typ, expression.evaluate(context)?) {
+                        Ok(value) => return Ok(value),
+                        Err(e) => error = Some(e),
+                    }
+                }
+                Err(error.unwrap_or_default())
+            }
+            Node::Not(expression) => Ok(Value::Bool(!expression.evaluate_bool(context)?)),
+            Node::All(expressions) => {
+                for expression in expressions {
+                    if !expression.evaluate_bool(context)? {
+                        return Ok(Value::Bool(false));
+                    }
+                }
+                Ok(Value::Bool(true))
+            }
+            Node::Any(expressions) => {
+                for expression in expressions {
+                    if expression.evaluate_bool(context)? {
+                        return Ok(Value::Bool(true));
+                    }
+                }
+                Ok(Value::Bool(false))
+            }
+            Node::Compare(comparison, left, right) => compare(
+                *comparison,
+                &left.evaluate(context)?,
+                &right.evaluate(context)?,
+            )
+            .map(Value::Bool),
+            Node::In(needle, haystack) => {
+                let needle = needle.evaluate(context)?;
+                match haystack.evaluate(context)? {
+                    Value::Array(values) => Ok(Value::Bool(values.contains(&needle))),
+                    Value::String(haystack) => match needle {
+                        Value::String(needle) => Ok(Value::Bool(haystack.contains(&needle))),
+                        needle => Err(format!("expected string but found {}", needle.typ())),
+                    },
+                    haystack => Err(format!(
+                        "expected array or string but found {}",
+                        haystack.typ()
+                    )),
+                }
+            }
+            Node::Arithmetic(arithmetic, expressions) => {
+                let values = expressions
+                    .iter()
+                    .map(|expression| expression.evaluate_number(context))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Value::Number(calculate(*arithmetic, &values)))
+            }
+            Node::Coalesce(expressions) => {
+                for expression in expressions {
+                    // Values which do not satisfy an assertion are skipped like null values
+                    match expression.evaluate(context) {
+                        Ok(Value::Null) | Err(_) => continue,
+                        Ok(value) => return Ok(value),
+                    }
+                }
+                Ok(Value::Null)
+            }
+            Node::Case { branches, fallback } => {
+                for (condition, output) in branches {
+                    if condition.evaluate_bool(context)? {
+                        return output.evaluate(context);
+                    }
+                }
+                fallback.evaluate(context)
+            }
+            Node::Match {
+                input,
+                branches,
+                fallback,
+            } => {
+                let input = input.evaluate(context)?;
+                branches
+                    .iter()
+                    .find(|(labels, _)| labels.contains(&input))
+                    .map_or(fallback.as_ref(), |(_, output)| output)
+                    .evaluate(context)
+            }
+            Node::Step {
+                input,
+                first,
+                stops,
+            } => {
+                let input = input.evaluate_number(context)?;
+                match stops.iter().rposition(|(stop, _)| *stop <= input) {
+                    Some(index) => stops[index].1.evaluate(context),
+                    None => first.evaluate(context),
+                }
+            }
+            Node::Interpolate {
+                interpolation,
+                input,
+                stops,
+            } => {
+                let input = input.evaluate_number(context)?;
+                let index = match stops.iter().position(|(stop, _)| *stop > input) {
+                    Some(0) => return stops[0].1.evaluate(context),
+                    Some(index) => index,
+                    None => return stops[stops.len() - 1].1.evaluate(context),
+                };
+                let (lower_stop, lower) = &stops[index - 1];
+                let (upper_stop, upper) = &stops[index];
+
+                let t = interpolation.factor(input, *lower_stop, *upper_stop);
+                interpolate(&lower.evaluate(context)?, &upper.evaluate(context)?, t)
+            }
+        }
+    }
+
+    pub fn evaluate_bool(&self, context: &EvaluationContext) -> Result<bool, String> {
+        let value = self.evaluate(context)?;
+        value
+            .as_bool()
+            .ok_or_else(|| format!("expected boolean but found {}", value.typ()))
+    }
+
+    pub fn evaluate_number(&self, context: &EvaluationContext) -> Result<f64, String> {
+        let value = self.evaluate(context)?;
+        value
+            .as_number()
+            .ok_or_else(|| format!("expected number but found {}", value.typ()))
+    }
+
+    fn children(&self) -> Vec<&Expression> {
+        match &self.node {
+            Node::Literal(_)
+            | Node::Get(_)
+            | Node::Has(_)
+            | Node::Id
+            | Node::GeometryType
+            | Node::FeatureState(_)
+            | Node::Zoom => Vec::new(),
+            Node::Assert(_, expression) | Node::Not(expression) => vec![expression],
+            Node::Convert(_, expressions)
+            | Node::All(expressions)
+            | Node::Any(expressions)
+            | Node::Arithmetic(_, expressions)
+            | Node::Coalesce(expressions) => expressions.iter().collect(),
+            Node::Compare(_, left, right) => vec![left, right],
+            Node::In(needle, haystack) => vec![needle, haystack],
+            Node::Case { branches, fallback } => branches
+                .iter()
+                .flat_map(|(condition, output)| [condition, output])
+                .chain([fallback.as_ref()])
+                .collect(),
+            Node::Match {
+                input,
+                branches,
+                fallback,
+            } => [input.as_ref()]
+                .into_iter()
+                .chain(branches.iter().map(|(_, output)| output))
+                .chain([fallback.as_ref()])
+                .collect(),
+            Node::Step {
+                input,
+                first,
+                stops,
+            } => [input.as_ref(), first.as_ref()]
+                .into_iter()
+                .chain(stops.iter().map(|(_, output)| output))
+                .collect(),
+            Node::Interpolate { input, stops, .. } => [input.as_ref()]
+                .into_iter()
+                .chain(stops.iter().map(|(_, output)| output))
+                .collect(),
+        }
+    }
+
+    fn visit<'a>(&'a self, visitor: &mut impl FnMut(&'a Expression)) {
+        visitor(self);
+        for child in self.children() {
+            child.visit(visitor);
+        }
+    }
+
+    fn any_node(&self, predicate: &impl Fn(&Node) -> bool) -> bool {
+        predicate(&self.node)
+            || self
+                .children()
+                .iter()
+                .any(|child| child.any_node(predicate))
+    }
+
+    fn new(node: Node, typ: Type) -> Self {
+        Self { node, typ }
+    }
+
+    /// Parses an expression. `expected` is a hint for literals, e.g. strings are parsed as colors
+    /// if a color is expected. The caller checks the type of the result.
+    fn parse_any(json: &Json, expected: Type) -> Result<Self, String> {
+        let items = match json {
+            Json::Array(items) => items,
+            Json::Object(_) => {
+                return Err("objects must be wrapped in a literal expression".to_string())
+            }
+            Json::String(color) if expected == Type::Color => return Self::color(color),
+            literal => return Self::literal(Value::from_json(literal)?),
+        };
+
+        let operator = match items.first() {
+            Some(Json::String(operator)) => operator.as_str(),
+            _ => return Err(
+                "expected an expression, literal arrays must be wrapped in a literal expression"
+                    .to_string(),
+            ),
+        };
+        let arguments = &items[1..];
+        let argument_count = |min: usize, max: usize| {
+            if arguments.len() < min || arguments.len() > max {
+                Err(format!(
+                    "wrong number of arguments for {}, found {}",
+                    operator,
+                    arguments.len()
+                ))
+            } else {
+                Ok(())
+            }
+        };
+        let key = |index: usize| match arguments.get(index) {
+            Some(Json::String(key)) => Ok(key.clone()),
+            _ => Err(format!("{} expects a string key", operator)),
+        };
+
+        match operator {
+            "literal" => {
+                argument_count(1, 1)?;
+                match (&arguments[0], expected) {
+                    (Json::String(color), Type::Color) => Self::color(color),
+                    (literal, _) => Self::literal(Value::from_json(literal)?),
+                }
+            }
+            // Properties of objects are not supported, i.e. the second argument
+            "get" => {
+                argument_count(1, 1)?;
+                Ok(Self::new(Node::Get(key(0)?), Type::Value))
+            }
+            "has" => {
+                argument_count(1, 1)?;
+                Ok(Self::new(Node::Has(key(0)?), Type::Boolean))
+            }
+            "feature-state" => {
+                argument_count(1, 1)?;
+                Ok(Self::new(Node::FeatureState(key(0)?), Type::Value))
+            }
+            "id" => {
+                argument_count(0, 0)?;
+                Ok(Self::new(Node::Id, Type::Value))
+            }
+            "geometry-type" => {
+                argument_count(0, 0)?;
+                Ok(Self::new(Node::GeometryType, Type::String))
+            }
+            "zoom" => {
+                argument_count(0, 0)?;
+                Ok(Self::new(Node::Zoom, Type::Number))
+            }
+            "boolean" | "number" | "string" => {
+                argument_count(1, usize::MAX)?;
+                let typ = match operator {
+                    "boolean" => Type::Boolean,
+                    "number" => Type::Number,
+                    _ => Type::String,
+                };
+                // Fallbacks are tried in order like in a coalesce expression
+                let expressions = arguments
+                    .iter()
+                    .map(|argument| Self::parse(argument, typ))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Self::new(Node::Coalesce(expressions), typ))
+            }
+            "to-boolean" | "to-number" | "to-string" | "to-color" => {
+                let typ = match operator {
+                    "to-boolean" => Type::Boolean,
+                    "to-number" => Type::Number,
+                    "to-string" => Type::String,
+                    _ => Type::Color,
+                };
+                if typ == Type::Boolean || typ == Type::String {
+                    argument_count(1, 1)?;
+                } else {
+                    argument_count(1, usize::MAX)?;
+                }
+                let expressions = Self::parse_all(arguments, Type::Value)?;
+                Ok(Self::new(Node::Convert(typ, expressions), typ))
+            }
+            "!" => {
+                argument_count(1, 1)?;
+                let expression = Self::parse(&arguments[0], Type::Boolean)?;
+                Ok(Self::new(Node::Not(Box::new(expression)), Type::Boolean))
+            }
+            "all" | "any" => {
+                let expressions = Self::parse_all(arguments, Type::Boolean)?;
+                let node = if operator == "all" {
+                    Node::All(expressions)
+                } else {
+                    Node::Any(expressions)
+                };
+                Ok(Self::new(node, Type::Boolean))
+            }
+            "==" | "!=" | "<" | "<=" | ">" | ">=" => {
+                // The optional collator is not supported
+                argument_count(2, 2)?;
+                let comparison = match operator {
+                    "==" => Comparison::Equal,
+                    "!=" => Comparison::NotEqual,
+                    "<" => Comparison::Less,
+                    "<=" => Comparison::LessOrEqual,
+                    ">" => Comparison::Greater,
+                    _ => Comparison::GreaterOrEqual,
+                };
+                let left = Self::parse_any(&arguments[0], Type::Value)?;
+                let right = Self::parse_any(&arguments[1], Type::Value)?;
+                check_comparable(comparison, left.typ, right.typ)?;
+                Ok(Self::new(
+                    Node::Compare(comparison, Box::new(left), Box::new(right)),
+                    Type::Boolean,
+                ))
+            }
+            "in" => {
+                argument_count(2, 2)?;
+                let needle = Self::parse(&arguments[0], Type::Value)?;
+                let haystack = Self::parse(&arguments[1], Type::Value)?;
+                if !matches!(haystack.typ, Type::Array | Type::String | Type::Value) {
+                    return Err(format!(
+                        "expected array or string but found {}",
+                        haystack.typ
+                    ));
+                }
+                Ok(Self::new(
+                    Node::In(Box::new(needle), Box::new(haystack)),
+                    Type::Boolean,
+                ))
+            }
+            "+" | "-" | "*" | "/" | "%" | "^" => {
+                let arithmetic = match operator {
+                    "+" => Arithmetic::Add,
+                    "-" => Arithmetic::Subtract,
+                    "*" => Arithmetic::Multiply,
+                    "/" => Arithmetic::Divide,
+                    "%" => Arithmetic::Remainder,
+                    _ => Arithmetic::Power,
+                };
+                match arithmetic {
+                    Arithmetic::Add | Arithmetic::Multiply => argument_count(2, usize::MAX)?,
+                    Arithmetic::Subtract => argument_count(1, 2)?,
+                    _ => argument_count(2, 2)?,
+                }
+                let expressions = Self::parse_all(arguments, Type::Number)?;
+                Ok(Self::new(
+                    Node::Arithmetic(arithmetic, expressions),
+                    Type::Number,
+                ))
+            }
+            "coalesce" => {
+                argument_count(1, usize::MAX)?;
+                let expressions = Self::parse_outputs(arguments.iter(), expected)?;
+                let typ = expressions[0].typ;
+                Ok(Self::new(Node::Coalesce(expressions), typ))
+            }
+            "case" => {
+                if arguments.len() < 3 || arguments.len() % 2 == 0 {
+                    return Err("case expects conditions and a fallback".to_string());
+                }
+                let conditions = arguments
+                    .iter()
+                    .step_by(2)
+                    .take(arguments.len() / 2)
+                    .map(|condition| Self::parse(condition, Type::Boolean))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut outputs = Self::parse_outputs(
+                    arguments.iter().skip(1).step_by(2).chain(arguments.last()),
+                    expected,
+                )?;
+                let typ = outputs[0].typ;
+                let fallback = outputs.pop().unwrap();
+                Ok(Self::new(
+                    Node::Case {
+                        branches: conditions.into_iter().zip(outputs).collect(),
+                        fallback: Box::new(fallback),
+                    },
+                    typ,
+                ))
+            }
+            "match" => {
+                if arguments.len() < 4 || arguments.len() % 2 == 1 {
+                    return Err("match expects an input, labels and a fallback".to_string());
+                }
+                let input = Self::parse_any(&arguments[0], Type::Value)?;
+                let label_arguments = &arguments[1..arguments.len() - 1];
+
+                let mut label_type = None;
+                let mut labels = Vec::new();
+                for label in label_arguments.iter().step_by(2) {
+                    let values = match label {
+                        Json::Array(values) if !values.is_empty() => values.iter().collect(),
+                        Json::Array(_) => return Err("match labels must not be empty".to_string()),
+                        label => vec![label],
+                    };
+                    let values = values
+                        .into_iter()
+                        .map(|value| match value {
+                            Json::String(value) => Ok(Value::String(value.clone())),
+                            Json::Number(value) if value.is_i64() || value.is_u64() => {
+                                Ok(Value::Number(value.as_f64().unwrap_or_default()))
+                            }
+                            value => Err(format!("invalid match label {}", value)),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    for value in &values {
+                        if *label_type.get_or_insert(value.typ()) != value.typ() {
+                            return Err("match labels must be of the same type".to_string());
+                        }
+                    }
+                    labels.push(values);
+                }
+
+                let label_type = label_type.unwrap_or(Type::Value);
+                if input.typ != Type::Value && input.typ != label_type {
+                    return Err(format!("expected {} but found {}", label_type, input.typ));
+                }
+
+                let mut outputs = Self::parse_outputs(
+                    label_arguments
+                        .iter()
+                        .skip(1)
+                        .step_by(2)
+                        .chain(arguments.last()),
+                    expected,
+                )?;
+                let typ = outputs[0].typ;
+                let fallback = outputs.pop().unwrap();
+                Ok(Self::new(
+                    Node::Match {
+                        input: Box::new(input),
+                        branches: labels.into_iter().zip(outputs).collect(),
+                        fallback: Box::new(fallback),
+                    },
+                    typ,
+                ))
+            }
+            "step" => {
+                if arguments.len() < 2 || arguments.len() % 2 == 1 {
+                    return Err("step expects an input, an output and stops".to_string());
+                }
+                let input = Self::parse(&arguments[0], Type::Number)?;
+                let stops = parse_stops(&arguments[2..])?;
+                let mut outputs = Self::parse_outputs(
+                    [&arguments[1]]
+                        .into_iter()
+                        .chain(arguments.iter().skip(3).step_by(2)),
+                    expected,
+                )?;
+                let typ = outputs[0].typ;
+                let first = outputs.remove(0);
+                Ok(Self::new(
+                    Node::Step {
+                        input: Box::new(input),
+                        first: Box::new(first),
+                        stops: stops.into_iter().zip(outputs).collect(),
+                    },
+                    typ,
+                ))
+            }
+            "interpolate" => {
+                if arguments.len() < 4 || arguments.len() % 2 == 1 {
+                    return Err("interpolate expects a type, an input and stops".to_string());
+                }
+                let interpolation = parse_interpolation(&arguments[0])?;
+                let input = Self::parse(&arguments[1], Type::Number)?;
+                let stops = parse_stops(&arguments[2..])?;
+                let outputs = Self::parse_outputs(arguments[3..].iter().step_by(2), expected)?;
+                let typ = outputs[0].typ;
+                if !matches!(typ, Type::Number | Type::Color) {
+                    return Err(format!("type {} is not interpolatable", typ));
+                }
+                Ok(Self::new(
+                    Node::Interpolate {
+                        interpolation,
+                        input: Box::new(input),
+                        stops: stops.into_iter().zip(outputs).collect(),
+                    },
+                    typ,
+                ))
+            }
+            operator => Err(format!("unknown expression {}", operator)),
+        }
+    }
+
+    fn color(color: &str) -> Result<Self, String> {
+        parse_color(color).map(|color| Self::new(Node::Literal(Value::Color(color)), Type::Color))
+    }
+
+    fn literal(value: Value) -> Result<Self, String> {
+        let typ = value.typ();
+        Ok(Self::new(Node::Literal(value), typ))
+    }
+
+    fn parse_all(arguments: &[Json], expected: Type) -> Result<Vec<Self>, String> {
+        arguments
+            .iter()
+            .map(|argument| Self::parse(argument, expected))
+            .collect()
+    }
+
+    /// Parses the outputs of a branching expression. All outputs must be of the same type. If
+    /// no type is expected, the type of the first output is expected for the remaining outputs.
+    fn parse_outputs<'a>(
+        outputs: impl Iterator<Item = &'a Json>,
+        expected: Type,
+    ) -> Result<Vec<Self>, String> {
+        let mut expected = expected;
+        let mut expressions = Vec::new();
+        for output in outputs {
+            let expression = Self::parse(output, expected)?;
+            if expected == Type::Value && expression.typ != Type::Null {
+                expected = expression.typ;
+            }
+            expressions.push(expression);
+        }
+        Ok(expressions)
+    }
+}
+
+fn parse_color(color: &str) -> Result<Color, String> {
+    Color::from_str(color).map_err(|e| format!("invalid color {}: {}", color, e))
+}
+
+fn parse_stops(arguments: &[Json]) -> Result<Vec<f64>, String> {
+    let stops = arguments
+        .iter()
+        .step_by(2)
+        .map(|stop| {
+            stop.as_f64()
+                .ok_or_else(|| "stops must be numeric literals".to_string())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if stops.windows(2).any(|stops| stops[0] >= stops[1]) {
+        return Err("stops must be in strictly ascending order".to_string());
+    }
+    Ok(stops)
+}
+
+fn parse_interpolation(json: &Json) -> Result<Interpolation, String> {
+    let items = json.as_array().map(Vec::as_slice).unwrap_or_default();
+    match (items.first().and_then(Json::as_str), items.get(1)) {
+        (Some("linear"), None) => Ok(Interpolation::Linear),
+        (Some("exponential"), Some(base)) => base
+            .as_f64()
+            .map(Interpolation::Exponential)
+            .ok_or_else(|| "exponential interpolation expects a numeric base".to_string()),
+        _ => Err(format!("unsupported interpolation type {}", json)),
+    }
+}
+
+/// Checks whether values of the types can be compared. Values of type [`Type::Value`] are checked
+/// during evaluation.
+fn check_comparable(comparison: Comparison, left: Type, right: Type) -> Result<(), String> {
+    let is_equality = matches!(comparison, Comparison::Equal | Comparison::NotEqual);
+    let is_valid = |typ: Type| {
+        if is_equality {
+            matches!(
+                typ,
+                Type::Boolean | Type::Number | Type::String | Type::Null | Type::Value
+            )
+        } else {
+            matches!(typ, Type::Number | Type::String | Type::Value)
+        }
+    };
+
+    if !is_valid(left) || !is_valid(right) {
+        return Err(format!("cannot compare {} and {}", left, right));
+    }
+    if left != Type::Value && right != Type::Value && left != right {
+        // Null can be compared for equality with any type
+        if !(is_equality && (left == Type::Null || right == Type::Null)) {
+            return Err(format!("cannot compare {} and {}", left, right));
+        }
+    }
+    Ok(())
+}
+
+fn compare(comparison: Comparison, left: &Value, right: &Value) -> Result<bool, String> {
+    let ordering = || match (left, right) {
+        (Value::Number(left), Value::Number(right)) => Ok(left.partial_cmp(right)),
+        (Value::String(left), Value::String(right)) => Ok(Some(left.cmp(right))),
+        (left, right) => Err(format!("cannot compare {} and {}", left.typ(), right.typ())),
+    };
+
+    Ok(match comparison {
+        Comparison::Equal => left == right,
+        Comparison::NotEqual => left != right,
+        Comparison::Less => ordering()? == Some(Ordering::Less),
+        Comparison::LessOrEqual => matches!(ordering()?, Some(Ordering::Less | Ordering::Equal)),
+        Comparison::Greater => ordering()? == Some(Ordering::Greater),
+        Comparison::GreaterOrEqual => {
+            matches!(ordering()?, Some(Ordering::Greater | Ordering::Equal))
+        }
+    })
+}
+
+fn calculate(arithmetic: Arithmetic, values: &[f64]) -> f64 {
+    match (arithmetic, values) {
+        (Arithmetic::Add, values) => values.iter().sum(),
+        (Arithmetic::Multiply, values) => values.iter().product(),
+        (Arithmetic::Subtract, [value]) => -value,
+        (Arithmetic::Subtract, [left, right]) => left - right,
+        (Arithmetic::Divide, [left, right]) => left / right,
+        (Arithmetic::Remainder, [left, right]) => left % right,
+        (Arithmetic::Power, [left, right]) => left.powf(*right),
+        // The number of arguments is checked while parsing
+        _ => f64::NAN,
+    }
+}
+
+fn convert(typ: Type, value: Value) -> Result<Value, String> {
+    match (typ, value) {
+        (Type::Boolean, value) => Ok(Value::Bool(match value {
+            Value::Null => false,
+            Value::Bool(value) => value,
+            Value::Number(value) => value != 0.0 && !value.is_nan(),
+            Value::String(value) => !value.is_empty(),
+            Value::Color(_) | Value::Array(_) => true,
+        })),
+        (Type::Number, Value::Null) => Ok(Value::Number(0.0)),
+        (Type::Number, Value::Bool(value)) => Ok(Value::Number(if value { 1.0 } else { 0.0 })),
+        (Type::Number, Value::Number(value)) => Ok(Value::Number(value)),
+        (Type::Number, Value::String(value)) => value
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| format!("could not convert {} to number", value)),
+        (Type::String, value) => Ok(Value::String(value.to_string())),
+        (Type::Color, Value::Color(color)) => Ok(Value::Color(color)),
+        (Type::Color, Value::String(color)) => parse_color(&color).map(Value::Color),
+        (typ, value) => Err(format!("could not convert {} to {}", value.typ(), typ)),
+    }
+}
+
+fn interpolate(lower: &Value, upper: &Value, t: f64) -> Result<Value, String> {
+    let mix = |lower: f64, upper: f64| lower + (upper - lower) * t;
+
+    match (lower, upper) {
+        (Value::Number(lower), Value::Number(upper)) => Ok(Value::Number(mix(*lower, *upper))),
+        (Value::Color(lower), Value::Color(upper)) => Ok(Value::Color(Color {
+            r: mix(lower.r, upper.r),
+            g: mix(lower.g, upper.g),
+            b: mix(lower.b, upper.b),
+            a: mix(lower.a, upper.a),
+        })),
+        (lower, upper) => Err(format!(
+            "cannot interpolate between {} and {}",
+            lower.typ(),
+            upper.typ()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::{
+        EvaluationContext, Expression, FeatureProperties, FeatureState, PropertyValue, Type, Value,
+    };
+
+    struct TestFeature(HashMap<&'static str, PropertyValue>);
+
+    impl FeatureProperties for TestFeature {
+        fn geometry_type(&self) -> PropertyValue {
+            PropertyValue::String("Polygon".to_string())
+        }
+
+        fn id(&self) -> PropertyValue {
+            PropertyValue::Number(7.0)
+        }
+
+        fn property(&self, key: &str) -> PropertyValue {
+            self.0.get(key).cloned().unwrap_or(PropertyValue::Null)
+        }
+    }
+
+    fn feature() -> TestFeature {
+        TestFeature(HashMap::from([
+            ("class", PropertyValue::String("residential".to_string())),
+            ("height", PropertyValue::Number(20.0)),
+        ]))
+    }
+
+    fn evaluate(json: serde_json::Value, expected: Type, zoom: f64) -> Value {
+        let feature = feature();
+        Expression::parse(&json, expected)
+            .unwrap()
+            .evaluate(&EvaluationContext::new(zoom).with_feature(&feature))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_match() {
+        let expression = json!([
+            "match",
+            ["get", "class"],
+            ["commercial", "retail"],
+            "red",
+            "residential",
+            "green",
+            "blue"
+        ]);
+        let color = evaluate(expression, Type::Color, 0.0);
+        assert_eq!(color.as_color().unwrap().to_hex_string(), "#008000");
+
+        let expression = json!(["match", ["get", "height"], [10, 20], 1, 0]);
+        assert_eq!(evaluate(expression, Type::Number, 0.0), Value::Number(1.0));
+    }
+
+    #[test]
+    fn test_case() {
+        let expression = json!([
+            "case",
+            [">", ["get", "height"], 50],
+            "tall",
+            ["has", "height"],
+            "low",
+            "unknown"
+        ]);
+        assert_eq!(
+            evaluate(expression, Type::String, 0.0),
+            Value::String("low".to_string())
+        );
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let expression = json!(["interpolate", ["linear"], ["zoom"], 10, 1, 20, 3]);
+        assert_eq!(
+            evaluate(expression.clone(), Type::Number, 5.0),
+            Value::Number(1.0)
+        );
+        assert_eq!(
+            evaluate(expression.clone(), Type::Number, 15.0),
+            Value::Number(2.0)
+        );
+        assert_eq!(evaluate(expression, Type::Number, 25.0), Value::Number(3.0));
+
+        let expression = json!(["interpolate", ["exponential", 2], ["zoom"], 0, 0, 2, 3]);
+        assert_eq!(evaluate(expression, Type::Number, 1.0), Value::Number(1.0));
+
+        let expression = json!(["interpolate", ["linear"], ["zoom"], 0, "black", 10, "white"]);
+        let color = evaluate(expression, Type::Color, 5.0);
+        assert!((color.as_color().unwrap().r - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_step() {
+        let expression = json!(["step", ["zoom"], 1, 10, 2, 15, 4]);
+        assert_eq!(
+            evaluate(expression.clone(), Type::Number, 9.0),
+            Value::Number(1.0)
+        );
+        assert_eq!(
+            evaluate(expression.clone(), Type::Number, 10.0),
+            Value::Number(2.0)
+        );
+        assert_eq!(evaluate(expression, Type::Number, 20.0), Value::Number(4.0));
+    }
+
+    #[test]
+    fn test_feature_state() {
+        let expression = Expression::parse(
+            &json!([
+                "case",
+                ["boolean", ["feature-state", "hover"], false],
+                1,
+                0.5
+            ]),
+            Type::Number,
+        )
+        .unwrap();
+        assert!(expression.is_zoom_constant());
+        assert!(!expression.is_feature_constant());
+
+        let state: FeatureState = HashMap::from([("hover".to_string(), PropertyValue::Bool(true))]);
+        let context = EvaluationContext::new(0.0);
+        assert_eq!(expression.evaluate(&context), Ok(Value::Number(0.5)));
+        assert_eq!(
+            expression.evaluate(&context.with_feature_state(&state)),
+            Ok(Value::Number(1.0))
+        );
+    }
+
+    #[test]
+    fn test_type_checking() {
+        let is_err =
+            |json: serde_json::Value, expected: Type| Expression::parse(&json, expected).is_err();
+        assert!(is_err(json!(["zoom"]), Type::Color));
+        assert!(is_err(json!(["==", ["zoom"], "a"]), Type::Boolean));
+        assert!(is_err(
+            json!(["match", ["get", "a"], 1, "x", 2]),
+            Type::Value
+        ));
+        assert!(is_err(
+            json!(["step", ["zoom"], 1, 5, 2, 3, 4]),
+            Type::Number
+        ));
+        assert!(is_err(
+            json!(["interpolate", ["linear"], ["zoom"], 0, "a", 1, "b"]),
+            Type::String
+        ));
+        assert!(is_err(json!([1, 2]), Type::Value));
+
+        // The type of feature properties is checked during evaluation
+        let expression = Expression::parse(&json!(["get", "class"]), Type::Number).unwrap();
+        let feature = feature();
+        assert!(expression
+            .evaluate(&EvaluationContext::new(0.0).with_feature(&feature))
+            .is_err());
+    }
+
+    #[test]
+    fn test_zoom_stops() {
+        let expression = Expression::parse(
+            &json!([
+                "interpolate",
+                ["linear"],
+                ["zoom"],
+                5,
+                ["step", ["zoom"], 1, 8, 2],
+                10,
+                3
+            ]),
+            Type::Number,
+        )
+        .unwrap();
+        assert_eq!(expression.zoom_stops(), vec![5.0, 10.0, 8.0]);
+    }
+}
@@ -0,0 +1,341 @@
+//! Filters of style layers which select the features of a source layer that are drawn. Both the
+//! legacy filter syntax like `["==", "class", "motorway"]` and the expression syntax like
+//! `["==", ["get", "class"], "motorway"]` are supported. Filters in the expression syntax are
+//! evaluated as [expressions](crate::style::expression).
+
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::style::expression::{
+    EvaluationContext, Expression, FeatureProperties, PropertyValue, Type,
+};
+
+fn literal(value: &Value) -> Result<PropertyValue, String> {
+    match value {
+        Value::Null => Ok(PropertyValue::Null),
+        Value::Bool(value) => Ok(PropertyValue::Bool(*value)),
+        Value::Number(value) => Ok(PropertyValue::Number(value.as_f64().unwrap_or(f64::NAN))),
+        Value::String(value) => Ok(PropertyValue::String(value.clone())),
+        value => Err(format!("expected a literal value, found {}", value)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn from_operator(operator: &str) -> Option<Self> {
+        match operator {
+            "==" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessOrEqual),
+            ">" => Some(Comparison::Greater),
+            ">=" => Some(Comparison::GreaterOrEqual),
+            _ => None,
+        }
+    }
+
+    /// Values of different types are never ordered, only strings and numbers can be ordered.
+    fn compare(&self, left: &PropertyValue, right: &PropertyValue) -> bool {
+        let ordering = match (left, right) {
+            (PropertyValue::Number(left), PropertyValue::Number(right)) => left.partial_cmp(right),
+            (PropertyValue::String(left), PropertyValue::String(right)) => Some(left.cmp(right)),
+            _ => None,
+        };
+
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => ordering == Some(Ordering::Less),
+            Comparison::LessOrEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            Comparison::Greater => ordering == Some(Ordering::Greater),
+            Comparison::GreaterOrEqual => {
+                matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Property(String),
+    GeometryType,
+    Id,
+    Literal(PropertyValue),
+}
+
+impl Operand {
+    /// The key of a legacy filter, `$type` and `$id` refer to the geometry type and the id
+    fn from_key(key: &Value) -> Result<Self, String> {
+        match key.as_str() {
+            Some("$type") => Ok(Operand::GeometryType),
+            Some("$id") => Ok(Operand::Id),
+            Some(key) => Ok(Operand::Property(key.to_string())),
+            None => Err(format!("expected a property key, found {}", key)),
+        }
+    }
+
+    fn evaluate(&self, feature: Option<&dyn FeatureProperties>) -> PropertyValue {
+        match (self, feature) {
+            (Operand::Literal(value), _) => value.clone(),
+            (_, None) => PropertyValue::Null,
+            (Operand::Property(key), Some(feature)) => feature.property(key),
+            (Operand::GeometryType, Some(feature)) => feature.geometry_type(),
+            (Operand::Id, Some(feature)) => feature.id(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Constant(bool),
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+    Has(Operand),
+    Compare(Comparison, Operand, Operand),
+    In(Operand, Vec<PropertyValue>),
+    Expression(Expression),
+}
+
+impl Condition {
+    fn parse(filter: &Value) -> Result<Self, String> {
+        match filter {
+            Value::Bool(value) => Ok(Condition::Constant(*value)),
+            Value::Array(items) if is_expression(items) => {
+                Expression::parse(filter, Type::Boolean).map(Condition::Expression)
+            }
+            Value::Array(items) => Self::parse_legacy(items),
+            filter => Err(format!("expected a filter, found {}", filter)),
+        }
+    }
+
+    fn parse_all(filters: &[Value]) -> Result<Vec<Self>, String> {
+        filters.iter().map(Self::parse).collect()
+    }
+
+    fn parse_legacy(items: &[Value]) -> Result<Self, String> {
+        let operator = items.first().and_then(Value::as_str).unwrap_or_default();
+        let argument = |index: usize| {
+            items
+                .get(index)
+                .ok_or_else(|| format!("missing argument of filter {}", operator))
+        };
+
+        let condition = match operator {
+            "all" => Condition::All(Self::parse_all(&items[1..])?),
+            "any" => Condition::Any(Self::parse_all(&items[1..])?),
+            "none" => Condition::Not(Box::new(Condition::Any(Self::parse_all(&items[1..])?))),
+            "has" => Condition::Has(Operand::from_key(argument(1)?)?),
+            "!has" => Condition::Not(Box::new(Condition::Has(Operand::from_key(argument(1)?)?))),
+            "in" | "!in" => {
+                let values = items
+                    .iter()
+                    .skip(2)
+                    .map(literal)
+                    .collect::<Result<_, _>>()?;
+                let condition = Condition::In(Operand::from_key(argument(1)?)?, values);
+                if operator == "in" {
+                    condition
+                } else {
+                    Condition::Not(Box::new(condition))
+                }
+            }
+            operator => match Comparison::from_operator(operator) {
+                Some(comparison) => Condition::Compare(
+                    comparison,
+                    Operand::from_key(argument(1)?)?,
+                    Operand::Literal(literal(argument(2)?)?),
+                ),
+                None => return Err(format!("unsupported filter {}", operator)),
+            },
+        };
+
+        Ok(condition)
+    }
+
+    fn evaluate(&self, context: &EvaluationContext) -> bool {
+        let feature = context.feature;
+        match self {
+            Condition::Constant(value) => *value,
+            Condition::All(conditions) => conditions.iter().all(|c| c.evaluate(context)),
+            Condition::Any(conditions) => conditions.iter().any(|c| c.evaluate(context)),
+            Condition::Not(condition) => !condition.evaluate(context),
+            Condition::Has(operand) => operand.evaluate(feature) != PropertyValue::Null,
+            Condition::Compare(comparison, left, right) => {
+                comparison.compare(&left.evaluate(feature), &right.evaluate(feature))
+            }
+            Condition::In(operand, values) => values.contains(&operand.evaluate(feature)),
+            // Like in MapLibre GL, features for which the evaluation fails are not drawn
+            Condition::Expression(expression) => expression.evaluate_bool(context).unwrap_or(false),
+        }
+    }
+}
+
+/// Decides like MapLibre GL whether a filter uses the expression syntax. Filters which are valid
+/// in both syntaxes are treated as legacy filters.
+fn is_expression(items: &[Value]) -> bool {
+    let operator = match items.first().and_then(Value::as_str) {
+        Some(operator) => operator,
+        None => return false,
+    };
+
+    match operator {
+        "has" => items.len() >= 2 && !matches!(items[1].as_str(), Some("$id" | "$type")),
+        "in" => items.len() >= 3 && (!items[1].is_string() || items[2].is_array()),
+        "!in" | "!has" | "none" => false,
+        "==" | "!=" | "<" | "<=" | ">" | ">=" => {
+            items.len() != 3 || items[1].is_array() || items[2].is_array()
+        }
+        "any" | "all" => items[1..].iter().all(|filter| match filter {
+            Value::Array(items) => is_expression(items),
+            filter => filter.is_boolean(),
+        }),
+        _ => true,
+    }
+}
+
+/// The `filter` of a style layer. The filter is kept in its JSON form, such that the style can be
+/// serialized again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Value", into = "Value")]
+pub struct Filter {
+    json: Value,
+    condition: Condition,
+}
+
+impl Filter {
+    /// Returns whether `feature` is drawn by the layer at the zoom level `zoom`.
+    pub fn evaluate(&self, zoom: f64, feature: &impl FeatureProperties) -> bool {
+        self.condition
+            .evaluate(&EvaluationContext::new(zoom).with_feature(feature))
+    }
+}
+
+impl TryFrom<Value> for Filter {
+    type Error = String;
+
+    fn try_from(json: Value) -> Result<Self, Self::Error> {
+        Ok(Self {
+            condition: Condition::parse(&json)?,
+            json,
+        })
+    }
+}
+
+impl From<Filter> for Value {
+    fn from(filter: Filter) -> Self {
+        filter.json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use crate::style::expression::{FeatureProperties, PropertyValue};
+
+    use super::Filter;
+
+    struct TestFeature {
+        geometry_type: &'static str,
+        properties: HashMap<&'static str, PropertyValue>,
+    }
+
+    impl FeatureProperties for TestFeature {
+        fn geometry_type(&self) -> PropertyValue {
+            PropertyValue::String(self.geometry_type.to_string())
+        }
+
+        fn id(&self) -> PropertyValue {
+            PropertyValue::Number(1.0)
+        }
+
+        fn property(&self, key: &str) -> PropertyValue {
+            self.properties
+                .get(key)
+                .cloned()
+                .unwrap_or(PropertyValue::Null)
+        }
+    }
+
+    fn motorway() -> TestFeature {
+        TestFeature {
+            geometry_type: "LineString",
+            properties: HashMap::from([
+                ("class", PropertyValue::String("motorway".to_string())),
+                ("layer", PropertyValue::Number(2.0)),
+            ]),
+        }
+    }
+
+    fn evaluate(filter: serde_json::Value) -> bool {
+        Filter::try_from(filter).unwrap().evaluate(0.0, &motorway())
+    }
+
+    #[test]
+    fn test_legacy_filters() {
+        assert!(evaluate(json!(["==", "class", "motorway"])));
+        assert!(!evaluate(json!(["!=", "class", "motorway"])));
+        assert!(evaluate(json!(["==", "$type", "LineString"])));
+        assert!(evaluate(json!(["==", "$id", 1])));
+        assert!(evaluate(json!([">=", "layer", 2])));
+        assert!(!evaluate(json!(["<", "layer", "2"])));
+        assert!(evaluate(json!(["in", "class", "primary", "motorway"])));
+        assert!(evaluate(json!(["!in", "class", "primary", "secondary"])));
+        assert!(evaluate(json!(["has", "class"])));
+        assert!(evaluate(json!(["!has", "name"])));
+        assert!(evaluate(json!([
+            "all",
+            ["==", "class", "motorway"],
+            ["none", ["==", "layer", 1]]
+        ])));
+    }
+
+    #[test]
+    fn test_expression_filters() {
+        assert!(evaluate(json!(["==", ["get", "class"], "motorway"])));
+        assert!(evaluate(json!(["==", ["geometry-type"], "LineString"])));
+        assert!(evaluate(json!([">", ["get", "layer"], 1])));
+        assert!(!evaluate(json!(["has", "name"])));
+        assert!(evaluate(json!(["!", ["has", "name"]])));
+        assert!(evaluate(json!([
+            "in",
+            ["get", "class"],
+            ["literal", ["primary", "motorway"]]
+        ])));
+        assert!(evaluate(json!([
+            "any",
+            ["==", ["get", "class"], "primary"],
+            ["==", ["id"], 1]
+        ])));
+        assert!(evaluate(json!(["<", ["zoom"], 1])));
+        assert!(!evaluate(json!(false)));
+    }
+
+    #[test]
+    fn test_unsupported_filter() {
+        assert!(Filter::try_from(json!(["within", {}])).is_err());
+    }
+
+    #[test]
+    fn test_serialization() {
+        let filter: Filter = serde_json::from_str(r#"["==","class","motorway"]"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&filter).unwrap(),
+            r#"["==","class","motorway"]"#
+        );
+    }
+}
@@ -3,6 +3,9 @@ use csscolorparser::Color;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::style::filter::Filter;
+use crate::style::property::StyleProperty;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BackgroundPaint {
     #[serde(rename = "background-color")]
@@ -15,18 +18,145 @@ pub struct BackgroundPaint {
 pub struct FillPaint {
     #[serde(rename = "fill-color")]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub fill_color: Option<Color>,
-    // TODO a lot
+    pub fill_color: Option<StyleProperty<Color>>,
+    /// Opacity of the fill in the range `[0, 1]`
+    #[serde(rename = "fill-opacity")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_opacity: Option<StyleProperty<f32>>,
+    /// Color of the outline, which is only drawn if the fill is antialiased. Defaults to the
+    /// `fill-color`.
+    #[serde(rename = "fill-outline-color")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_outline_color: Option<StyleProperty<Color>>,
+    /// Whether the fill is antialiased by an outline of one pixel
+    #[serde(rename = "fill-antialias")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_antialias: Option<bool>,
+    /// Offset of the fill in pixels, positive values move it to the right and down
+    #[serde(rename = "fill-translate")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_translate: Option<StyleProperty<Vec<f32>>>,
+    /// Name of an image of the sprite which is repeated within the fill instead of its color
+    #[serde(rename = "fill-pattern")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_pattern: Option<StyleProperty<String>>,
+    // TODO fill-translate-anchor
+}
+
+impl FillPaint {
+    pub fn antialias(&self) -> bool {
+        self.fill_antialias.unwrap_or(true)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LinePaint {
     #[serde(rename = "line-color")]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub line_color: Option<Color>,
+    pub line_color: Option<StyleProperty<Color>>,
+    /// Width of the line in pixels
+    #[serde(rename = "line-width")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_width: Option<StyleProperty<f32>>,
+    /// Opacity of the line in the range `[0, 1]`
+    #[serde(rename = "line-opacity")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_opacity: Option<StyleProperty<f32>>,
+    /// Offset of the line in pixels. Positive values offset the line to the right, relative to
+    /// the direction of the line.
+    #[serde(rename = "line-offset")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_offset: Option<StyleProperty<f32>>,
+    /// Alternating lengths of dashes and gaps in line widths
+    #[serde(rename = "line-dasharray")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_dasharray: Option<StyleProperty<Vec<f32>>>,
+    // TODO line-blur, line-gap-width, line-gradient, line-pattern, line-translate
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RasterPaint {
+    #[serde(rename = "raster-opacity")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raster_opacity: Option<f32>,
     // TODO a lot
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HillshadePaint {
+    /// Direction of the light source in degrees, clockwise from north
+    #[serde(rename = "hillshade-illumination-direction")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hillshade_illumination_direction: Option<f32>,
+    /// Intensity of the shading in the range `[0, 1]`
+    #[serde(rename = "hillshade-exaggeration")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hillshade_exaggeration: Option<f32>,
+    #[serde(rename = "hillshade-shadow-color")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hillshade_shadow_color: Option<Color>,
+    #[serde(rename = "hillshade-highlight-color")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hillshade_highlight_color: Option<Color>,
+    /// Color which emphasizes steep slopes
+    #[serde(rename = "hillshade-accent-color")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hillshade_accent_color: Option<Color>,
+    // TODO hillshade-illumination-anchor
+}
+
+/// The display of the ends of lines
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineCap {
+    Butt,
+    Round,
+    Square,
+}
+
+impl Default for LineCap {
+    fn default() -> Self {
+        LineCap::Butt
+    }
+}
+
+/// The display of lines where they bend
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineJoin {
+    Bevel,
+    Round,
+    Miter,
+}
+
+impl Default for LineJoin {
+    fn default() -> Self {
+        LineJoin::Miter
+    }
+}
+
+/// Default of `line-miter-limit` according to the style specification
+pub const DEFAULT_MITER_LIMIT: f32 = 2.0;
+
+/// Layout properties of a style layer. Unlike paint properties, they affect the tessellation and
+/// are evaluated once per tile. Properties which do not apply to the type of the layer are
+/// ignored.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LayerLayout {
+    #[serde(rename = "line-cap")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_cap: Option<LineCap>,
+    #[serde(rename = "line-join")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_join: Option<LineJoin>,
+    /// Joins are drawn as bevels instead of miters if the ratio of the miter length to the line
+    /// width exceeds this limit
+    #[serde(rename = "line-miter-limit")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_miter_limit: Option<f32>,
+    // TODO visibility, symbol layout
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type", content = "paint")]
 pub enum LayerPaint {
@@ -36,21 +166,74 @@ pub enum LayerPaint {
     Line(LinePaint),
     #[serde(rename = "fill")]
     Fill(FillPaint),
+    #[serde(rename = "raster")]
+    Raster(RasterPaint),
+    #[serde(rename = "hillshade")]
+    Hillshade(HillshadePaint),
 }
 
 impl LayerPaint {
+    /// The color of background layers
     pub fn get_color(&self) -> Option<Alpha<EncodedSrgb<f32>>> {
         match self {
             LayerPaint::Background(paint) => paint
                 .background_color
                 .as_ref()
                 .map(|color| color.clone().into()),
-            LayerPaint::Line(paint) => paint.line_color.as_ref().map(|color| color.clone().into()),
-            LayerPaint::Fill(paint) => paint.fill_color.as_ref().map(|color| color.clone().into()),
+            _ => None,
+        }
+    }
+
+    /// Returns whether a paint property which is evaluated per feature changes its value at a
+    /// zoom level between `from` and `to`.
+    pub fn crosses_zoom_stop(&self, from: f64, to: f64) -> bool {
+        match self {
+            LayerPaint::Line(paint) => {
+                paint
+                    .line_color
+                    .as_ref()
+                    .map_or(false, |color| color.crosses_zoom_stop(from, to))
+                    || [&paint.line_width, &paint.line_opacity, &paint.line_offset]
+                        .iter()
+                        .filter_map(|property| property.as_ref())
+                        .any(|property| property.crosses_zoom_stop(from, to))
+            }
+            LayerPaint::Fill(paint) => {
+                [&paint.fill_color, &paint.fill_outline_color]
+                    .iter()
+                    .filter_map(|property| property.as_ref())
+                    .any(|property| property.crosses_zoom_stop(from, to))
+                    || paint
+                        .fill_opacity
+                        .as_ref()
+                        .map_or(false, |opacity| opacity.crosses_zoom_stop(from, to))
+                    || paint
+                        .fill_pattern
+                        .as_ref()
+                        .map_or(false, |pattern| pattern.crosses_zoom_stop(from, to))
+            }
+            LayerPaint::Background(_) | LayerPaint::Raster(_) | LayerPaint::Hillshade(_) => false,
         }
     }
 }
 
+/// Type of layers which fill polygons
+pub const FILL_LAYER_TYPE: &str = "fill";
+
+/// Type of layers which draw the images of raster sources
+pub const RASTER_LAYER_TYPE: &str = "raster";
+
+/// Type of layers which shade the elevations of raster DEM sources
+pub const HILLSHADE_LAYER_TYPE: &str = "hillshade";
+
+/// Name of the source layer under which the image of a raster tile is managed. Raster tiles do not
+/// consist of layers, therefore raster and hillshade layers implicitly use this source layer.
+pub const RASTER_SOURCE_LAYER: &str = "raster";
+
+/// Name of the single layer of the tiles which are sliced from GeoJSON sources. Layers without a
+/// source layer implicitly use this source layer.
+pub const GEOJSON_SOURCE_LAYER: &str = "_geojsonTileLayer";
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct StyleLayer {
     #[serde(skip)]
@@ -58,8 +241,12 @@ pub struct StyleLayer {
     pub id: String,
     #[serde(rename = "type")]
     pub typ: String,
-    // TODO filter
-    // TODO layout
+    /// Selects the features of the source layer which are drawn. Without filter all features
+    /// are drawn.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<LayerLayout>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub maxzoom: Option<u8>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -71,16 +258,60 @@ pub struct StyleLayer {
     pub paint: Option<LayerPaint>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source: Option<String>,
+    #[serde(rename = "source-layer")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source_layer: Option<String>,
 }
 
+impl StyleLayer {
+    pub fn is_fill(&self) -> bool {
+        self.typ == FILL_LAYER_TYPE
+    }
+
+    /// Whether the polygons of fill layers are outlined, such that their edges are antialiased
+    pub fn has_outlines(&self) -> bool {
+        match &self.paint {
+            Some(LayerPaint::Fill(paint)) => self.is_fill() && paint.antialias(),
+            _ => self.is_fill(),
+        }
+    }
+
+    pub fn is_raster(&self) -> bool {
+        self.typ == RASTER_LAYER_TYPE
+    }
+
+    pub fn is_hillshade(&self) -> bool {
+        self.typ == HILLSHADE_LAYER_TYPE
+    }
+
+    /// The layer within the tiles of the source which is drawn by this layer. For raster and
+    /// hillshade layers this is always [`RASTER_SOURCE_LAYER`], layers of GeoJSON sources do not
+    /// specify a source layer and use [`GEOJSON_SOURCE_LAYER`].
+    pub fn source_layer_name(&self) -> Option<&str> {
+        if self.is_raster() || self.is_hillshade() {
+            Some(RASTER_SOURCE_LAYER)
+        } else {
+            self.source_layer.as_deref().or(Some(GEOJSON_SOURCE_LAYER))
+        }
+    }
+
+    /// The opacity of raster layers in the range `[0, 1]`
+    pub fn raster_opacity(&self) -> f32 {
+        match &self.paint {
+            Some(LayerPaint::Raster(paint)) => paint.raster_opacity.unwrap_or(1.0).clamp(0.0, 1.0),
+            _ => 1.0,
+        }
+    }
+}
+
 impl Default for StyleLayer {
     fn default() -> Self {
         Self {
             index: 0,
             id: "id".to_string(),
             typ: "fill".to_string(),
+            filter: None,
+            layout: None,
             maxzoom: None,
             minzoom: None,
             metadata: None,
@@ -1,4 +1,7 @@
+pub mod expression;
+pub mod filter;
 pub mod layer;
+pub mod property;
 pub mod source;
 mod style;
 
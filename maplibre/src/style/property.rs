@@ -0,0 +1,358 @@
+//! Properties of style layers whose values are either constant or computed by
+//! [expressions](crate::style::expression), like `["get", "color"]`. The legacy functions of the
+//! style specification, like `{"base": 1.5, "stops": [[5, 1], [10, 4]]}`, are converted into the
+//! equivalent expressions.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use csscolorparser::Color;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value as Json};
+
+use crate::style::expression::{EvaluationContext, Expression, Type, Value};
+
+/// Type of the values of a [`StyleProperty`]
+pub trait PropertyType: Sized + Clone + fmt::Debug {
+    /// The type of the expressions which compute the values
+    const TYPE: Type;
+
+    fn from_value(value: &Value) -> Option<Self>;
+
+    fn to_json(&self) -> Json;
+}
+
+impl PropertyType for Color {
+    const TYPE: Type = Type::Color;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Color(color) => Some(color.clone()),
+            Value::String(color) => Color::from_str(color).ok(),
+            _ => None,
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        Json::String(self.to_hex_string())
+    }
+}
+
+impl PropertyType for f32 {
+    const TYPE: Type = Type::Number;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_number().map(|value| value as f32)
+    }
+
+    fn to_json(&self) -> Json {
+        json!(self)
+    }
+}
+
+impl PropertyType for String {
+    const TYPE: Type = Type::String;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        Json::String(self.clone())
+    }
+}
+
+impl PropertyType for Vec<f32> {
+    const TYPE: Type = Type::Array;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Array(values) => values
+                .iter()
+                .map(|value| value.as_number().map(|value| value as f32))
+                .collect(),
+            _ => None,
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        json!(self)
+    }
+}
+
+/// A property of a style layer. The property is kept in its JSON form, such that the style can be
+/// serialized again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "Json", into = "Json")]
+#[serde(bound(serialize = "T: Clone", deserialize = "T: PropertyType"))]
+pub struct StyleProperty<T> {
+    json: Json,
+    expression: Expression,
+    typ: PhantomData<T>,
+}
+
+impl<T: PropertyType> StyleProperty<T> {
+    /// A property with a constant value
+    pub fn constant(value: T) -> Self {
+        Self::try_from(value.to_json()).expect("constant values are valid properties")
+    }
+
+    /// Returns whether the value depends neither on the zoom level nor on the feature
+    pub fn is_constant(&self) -> bool {
+        self.expression.is_zoom_constant() && self.expression.is_feature_constant()
+    }
+
+    /// Returns whether the value changes abruptly or starts to change at a zoom level between
+    /// `from` and `to`.
+    pub fn crosses_zoom_stop(&self, from: f64, to: f64) -> bool {
+        self.expression
+            .zoom_stops()
+            .iter()
+            .any(|stop| (from < *stop) != (to < *stop))
+    }
+
+    /// Evaluates the property. Like in MapLibre GL, the caller falls back to the default value
+    /// of the property if the evaluation fails, e.g. if a feature lacks a property.
+    pub fn evaluate(&self, context: &EvaluationContext) -> Option<T> {
+        self.expression
+            .evaluate(context)
+            .ok()
+            .as_ref()
+            .and_then(T::from_value)
+    }
+}
+
+impl<T: PropertyType> TryFrom<Json> for StyleProperty<T> {
+    type Error = String;
+
+    fn try_from(json: Json) -> Result<Self, Self::Error> {
+        let expression = match &json {
+            Json::Object(function) => {
+                Expression::parse(&convert_function(function, T::TYPE)?, T::TYPE)?
+            }
+            // Constant arrays like `[2, 1]` do not need to be wrapped in a literal expression
+            Json::Array(items) if T::TYPE == Type::Array && !is_operator(items) => {
+                Expression::parse(&json!(["literal", json]), T::TYPE)?
+            }
+            json => Expression::parse(json, T::TYPE)?,
+        };
+
+        Ok(Self {
+            json,
+            expression,
+            typ: PhantomData,
+        })
+    }
+}
+
+impl<T> From<StyleProperty<T>> for Json {
+    fn from(property: StyleProperty<T>) -> Self {
+        property.json
+    }
+}
+
+/// Converts a legacy function into an expression like MapLibre GL does. Functions with a
+/// `property` are evaluated against features, otherwise against the zoom level. The `default` is
+/// only supported by categorical functions. `typ` is the type of the values of the property.
+fn convert_function(function: &Map<String, Json>, typ: Type) -> Result<Json, String> {
+    let input = match function.get("property") {
+        Some(Json::String(property)) => json!(["get", property]),
+        Some(property) => return Err(format!("expected a property name, found {}", property)),
+        None => json!(["zoom"]),
+    };
+
+    let default_kind = if matches!(typ, Type::Number | Type::Color) {
+        "exponential"
+    } else {
+        "interval"
+    };
+    let kind = function
+        .get("type")
+        .and_then(Json::as_str)
+        .unwrap_or(default_kind);
+    if kind == "identity" {
+        return Ok(input);
+    }
+
+    let stops = function
+        .get("stops")
+        .and_then(Json::as_array)
+        .filter(|stops| !stops.is_empty())
+        .ok_or_else(|| "functions must have stops".to_string())?
+        .iter()
+        .map(|stop| match stop.as_array().map(Vec::as_slice) {
+            Some([input, output]) => Ok((input.clone(), literal(output))),
+            _ => Err(format!("expected a stop of a function, found {}", stop)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut expression = match kind {
+        "exponential" => {
+            let base = function.get("base").and_then(Json::as_f64).unwrap_or(1.0);
+            let interpolation = if base == 1.0 {
+                json!(["linear"])
+            } else {
+                json!(["exponential", base])
+            };
+            vec![json!("interpolate"), interpolation, input]
+        }
+        "interval" => vec![json!("step"), input, stops[0].1.clone()],
+        "categorical" => vec![json!("match"), input],
+        kind => return Err(format!("unsupported function type {}", kind)),
+    };
+
+    let skipped_stops = if kind == "interval" { 1 } else { 0 };
+    for (input, output) in stops.into_iter().skip(skipped_stops) {
+        expression.push(input);
+        expression.push(output);
+    }
+
+    if kind == "categorical" {
+        let default = function
+            .get("default")
+            .ok_or_else(|| "categorical functions need a default".to_string())?;
+        expression.push(literal(default));
+    }
+
+    Ok(Json::Array(expression))
+}
+
+fn is_operator(items: &[Json]) -> bool {
+    matches!(items.first(), Some(Json::String(_)))
+}
+
+/// Arrays within functions are values, which must be wrapped in expressions
+fn literal(value: &Json) -> Json {
+    match value {
+        Json::Array(_) => json!(["literal", value]),
+        value => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    use csscolorparser::Color;
+    use serde_json::json;
+
+    use crate::style::expression::{EvaluationContext, FeatureProperties, PropertyValue};
+
+    use super::StyleProperty;
+
+    struct TestFeature(HashMap<&'static str, PropertyValue>);
+
+    impl FeatureProperties for TestFeature {
+        fn geometry_type(&self) -> PropertyValue {
+            PropertyValue::String("Polygon".to_string())
+        }
+
+        fn id(&self) -> PropertyValue {
+            PropertyValue::Null
+        }
+
+        fn property(&self, key: &str) -> PropertyValue {
+            self.0.get(key).cloned().unwrap_or(PropertyValue::Null)
+        }
+    }
+
+    fn width(json: serde_json::Value, zoom: f64) -> Option<f32> {
+        StyleProperty::<f32>::try_from(json)
+            .unwrap()
+            .evaluate(&EvaluationContext::new(zoom))
+    }
+
+    #[test]
+    fn test_constant() {
+        let color = StyleProperty::constant(Color::from_str("red").unwrap());
+        assert!(color.is_constant());
+        assert_eq!(
+            color.evaluate(&EvaluationContext::new(0.0)),
+            Some(Color::from_str("red").unwrap())
+        );
+        assert_eq!(width(json!(2), 0.0), Some(2.0));
+
+        let pattern = StyleProperty::<String>::try_from(json!("grass")).unwrap();
+        assert_eq!(
+            pattern.evaluate(&EvaluationContext::new(0.0)),
+            Some("grass".to_string())
+        );
+    }
+
+    #[test]
+    fn test_zoom_function() {
+        let stops = json!({"stops": [[10, 1], [20, 11]]});
+        assert_eq!(width(stops.clone(), 5.0), Some(1.0));
+        assert_eq!(width(stops.clone(), 15.0), Some(6.0));
+
+        let property = StyleProperty::<f32>::try_from(stops).unwrap();
+        assert!(!property.is_constant());
+        assert!(property.crosses_zoom_stop(9.5, 10.0));
+        assert!(property.crosses_zoom_stop(20.5, 19.0));
+        assert!(!property.crosses_zoom_stop(10.0, 19.0));
+
+        let interval = json!({"type": "interval", "stops": [[10, 1], [15, 4]]});
+        assert_eq!(width(interval.clone(), 5.0), Some(1.0));
+        assert_eq!(width(interval.clone(), 12.0), Some(1.0));
+        assert_eq!(width(interval, 15.0), Some(4.0));
+    }
+
+    #[test]
+    fn test_property_function() {
+        let feature = TestFeature(HashMap::from([(
+            "class",
+            PropertyValue::String("water".to_string()),
+        )]));
+        let context = EvaluationContext::new(0.0).with_feature(&feature);
+
+        let color = StyleProperty::<Color>::try_from(json!({
+            "property": "class",
+            "type": "categorical",
+            "stops": [["grass", "green"], ["water", "blue"]],
+            "default": "black"
+        }))
+        .unwrap();
+        assert_eq!(
+            color.evaluate(&context),
+            Some(Color::from_str("blue").unwrap())
+        );
+
+        let color = StyleProperty::<Color>::try_from(json!(["get", "color"])).unwrap();
+        assert_eq!(color.evaluate(&context), None);
+    }
+
+    #[test]
+    fn test_array() {
+        let context = EvaluationContext::new(0.0);
+        let dasharray = |json| {
+            StyleProperty::<Vec<f32>>::try_from(json)
+                .unwrap()
+                .evaluate(&context)
+        };
+
+        assert_eq!(dasharray(json!([2, 1])), Some(vec![2.0, 1.0]));
+        assert_eq!(dasharray(json!(["literal", [2, 1]])), Some(vec![2.0, 1.0]));
+        assert_eq!(
+            dasharray(json!({"stops": [[0, [1, 1]], [10, [3, 1]]]})),
+            Some(vec![1.0, 1.0])
+        );
+    }
+
+    #[test]
+    fn test_invalid_property() {
+        assert!(StyleProperty::<f32>::try_from(json!("red")).is_err());
+        assert!(StyleProperty::<Color>::try_from(json!({"stops": []})).is_err());
+    }
+
+    #[test]
+    fn test_serialization() {
+        let json = r#"{"base":1.5,"stops":[[5,1],[10,4]]}"#;
+        let property: StyleProperty<f32> = serde_json::from_str(json).unwrap();
+        assert_eq!(serde_json::to_string(&property).unwrap(), json);
+    }
+}
@@ -1,9 +1,18 @@
+use crate::tilejson::TileJSON;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 pub type TileUrl = String;
 
 pub type TileJSONUrl = String;
 
+/// Default of the `minzoom` of a source according to the style specification
+pub const DEFAULT_MINZOOM: u8 = 0;
+/// Default of the `maxzoom` of a source according to the style specification
+pub const DEFAULT_MAXZOOM: u8 = 22;
+/// Default of the `maxzoom` of a GeoJSON source according to the style specification
+pub const GEOJSON_DEFAULT_MAXZOOM: u8 = 18;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum TileAddressingScheme {
     #[serde(rename = "xyz")]
@@ -32,17 +41,140 @@ pub struct VectorSource {
     /// Min zoom level at which tiles are available
     #[serde(skip_serializing_if = "Option::is_none")]
     pub minzoom: Option<u8>,
-    // TODO: promoteId
+    /// Property which is used as the id of the features instead of their id within the tile
+    #[serde(rename = "promoteId")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub promote_id: Option<PromoteId>,
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub scheme: Option<TileAddressingScheme>,
     /// Array of URLs which can contain place holders like {x}, {y}, {z}.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tiles: Option<TileUrl>,
-    // url: Option<TileJSONUrl>,
+    pub tiles: Option<Vec<TileUrl>>,
+    /// URL to a TileJSON resource which describes the tiles of this source
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<TileJSONUrl>,
     // TODO volatile
 }
 
+impl VectorSource {
+    /// Fills the properties of this source which are not set yet with the values from a TileJSON.
+    /// Properties which are set explicitly within the style take precedence.
+    pub fn merge_tilejson(&mut self, tilejson: TileJSON) {
+        if self.tiles.is_none() {
+            self.tiles = Some(tilejson.tiles);
+        }
+
+        if self.minzoom.is_none() {
+            self.minzoom = tilejson.minzoom;
+        }
+
+        if self.maxzoom.is_none() {
+            self.maxzoom = tilejson.maxzoom;
+        }
+
+        if self.bounds.is_none() {
+            self.bounds = tilejson.bounds.and_then(|bounds| match bounds.as_slice() {
+                [west, south, east, north] => {
+                    Some((*west as f64, *south as f64, *east as f64, *north as f64))
+                }
+                _ => None,
+            });
+        }
+
+        if self.scheme.is_none() {
+            self.scheme = tilejson.scheme.and_then(|scheme| match scheme.as_str() {
+                "xyz" => Some(TileAddressingScheme::XYZ),
+                "tms" => Some(TileAddressingScheme::TMS),
+                _ => None,
+            });
+        }
+
+        if self.attribution.is_none() {
+            self.attribution = tilejson.attribution;
+        }
+    }
+}
+
+/// The `promoteId` of a source. Either a single property is promoted for all source layers, or
+/// the property is given per source layer.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum PromoteId {
+    Property(String),
+    PerLayer(HashMap<String, String>),
+}
+
+impl PromoteId {
+    /// The property which is promoted to the id of the features within the source layer
+    /// `layer_name`
+    pub fn property(&self, layer_name: &str) -> Option<&str> {
+        match self {
+            PromoteId::Property(property) => Some(property.as_str()),
+            PromoteId::PerLayer(properties) => properties.get(layer_name).map(String::as_str),
+        }
+    }
+}
+
+/// Encoding of the elevations within the tiles of a `raster-dem` source
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DemEncoding {
+    /// [Terrain-RGB](https://docs.mapbox.com/data/tilesets/reference/mapbox-terrain-rgb-v1/)
+    #[serde(rename = "mapbox")]
+    Mapbox,
+    /// [Terrarium](https://github.com/tilezen/joerd/blob/master/docs/formats.md#terrarium)
+    #[serde(rename = "terrarium")]
+    Terrarium,
+}
+
+impl Default for DemEncoding {
+    fn default() -> Self {
+        DemEncoding::Mapbox
+    }
+}
+
+/// Source of raster tiles which encode elevations in their colors. The tiles are described like
+/// the tiles of raster sources.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RasterDemSource {
+    #[serde(flatten)]
+    pub source: VectorSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<DemEncoding>,
+}
+
+/// The `data` of a GeoJSON source
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum GeoJsonData {
+    /// URL of a GeoJSON file
+    Url(String),
+    /// Inline GeoJSON object
+    Inline(serde_json::Value),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GeoJsonSource {
+    pub data: GeoJsonData,
+    /// String which contains attribution information for the data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<String>,
+    /// Max zoom level at which tiles are created. Higher zoom levels overzoom these tiles.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maxzoom: Option<u8>,
+    /// Size of the buffer around each tile in tile units. A tile is 4096 units wide.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buffer: Option<u32>,
+    /// Tolerance of the simplification in tile units. Higher means simpler geometries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<f64>,
+    /// Property which is used as the id of the features instead of their `id`
+    #[serde(rename = "promoteId")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub promote_id: Option<PromoteId>,
+    // TODO cluster, lineMetrics, generateId, filter
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum Source {
@@ -50,4 +182,106 @@ pub enum Source {
     Vector(VectorSource),
     #[serde(rename = "raster")]
     Raster(VectorSource),
+    #[serde(rename = "raster-dem")]
+    RasterDem(RasterDemSource),
+    #[serde(rename = "geojson")]
+    GeoJson(GeoJsonSource),
+}
+
+impl Source {
+    /// The lowest zoom level at which tiles of this source are available
+    pub fn minzoom(&self) -> u8 {
+        match self {
+            Source::Vector(source)
+            | Source::Raster(source)
+            | Source::RasterDem(RasterDemSource { source, .. }) => {
+                source.minzoom.unwrap_or(DEFAULT_MINZOOM)
+            }
+            Source::GeoJson(_) => DEFAULT_MINZOOM,
+        }
+    }
+
+    /// The bounds in which tiles of this source are available as `(west, south, east, north)`
+    pub fn bounds(&self) -> Option<(f64, f64, f64, f64)> {
+        match self {
+            Source::Vector(source)
+            | Source::Raster(source)
+            | Source::RasterDem(RasterDemSource { source, .. }) => source.bounds,
+            Source::GeoJson(_) => None,
+        }
+    }
+
+    /// The property which is promoted to the id of the features. Only vector and GeoJSON sources
+    /// contain features.
+    pub fn promote_id(&self) -> Option<&PromoteId> {
+        match self {
+            Source::Vector(source) => source.promote_id.as_ref(),
+            Source::GeoJson(source) => source.promote_id.as_ref(),
+            Source::Raster(_) | Source::RasterDem(_) => None,
+        }
+    }
+
+    /// The highest zoom level at which tiles of this source are available. Tiles of higher zoom
+    /// levels are rendered by overzooming tiles of this zoom level.
+    pub fn maxzoom(&self) -> u8 {
+        match self {
+            Source::Vector(source)
+            | Source::Raster(source)
+            | Source::RasterDem(RasterDemSource { source, .. }) => {
+                source.maxzoom.unwrap_or(DEFAULT_MAXZOOM)
+            }
+            Source::GeoJson(source) => source.maxzoom.unwrap_or(GEOJSON_DEFAULT_MAXZOOM),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_tilejson() {
+        // language=JSON
+        let source_json_str = r#"
+        {
+            "url": "https://example.com/tiles.json",
+            "maxzoom": 12
+        }
+        "#;
+        // language=JSON
+        let tilejson_str = r#"
+        {
+            "tilejson": "2.2.0",
+            "scheme": "tms",
+            "tiles": ["https://example.com/{z}/{x}/{y}.pbf"],
+            "minzoom": 0,
+            "maxzoom": 14,
+            "bounds": [5.8, 47.2, 15.1, 55.1]
+        }
+        "#;
+
+        let mut source: VectorSource = serde_json::from_str(source_json_str).unwrap();
+        source.merge_tilejson(serde_json::from_str(tilejson_str).unwrap());
+
+        assert_eq!(
+            source.tiles,
+            Some(vec!["https://example.com/{z}/{x}/{y}.pbf".to_string()])
+        );
+        assert_eq!(source.minzoom, Some(0));
+        assert_eq!(source.maxzoom, Some(12));
+        assert!(matches!(source.scheme, Some(TileAddressingScheme::TMS)));
+        assert!(source.bounds.is_some());
+    }
+
+    #[test]
+    fn test_promote_id() {
+        let source: VectorSource =
+            serde_json::from_str(r#"{"promoteId": {"building": "osm_id"}}"#).unwrap();
+        let promote_id = source.promote_id.unwrap();
+        assert_eq!(promote_id.property("building"), Some("osm_id"));
+        assert_eq!(promote_id.property("water"), None);
+
+        let source: VectorSource = serde_json::from_str(r#"{"promoteId": "osm_id"}"#).unwrap();
+        assert_eq!(source.promote_id.unwrap().property("water"), Some("osm_id"));
+    }
 }
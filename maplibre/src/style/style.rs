@@ -1,5 +1,6 @@
-use crate::style::layer::{LayerPaint, LinePaint, StyleLayer};
-use crate::style::source::Source;
+use crate::style::layer::{FillPaint, LayerPaint, LinePaint, StyleLayer};
+use crate::style::property::StyleProperty;
+use crate::style::source::{Source, TileAddressingScheme, VectorSource};
 use csscolorparser::Color;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -12,6 +13,31 @@ pub struct Style {
     pub metadata: HashMap<String, String>,
     pub sources: HashMap<String, Source>,
     pub layers: Vec<StyleLayer>,
+    /// URL of the sprite without file extension. The sprite holds the images of patterns.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sprite: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub terrain: Option<Terrain>,
+}
+
+/// Elevation of the terrain, which displaces the geometry of all layers. The terrain is read from
+/// a `raster-dem` source.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Terrain {
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exaggeration: Option<f32>,
+}
+
+impl Terrain {
+    /// Default of `exaggeration` according to the style specification
+    const DEFAULT_EXAGGERATION: f32 = 1.0;
+
+    pub fn exaggeration(&self) -> f32 {
+        self.exaggeration.unwrap_or(Self::DEFAULT_EXAGGERATION)
+    }
 }
 
 impl Default for Style {
@@ -20,113 +46,192 @@ impl Default for Style {
             version: 8,
             name: "Default Style".to_string(),
             metadata: Default::default(),
-            sources: Default::default(),
+            sources: HashMap::from([(
+                "openmaptiles".to_string(),
+                Source::Vector(VectorSource {
+                    attribution: None,
+                    bounds: None,
+                    maxzoom: None,
+                    minzoom: None,
+                    promote_id: None,
+                    scheme: Some(TileAddressingScheme::TMS),
+                    tiles: Some(vec![
+                        "https://maps.tuerantuer.org/europe_germany/{z}/{x}/{y}.pbf".to_string(),
+                    ]),
+                    url: None,
+                }),
+            )]),
             layers: vec![
                 StyleLayer {
                     index: 0,
                     id: "park".to_string(),
                     typ: "fill".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
-                    paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("lightgreen").unwrap()),
+                    paint: Some(LayerPaint::Fill(FillPaint {
+                        fill_color: Some(StyleProperty::constant(
+                            Color::from_str("lightgreen").unwrap(),
+                        )),
+                        fill_opacity: None,
+                        fill_outline_color: None,
+                        fill_antialias: None,
+                        fill_translate: None,
+                        fill_pattern: None,
                     })),
-                    source: None,
+                    source: Some("openmaptiles".to_string()),
                     source_layer: Some("park".to_string()),
                 },
                 StyleLayer {
                     index: 1,
                     id: "landuse".to_string(),
                     typ: "fill".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
-                    paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("lightgreen").unwrap()),
+                    paint: Some(LayerPaint::Fill(FillPaint {
+                        fill_color: Some(StyleProperty::constant(
+                            Color::from_str("lightgreen").unwrap(),
+                        )),
+                        fill_opacity: None,
+                        fill_outline_color: None,
+                        fill_antialias: None,
+                        fill_translate: None,
+                        fill_pattern: None,
                     })),
-                    source: None,
+                    source: Some("openmaptiles".to_string()),
                     source_layer: Some("landuse".to_string()),
                 },
                 StyleLayer {
                     index: 2,
                     id: "landcover".to_string(),
                     typ: "fill".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
-                    paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("lightgreen").unwrap()),
+                    paint: Some(LayerPaint::Fill(FillPaint {
+                        fill_color: Some(StyleProperty::constant(
+                            Color::from_str("lightgreen").unwrap(),
+                        )),
+                        fill_opacity: None,
+                        fill_outline_color: None,
+                        fill_antialias: None,
+                        fill_translate: None,
+                        fill_pattern: None,
                     })),
-                    source: None,
+                    source: Some("openmaptiles".to_string()),
                     source_layer: Some("landcover".to_string()),
                 },
                 StyleLayer {
                     index: 3,
                     id: "1transportation".to_string(),
                     typ: "line".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
                     paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("violet").unwrap()),
+                        line_color: Some(StyleProperty::constant(
+                            Color::from_str("violet").unwrap(),
+                        )),
+                        line_width: None,
+                        line_opacity: None,
+                        line_offset: None,
+                        line_dasharray: None,
                     })),
-                    source: None,
+                    source: Some("openmaptiles".to_string()),
                     source_layer: Some("transportation".to_string()),
                 },
                 StyleLayer {
                     index: 4,
                     id: "building".to_string(),
                     typ: "fill".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
-                    paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("grey").unwrap()),
+                    paint: Some(LayerPaint::Fill(FillPaint {
+                        fill_color: Some(StyleProperty::constant(Color::from_str("grey").unwrap())),
+                        fill_opacity: None,
+                        fill_outline_color: None,
+                        fill_antialias: None,
+                        fill_translate: None,
+                        fill_pattern: None,
                     })),
-                    source: None,
+                    source: Some("openmaptiles".to_string()),
                     source_layer: Some("building".to_string()),
                 },
                 StyleLayer {
                     index: 4,
                     id: "water".to_string(),
                     typ: "fill".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
-                    paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("blue").unwrap()),
+                    paint: Some(LayerPaint::Fill(FillPaint {
+                        fill_color: Some(StyleProperty::constant(Color::from_str("blue").unwrap())),
+                        fill_opacity: None,
+                        fill_outline_color: None,
+                        fill_antialias: None,
+                        fill_translate: None,
+                        fill_pattern: None,
                     })),
-                    source: None,
+                    source: Some("openmaptiles".to_string()),
                     source_layer: Some("water".to_string()),
                 },
                 StyleLayer {
                     index: 6,
                     id: "waterway".to_string(),
-                    typ: "fill".to_string(),
+                    typ: "line".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
                     paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("blue").unwrap()),
+                        line_color: Some(StyleProperty::constant(Color::from_str("blue").unwrap())),
+                        line_width: None,
+                        line_opacity: None,
+                        line_offset: None,
+                        line_dasharray: None,
                     })),
-                    source: None,
+                    source: Some("openmaptiles".to_string()),
                     source_layer: Some("waterway".to_string()),
                 },
                 StyleLayer {
                     index: 7,
                     id: "boundary".to_string(),
                     typ: "line".to_string(),
+                    filter: None,
+                    layout: None,
                     maxzoom: None,
                     minzoom: None,
                     metadata: None,
                     paint: Some(LayerPaint::Line(LinePaint {
-                        line_color: Some(Color::from_str("black").unwrap()),
+                        line_color: Some(StyleProperty::constant(
+                            Color::from_str("black").unwrap(),
+                        )),
+                        line_width: None,
+                        line_opacity: None,
+                        line_offset: None,
+                        line_dasharray: None,
                     })),
-                    source: None,
+                    source: Some("openmaptiles".to_string()),
                     source_layer: Some("boundary".to_string()),
                 },
             ],
+            sprite: None,
+            terrain: None,
         }
     }
 }
@@ -134,6 +239,8 @@ impl Default for Style {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::style::layer::{GEOJSON_SOURCE_LAYER, RASTER_SOURCE_LAYER};
+    use crate::style::source::{DemEncoding, GeoJsonData, GeoJsonSource, RasterDemSource};
 
     #[test]
     fn test_reading() {
@@ -190,4 +297,226 @@ mod tests {
 
         let _style: Style = serde_json::from_str(style_json_str).unwrap();
     }
+
+    #[test]
+    fn test_reading_raster() {
+        // language=JSON
+        let style_json_str = r##"
+        {
+          "version": 8,
+          "name": "Test Style",
+          "metadata": {},
+          "sources": {
+            "satellite": {
+              "type": "raster",
+              "tiles": ["https://example.com/{z}/{x}/{y}.jpg"],
+              "maxzoom": 18
+            }
+          },
+          "layers": [
+            {
+              "id": "satellite",
+              "type": "raster",
+              "source": "satellite",
+              "paint": {"raster-opacity": 0.5}
+            }
+          ]
+        }
+        "##;
+
+        let style: Style = serde_json::from_str(style_json_str).unwrap();
+        assert!(matches!(
+            style.sources.get("satellite"),
+            Some(Source::Raster(_))
+        ));
+
+        let layer = &style.layers[0];
+        assert!(layer.is_raster());
+        assert_eq!(layer.source_layer_name(), Some(RASTER_SOURCE_LAYER));
+        assert_eq!(layer.raster_opacity(), 0.5);
+    }
+
+    #[test]
+    fn test_reading_hillshade() {
+        // language=JSON
+        let style_json_str = r##"
+        {
+          "version": 8,
+          "name": "Test Style",
+          "metadata": {},
+          "sources": {
+            "terrain": {
+              "type": "raster-dem",
+              "tiles": ["https://example.com/terrain/{z}/{x}/{y}.png"],
+              "maxzoom": 12,
+              "encoding": "terrarium"
+            }
+          },
+          "layers": [
+            {
+              "id": "hills",
+              "type": "hillshade",
+              "source": "terrain",
+              "paint": {
+                "hillshade-illumination-direction": 270,
+                "hillshade-shadow-color": "#473B24"
+              }
+            }
+          ]
+        }
+        "##;
+
+        let style: Style = serde_json::from_str(style_json_str).unwrap();
+        let source = style.sources.get("terrain").unwrap();
+        assert!(matches!(
+            source,
+            Source::RasterDem(RasterDemSource {
+                encoding: Some(DemEncoding::Terrarium),
+                ..
+            })
+        ));
+        assert_eq!(source.maxzoom(), 12);
+
+        let layer = &style.layers[0];
+        assert!(layer.is_hillshade());
+        assert_eq!(layer.source_layer_name(), Some(RASTER_SOURCE_LAYER));
+        match &layer.paint {
+            Some(LayerPaint::Hillshade(paint)) => {
+                assert_eq!(paint.hillshade_illumination_direction, Some(270.0));
+                assert!(paint.hillshade_shadow_color.is_some());
+                assert!(paint.hillshade_exaggeration.is_none());
+            }
+            _ => panic!("expected hillshade paint"),
+        }
+    }
+
+    #[test]
+    fn test_reading_fill() {
+        // language=JSON
+        let style_json_str = r##"
+        {
+          "version": 8,
+          "name": "Test Style",
+          "metadata": {},
+          "sprite": "https://example.com/sprite",
+          "sources": {
+            "openmaptiles": {
+              "type": "vector",
+              "url": "https://maps.tuerantuer.org/europe_germany/tiles.json"
+            }
+          },
+          "layers": [
+            {
+              "id": "wetland",
+              "type": "fill",
+              "source": "openmaptiles",
+              "source-layer": "landcover",
+              "paint": {
+                "fill-pattern": "wetland",
+                "fill-opacity": 0.5,
+                "fill-outline-color": "#3D3D3D",
+                "fill-translate": [2, 1],
+                "fill-antialias": false
+              }
+            }
+          ]
+        }
+        "##;
+
+        let style: Style = serde_json::from_str(style_json_str).unwrap();
+        assert_eq!(style.sprite.as_deref(), Some("https://example.com/sprite"));
+
+        let layer = &style.layers[0];
+        assert!(layer.is_fill());
+        assert!(!layer.has_outlines());
+        match &layer.paint {
+            Some(LayerPaint::Fill(paint)) => {
+                assert!(paint.fill_color.is_none());
+                assert!(paint.fill_outline_color.is_some());
+                assert!(paint.fill_translate.is_some());
+                assert!(paint.fill_pattern.is_some());
+            }
+            _ => panic!("expected fill paint"),
+        }
+    }
+
+    #[test]
+    fn test_reading_terrain() {
+        // language=JSON
+        let style_json_str = r##"
+        {
+          "version": 8,
+          "name": "Test Style",
+          "metadata": {},
+          "sources": {
+            "dem": {
+              "type": "raster-dem",
+              "tiles": ["https://example.com/terrain/{z}/{x}/{y}.png"]
+            }
+          },
+          "layers": [],
+          "terrain": {"source": "dem", "exaggeration": 1.5}
+        }
+        "##;
+
+        let style: Style = serde_json::from_str(style_json_str).unwrap();
+        let terrain = style.terrain.unwrap();
+        assert_eq!(terrain.source, "dem");
+        assert_eq!(terrain.exaggeration(), 1.5);
+
+        let terrain: Terrain = serde_json::from_str(r#"{"source": "dem"}"#).unwrap();
+        assert_eq!(terrain.exaggeration(), 1.0);
+    }
+
+    #[test]
+    fn test_reading_geojson() {
+        // language=JSON
+        let style_json_str = r##"
+        {
+          "version": 8,
+          "name": "Test Style",
+          "metadata": {},
+          "sources": {
+            "inline": {
+              "type": "geojson",
+              "data": {"type": "Point", "coordinates": [13.4, 52.5]}
+            },
+            "remote": {
+              "type": "geojson",
+              "data": "https://example.com/vehicles.geojson",
+              "maxzoom": 14
+            }
+          },
+          "layers": [
+            {
+              "id": "vehicles",
+              "type": "fill",
+              "source": "remote"
+            }
+          ]
+        }
+        "##;
+
+        let style: Style = serde_json::from_str(style_json_str).unwrap();
+        assert!(matches!(
+            style.sources.get("inline"),
+            Some(Source::GeoJson(GeoJsonSource {
+                data: GeoJsonData::Inline(_),
+                ..
+            }))
+        ));
+        let remote = style.sources.get("remote").unwrap();
+        assert!(matches!(
+            remote,
+            Source::GeoJson(GeoJsonSource {
+                data: GeoJsonData::Url(_),
+                ..
+            })
+        ));
+        assert_eq!(remote.maxzoom(), 14);
+        assert_eq!(
+            style.layers[0].source_layer_name(),
+            Some(GEOJSON_SOURCE_LAYER)
+        );
+    }
 }
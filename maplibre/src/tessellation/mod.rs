@@ -5,10 +5,12 @@ use std::ops::Add;
 
 use crate::render::ShaderVertex;
 use lyon::tessellation::{
-    FillVertex, FillVertexConstructor, StrokeVertex, StrokeVertexConstructor, VertexBuffers,
+    FillVertex, FillVertexConstructor, Side, StrokeOptions, StrokeVertex, StrokeVertexConstructor,
+    VertexBuffers,
 };
 
 use crate::error::Error;
+use crate::style::layer::{LineCap, LineJoin, StyleLayer, DEFAULT_MITER_LIMIT};
 use wgpu::BufferAddress;
 
 pub mod zero_tessellator;
@@ -34,13 +36,45 @@ impl FillVertexConstructor<ShaderVertex> for VertexConstructor {
 
 impl StrokeVertexConstructor<ShaderVertex> for VertexConstructor {
     fn new_vertex(&mut self, vertex: StrokeVertex) -> ShaderVertex {
-        ShaderVertex::new(
+        ShaderVertex::stroke(
             vertex.position_on_path().to_array(),
             vertex.normal().to_array(),
+            vertex.advancement(),
+            match vertex.side() {
+                Side::Left => 1.0,
+                Side::Right => -1.0,
+            },
         )
     }
 }
 
+/// Returns the options with which the lines of `style_layer` are tessellated. The width of the
+/// lines is applied within the vertex shader.
+pub fn stroke_options(style_layer: &StyleLayer) -> StrokeOptions {
+    let layout = style_layer.layout.clone().unwrap_or_default();
+
+    let line_cap = match layout.line_cap.unwrap_or_default() {
+        LineCap::Butt => lyon::tessellation::LineCap::Butt,
+        LineCap::Round => lyon::tessellation::LineCap::Round,
+        LineCap::Square => lyon::tessellation::LineCap::Square,
+    };
+    let line_join = match layout.line_join.unwrap_or_default() {
+        LineJoin::Bevel => lyon::tessellation::LineJoin::Bevel,
+        LineJoin::Round => lyon::tessellation::LineJoin::Round,
+        LineJoin::Miter => lyon::tessellation::LineJoin::Miter,
+    };
+    // Lyon does not accept miter limits below its minimum
+    let miter_limit = layout
+        .line_miter_limit
+        .unwrap_or(DEFAULT_MITER_LIMIT)
+        .max(StrokeOptions::MINIMUM_MITER_LIMIT);
+
+    StrokeOptions::tolerance(DEFAULT_TOLERANCE)
+        .with_line_cap(line_cap)
+        .with_line_join(line_join)
+        .with_miter_limit(miter_limit)
+}
+
 /// Vertex buffer which includes additional padding to fulfill the `wgpu::COPY_BUFFER_ALIGNMENT`.
 #[derive(Clone)]
 pub struct OverAlignedVertexBuffer<V, I> {
@@ -55,6 +89,12 @@ impl<V, I> OverAlignedVertexBuffer<V, I> {
             usable_indices: 0,
         }
     }
+
+    /// Approximate amount of heap memory in bytes which is allocated by the buffer.
+    pub fn approximate_size(&self) -> usize {
+        self.buffer.vertices.capacity() * std::mem::size_of::<V>()
+            + self.buffer.indices.capacity() * std::mem::size_of::<I>()
+    }
 }
 
 impl<V: Pod, I: Pod> From<VertexBuffers<V, I>> for OverAlignedVertexBuffer<V, I> {
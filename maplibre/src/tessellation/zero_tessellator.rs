@@ -19,11 +19,19 @@ pub struct ZeroTessellator<I: std::ops::Add + From<lyon::tessellation::VertexId>
     path_builder: RefCell<Builder>,
     path_open: bool,
     is_point: bool,
+    stroke_options: StrokeOptions,
+    /// Whether polygons are outlined in addition to being filled
+    outlines: bool,
 
     pub buffer: VertexBuffers<ShaderVertex, I>,
 
-    pub feature_indices: Vec<u32>,
-    current_index: usize,
+    /// Holds for each feature the count of vertices
+    pub feature_vertices: Vec<u32>,
+    /// Holds for each feature the count of vertices of its outline, which precede the vertices of
+    /// its fill
+    pub feature_outline_vertices: Vec<u32>,
+    current_vertex: usize,
+    current_outline_vertices: u32,
 }
 
 impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> Default
@@ -33,20 +41,41 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> Default
         Self {
             path_builder: RefCell::new(Path::builder()),
             buffer: VertexBuffers::new(),
-            feature_indices: Vec::new(),
-            current_index: 0,
+            feature_vertices: Vec::new(),
+            feature_outline_vertices: Vec::new(),
+            current_vertex: 0,
+            current_outline_vertices: 0,
             path_open: false,
             is_point: false,
+            stroke_options: StrokeOptions::tolerance(DEFAULT_TOLERANCE),
+            outlines: false,
         }
     }
 }
 
 impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> ZeroTessellator<I> {
-    fn update_feature_indices(&mut self) {
-        let next_index = self.buffer.indices.len();
-        let indices = (next_index - self.current_index) as u32;
-        self.feature_indices.push(indices);
-        self.current_index = next_index;
+    /// Creates a tessellator which tessellates lines with `stroke_options`
+    pub fn new(stroke_options: StrokeOptions) -> Self {
+        Self {
+            stroke_options,
+            ..Default::default()
+        }
+    }
+
+    /// Outlines polygons with `stroke_options` in addition to filling them
+    pub fn with_outlines(mut self, outlines: bool) -> Self {
+        self.outlines = outlines;
+        self
+    }
+
+    fn update_feature_vertices(&mut self) {
+        let next_vertex = self.buffer.vertices.len();
+        let vertices = (next_vertex - self.current_vertex) as u32;
+        self.feature_vertices.push(vertices);
+        self.feature_outline_vertices
+            .push(self.current_outline_vertices);
+        self.current_vertex = next_vertex;
+        self.current_outline_vertices = 0;
     }
 
     fn tessellate_strokes(&mut self) {
@@ -55,7 +84,7 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> ZeroTesse
         StrokeTessellator::new()
             .tessellate_path(
                 &path_builder.build(),
-                &StrokeOptions::tolerance(DEFAULT_TOLERANCE),
+                &self.stroke_options,
                 &mut BuffersBuilder::new(&mut self.buffer, VertexConstructor {}),
             )
             .unwrap();
@@ -69,11 +98,25 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> ZeroTesse
     }
 
     fn tessellate_fill(&mut self) {
-        let path_builder = self.path_builder.replace(Path::builder());
+        let path = self.path_builder.replace(Path::builder()).build();
+
+        // The outline precedes the fill, such that the fill does not cover the inner half of the
+        // outline
+        if self.outlines {
+            let first_vertex = self.buffer.vertices.len();
+            StrokeTessellator::new()
+                .tessellate_path(
+                    &path,
+                    &self.stroke_options,
+                    &mut BuffersBuilder::new(&mut self.buffer, VertexConstructor {}),
+                )
+                .unwrap();
+            self.current_outline_vertices += (self.buffer.vertices.len() - first_vertex) as u32;
+        }
 
         FillTessellator::new()
             .tessellate_path(
-                &path_builder.build(),
+                &path,
                 &FillOptions::tolerance(DEFAULT_TOLERANCE).with_fill_rule(FillRule::NonZero),
                 &mut BuffersBuilder::new(&mut self.buffer, VertexConstructor {}),
             )
@@ -188,7 +231,7 @@ impl<I: std::ops::Add + From<lyon::tessellation::VertexId> + MaxIndex> FeaturePr
     for ZeroTessellator<I>
 {
     fn feature_end(&mut self, _idx: u64) -> geozero::error::Result<()> {
-        self.update_feature_indices();
+        self.update_feature_vertices();
         Ok(())
     }
 }
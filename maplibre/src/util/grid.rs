@@ -41,9 +41,9 @@ pub fn google_mercator() -> Grid {
     )
 }
 
-///
-/// Returns coordinates for tiles within bavaria according to the specified grid.
-/// The grid is responsible for defining the coordinate system. For example whether
+/// Returns coordinates for all tiles within `extent` for the zoom levels `min_zoom` up to and
+/// including `max_zoom`. The `extent` is given in WGS84 coordinates. The grid is responsible for
+/// defining the coordinate system. For example whether
 /// [Slippy map tilenames](https://wiki.openstreetmap.org/wiki/Slippy_map_tilenames) (also known as
 /// XYZ) or [TMS](https://wiki.osgeo.org/wiki/Tile_Map_Service_Specification#TileMap_Diagram) is
 /// used.
@@ -52,16 +52,29 @@ pub fn google_mercator() -> Grid {
 ///
 /// * https://www.maptiler.com/google-maps-coordinates-tile-bounds-projection
 /// * https://gist.github.com/maptiler/fddb5ce33ba995d5523de9afdf8ef118
+pub fn tile_coordinates(
+    grid: &Grid,
+    extent: &Extent,
+    min_zoom: u8,
+    max_zoom: u8,
+) -> impl Iterator<Item = (u8, u32, u32)> {
+    let tile_limits = grid.tile_limits(extent_wgs84_to_merc(extent), 0);
+    GridIterator::new(min_zoom, max_zoom, tile_limits)
+}
+
+/// Returns coordinates for tiles within bavaria according to the specified grid.
+/// See [`tile_coordinates`].
 pub fn tile_coordinates_bavaria(grid: &Grid, zoom: u8) -> Vec<(u8, u32, u32)> {
-    let tile_limits = grid.tile_limits(
-        extent_wgs84_to_merc(&Extent {
+    tile_coordinates(
+        grid,
+        &Extent {
             minx: 8.9771580802,
             miny: 47.2703623267,
             maxx: 13.8350427083,
             maxy: 50.5644529365,
-        }),
-        0,
-    );
-
-    GridIterator::new(zoom, zoom, tile_limits).collect()
+        },
+        zoom,
+        zoom,
+    )
+    .collect()
 }
@@ -1,11 +1,14 @@
 use crate::platform::http_client::WHATWGFetchHttpClient;
+use crate::platform::indexed_db_cache::IndexedDbCacheBackend;
 use crate::platform::schedule_method::WebWorkerPoolScheduleMethod;
 
+use maplibre::io::persistent_cache::CachePolicy;
 use maplibre::io::scheduler::Scheduler;
 
 use maplibre::MapBuilder;
 use maplibre_winit::winit::{WinitMapWindow, WinitMapWindowConfig};
 use std::panic;
+use std::sync::Arc;
 use wasm_bindgen::prelude::*;
 
 mod error;
@@ -57,6 +60,10 @@ pub async fn run(scheduler_ptr: *mut Scheduler<WebWorkerPoolScheduleMethod>) {
     MapBuilder::new()
         .with_map_window_config(WinitMapWindowConfig::new("maplibre".to_string()))
         .with_http_client(WHATWGFetchHttpClient::new())
+        .with_tile_cache(
+            Arc::new(IndexedDbCacheBackend::new("maplibre-tile-cache")),
+            CachePolicy::default(),
+        )
         .with_existing_scheduler(*scheduler)
         .build()
         .initialize()
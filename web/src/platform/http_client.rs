@@ -1,10 +1,10 @@
 use js_sys::{ArrayBuffer, Uint8Array};
-use maplibre::io::source_client::HTTPClient;
+use maplibre::io::source_client::{CacheHeaders, ConditionalResponse, HTTPClient};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::JsFuture;
 
-use web_sys::{Request, RequestInit, Response, WorkerGlobalScope};
+use web_sys::{Headers, Request, RequestInit, Response, WorkerGlobalScope};
 
 use crate::error::WebError;
 use async_trait::async_trait;
@@ -18,10 +18,18 @@ impl WHATWGFetchHttpClient {
         Self {}
     }
 
-    async fn fetch_array_buffer(url: &str) -> Result<JsValue, JsValue> {
+    async fn fetch_response(url: &str, headers: &[(&str, String)]) -> Result<Response, JsValue> {
         let mut opts = RequestInit::new();
         opts.method("GET");
 
+        if !headers.is_empty() {
+            let request_headers = Headers::new()?;
+            for (name, value) in headers {
+                request_headers.set(name, value)?;
+            }
+            opts.headers(&request_headers);
+        }
+
         let request = Request::new_with_str_and_init(url, &opts)?;
 
         // Get the global scope
@@ -33,14 +41,27 @@ impl WHATWGFetchHttpClient {
         let maybe_response = JsFuture::from(scope.fetch_with_request(&request)).await?;
         assert!(maybe_response.is_instance_of::<Response>());
         let response: Response = maybe_response.dyn_into().unwrap();
+        Ok(response)
+    }
 
-        // Get ArrayBuffer
-        let maybe_array_buffer = JsFuture::from(response.array_buffer()?).await?;
-        Ok(maybe_array_buffer)
+    async fn fetch_array_buffer(response: &Response) -> Result<JsValue, JsValue> {
+        JsFuture::from(response.array_buffer()?).await
+    }
+
+    async fn fetch_bytes(&self, url: &str, headers: &[(&str, String)]) -> Result<Vec<u8>, Error> {
+        let response = Self::fetch_response(url, headers)
+            .await
+            .map_err(network_error)?;
+        check_status(url, response.status())?;
+
+        Self::read_body(&response).await
     }
 
-    async fn fetch_bytes(&self, url: &str) -> Result<Vec<u8>, WebError> {
-        let maybe_array_buffer = Self::fetch_array_buffer(url).await?;
+    async fn read_body(response: &Response) -> Result<Vec<u8>, Error> {
+        // Get ArrayBuffer
+        let maybe_array_buffer = Self::fetch_array_buffer(response)
+            .await
+            .map_err(network_error)?;
 
         assert!(maybe_array_buffer.is_instance_of::<ArrayBuffer>());
         let array_buffer: ArrayBuffer = maybe_array_buffer.dyn_into().unwrap();
@@ -54,6 +75,31 @@ impl WHATWGFetchHttpClient {
     }
 }
 
+fn network_error(e: JsValue) -> Error {
+    let WebError(msg) = e.into();
+    Error::Network(msg)
+}
+
+fn cache_headers(response: &Response) -> CacheHeaders {
+    let headers = response.headers();
+    CacheHeaders {
+        cache_control: headers.get("Cache-Control").ok().flatten(),
+        etag: headers.get("ETag").ok().flatten(),
+    }
+}
+
+/// Classifies unsuccessful HTTP statuses. Only server errors, timeouts and rate limiting are
+/// considered transient.
+fn check_status(url: &str, status: u16) -> Result<(), Error> {
+    let message = || format!("{} responded with {}", url, status);
+    match status {
+        204 | 404 | 410 => Err(Error::NotFound(message())),
+        408 | 429 | 500..=599 => Err(Error::Network(message())),
+        400..=499 => Err(Error::IO(message())),
+        _ => Ok(()),
+    }
+}
+
 impl Clone for WHATWGFetchHttpClient {
     fn clone(&self) -> Self {
         WHATWGFetchHttpClient {}
@@ -63,8 +109,49 @@ impl Clone for WHATWGFetchHttpClient {
 #[async_trait(?Send)]
 impl HTTPClient for WHATWGFetchHttpClient {
     async fn fetch(&self, url: &str) -> Result<Vec<u8>, Error> {
-        self.fetch_bytes(url)
+        self.fetch_bytes(url, &[]).await
+    }
+
+    async fn fetch_range(&self, url: &str, offset: u64, length: u64) -> Result<Vec<u8>, Error> {
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+
+        let range = format!("bytes={}-{}", offset, offset + length - 1);
+        let mut bytes = self.fetch_bytes(url, &[("Range", range)]).await?;
+
+        // The server ignored the range, therefore the range is extracted from the full body
+        if bytes.len() as u64 > length {
+            let start = (offset as usize).min(bytes.len());
+            let end = (offset.saturating_add(length) as usize).min(bytes.len());
+            bytes = bytes[start..end].to_vec();
+        }
+
+        Ok(bytes)
+    }
+
+    async fn fetch_conditional(
+        &self,
+        url: &str,
+        etag: Option<&str>,
+    ) -> Result<ConditionalResponse, Error> {
+        let headers: Vec<(&str, String)> = etag
+            .map(|etag| ("If-None-Match", etag.to_string()))
+            .into_iter()
+            .collect();
+
+        let response = Self::fetch_response(url, &headers)
             .await
-            .map_err(|WebError(msg)| Error::Network(msg))
+            .map_err(network_error)?;
+        let headers = cache_headers(&response);
+        if response.status() == 304 {
+            return Ok(ConditionalResponse::NotModified { headers });
+        }
+
+        check_status(url, response.status())?;
+        Ok(ConditionalResponse::Modified {
+            data: Self::read_body(&response).await?,
+            headers,
+        })
     }
 }
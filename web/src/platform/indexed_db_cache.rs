@@ -0,0 +1,257 @@
+//! Cache backend which stores tiles in [IndexedDB](https://developer.mozilla.org/en-US/docs/Web/API/IndexedDB_API).
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+
+use async_trait::async_trait;
+use js_sys::{Array, Function, Promise, Uint8Array};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbFactory, IdbObjectStore, IdbRequest, IdbTransactionMode};
+
+use maplibre::error::Error;
+use maplibre::io::persistent_cache::{CacheBackend, CacheKey, CacheMetadata, CachedTile};
+
+const DATABASE_VERSION: u32 = 1;
+/// Object store which holds the data of the tiles
+const TILES_STORE: &str = "tiles";
+/// Object store which holds the metadata of the tiles. It is separate from the data, such that
+/// the index of the cache can be restored without loading all tiles.
+const METADATA_STORE: &str = "metadata";
+
+#[derive(Serialize, Deserialize)]
+struct MetadataRecord {
+    key: CacheKey,
+    metadata: CacheMetadata,
+}
+
+thread_local! {
+    /// Databases which have been opened on this thread, keyed by their names
+    static DATABASES: RefCell<HashMap<String, IdbDatabase>> = RefCell::new(HashMap::new());
+}
+
+/// Stores tiles in an IndexedDB database. IndexedDB objects can not be shared between threads
+/// like the backend, therefore the database is opened once on each thread which uses the backend
+/// and kept open afterwards.
+pub struct IndexedDbCacheBackend {
+    name: String,
+}
+
+impl IndexedDbCacheBackend {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+        }
+    }
+
+    /// Opens the database and creates the object stores if the database does not exist yet.
+    async fn open(&self) -> Result<IdbDatabase, Error> {
+        // indexedDB is available within windows as well as workers
+        let factory: IdbFactory = js_sys::Reflect::get(&js_sys::global(), &"indexedDB".into())
+            .map_err(idb_error)?
+            .dyn_into()
+            .map_err(|_e| Error::IO("IndexedDB is not available".to_string()))?;
+
+        let request = factory
+            .open_with_u32(&self.name, DATABASE_VERSION)
+            .map_err(idb_error)?;
+
+        let upgrade_request = request.clone();
+        let onupgradeneeded = Closure::once_into_js(move || {
+            if let Some(database) = upgrade_request
+                .result()
+                .ok()
+                .and_then(|result| result.dyn_into::<IdbDatabase>().ok())
+            {
+                for store in [TILES_STORE, METADATA_STORE] {
+                    if let Err(e) = database.create_object_store(store) {
+                        log::error!("IndexedDB store {} can not be created: {:?}", store, e);
+                    }
+                }
+            }
+        });
+        request.set_onupgradeneeded(Some(onupgradeneeded.unchecked_ref()));
+
+        request_future(&request)
+            .await?
+            .dyn_into()
+            .map_err(|_e| Error::IO("IndexedDB database can not be opened".to_string()))
+    }
+
+    /// Returns the database, which is opened if this thread did not open it yet.
+    async fn database(&self) -> Result<IdbDatabase, Error> {
+        let opened = DATABASES.with(|databases| databases.borrow().get(&self.name).cloned());
+        if let Some(database) = opened {
+            return Ok(database);
+        }
+
+        let database = self.open().await?;
+        // Another operation on this thread might have opened the database in the meantime
+        Ok(DATABASES.with(|databases| {
+            databases
+                .borrow_mut()
+                .entry(self.name.clone())
+                .or_insert(database)
+                .clone()
+        }))
+    }
+
+    /// Opens the tiles and metadata stores within a new transaction. The transaction commits
+    /// automatically once control returns to the event loop without pending requests, therefore
+    /// all requests of an operation have to be issued before the first one is awaited.
+    async fn stores(
+        &self,
+        mode: IdbTransactionMode,
+    ) -> Result<(IdbObjectStore, IdbObjectStore), Error> {
+        let database = self.database().await?;
+        let store_names = Array::of2(&TILES_STORE.into(), &METADATA_STORE.into());
+        let transaction = database
+            .transaction_with_str_sequence_and_mode(&store_names, mode)
+            .map_err(idb_error)?;
+        let tiles = transaction.object_store(TILES_STORE).map_err(idb_error)?;
+        let metadata = transaction
+            .object_store(METADATA_STORE)
+            .map_err(idb_error)?;
+        Ok((tiles, metadata))
+    }
+}
+
+fn idb_error(e: JsValue) -> Error {
+    Error::IO(format!("IndexedDB operation failed: {:?}", e))
+}
+
+fn key_string(key: &CacheKey) -> JsValue {
+    JsValue::from_str(&format!(
+        "{}/{}/{}/{}",
+        key.source_id, key.coords.z, key.coords.x, key.coords.y
+    ))
+}
+
+fn parse_record(value: &JsValue) -> Option<MetadataRecord> {
+    value
+        .as_string()
+        .and_then(|json| serde_json::from_str(&json).ok())
+}
+
+fn record_value(key: &CacheKey, metadata: &CacheMetadata) -> Result<JsValue, Error> {
+    let json = serde_json::to_string(&MetadataRecord {
+        key: key.clone(),
+        metadata: metadata.clone(),
+    })
+    .map_err(|e| Error::IO(e.to_string()))?;
+    Ok(JsValue::from_str(&json))
+}
+
+/// Returns a future which resolves once the IndexedDB `request` succeeded. The handlers are
+/// attached immediately, such that events which fire before the future is polled are not missed.
+fn request_future(request: &IdbRequest) -> impl Future<Output = Result<JsValue, Error>> {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let success_request = request.clone();
+        let onsuccess = Closure::once_into_js(move || {
+            let result = success_request.result().unwrap_or(JsValue::UNDEFINED);
+            let _ = resolve.call1(&JsValue::UNDEFINED, &result);
+        });
+        request.set_onsuccess(Some(onsuccess.unchecked_ref()));
+
+        let error_request = request.clone();
+        let onerror = Closure::once_into_js(move || {
+            let error = error_request
+                .error()
+                .ok()
+                .flatten()
+                .map_or(JsValue::UNDEFINED, JsValue::from);
+            let _ = reject.call1(&JsValue::UNDEFINED, &error);
+        });
+        request.set_onerror(Some(onerror.unchecked_ref()));
+    });
+
+    let future = JsFuture::from(promise);
+    async move { future.await.map_err(idb_error) }
+}
+
+#[async_trait(?Send)]
+impl CacheBackend for IndexedDbCacheBackend {
+    async fn get(&self, key: &CacheKey) -> Result<Option<CachedTile>, Error> {
+        let (tiles, metadata) = self.stores(IdbTransactionMode::Readonly).await?;
+        let key_value = key_string(key);
+
+        let record_future = request_future(&metadata.get(&key_value).map_err(idb_error)?);
+        let data_future = request_future(&tiles.get(&key_value).map_err(idb_error)?);
+        let record = parse_record(&record_future.await?);
+        let data = data_future.await?;
+
+        match (record, data.dyn_into::<Uint8Array>()) {
+            (Some(record), Ok(data)) => Ok(Some(CachedTile {
+                data: data.to_vec(),
+                metadata: record.metadata,
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    async fn put(&self, key: &CacheKey, tile: &CachedTile) -> Result<(), Error> {
+        let (tiles, metadata) = self.stores(IdbTransactionMode::Readwrite).await?;
+        let key_value = key_string(key);
+
+        let data = Uint8Array::from(tile.data.as_slice());
+        let data_request = tiles.put_with_key(&data, &key_value).map_err(idb_error)?;
+        let metadata_request = metadata
+            .put_with_key(&record_value(key, &tile.metadata)?, &key_value)
+            .map_err(idb_error)?;
+        let data_future = request_future(&data_request);
+        let metadata_future = request_future(&metadata_request);
+        data_future.await?;
+        metadata_future.await?;
+        Ok(())
+    }
+
+    async fn update_metadata(&self, key: &CacheKey, metadata: &CacheMetadata) -> Result<(), Error> {
+        let key_value = key_string(key);
+
+        // The transaction of the lookup is inactive once it is awaited, therefore the metadata
+        // is written within a separate transaction
+        let (_tiles, metadata_store) = self.stores(IdbTransactionMode::Readonly).await?;
+        let existing = request_future(&metadata_store.get(&key_value).map_err(idb_error)?).await?;
+        if parse_record(&existing).is_none() {
+            return Ok(());
+        }
+
+        let (_tiles, metadata_store) = self.stores(IdbTransactionMode::Readwrite).await?;
+        let request = metadata_store
+            .put_with_key(&record_value(key, metadata)?, &key_value)
+            .map_err(idb_error)?;
+        request_future(&request).await?;
+        Ok(())
+    }
+
+    async fn remove(&self, key: &CacheKey) -> Result<(), Error> {
+        let (tiles, metadata) = self.stores(IdbTransactionMode::Readwrite).await?;
+        let key_value = key_string(key);
+
+        let data_request = tiles.delete(&key_value).map_err(idb_error)?;
+        let metadata_request = metadata.delete(&key_value).map_err(idb_error)?;
+        let data_future = request_future(&data_request);
+        let metadata_future = request_future(&metadata_request);
+        data_future.await?;
+        metadata_future.await?;
+        Ok(())
+    }
+
+    async fn entries(&self) -> Result<Vec<(CacheKey, CacheMetadata)>, Error> {
+        let (_tiles, metadata) = self.stores(IdbTransactionMode::Readonly).await?;
+
+        let records: Array = request_future(&metadata.get_all().map_err(idb_error)?)
+            .await?
+            .dyn_into()
+            .map_err(|_e| Error::IO("IndexedDB returned invalid entries".to_string()))?;
+
+        Ok(records
+            .iter()
+            .filter_map(|value| parse_record(&value))
+            .map(|record| (record.key, record.metadata))
+            .collect())
+    }
+}
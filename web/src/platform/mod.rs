@@ -1,4 +1,5 @@
 pub mod http_client;
+pub mod indexed_db_cache;
 pub mod legacy_webworker_fetcher;
 pub mod pool;
 pub mod schedule_method;